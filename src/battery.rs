@@ -0,0 +1,161 @@
+/// Battery chemistry, selecting the discharge curve of
+/// [battery_soc()](fn.battery_soc.html).
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Chemistry {
+    /// Lithium polymer, 4.2 V per cell when full.
+    LiPo,
+    /// Lithium iron phosphate, 3.6 V per cell when full.
+    LiFePo4,
+    /// Sealed lead acid, 2.1 V per cell (12.7 V per 6-cell battery) when full.
+    Sla,
+}
+
+// Resting voltage per cell for the state of charge in percents, in increasing
+// order of voltage.
+const LIPO_CURVE: &[(f32, f32)] = &[
+    (3.27, 0.0),
+    (3.61, 5.0),
+    (3.69, 10.0),
+    (3.71, 15.0),
+    (3.73, 20.0),
+    (3.75, 25.0),
+    (3.77, 30.0),
+    (3.79, 35.0),
+    (3.80, 40.0),
+    (3.82, 45.0),
+    (3.84, 50.0),
+    (3.85, 55.0),
+    (3.87, 60.0),
+    (3.91, 65.0),
+    (3.95, 70.0),
+    (3.98, 75.0),
+    (4.02, 80.0),
+    (4.08, 85.0),
+    (4.11, 90.0),
+    (4.15, 95.0),
+    (4.20, 100.0),
+];
+
+const LIFEPO4_CURVE: &[(f32, f32)] = &[
+    (2.50, 0.0),
+    (3.00, 5.0),
+    (3.13, 10.0),
+    (3.20, 20.0),
+    (3.23, 30.0),
+    (3.25, 40.0),
+    (3.26, 50.0),
+    (3.28, 60.0),
+    (3.30, 70.0),
+    (3.33, 80.0),
+    (3.35, 90.0),
+    (3.40, 99.0),
+    (3.60, 100.0),
+];
+
+const SLA_CURVE: &[(f32, f32)] = &[
+    (1.93, 0.0),
+    (1.98, 10.0),
+    (2.01, 20.0),
+    (2.03, 30.0),
+    (2.05, 40.0),
+    (2.07, 50.0),
+    (2.08, 60.0),
+    (2.10, 70.0),
+    (2.11, 80.0),
+    (2.12, 90.0),
+    (2.13, 100.0),
+];
+
+impl Chemistry {
+    fn curve(self) -> &'static [(f32, f32)] {
+        match self {
+            Chemistry::LiPo => LIPO_CURVE,
+            Chemistry::LiFePo4 => LIFEPO4_CURVE,
+            Chemistry::Sla => SLA_CURVE,
+        }
+    }
+}
+
+/// Approximate state of charge in percents, between 0.0 and 100.0, of a pack
+/// of *cells* cells in series measuring *volts*, for ex. the reading of
+/// `Sabertooth2x32::get_voltage()`.
+///
+/// The curves are for a battery at rest: under load the voltage sags and
+/// the state of charge is underestimated, so it is better read when the
+/// motors are stopped. The result is only an indication for a user
+/// interface, not a measure of the remaining capacity. Return 0.0 if
+/// *cells* is 0.
+///
+/// # Example
+///
+/// ```
+/// use saberrs::{battery_soc, Chemistry};
+///
+/// assert_eq!(100.0, battery_soc(12.6, Chemistry::LiPo, 3));
+/// assert!(battery_soc(10.0, Chemistry::LiPo, 3) < 5.0);
+/// ```
+pub fn battery_soc(volts: f32, chemistry: Chemistry, cells: u8) -> f32 {
+    if cells == 0 || !volts.is_finite() {
+        return 0.0;
+    }
+    let cell_volts = volts / f32::from(cells);
+    let curve = chemistry.curve();
+    let (min_volts, min_soc) = curve[0];
+    let (max_volts, max_soc) = curve[curve.len() - 1];
+    if cell_volts <= min_volts {
+        return min_soc;
+    }
+    if cell_volts >= max_volts {
+        return max_soc;
+    }
+    for pair in curve.windows(2) {
+        let (low_volts, low_soc) = pair[0];
+        let (high_volts, high_soc) = pair[1];
+        if cell_volts <= high_volts {
+            let ratio = (cell_volts - low_volts) / (high_volts - low_volts);
+            return low_soc + ratio * (high_soc - low_soc);
+        }
+    }
+    max_soc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lipo() {
+        assert_eq!(100.0, battery_soc(12.6, Chemistry::LiPo, 3));
+        assert_eq!(100.0, battery_soc(17.0, Chemistry::LiPo, 4));
+        let soc = battery_soc(4.02 * 4.0, Chemistry::LiPo, 4);
+        assert!((soc - 80.0).abs() < 1e-3, "{}", soc);
+        let soc = battery_soc(3.5 * 3.0, Chemistry::LiPo, 3);
+        assert!(soc > 0.0 && soc < 5.0, "{}", soc);
+        assert_eq!(0.0, battery_soc(9.0, Chemistry::LiPo, 3));
+    }
+
+    #[test]
+    fn other_chemistries() {
+        assert_eq!(100.0, battery_soc(12.8, Chemistry::Sla, 6));
+        let soc = battery_soc(12.42, Chemistry::Sla, 6);
+        assert!((soc - 50.0).abs() < 1e-3, "{}", soc);
+        assert_eq!(0.0, battery_soc(10.5, Chemistry::Sla, 6));
+        let soc = battery_soc(13.04, Chemistry::LiFePo4, 4);
+        assert!((soc - 50.0).abs() < 1e-3, "{}", soc);
+    }
+
+    #[test]
+    fn invalid() {
+        assert_eq!(0.0, battery_soc(12.6, Chemistry::LiPo, 0));
+        assert_eq!(0.0, battery_soc(f32::NAN, Chemistry::LiPo, 3));
+    }
+
+    #[test]
+    fn curves_increasing() {
+        for chemistry in &[Chemistry::LiPo, Chemistry::LiFePo4, Chemistry::Sla] {
+            for pair in chemistry.curve().windows(2) {
+                assert!(pair[0].0 < pair[1].0 && pair[0].1 < pair[1].1);
+            }
+        }
+    }
+}
@@ -0,0 +1,473 @@
+//! Conversions between user units and the values sent on the wire.
+//!
+//! These are the conversions used by the interfaces, with the same
+//! validation, for ex. for precomputing tables of wire values. Out of range
+//! inputs return `Error::InvalidInput`.
+//!
+//! # Example
+//!
+//! ```
+//! use saberrs::convert;
+//! # use saberrs::Result;
+//! # fn example() -> Result<()> {
+//! assert_eq!(1023, convert::ratio_to_value(0.5)?);
+//! assert_eq!(-2047, convert::percent_to_value(-100.0)?);
+//! assert!(convert::ratio_to_value(1.5).is_err());
+//! # Ok(())
+//! # }
+//! # example().unwrap();
+//! ```
+
+use std::time::Duration;
+
+use crate::error::{Error, Result};
+
+/// Maximum value of the speed, drive, turn, power, ramp and aux commands.
+pub const RANGE_MAX: i32 = 2047;
+
+/// Minimum value of the speed, drive, turn, power, ramp and aux commands.
+pub const RANGE_MIN: i32 = -2047;
+
+/// Longest serial timeout of the Sabertooth 2x32, in milliseconds, limited by
+/// the 14 bits of the data value.
+pub const MAX_SERIAL_TIMEOUT_MS: u32 = 16383;
+
+/// Lowest minimum voltage of the Sabertooth 2x60, in volts.
+pub const MIN_VOLTAGE_2X60_LOW: f32 = 6.0;
+
+/// Highest minimum voltage of the Sabertooth 2x60, in volts.
+pub const MIN_VOLTAGE_2X60_HIGH: f32 = 30.0;
+
+/// Highest maximum voltage of the Sabertooth 2x60, in volts (127 / 5.12).
+pub const MAX_VOLTAGE_2X60_HIGH: f32 = 24.804_688;
+
+// Units per volt of the minimum and maximum voltages of the Sabertooth 2x60.
+const MIN_VOLTAGE_2X60_SCALE: f32 = 5.0;
+const MAX_VOLTAGE_2X60_SCALE: f32 = 5.12;
+
+fn out_of_range<T>(what: &str, value: impl std::fmt::Display, range: &str) -> Result<T> {
+    Err(Error::InvalidInput(format!(
+        "{} ({}) out of range {}",
+        what, value, range
+    )))
+}
+
+/// Check that the range -*max*~*max* of the `_in_range` conversions is
+/// within -2047~2047 and not empty.
+pub(crate) fn check_range(max: i32) -> Result<()> {
+    if !(1..=RANGE_MAX).contains(&max) {
+        let msg = format!("range should be 1~{} (was {})", RANGE_MAX, max);
+        return Err(Error::InvalidInput(msg));
+    }
+    Ok(())
+}
+
+/// Convert a ratio between -1.0 and 1.0 to a command value between -2047 and
+/// 2047. The result is truncated toward zero, for ex. 0.5 gives 1023.
+pub fn ratio_to_value(ratio: f32) -> Result<i32> {
+    ratio_to_value_in_range(ratio, RANGE_MAX)
+}
+
+/// Same as `ratio_to_value()`, for a range of -*max*~*max*, as configured
+/// with `PlainText::set_range()`. *max* must be 1~2047.
+pub fn ratio_to_value_in_range(ratio: f32, max: i32) -> Result<i32> {
+    check_range(max)?;
+    // NaN compares false with everything, it must be checked explicitly.
+    if ratio.is_nan() || ratio.abs() > 1.0 {
+        return out_of_range("value", ratio, "-1.0~1.0");
+    }
+
+    let value = (ratio * max as f32) as i32;
+
+    if value > max {
+        Ok(max)
+    } else if value < -max {
+        Ok(-max)
+    } else {
+        Ok(value)
+    }
+}
+
+/// Check a double precision ratio between -1.0 and 1.0 and round it to the
+/// nearest `f32`, as done by the `_f64` methods of the interfaces. The range
+/// is checked before rounding: for ex. `1.0 + 1e-12` is out of range, and
+/// `1.0 - 1e-12` gives 1.0.
+pub fn ratio_from_f64(ratio: f64) -> Result<f32> {
+    if ratio.is_nan() || ratio.abs() > 1.0 {
+        return out_of_range("value", ratio, "-1.0~1.0");
+    }
+    Ok(ratio as f32)
+}
+
+/// Same as `ratio_to_value()`, for a double precision ratio, see
+/// `ratio_from_f64()`.
+pub fn ratio_to_value_f64(ratio: f64) -> Result<i32> {
+    ratio_to_value(ratio_from_f64(ratio)?)
+}
+
+/// Convert a value between -2047 and 2047, as read from the Sabertooth, to a
+/// ratio between -1.0 and 1.0.
+pub fn value_to_ratio(value: i32) -> Result<f32> {
+    value_to_ratio_in_range(value, RANGE_MAX)
+}
+
+/// Same as `value_to_ratio()`, for a range of -*max*~*max*. *max* must be
+/// 1~2047.
+pub fn value_to_ratio_in_range(value: i32, max: i32) -> Result<f32> {
+    check_range(max)?;
+    if !(-max..=max).contains(&value) {
+        return out_of_range("value", value, &format!("{}~{}", -max, max));
+    }
+    Ok(value as f32 / max as f32)
+}
+
+/// Same as `value_to_ratio()`, clamping a value out of -2047~2047 to -1.0 or
+/// 1.0 instead of failing, for ex. for logging a transient out of range
+/// reading.
+pub fn value_to_ratio_saturating(value: i32) -> f32 {
+    value.clamp(RANGE_MIN, RANGE_MAX) as f32 / RANGE_MAX as f32
+}
+
+/// Same as `value_to_ratio_saturating()`, for a range of -*max*~*max*. Only
+/// the value is clamped: *max* must be 1~2047.
+pub fn value_to_ratio_in_range_saturating(value: i32, max: i32) -> Result<f32> {
+    check_range(max)?;
+    Ok(value.clamp(-max, max) as f32 / max as f32)
+}
+
+/// Convert a percentage between -100.0 and 100.0 to a command value between
+/// -2047 and 2047. The result is truncated toward zero, like
+/// `ratio_to_value()`.
+pub fn percent_to_value(percent: f32) -> Result<i32> {
+    if percent.is_nan() || percent.abs() > 100.0 {
+        return out_of_range("percentage", percent, "-100.0~100.0");
+    }
+    ratio_to_value(percent / 100.0)
+}
+
+/// Same as `percent_to_value()`, for a double precision percentage. The range
+/// is checked in double precision, like `ratio_from_f64()`.
+pub fn percent_to_value_f64(percent: f64) -> Result<i32> {
+    if percent.is_nan() || percent.abs() > 100.0 {
+        return out_of_range("percentage", percent, "-100.0~100.0");
+    }
+    percent_to_value(percent as f32)
+}
+
+/// Convert a value between -2047 and 2047 to a percentage between -100.0 and
+/// 100.0.
+pub fn value_to_percent(value: i32) -> Result<f32> {
+    Ok(value_to_ratio(value)? * 100.0)
+}
+
+/// Convert a serial timeout to the value of the timeout command, in
+/// milliseconds. The resolution is the millisecond, the rest is truncated,
+/// and a zero value disables the timeout.
+pub fn timeout_to_value(timeout: Duration) -> Result<i32> {
+    let millis = timeout.as_millis();
+    if millis > u128::from(MAX_SERIAL_TIMEOUT_MS) {
+        let msg = format!(
+            "serial timeout should be at most {} ms (was {} ms)",
+            MAX_SERIAL_TIMEOUT_MS, millis
+        );
+        return Err(Error::InvalidInput(msg));
+    }
+    Ok(millis as i32)
+}
+
+/// Convert the value of the timeout command, in milliseconds, to a serial
+/// timeout.
+pub fn value_to_timeout(value: i32) -> Result<Duration> {
+    if !(0..=MAX_SERIAL_TIMEOUT_MS as i32).contains(&value) {
+        return out_of_range(
+            "serial timeout",
+            value,
+            &format!("0~{}", MAX_SERIAL_TIMEOUT_MS),
+        );
+    }
+    Ok(Duration::from_millis(value as u64))
+}
+
+/// Convert a voltage between 6.0 and 30.0 V to the value of the minimum
+/// voltage command of the Sabertooth 2x60, (volts - 6) * 5 rounded to the
+/// nearest integer.
+pub fn volts_to_min_voltage_2x60(volts: f32) -> Result<u8> {
+    if !(MIN_VOLTAGE_2X60_LOW..=MIN_VOLTAGE_2X60_HIGH).contains(&volts) {
+        return out_of_range(
+            "minimum voltage",
+            volts,
+            &format!("{}~{} V", MIN_VOLTAGE_2X60_LOW, MIN_VOLTAGE_2X60_HIGH),
+        );
+    }
+    Ok(((volts - MIN_VOLTAGE_2X60_LOW) * MIN_VOLTAGE_2X60_SCALE).round() as u8)
+}
+
+/// Convert the value of the minimum voltage command of the Sabertooth 2x60,
+/// between 0 and 120, to volts.
+pub fn min_voltage_2x60_to_volts(value: u8) -> Result<f32> {
+    let max = ((MIN_VOLTAGE_2X60_HIGH - MIN_VOLTAGE_2X60_LOW) * MIN_VOLTAGE_2X60_SCALE) as u8;
+    if value > max {
+        return out_of_range("minimum voltage value", value, &format!("0~{}", max));
+    }
+    Ok(MIN_VOLTAGE_2X60_LOW + f32::from(value) / MIN_VOLTAGE_2X60_SCALE)
+}
+
+/// Convert a voltage between 0.0 and 24.8 V to the value of the maximum
+/// voltage command of the Sabertooth 2x60, volts * 5.12 rounded to the
+/// nearest integer.
+pub fn volts_to_max_voltage_2x60(volts: f32) -> Result<u8> {
+    if !(0.0..=MAX_VOLTAGE_2X60_HIGH).contains(&volts) {
+        return out_of_range(
+            "maximum voltage",
+            volts,
+            &format!("0~{} V", MAX_VOLTAGE_2X60_HIGH),
+        );
+    }
+    Ok((volts * MAX_VOLTAGE_2X60_SCALE).round() as u8)
+}
+
+/// Convert the value of the maximum voltage command of the Sabertooth 2x60,
+/// between 0 and 127, to volts.
+pub fn max_voltage_2x60_to_volts(value: u8) -> Result<f32> {
+    if value > 127 {
+        return out_of_range("maximum voltage value", value, "0~127");
+    }
+    Ok(f32::from(value) / MAX_VOLTAGE_2X60_SCALE)
+}
+
+/// Convert a deadband in percent, between 0.0 and 100.0 as shown by
+/// DEScribe, to the value of the deadband command, between 0 and 127. The
+/// value is the half-width of the band of commands around the center that
+/// stop the motors, out of the 127 steps of each direction: percent * 127 /
+/// 100 rounded to the nearest integer, for ex. 10% is 13.
+pub fn deadband_from_percent(percent: f32) -> Result<u8> {
+    if !(0.0..=100.0).contains(&percent) {
+        return out_of_range("deadband", percent, "0~100%");
+    }
+    Ok((percent * 127.0 / 100.0).round() as u8)
+}
+
+/// Convert the value of the deadband command, between 0 and 127, to percent,
+/// see [deadband_from_percent()](fn.deadband_from_percent.html).
+pub fn deadband_to_percent(value: u8) -> Result<f32> {
+    if value > 127 {
+        return out_of_range("deadband value", value, "0~127");
+    }
+    Ok(f32::from(value) * 100.0 / 127.0)
+}
+
+/// Convert a temperature from degrees celsius to degrees fahrenheit.
+pub fn celsius_to_fahrenheit(celsius: f32) -> f32 {
+    celsius * 1.8 + 32.0
+}
+
+/// Convert a temperature from degrees fahrenheit to degrees celsius.
+pub fn fahrenheit_to_celsius(fahrenheit: f32) -> f32 {
+    (fahrenheit - 32.0) / 1.8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ratio_boundaries() {
+        assert_eq!(RANGE_MAX, ratio_to_value(1.0).unwrap());
+        assert_eq!(RANGE_MIN, ratio_to_value(-1.0).unwrap());
+        assert_eq!(0, ratio_to_value(0.0).unwrap());
+        assert_eq!(0, ratio_to_value(-0.0).unwrap());
+        assert_eq!(1023, ratio_to_value(0.5).unwrap());
+        assert_eq!(-1023, ratio_to_value(-0.5).unwrap());
+        assert_eq!(409, ratio_to_value_in_range(0.5, 819).unwrap());
+        for &ratio in &[1.0001, -1.0001, f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+            match ratio_to_value(ratio) {
+                Err(Error::InvalidInput(_)) => {}
+                res => panic!("{}: expected an invalid input error, got {:?}", ratio, res),
+            }
+        }
+
+        assert_eq!(1.0, value_to_ratio(RANGE_MAX).unwrap());
+        assert_eq!(-1.0, value_to_ratio(RANGE_MIN).unwrap());
+        assert_eq!(0.0, value_to_ratio(0).unwrap());
+        assert!(value_to_ratio(2048).is_err());
+        assert!(value_to_ratio(-2048).is_err());
+        assert!(value_to_ratio_in_range(820, 819).is_err());
+
+        assert_eq!(1.0, value_to_ratio_saturating(2048));
+        assert_eq!(-1.0, value_to_ratio_saturating(-2048));
+        assert_eq!(-1.0, value_to_ratio_saturating(i32::MIN));
+        assert_eq!(1.0, value_to_ratio_in_range_saturating(820, 819).unwrap());
+        for value in RANGE_MIN..=RANGE_MAX {
+            assert_eq!(
+                value_to_ratio(value).unwrap(),
+                value_to_ratio_saturating(value)
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_ranges() {
+        for &max in &[0, -1, RANGE_MAX + 1, i32::MIN] {
+            for res in &[
+                value_to_ratio_in_range(0, max),
+                value_to_ratio_in_range_saturating(0, max),
+                ratio_to_value_in_range(0.5, max).map(|v| v as f32),
+            ] {
+                match res {
+                    Err(Error::InvalidInput(_)) => {}
+                    res => panic!("{}: expected an invalid input error, got {:?}", max, res),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn ratio_round_trip() {
+        for value in RANGE_MIN..=RANGE_MAX {
+            let ratio = value_to_ratio(value).unwrap();
+            assert!(ratio.abs() <= 1.0);
+            let back = ratio_to_value(ratio).unwrap();
+            // The truncation may lose one unit of the exact ratio.
+            assert!((back - value).abs() <= 1, "{} gave {}", value, back);
+            assert_eq!(back.signum(), value.signum());
+        }
+        for max in &[1, 100, 819, RANGE_MAX] {
+            for value in -max..=*max {
+                let ratio = value_to_ratio_in_range(value, *max).unwrap();
+                let back = ratio_to_value_in_range(ratio, *max).unwrap();
+                assert!((back - value).abs() <= 1, "{}/{} gave {}", value, max, back);
+            }
+        }
+    }
+
+    #[test]
+    fn f64_ratios() {
+        for value in RANGE_MIN..=RANGE_MAX {
+            let ratio = value_to_ratio(value).unwrap();
+            assert_eq!(
+                ratio_to_value(ratio).unwrap(),
+                ratio_to_value_f64(f64::from(ratio)).unwrap()
+            );
+            let percent = value_to_percent(value).unwrap();
+            assert_eq!(
+                percent_to_value(percent).unwrap(),
+                percent_to_value_f64(f64::from(percent)).unwrap()
+            );
+        }
+
+        // Out of range in double precision, but not once rounded to f32.
+        assert_eq!(1.0, (1.0 + 1e-12) as f32);
+        for &ratio in &[1.0 + 1e-12, -1.0 - 1e-12, f64::NAN, f64::INFINITY] {
+            assert!(ratio_to_value_f64(ratio).is_err(), "{} accepted", ratio);
+        }
+        assert_eq!(RANGE_MAX, ratio_to_value_f64(1.0 - 1e-12).unwrap());
+        assert_eq!(RANGE_MIN, ratio_to_value_f64(-1.0 + 1e-12).unwrap());
+        assert_eq!(RANGE_MAX - 1, ratio_to_value_f64(2046.5 / 2047.0).unwrap());
+        assert!(percent_to_value_f64(100.0 + 1e-10).is_err());
+        assert!(percent_to_value_f64(f64::NAN).is_err());
+        assert_eq!(RANGE_MIN, percent_to_value_f64(-100.0).unwrap());
+    }
+
+    #[test]
+    fn percent_boundaries() {
+        assert_eq!(RANGE_MAX, percent_to_value(100.0).unwrap());
+        assert_eq!(RANGE_MIN, percent_to_value(-100.0).unwrap());
+        assert_eq!(1023, percent_to_value(50.0).unwrap());
+        assert_eq!(0, percent_to_value(0.0).unwrap());
+        assert!(percent_to_value(100.01).is_err());
+        assert!(percent_to_value(-100.01).is_err());
+        assert!(percent_to_value(f32::NAN).is_err());
+        assert_eq!(100.0, value_to_percent(RANGE_MAX).unwrap());
+        assert_eq!(-100.0, value_to_percent(RANGE_MIN).unwrap());
+        assert!(value_to_percent(2048).is_err());
+
+        for value in RANGE_MIN..=RANGE_MAX {
+            let percent = value_to_percent(value).unwrap();
+            let back = percent_to_value(percent).unwrap();
+            assert!((back - value).abs() <= 1, "{} gave {}", value, back);
+        }
+    }
+
+    #[test]
+    fn timeout_round_trip() {
+        assert_eq!(0, timeout_to_value(Duration::from_millis(0)).unwrap());
+        assert_eq!(
+            100,
+            timeout_to_value(Duration::from_micros(100_999)).unwrap()
+        );
+        assert_eq!(
+            16383,
+            timeout_to_value(Duration::from_millis(16383)).unwrap()
+        );
+        assert!(timeout_to_value(Duration::from_millis(16384)).is_err());
+        assert!(timeout_to_value(Duration::from_secs(u64::MAX)).is_err());
+        assert!(value_to_timeout(-1).is_err());
+        assert!(value_to_timeout(16384).is_err());
+
+        for value in 0..=MAX_SERIAL_TIMEOUT_MS as i32 {
+            let timeout = value_to_timeout(value).unwrap();
+            assert_eq!(value, timeout_to_value(timeout).unwrap());
+        }
+    }
+
+    #[test]
+    fn voltage_2x60_round_trip() {
+        assert_eq!(0, volts_to_min_voltage_2x60(6.0).unwrap());
+        assert_eq!(30, volts_to_min_voltage_2x60(12.0).unwrap());
+        assert_eq!(120, volts_to_min_voltage_2x60(30.0).unwrap());
+        assert!(volts_to_min_voltage_2x60(5.9).is_err());
+        assert!(volts_to_min_voltage_2x60(30.1).is_err());
+        assert!(volts_to_min_voltage_2x60(f32::NAN).is_err());
+        assert!(min_voltage_2x60_to_volts(121).is_err());
+
+        assert_eq!(0, volts_to_max_voltage_2x60(0.0).unwrap());
+        assert_eq!(123, volts_to_max_voltage_2x60(24.0).unwrap());
+        assert_eq!(
+            127,
+            volts_to_max_voltage_2x60(MAX_VOLTAGE_2X60_HIGH).unwrap()
+        );
+        assert!(volts_to_max_voltage_2x60(-0.1).is_err());
+        assert!(volts_to_max_voltage_2x60(24.9).is_err());
+        assert!(volts_to_max_voltage_2x60(f32::NAN).is_err());
+        assert!(max_voltage_2x60_to_volts(128).is_err());
+
+        for value in 0..=120 {
+            let volts = min_voltage_2x60_to_volts(value).unwrap();
+            assert_eq!(value, volts_to_min_voltage_2x60(volts).unwrap());
+        }
+        for value in 0..=127 {
+            let volts = max_voltage_2x60_to_volts(value).unwrap();
+            assert_eq!(value, volts_to_max_voltage_2x60(volts).unwrap());
+        }
+    }
+
+    #[test]
+    fn deadband_round_trip() {
+        assert_eq!(0, deadband_from_percent(0.0).unwrap());
+        assert_eq!(3, deadband_from_percent(2.4).unwrap());
+        assert_eq!(6, deadband_from_percent(5.0).unwrap());
+        assert_eq!(13, deadband_from_percent(10.0).unwrap());
+        assert_eq!(32, deadband_from_percent(25.0).unwrap());
+        assert_eq!(64, deadband_from_percent(50.0).unwrap());
+        assert_eq!(127, deadband_from_percent(100.0).unwrap());
+        assert!(deadband_from_percent(-0.1).is_err());
+        assert!(deadband_from_percent(100.1).is_err());
+        assert!(deadband_from_percent(f32::NAN).is_err());
+        assert_eq!(100.0, deadband_to_percent(127).unwrap());
+        assert!(deadband_to_percent(128).is_err());
+
+        for value in 0..=127 {
+            let percent = deadband_to_percent(value).unwrap();
+            assert_eq!(value, deadband_from_percent(percent).unwrap());
+        }
+    }
+
+    #[test]
+    fn temperature() {
+        assert_eq!(86.0, celsius_to_fahrenheit(30.0));
+        assert_eq!(32.0, celsius_to_fahrenheit(0.0));
+        assert_eq!(-40.0, celsius_to_fahrenheit(-40.0));
+        assert_eq!(100.0, fahrenheit_to_celsius(212.0));
+        assert_eq!(-40.0, fahrenheit_to_celsius(-40.0));
+    }
+}
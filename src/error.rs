@@ -17,6 +17,20 @@ pub enum Error {
     /// The response from the Sabertooth is invalid.
     Response(String),
 
+    /// The command was refused by a safety setting of the interface.
+    Safety(String),
+
+    /// The echo of the transmitted bytes does not match them, see
+    /// `EchoMode::VerifyAndDiscard`.
+    Echo(String),
+
+    /// The reply is garbled in a way only explained by two Sabertooths
+    /// answering at once, most probably because they share the same address.
+    AddressCollision(String),
+
+    /// The serial port is already opened, most probably by another process.
+    PortInUse(String),
+
     /// Other error
     Other,
 
@@ -25,12 +39,77 @@ pub enum Error {
     Serial(serialport::Error),
 }
 
+/// Errors of the operating system reporting a device gone, for ex. an USB
+/// adapter unplugged: EIO, ENXIO and ENODEV.
+#[cfg(unix)]
+const DISCONNECTED_OS_ERRORS: [i32; 3] = [5, 6, 19];
+
+/// Errors of the operating system reporting a device gone, for ex. an USB
+/// adapter unplugged: ERROR_BAD_COMMAND, ERROR_GEN_FAILURE and
+/// ERROR_DEVICE_NOT_CONNECTED.
+#[cfg(windows)]
+const DISCONNECTED_OS_ERRORS: [i32; 3] = [22, 31, 1167];
+
+#[cfg(not(any(unix, windows)))]
+const DISCONNECTED_OS_ERRORS: [i32; 0] = [];
+
+fn io_disconnected(e: &io::Error) -> bool {
+    match e.raw_os_error() {
+        Some(code) => DISCONNECTED_OS_ERRORS.contains(&code),
+        None => matches!(
+            e.kind(),
+            io::ErrorKind::BrokenPipe | io::ErrorKind::NotConnected
+        ),
+    }
+}
+
+impl Error {
+    /// Whether the error means that the serial device is gone, for ex. the
+    /// USB adapter was unplugged, as opposed to a corrupted reply or a
+    /// timeout. The port should then be reopened.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use saberrs::sabertooth2x32::{PacketSerial, Sabertooth2x32};
+    /// # use saberrs::Result;
+    /// # fn example() -> Result<()> {
+    /// let mut saber = PacketSerial::new("/dev/ttyUSB0")?;
+    /// match saber.set_speed(1, 0.5) {
+    ///     Err(e) if e.is_disconnected() => saber = PacketSerial::new("/dev/ttyUSB0")?,
+    ///     res => res?,
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn is_disconnected(&self) -> bool {
+        match self {
+            Error::Io(e) => io_disconnected(e),
+            #[cfg(feature = "serialport")]
+            Error::Serial(e) => match e.kind() {
+                serialport::ErrorKind::NoDevice => true,
+                serialport::ErrorKind::Io(kind) => io_disconnected(&io::Error::from(kind)),
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+}
+
 impl fmt::Display for Error {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> std::result::Result<(), fmt::Error> {
         match self {
             Error::Io(e) => write!(fmt, "IO error: {}", e),
             Error::InvalidInput(msg) => write!(fmt, "Invalid input: {}", msg),
             Error::Response(msg) => write!(fmt, "Invalid response from Sabertooth: {}", msg),
+            Error::Safety(msg) => write!(fmt, "Refused for safety: {}", msg),
+            Error::Echo(msg) => write!(fmt, "Wrong echo: {}", msg),
+            Error::AddressCollision(msg) => write!(
+                fmt,
+                "Reply collision, check for Sabertooths sharing the same address: {}",
+                msg
+            ),
+            Error::PortInUse(msg) => write!(fmt, "Port in use: {}", msg),
             Error::Other => write!(fmt, "Other saberrs error"),
 
             #[cfg(feature = "serialport")]
@@ -45,7 +124,13 @@ impl std::error::Error for Error {
             Error::Io(e) => Some(e),
             Error::InvalidInput(_) => None,
             Error::Response(_) => None,
+            Error::Safety(_) => None,
+            Error::Echo(_) => None,
+            Error::AddressCollision(_) => None,
+            Error::PortInUse(_) => None,
             Error::Other => None,
+
+            #[cfg(feature = "serialport")]
             Error::Serial(e) => Some(e),
         }
     }
@@ -57,6 +142,7 @@ impl From<io::Error> for Error {
     }
 }
 
+#[cfg(feature = "serialport")]
 impl From<serialport::Error> for Error {
     fn from(e: serialport::Error) -> Self {
         Self::Serial(e)
@@ -0,0 +1,48 @@
+use crate::convert;
+use crate::sabertooth2x32::{PacketType, DEFAULT_ADDRESS, DEFAULT_PACKET_TYPE};
+
+/// Version of the crate and protocol constants in effect, see
+/// [info()](fn.info.html).
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Info {
+    /// Version of the `saberrs` crate.
+    pub version: &'static str,
+    /// Raw command value matching a ratio of 1.0.
+    pub range_max: i32,
+    /// Default address of the packet serial interfaces.
+    pub default_address: u8,
+    /// Default packet type of the packet serial interfaces.
+    pub default_packet_type: PacketType,
+}
+
+/// Return the version of the crate and the protocol constants in effect,
+/// for example for including them in logs or bug reports.
+///
+/// # Example
+///
+/// ```rust
+/// let info = saberrs::info();
+/// println!("saberrs v{}", info.version);
+/// ```
+pub fn info() -> Info {
+    Info {
+        version: env!("CARGO_PKG_VERSION"),
+        range_max: convert::RANGE_MAX,
+        default_address: DEFAULT_ADDRESS,
+        default_packet_type: DEFAULT_PACKET_TYPE,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constants() {
+        let info = info();
+        assert_eq!(env!("CARGO_PKG_VERSION"), info.version);
+        assert_eq!(convert::RANGE_MAX, info.range_max);
+        assert_eq!(DEFAULT_ADDRESS, info.default_address);
+        assert_eq!(DEFAULT_PACKET_TYPE, info.default_packet_type);
+    }
+}
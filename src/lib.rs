@@ -126,14 +126,31 @@
 //! Features:
 //!
 //! - `serialport`, enabled by default, allows the usage of the crate
-//! [serialport] for providing [SabertoothPort] and [SabertoothPortShared].
-//! If this feature is disabled [SabertoothSerial] needs to be implemented
-//! manually.
+//!   [serialport] for providing [SabertoothPort] and [SabertoothPortShared].
+//!   If this feature is disabled [SabertoothSerial] needs to be implemented
+//!   manually.
+//! - `log`, enabled by default, emits logs with the crate [log]: the frames
+//!   in debug builds, and warnings such as commands sent to a motor shut
+//!   down. Without it the crate has no logging dependency.
+//! - `fuzz`, disabled by default, exposes
+//!   [sabertooth2x32::parse_fuzz](sabertooth2x32/fn.parse_fuzz.html) as an
+//!   entry point for fuzzing the response parsers.
+//! - `test-util`, disabled by default, exposes
+//!   [sabertooth2x32::Fake2x32Packet](sabertooth2x32/struct.Fake2x32Packet.html),
+//!   an emulated Sabertooth for testing applications without a serial port.
+//! - `gpio-sysfs`, disabled by default, provides `SysfsGpio`, a
+//!   [PowerControl] switching the power of the Sabertooth with a GPIO of the
+//!   Linux sysfs interface.
+//! - `termios`, disabled by default, provides `TermiosPort`, a
+//!   [SabertoothSerial] configuring the serial line with the termios calls of
+//!   the C library, on Unix targets where [serialport] is not usable, for ex.
+//!   musl. It can be used without the `serialport` feature.
 //!
 //! Dependencies:
 //!
 //! - [serialport] for the `serialport` feature.
-//! - [log] for emitting logs.
+//! - [log] for the `log` feature.
+//! - [libc] for the `termios` feature.
 //!
 //! # Disclaimer
 //!
@@ -150,18 +167,35 @@
 //! [SabertoothPortShared]: struct.SabertoothPortShared.html
 //! [serialport]: https://crates.io/crates/serialport
 //! [log]: https://crates.io/crates/log
+//! [libc]: https://crates.io/crates/libc
 
+pub use battery::{battery_soc, Chemistry};
 pub use error::{Error, Result};
-pub use port::SabertoothSerial;
+pub use info::{info, Info};
+pub use port::{Framing, Parity, SabertoothSerial, StreamPort, StreamSetter};
+pub use power::PowerControl;
+pub use utils::{rescale_2x32_to_2x60, rescale_2x60_to_2x32, step_toward};
+
+#[cfg(all(feature = "gpio-sysfs", target_os = "linux"))]
+pub use power::SysfsGpio;
 
 #[cfg(feature = "serialport")]
-pub use port::sabertoothport::{SabertoothPort, SabertoothPortShared};
+pub use port::sabertoothport::{
+    PortSettings, SabertoothPort, SabertoothPortBuilder, SabertoothPortShared,
+};
+
+#[cfg(all(feature = "termios", unix))]
+pub use port::termiosport::{TermiosPort, TermiosPortBuilder};
 
 #[macro_use]
 mod utils;
 
+mod battery;
+pub mod convert;
 mod error;
+mod info;
 mod port;
+mod power;
 
 /// Interface for the [Sabertooth 2x32].
 ///
@@ -1,7 +1,52 @@
 use std::io;
-use std::time::Duration;
+use std::thread;
+use std::time::{Duration, Instant};
 
-use crate::error::Result;
+use crate::error::{Error, Result};
+use crate::utils::WOULD_BLOCK_PAUSE;
+
+/// Parity bit of a [Framing](struct.Framing.html).
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Parity {
+    None,
+    Odd,
+    Even,
+}
+
+/// Character framing of a serial line, see `SabertoothSerial::framing()`.
+///
+/// The Sabertooth only receives 8N1, the default: another framing is only
+/// useful when a converter, for ex. a serial device server, translates it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Framing {
+    /// Number of data bits, 5 to 8.
+    pub data_bits: u8,
+    /// Parity bit, none for 8N1.
+    pub parity: Parity,
+    /// Number of stop bits, 1 or 2.
+    pub stop_bits: u8,
+}
+
+impl Default for Framing {
+    fn default() -> Self {
+        Framing {
+            data_bits: 8,
+            parity: Parity::None,
+            stop_bits: 1,
+        }
+    }
+}
+
+impl std::fmt::Display for Framing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let parity = match self.parity {
+            Parity::None => 'N',
+            Parity::Odd => 'O',
+            Parity::Even => 'E',
+        };
+        write!(f, "{}{}{}", self.data_bits, parity, self.stop_bits)
+    }
+}
 
 /// Minimal serial port trait.
 ///
@@ -38,6 +83,9 @@ pub trait SabertoothSerial: io::Write + io::Read {
     fn timeout(&self) -> Duration;
 
     /// Set the baud rate of the serial port.
+    ///
+    /// Implementations should first wait for the bytes already written to be
+    /// transmitted, so that they are not sent at the new rate.
     fn set_baud_rate(&mut self, baud_rate: u32) -> Result<()>;
 
     /// Get the current baud rate setting of the serial port.
@@ -45,6 +93,254 @@ pub trait SabertoothSerial: io::Write + io::Read {
 
     /// Clear the tx and rx buffer, remaining bytes will be lost.
     fn clear_all(&self) -> Result<()>;
+
+    /// Get the character framing of the serial port. The default
+    /// implementation, for ports without such setting, returns 8N1.
+    fn framing(&self) -> Result<Framing> {
+        Ok(Framing::default())
+    }
+
+    /// Read exactly `buf.len()` bytes, possibly received in several pieces,
+    /// waiting at most *timeout* in total. This is the binary counterpart of
+    /// the line reading of the "Plain Text" interface, for decoding custom
+    /// replies.
+    ///
+    /// If the bytes are not all received in time, the error is an
+    /// `Error::Io` of kind `TimedOut` whose message tells how many arrived,
+    /// or of kind `UnexpectedEof` if the port reaches the end of its stream.
+    /// The timeout of the port is changed during the read and restored
+    /// afterwards.
+    fn read_exact_timeout(&mut self, buf: &mut [u8], timeout: Duration) -> Result<()> {
+        let previous = self.timeout();
+        let deadline = Instant::now() + timeout;
+        let mut received = 0;
+        let res = loop {
+            if received == buf.len() {
+                break Ok(());
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining == Duration::from_secs(0) {
+                let msg = format!(
+                    "{} of {} bytes received before the timeout",
+                    received,
+                    buf.len()
+                );
+                break Err(Error::Io(io::Error::new(io::ErrorKind::TimedOut, msg)));
+            }
+            if let Err(e) = self.set_timeout(remaining) {
+                break Err(e);
+            }
+            match self.read(&mut buf[received..]) {
+                Ok(0) => {
+                    let msg = format!(
+                        "{} of {} bytes received before the end of the stream",
+                        received,
+                        buf.len()
+                    );
+                    break Err(Error::Io(io::Error::new(io::ErrorKind::UnexpectedEof, msg)));
+                }
+                Ok(n) => received += n,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    thread::sleep(WOULD_BLOCK_PAUSE);
+                }
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::TimedOut | io::ErrorKind::Interrupted
+                    ) => {}
+                Err(e) => break Err(e.into()),
+            }
+        };
+        self.set_timeout(previous)?;
+        res
+    }
+}
+
+/// A borrowed port can be used by an interface, so that several interfaces
+/// take their turn on the same serial line, for ex. Sabertooth with different
+/// addresses chained on one bus.
+///
+/// Each request is a write followed by a read: the borrow guarantees that the
+/// other interfaces cannot write on the port before the reply has been read.
+///
+/// # Example
+///
+/// ```rust
+/// use saberrs::{Result, SabertoothPort};
+/// use saberrs::sabertooth2x32::{PacketSerial, Sabertooth2x32};
+///
+/// # fn example() -> Result<()> {
+/// let mut dev = SabertoothPort::new("/dev/ttyS2")?;
+/// PacketSerial::from(&mut dev).with_address(128).set_speed(1, 0.5)?;
+/// let current = PacketSerial::from(&mut dev).with_address(129).get_current(1)?;
+/// # Ok(())}
+/// ```
+impl<T: SabertoothSerial + ?Sized> SabertoothSerial for &mut T {
+    fn set_timeout(&mut self, timeout: Duration) -> Result<()> {
+        (**self).set_timeout(timeout)
+    }
+
+    fn timeout(&self) -> Duration {
+        (**self).timeout()
+    }
+
+    fn set_baud_rate(&mut self, baud_rate: u32) -> Result<()> {
+        (**self).set_baud_rate(baud_rate)
+    }
+
+    fn baud_rate(&self) -> Result<u32> {
+        (**self).baud_rate()
+    }
+
+    fn clear_all(&self) -> Result<()> {
+        (**self).clear_all()
+    }
+
+    fn framing(&self) -> Result<Framing> {
+        (**self).framing()
+    }
+
+    fn read_exact_timeout(&mut self, buf: &mut [u8], timeout: Duration) -> Result<()> {
+        (**self).read_exact_timeout(buf, timeout)
+    }
+}
+
+/// Function applying a setting to the underlying stream of a
+/// [StreamPort](struct.StreamPort.html).
+pub type StreamSetter<V> = Box<dyn FnMut(V) -> Result<()> + Send>;
+
+/// `SabertoothSerial` over any reader and writer, for ex. the two halves of a
+/// network stream or the pipes of a process forwarding to a serial port.
+///
+/// A stream has no baud rate nor timeout: changing them is delegated to the
+/// setters given with `with_baud_rate_setter()` and `with_timeout_setter()`,
+/// and fails with `Error::InvalidInput` without them. The values reported by
+/// `baud_rate()` and `timeout()` are the last ones set, 9600 and 100 ms
+/// initially (see `with_timeout()`), and are used for the timing of the
+/// interfaces. `clear_all()` cannot discard anything and does nothing.
+///
+/// # Example
+///
+/// ```
+/// use std::net::TcpStream;
+/// use saberrs::{Result, StreamPort};
+/// use saberrs::sabertooth2x32::{PacketSerial, Sabertooth2x32};
+///
+/// # fn example() -> Result<()> {
+/// let stream = TcpStream::connect("192.168.1.10:4000")?;
+/// let reader = stream.try_clone()?;
+/// let timeout_stream = stream.try_clone()?;
+/// let port = StreamPort::new(reader, stream).with_timeout_setter(Box::new(move |timeout| {
+///     timeout_stream.set_read_timeout(Some(timeout))?;
+///     Ok(())
+/// }));
+/// let mut saber = PacketSerial::from(port);
+/// saber.set_speed(1, 0.5)?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct StreamPort<R: io::Read, W: io::Write> {
+    reader: R,
+    writer: W,
+    baud_rate: u32,
+    timeout: Duration,
+    baud_rate_setter: Option<StreamSetter<u32>>,
+    timeout_setter: Option<StreamSetter<Duration>>,
+}
+
+impl<R: io::Read, W: io::Write> StreamPort<R, W> {
+    /// Read from *reader* and write to *writer*.
+    pub fn new(reader: R, writer: W) -> Self {
+        StreamPort {
+            reader,
+            writer,
+            baud_rate: 9600,
+            timeout: Duration::from_millis(100),
+            baud_rate_setter: None,
+            timeout_setter: None,
+        }
+    }
+
+    /// Report *timeout* as the timeout of the stream, without applying it.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Apply the baud rates set with *setter*.
+    pub fn with_baud_rate_setter(mut self, setter: StreamSetter<u32>) -> Self {
+        self.baud_rate_setter = Some(setter);
+        self
+    }
+
+    /// Apply the timeouts set with *setter*.
+    pub fn with_timeout_setter(mut self, setter: StreamSetter<Duration>) -> Self {
+        self.timeout_setter = Some(setter);
+        self
+    }
+
+    /// Return the reader and the writer.
+    pub fn into_inner(self) -> (R, W) {
+        (self.reader, self.writer)
+    }
+}
+
+impl<R: io::Read, W: io::Write> io::Read for StreamPort<R, W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.reader.read(buf)
+    }
+}
+
+impl<R: io::Read, W: io::Write> io::Write for StreamPort<R, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl<R: io::Read, W: io::Write> SabertoothSerial for StreamPort<R, W> {
+    fn set_timeout(&mut self, timeout: Duration) -> Result<()> {
+        match self.timeout_setter.as_mut() {
+            Some(setter) => setter(timeout)?,
+            None => {
+                let msg = "the timeout of this stream cannot be changed".to_string();
+                return Err(Error::InvalidInput(msg));
+            }
+        }
+        self.timeout = timeout;
+        Ok(())
+    }
+
+    fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    fn set_baud_rate(&mut self, baud_rate: u32) -> Result<()> {
+        match self.baud_rate_setter.as_mut() {
+            Some(setter) => {
+                // Like a serial port, let the pending output go first.
+                self.writer.flush()?;
+                setter(baud_rate)?;
+            }
+            None => {
+                let msg = "the baud rate of this stream cannot be changed".to_string();
+                return Err(Error::InvalidInput(msg));
+            }
+        }
+        self.baud_rate = baud_rate;
+        Ok(())
+    }
+
+    fn baud_rate(&self) -> Result<u32> {
+        Ok(self.baud_rate)
+    }
+
+    fn clear_all(&self) -> Result<()> {
+        Ok(())
+    }
 }
 
 /// `SabertoothPort` and `SabertoothPortShared` are optional concrete
@@ -57,11 +353,13 @@ pub mod sabertoothport {
     use std::cell::RefCell;
     use std::io;
     use std::rc::Rc;
+    use std::sync::mpsc;
+    use std::thread;
     use std::time::Duration;
 
-    use serialport::{self, ClearBuffer, DataBits, FlowControl, Parity, SerialPort, StopBits};
+    use serialport::{self, ClearBuffer, DataBits, FlowControl, SerialPort, StopBits};
 
-    use crate::{Result, SabertoothSerial};
+    use crate::{Error, Framing, Parity, Result, SabertoothSerial};
 
     /// Default baud rate setting when opening a `SabertoothPort`.
     const DEFAULT_BAUDRATE: u32 = 9600;
@@ -81,15 +379,288 @@ pub mod sabertoothport {
     /// Default stop bits setting when opening a `SabertoothPort`
     const DEFAULT_STOP_BITS: StopBits = StopBits::One;
 
+    fn to_framing(data_bits: DataBits, parity: serialport::Parity, stop_bits: StopBits) -> Framing {
+        Framing {
+            data_bits: data_bits.into(),
+            parity: match parity {
+                serialport::Parity::None => Parity::None,
+                serialport::Parity::Odd => Parity::Odd,
+                serialport::Parity::Even => Parity::Even,
+            },
+            stop_bits: stop_bits.into(),
+        }
+    }
+
+    fn to_serialport_parity(parity: Parity) -> serialport::Parity {
+        match parity {
+            Parity::None => serialport::Parity::None,
+            Parity::Odd => serialport::Parity::Odd,
+            Parity::Even => serialport::Parity::Even,
+        }
+    }
+
+    fn read_framing(dev: &dyn SerialPort) -> Result<Framing> {
+        Ok(to_framing(
+            dev.data_bits()?,
+            dev.parity()?,
+            dev.stop_bits()?,
+        ))
+    }
+
     fn open_default_serialport(port: &str) -> Result<Box<dyn SerialPort>> {
-        let ser = serialport::new(port, DEFAULT_BAUDRATE)
-            .timeout(DEFAULT_TIMEOUT)
-            .data_bits(DEFAULT_DATA_BITS)
-            .flow_control(DEFAULT_FLOW_CONTROL)
-            .parity(DEFAULT_PARITY)
-            .stop_bits(DEFAULT_STOP_BITS)
-            .open()?;
-        Ok(ser)
+        SabertoothPortBuilder::new(port).open_serialport()
+    }
+
+    /// Builder for opening a [SabertoothPort](struct.SabertoothPort.html) or a
+    /// [SabertoothPortShared](struct.SabertoothPortShared.html) with other
+    /// settings than the default ones.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use std::time::Duration;
+    /// use saberrs::{Result, SabertoothPort};
+    ///
+    /// # fn example() -> Result<()> {
+    /// let dev = SabertoothPort::builder("/dev/ttyUSB0")
+    ///     .baud_rate(38400)
+    ///     .open_timeout(Duration::from_secs(2))
+    ///     .open()?;
+    /// # Ok(())}
+    /// ```
+    ///
+    /// Behind a serial device server forwarding 8E1 to the Sabertooth as 8N1:
+    ///
+    /// ```rust
+    /// use saberrs::{Parity, Result, SabertoothPort};
+    ///
+    /// # fn example() -> Result<()> {
+    /// let dev = SabertoothPort::builder("/dev/ttyr00").parity(Parity::Even).open()?;
+    /// # Ok(())}
+    /// ```
+    ///
+    /// **Requires** the "serialport" feature (enabled by default).
+    #[derive(Clone, Debug)]
+    pub struct SabertoothPortBuilder {
+        path: String,
+        baud_rate: u32,
+        timeout: Duration,
+        open_timeout: Option<Duration>,
+        exclusive: bool,
+        data_bits: DataBits,
+        parity: Parity,
+        stop_bits: StopBits,
+    }
+
+    impl SabertoothPortBuilder {
+        fn new(path: &str) -> Self {
+            SabertoothPortBuilder {
+                path: path.to_string(),
+                baud_rate: DEFAULT_BAUDRATE,
+                timeout: DEFAULT_TIMEOUT,
+                open_timeout: None,
+                exclusive: true,
+                data_bits: DEFAULT_DATA_BITS,
+                parity: DEFAULT_PARITY,
+                stop_bits: DEFAULT_STOP_BITS,
+            }
+        }
+
+        /// Set the baud rate, 9600 by default.
+        pub fn baud_rate(mut self, baud_rate: u32) -> Self {
+            self.baud_rate = baud_rate;
+            self
+        }
+
+        /// Set the timeout of the reads and writes, 100 ms by default.
+        pub fn timeout(mut self, timeout: Duration) -> Self {
+            self.timeout = timeout;
+            self
+        }
+
+        /// Fail with an `Error::Io` of kind `TimedOut` if opening the port
+        /// takes longer than *timeout*. By default there is no limit.
+        ///
+        /// Opening a serial port is usually immediate, but some USB adapters
+        /// or drivers block in the `open()` system call or in the
+        /// configuration of the line (Linux and macOS) or in `CreateFile()`
+        /// (Windows), which cannot be cancelled. The port is then opened in a
+        /// separate thread: on a timeout this thread is left behind, and the
+        /// port is closed if it opens eventually.
+        pub fn open_timeout(mut self, timeout: Duration) -> Self {
+            self.open_timeout = Some(timeout);
+            self
+        }
+
+        /// Request exclusive access to the port, which is the default. Another
+        /// attempt to open an exclusive port fails with `Error::PortInUse`, so
+        /// that two processes cannot interleave their frames.
+        ///
+        /// On Unix the lock is `TIOCEXCL` plus an exclusive `flock()`, opening
+        /// without it still takes a shared `flock()`. Windows always opens
+        /// serial ports exclusively, this setting has no effect there.
+        pub fn exclusive(mut self, exclusive: bool) -> Self {
+            self.exclusive = exclusive;
+            self
+        }
+
+        /// Set the number of data bits, 8 by default.
+        pub fn data_bits(mut self, data_bits: DataBits) -> Self {
+            self.data_bits = data_bits;
+            self
+        }
+
+        /// Set the parity, none by default.
+        pub fn parity(mut self, parity: Parity) -> Self {
+            self.parity = parity;
+            self
+        }
+
+        /// Set the number of stop bits, 1 by default.
+        pub fn stop_bits(mut self, stop_bits: StopBits) -> Self {
+            self.stop_bits = stop_bits;
+            self
+        }
+
+        /// Framing resulting from the data bits, parity and stop bits, as
+        /// passed to serialport when opening the port.
+        pub fn framing(&self) -> Framing {
+            to_framing(
+                self.data_bits,
+                to_serialport_parity(self.parity),
+                self.stop_bits,
+            )
+        }
+
+        /// Open a `SabertoothPort`.
+        pub fn open(self) -> Result<SabertoothPort> {
+            Ok(SabertoothPort {
+                dev: self.open_serialport()?,
+            })
+        }
+
+        /// Open a `SabertoothPortShared`.
+        pub fn open_shared(self) -> Result<SabertoothPortShared> {
+            Ok(SabertoothPortShared {
+                dev: Rc::new(RefCell::new(self.open_serialport()?)),
+            })
+        }
+
+        fn open_serialport(self) -> Result<Box<dyn SerialPort>> {
+            let open_timeout = match self.open_timeout {
+                Some(open_timeout) => open_timeout,
+                None => return self.open_blocking(),
+            };
+            let path = self.path.clone();
+            let (tx, rx) = mpsc::channel();
+            thread::spawn(move || {
+                // The receiver is gone after a timeout, the port is dropped.
+                let _ = tx.send(self.open_blocking());
+            });
+            match rx.recv_timeout(open_timeout) {
+                Ok(res) => res,
+                Err(_) => {
+                    let msg = format!("opening {} took more than {:?}", path, open_timeout);
+                    Err(io::Error::new(io::ErrorKind::TimedOut, msg).into())
+                }
+            }
+        }
+
+        /// Settings passed to serialport when opening the port.
+        fn serialport_builder(&self) -> serialport::SerialPortBuilder {
+            let builder = serialport::new(&self.path, self.baud_rate)
+                .timeout(self.timeout)
+                .data_bits(self.data_bits)
+                .flow_control(DEFAULT_FLOW_CONTROL)
+                .parity(to_serialport_parity(self.parity))
+                .stop_bits(self.stop_bits);
+            #[cfg(unix)]
+            let builder = builder.exclusive(self.exclusive);
+            builder
+        }
+
+        fn open_blocking(&self) -> Result<Box<dyn SerialPort>> {
+            let framing = self.framing();
+            if framing != Framing::default() {
+                // Not an error: a converter may translate it.
+                log_warn!(
+                    "{}: the Sabertooth only receives 8N1, {} needs a converter",
+                    self.path,
+                    framing
+                );
+            }
+            self.serialport_builder().open().map_err(|e| {
+                if e.kind() == serialport::ErrorKind::NoDevice && self.is_locked() {
+                    let msg = format!(
+                        "{} is already open, another process may own it ({})",
+                        self.path, e
+                    );
+                    Error::PortInUse(msg)
+                } else {
+                    e.into()
+                }
+            })
+        }
+
+        /// Whether a `NoDevice` error opening the port means that another
+        /// process holds it.
+        #[cfg(unix)]
+        fn is_locked(&self) -> bool {
+            // When opening, serialport only returns NoDevice for EBUSY from
+            // TIOCEXCL and for a failed flock().
+            true
+        }
+
+        /// Whether a `NoDevice` error opening the port means that another
+        /// process holds it.
+        #[cfg(windows)]
+        fn is_locked(&self) -> bool {
+            // serialport returns NoDevice for a missing port as well: probe
+            // it, a serial port already open is denied to other openers.
+            const ERROR_ACCESS_DENIED: i32 = 5;
+            let path = if self.path.starts_with('\\') {
+                self.path.clone()
+            } else {
+                format!(r"\\.\{}", self.path)
+            };
+            match std::fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(path)
+            {
+                Ok(_) => false,
+                Err(e) => e.raw_os_error() == Some(ERROR_ACCESS_DENIED),
+            }
+        }
+
+        #[cfg(not(any(unix, windows)))]
+        fn is_locked(&self) -> bool {
+            false
+        }
+    }
+
+    /// Snapshot of the settings of a serial port, see `SabertoothPort::settings()`.
+    ///
+    /// **Requires** the "serialport" feature (enabled by default).
+    #[derive(Clone, Copy, PartialEq, Debug)]
+    pub struct PortSettings {
+        pub baud_rate: u32,
+        pub data_bits: DataBits,
+        pub parity: serialport::Parity,
+        pub stop_bits: StopBits,
+        pub flow_control: FlowControl,
+        pub timeout: Duration,
+    }
+
+    fn read_settings(dev: &dyn SerialPort) -> Result<PortSettings> {
+        Ok(PortSettings {
+            baud_rate: dev.baud_rate()?,
+            data_bits: dev.data_bits()?,
+            parity: dev.parity()?,
+            stop_bits: dev.stop_bits()?,
+            flow_control: dev.flow_control()?,
+            timeout: dev.timeout(),
+        })
     }
 
     /// Raw Sabertooth controller.
@@ -108,6 +679,16 @@ pub mod sabertoothport {
             let ser = open_default_serialport(port)?;
             Ok(SabertoothPort { dev: ser })
         }
+
+        /// Return a builder for opening the port *port* with other settings.
+        pub fn builder(port: &str) -> SabertoothPortBuilder {
+            SabertoothPortBuilder::new(port)
+        }
+
+        /// Read the current settings of the port from the operating system.
+        pub fn settings(&self) -> Result<PortSettings> {
+            read_settings(self.dev.as_ref())
+        }
     }
 
     impl SabertoothSerial for SabertoothPort {
@@ -119,7 +700,11 @@ pub mod sabertoothport {
             self.dev.timeout()
         }
 
+        /// Wait for the pending output to be transmitted (`tcdrain()` on Unix),
+        /// then change the rate. This takes as long as transmitting the pending
+        /// bytes, for ex. about 10 ms for a CRC packet at 9600 baud.
         fn set_baud_rate(&mut self, baud_rate: u32) -> Result<()> {
+            self.dev.flush()?;
             Ok(self.dev.set_baud_rate(baud_rate)?)
         }
 
@@ -130,6 +715,10 @@ pub mod sabertoothport {
         fn clear_all(&self) -> Result<()> {
             Ok(self.dev.clear(ClearBuffer::All)?)
         }
+
+        fn framing(&self) -> Result<Framing> {
+            read_framing(self.dev.as_ref())
+        }
     }
 
     impl io::Read for SabertoothPort {
@@ -203,6 +792,11 @@ pub mod sabertoothport {
                 dev: Rc::new(RefCell::new(ser)),
             })
         }
+
+        /// Same as `SabertoothPort::settings()`.
+        pub fn settings(&self) -> Result<PortSettings> {
+            read_settings(self.dev.borrow().as_ref())
+        }
     }
 
     impl SabertoothSerial for SabertoothPortShared {
@@ -214,8 +808,11 @@ pub mod sabertoothport {
             self.dev.borrow_mut().timeout()
         }
 
+        /// Same as `SabertoothPort::set_baud_rate()`.
         fn set_baud_rate(&mut self, baud_rate: u32) -> Result<()> {
-            Ok(self.dev.borrow_mut().set_baud_rate(baud_rate)?)
+            let mut dev = self.dev.borrow_mut();
+            dev.flush()?;
+            Ok(dev.set_baud_rate(baud_rate)?)
         }
 
         fn baud_rate(&self) -> Result<u32> {
@@ -225,6 +822,10 @@ pub mod sabertoothport {
         fn clear_all(&self) -> Result<()> {
             Ok(self.dev.borrow_mut().clear(ClearBuffer::All)?)
         }
+
+        fn framing(&self) -> Result<Framing> {
+            read_framing(self.dev.borrow().as_ref())
+        }
     }
 
     impl io::Read for SabertoothPortShared {
@@ -255,4 +856,335 @@ pub mod sabertoothport {
             )
         }
     }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn builder_framing() {
+            let builder = SabertoothPortBuilder::new("/dev/null");
+            assert_eq!(Framing::default(), builder.framing());
+
+            let builder = builder.data_bits(DataBits::Seven).stop_bits(StopBits::Two);
+            for &parity in &[Parity::None, Parity::Odd, Parity::Even] {
+                let framing = builder.clone().parity(parity).framing();
+                assert_eq!(parity, framing.parity);
+                assert_eq!(7, framing.data_bits);
+                assert_eq!(2, framing.stop_bits);
+            }
+            assert_eq!("7E2", builder.parity(Parity::Even).framing().to_string());
+        }
+    }
+}
+
+/// `TermiosPort` is an alternative concrete implementation of the trait
+/// `SabertoothSerial` on Unix, configuring the serial line with the termios
+/// calls of the C library, for targets where the `serialport` crate is not
+/// usable, for ex. musl.
+#[cfg(all(feature = "termios", unix))]
+pub mod termiosport {
+    use std::fs::{File, OpenOptions};
+    use std::io::{self, Write};
+    use std::os::unix::fs::OpenOptionsExt;
+    use std::os::unix::io::{AsRawFd, RawFd};
+    use std::time::Duration;
+
+    use crate::{Error, Framing, Parity, Result, SabertoothSerial};
+
+    /// Default baud rate setting when opening a `TermiosPort`.
+    const DEFAULT_BAUDRATE: u32 = 9600;
+
+    /// Default timeout setting when opening a `TermiosPort`.
+    const DEFAULT_TIMEOUT: Duration = Duration::from_millis(100);
+
+    /// Baud rates supported by a `TermiosPort`, with their termios constant.
+    const BAUD_RATES: [(u32, libc::speed_t); 12] = [
+        (1200, libc::B1200),
+        (2400, libc::B2400),
+        (4800, libc::B4800),
+        (9600, libc::B9600),
+        (19200, libc::B19200),
+        (38400, libc::B38400),
+        (57600, libc::B57600),
+        (115200, libc::B115200),
+        (230400, libc::B230400),
+        (460800, libc::B460800),
+        (500000, libc::B500000),
+        (921600, libc::B921600),
+    ];
+
+    /// Turn the return value of a C library call into an `io::Result`.
+    fn check(ret: libc::c_int) -> io::Result<libc::c_int> {
+        if ret == -1 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(ret)
+        }
+    }
+
+    fn get_termios(fd: RawFd) -> io::Result<libc::termios> {
+        // Safety: termios is a plain C struct, filled by tcgetattr().
+        let mut termios: libc::termios = unsafe { std::mem::zeroed() };
+        check(unsafe { libc::tcgetattr(fd, &mut termios) })?;
+        Ok(termios)
+    }
+
+    fn set_termios(fd: RawFd, termios: &libc::termios) -> io::Result<()> {
+        check(unsafe { libc::tcsetattr(fd, libc::TCSANOW, termios) })?;
+        Ok(())
+    }
+
+    fn speed(baud_rate: u32) -> Result<libc::speed_t> {
+        match BAUD_RATES.iter().find(|&&(rate, _)| rate == baud_rate) {
+            Some(&(_, speed)) => Ok(speed),
+            None => {
+                let msg = format!("unsupported baud rate {}", baud_rate);
+                Err(Error::InvalidInput(msg))
+            }
+        }
+    }
+
+    /// Wait until *fd* is ready for *events*, at most *timeout*.
+    fn wait(fd: RawFd, events: libc::c_short, timeout: Duration) -> io::Result<()> {
+        let mut millis = timeout.as_millis().min(libc::c_int::MAX as u128) as libc::c_int;
+        if millis == 0 && timeout > Duration::from_secs(0) {
+            millis = 1;
+        }
+        let mut pollfd = libc::pollfd {
+            fd,
+            events,
+            revents: 0,
+        };
+        match check(unsafe { libc::poll(&mut pollfd, 1, millis) })? {
+            0 => Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "serial port timed out",
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Builder for opening a [TermiosPort](struct.TermiosPort.html) with other
+    /// settings than the default ones. The line is always 8N1, without flow
+    /// control.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use std::time::Duration;
+    /// use saberrs::{Result, TermiosPort};
+    ///
+    /// # fn example() -> Result<()> {
+    /// let dev = TermiosPort::builder("/dev/ttyUSB0")
+    ///     .baud_rate(38400)
+    ///     .timeout(Duration::from_millis(50))
+    ///     .open()?;
+    /// # Ok(())}
+    /// ```
+    ///
+    /// **Requires** the "termios" feature, on Unix.
+    #[derive(Clone, Debug)]
+    pub struct TermiosPortBuilder {
+        path: String,
+        baud_rate: u32,
+        timeout: Duration,
+        exclusive: bool,
+    }
+
+    impl TermiosPortBuilder {
+        fn new(path: &str) -> Self {
+            TermiosPortBuilder {
+                path: path.to_string(),
+                baud_rate: DEFAULT_BAUDRATE,
+                timeout: DEFAULT_TIMEOUT,
+                exclusive: true,
+            }
+        }
+
+        /// Set the baud rate, 9600 by default.
+        pub fn baud_rate(mut self, baud_rate: u32) -> Self {
+            self.baud_rate = baud_rate;
+            self
+        }
+
+        /// Set the timeout of the reads and writes, 100 ms by default.
+        pub fn timeout(mut self, timeout: Duration) -> Self {
+            self.timeout = timeout;
+            self
+        }
+
+        /// Same as `SabertoothPortBuilder::exclusive()`.
+        pub fn exclusive(mut self, exclusive: bool) -> Self {
+            self.exclusive = exclusive;
+            self
+        }
+
+        /// Open a `TermiosPort`.
+        pub fn open(self) -> Result<TermiosPort> {
+            let speed = speed(self.baud_rate)?;
+            let file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .custom_flags(libc::O_NOCTTY | libc::O_NONBLOCK)
+                .open(&self.path)
+                .map_err(|e| self.in_use_or(e))?;
+            let fd = file.as_raw_fd();
+            let lock = if self.exclusive {
+                check(unsafe { libc::ioctl(fd, libc::TIOCEXCL as _) })?;
+                libc::LOCK_EX
+            } else {
+                libc::LOCK_SH
+            };
+            check(unsafe { libc::flock(fd, lock | libc::LOCK_NB) })
+                .map_err(|e| self.in_use_or(e))?;
+
+            let mut termios = get_termios(fd)?;
+            unsafe { libc::cfmakeraw(&mut termios) };
+            termios.c_cflag |= libc::CREAD | libc::CLOCAL;
+            termios.c_cflag &= !(libc::CSTOPB | libc::CRTSCTS);
+            termios.c_cc[libc::VMIN] = 0;
+            termios.c_cc[libc::VTIME] = 0;
+            check(unsafe { libc::cfsetspeed(&mut termios, speed) })?;
+            set_termios(fd, &termios)?;
+            Ok(TermiosPort {
+                file,
+                timeout: self.timeout,
+            })
+        }
+
+        /// `Error::PortInUse` if *e* means that another process holds the
+        /// port, else *e*.
+        fn in_use_or(&self, e: io::Error) -> Error {
+            match e.raw_os_error() {
+                Some(libc::EBUSY) | Some(libc::EWOULDBLOCK) => {
+                    let msg = format!(
+                        "{} is already open, another process may own it ({})",
+                        self.path, e
+                    );
+                    Error::PortInUse(msg)
+                }
+                _ => e.into(),
+            }
+        }
+    }
+
+    /// Serial port configured with termios, used like a
+    /// [SabertoothPort](struct.SabertoothPort.html).
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use saberrs::{Result, TermiosPort};
+    /// use saberrs::sabertooth2x32::{PacketSerial, Sabertooth2x32};
+    ///
+    /// # fn example() -> Result<()> {
+    /// let mut saber = PacketSerial::from(TermiosPort::new("/dev/ttyUSB0")?);
+    /// saber.set_speed(1, 0.5)?;
+    /// # Ok(())}
+    /// ```
+    ///
+    /// **Requires** the "termios" feature, on Unix.
+    pub struct TermiosPort {
+        file: File,
+        timeout: Duration,
+    }
+
+    impl TermiosPort {
+        /// Create a new `TermiosPort` with a default configuration.
+        pub fn new(port: &str) -> Result<TermiosPort> {
+            TermiosPortBuilder::new(port).open()
+        }
+
+        /// Return a builder for opening the port *port* with other settings.
+        pub fn builder(port: &str) -> TermiosPortBuilder {
+            TermiosPortBuilder::new(port)
+        }
+    }
+
+    impl SabertoothSerial for TermiosPort {
+        fn set_timeout(&mut self, timeout: Duration) -> Result<()> {
+            self.timeout = timeout;
+            Ok(())
+        }
+
+        fn timeout(&self) -> Duration {
+            self.timeout
+        }
+
+        /// Same as `SabertoothPort::set_baud_rate()`.
+        fn set_baud_rate(&mut self, baud_rate: u32) -> Result<()> {
+            let speed = speed(baud_rate)?;
+            self.flush()?;
+            let fd = self.file.as_raw_fd();
+            let mut termios = get_termios(fd)?;
+            check(unsafe { libc::cfsetspeed(&mut termios, speed) })?;
+            set_termios(fd, &termios)?;
+            Ok(())
+        }
+
+        fn baud_rate(&self) -> Result<u32> {
+            let termios = get_termios(self.file.as_raw_fd())?;
+            let speed = unsafe { libc::cfgetospeed(&termios) };
+            match BAUD_RATES.iter().find(|&&(_, s)| s == speed) {
+                Some(&(rate, _)) => Ok(rate),
+                None => {
+                    let msg = format!("unknown termios speed {:#o}", speed);
+                    Err(io::Error::other(msg).into())
+                }
+            }
+        }
+
+        fn clear_all(&self) -> Result<()> {
+            check(unsafe { libc::tcflush(self.file.as_raw_fd(), libc::TCIOFLUSH) })?;
+            Ok(())
+        }
+
+        fn framing(&self) -> Result<Framing> {
+            let cflag = get_termios(self.file.as_raw_fd())?.c_cflag;
+            let data_bits = match cflag & libc::CSIZE {
+                libc::CS5 => 5,
+                libc::CS6 => 6,
+                libc::CS7 => 7,
+                _ => 8,
+            };
+            let parity = match (cflag & libc::PARENB != 0, cflag & libc::PARODD != 0) {
+                (false, _) => Parity::None,
+                (true, true) => Parity::Odd,
+                (true, false) => Parity::Even,
+            };
+            let stop_bits = if cflag & libc::CSTOPB != 0 { 2 } else { 1 };
+            Ok(Framing {
+                data_bits,
+                parity,
+                stop_bits,
+            })
+        }
+    }
+
+    impl io::Read for TermiosPort {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            wait(self.file.as_raw_fd(), libc::POLLIN, self.timeout)?;
+            self.file.read(buf)
+        }
+    }
+
+    impl io::Write for TermiosPort {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            wait(self.file.as_raw_fd(), libc::POLLOUT, self.timeout)?;
+            self.file.write(buf)
+        }
+
+        /// Wait for the written bytes to be transmitted (`tcdrain()`).
+        fn flush(&mut self) -> io::Result<()> {
+            check(unsafe { libc::tcdrain(self.file.as_raw_fd()) })?;
+            Ok(())
+        }
+    }
+
+    impl std::fmt::Debug for TermiosPort {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "TermiosPort({})", self.file.as_raw_fd())
+        }
+    }
 }
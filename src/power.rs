@@ -0,0 +1,139 @@
+use std::thread;
+use std::time::Duration;
+
+use crate::error::Result;
+
+/// Switch of the power supply of a Sabertooth, for ex. a GPIO driving its
+/// power enable, to recover by power-cycling it.
+///
+/// The interfaces call it from `power_cycle()`, which also sends again the
+/// settings lost by the Sabertooth.
+pub trait PowerControl {
+    /// Cut the power.
+    fn power_off(&mut self) -> Result<()>;
+
+    /// Restore the power.
+    fn power_on(&mut self) -> Result<()>;
+
+    /// Cut the power for *delay*, then restore it.
+    fn cycle(&mut self, delay: Duration) -> Result<()> {
+        self.power_off()?;
+        thread::sleep(delay);
+        self.power_on()
+    }
+}
+
+impl<P: PowerControl + ?Sized> PowerControl for &mut P {
+    fn power_off(&mut self) -> Result<()> {
+        (**self).power_off()
+    }
+
+    fn power_on(&mut self) -> Result<()> {
+        (**self).power_on()
+    }
+
+    fn cycle(&mut self, delay: Duration) -> Result<()> {
+        (**self).cycle(delay)
+    }
+}
+
+#[cfg(all(feature = "gpio-sysfs", target_os = "linux"))]
+pub use self::sysfs::SysfsGpio;
+
+#[cfg(all(feature = "gpio-sysfs", target_os = "linux"))]
+mod sysfs {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    use super::PowerControl;
+    use crate::error::Result;
+
+    /// `PowerControl` with a GPIO of the Linux sysfs interface
+    /// (`/sys/class/gpio`).
+    ///
+    /// **Requires** the "gpio-sysfs" feature, Linux only.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use saberrs::{Result, SysfsGpio};
+    /// use saberrs::sabertooth2x32::PacketSerial;
+    ///
+    /// # fn example() -> Result<()> {
+    /// let mut enable = SysfsGpio::new(17, false)?;
+    /// let mut saber = PacketSerial::new("/dev/ttyUSB0")?;
+    /// saber.power_cycle(&mut enable, Duration::from_millis(500), Duration::from_secs(1))?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[derive(Debug)]
+    pub struct SysfsGpio {
+        value: PathBuf,
+        active_low: bool,
+    }
+
+    impl SysfsGpio {
+        /// Use the GPIO *pin* as an output, exporting it if needed. The power
+        /// is on while the output is high, or low if *active_low* is `true`.
+        ///
+        /// An input is turned into an output keeping the power on.
+        pub fn new(pin: u32, active_low: bool) -> Result<Self> {
+            let dir = PathBuf::from(format!("/sys/class/gpio/gpio{}", pin));
+            if !dir.exists() {
+                fs::write("/sys/class/gpio/export", pin.to_string())?;
+            }
+            let direction = fs::read_to_string(dir.join("direction"))?;
+            if direction.trim() != "out" {
+                let level = if active_low { "low" } else { "high" };
+                fs::write(dir.join("direction"), level)?;
+            }
+            Ok(SysfsGpio::from_value_path(dir.join("value"), active_low))
+        }
+
+        /// Write directly to the file *value* of a GPIO already configured as
+        /// an output.
+        pub fn from_value_path<P: AsRef<Path>>(value: P, active_low: bool) -> Self {
+            SysfsGpio {
+                value: value.as_ref().to_path_buf(),
+                active_low,
+            }
+        }
+
+        fn write(&self, on: bool) -> Result<()> {
+            let high = on != self.active_low;
+            fs::write(&self.value, if high { "1" } else { "0" })?;
+            Ok(())
+        }
+    }
+
+    impl PowerControl for SysfsGpio {
+        fn power_off(&mut self) -> Result<()> {
+            self.write(false)
+        }
+
+        fn power_on(&mut self) -> Result<()> {
+            self.write(true)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn write_value() {
+            let path = std::env::temp_dir().join(format!("saberrs-gpio-{}", std::process::id()));
+            let mut gpio = SysfsGpio::from_value_path(&path, false);
+            gpio.power_off().unwrap();
+            assert_eq!("0", fs::read_to_string(&path).unwrap());
+            gpio.power_on().unwrap();
+            assert_eq!("1", fs::read_to_string(&path).unwrap());
+
+            let mut gpio = SysfsGpio::from_value_path(&path, true);
+            gpio.power_off().unwrap();
+            assert_eq!("1", fs::read_to_string(&path).unwrap());
+            fs::remove_file(&path).unwrap();
+        }
+    }
+}
@@ -0,0 +1,38 @@
+use std::time::{Duration, Instant};
+
+/// Last reading of a quantity and when it was taken.
+#[derive(Default)]
+pub(crate) struct CachedReading(Option<(f32, Instant)>);
+
+impl CachedReading {
+    /// The reading, if it is at most *max_age* old at *now*.
+    pub(crate) fn get(&self, max_age: Duration, now: Instant) -> Option<f32> {
+        match self.0 {
+            Some((value, at)) if now.saturating_duration_since(at) <= max_age => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Store a reading taken at *at*.
+    pub(crate) fn store(&mut self, value: f32, at: Instant) {
+        self.0 = Some((value, at));
+    }
+}
+
+/// Cached readings of the interfaces, one per quantity and channel.
+#[derive(Default)]
+pub(crate) struct ReadingCache {
+    voltage: [CachedReading; 2],
+}
+
+impl ReadingCache {
+    /// Battery voltage reading of the motor *channel*, `None` for an invalid
+    /// channel.
+    pub(crate) fn voltage(&mut self, channel: usize) -> Option<&mut CachedReading> {
+        self.voltage.get_mut(channel.wrapping_sub(1))
+    }
+
+    pub(crate) fn invalidate(&mut self) {
+        *self = ReadingCache::default();
+    }
+}
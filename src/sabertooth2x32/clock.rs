@@ -0,0 +1,39 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Function returning the current instant, replacing `Instant::now()`, see
+/// `PacketSerial::set_clock()` and `RateLimiter::with_clock()`.
+pub type NowFn = Box<dyn Fn() -> Instant + Send>;
+
+/// Function waiting for a duration, replacing `std::thread::sleep()`, see
+/// `PacketSerial::set_clock()` and `RateLimiter::with_clock()`.
+pub type SleepFn = Box<dyn FnMut(Duration) + Send>;
+
+/// Source of time of the delays and ages: the system clock, or the functions
+/// set by the application, for ex. to run on a simulated clock.
+#[derive(Default)]
+pub(crate) struct Clock(Option<(NowFn, SleepFn)>);
+
+impl Clock {
+    pub(crate) fn new(now: NowFn, sleep: SleepFn) -> Self {
+        Clock(Some((now, sleep)))
+    }
+
+    pub(crate) fn now(&self) -> Instant {
+        match &self.0 {
+            Some((now, _)) => now(),
+            None => Instant::now(),
+        }
+    }
+
+    pub(crate) fn elapsed(&self, since: Instant) -> Duration {
+        self.now().saturating_duration_since(since)
+    }
+
+    pub(crate) fn sleep(&mut self, duration: Duration) {
+        match &mut self.0 {
+            Some((_, sleep)) => sleep(duration),
+            None => thread::sleep(duration),
+        }
+    }
+}
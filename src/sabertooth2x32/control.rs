@@ -0,0 +1,115 @@
+use super::Sabertooth2x32;
+use crate::error::{Error, Result};
+
+/// Proportional controller, driving a motor from a target and a measured
+/// value, for ex. speeds measured by an external encoder.
+///
+/// The command sent is the target plus *gain* times the error (target minus
+/// measured), clamped to -1.0~1.0. This feed-forward keeps the motor running
+/// when the error is zero. It is a building block: there is no integral or
+/// derivative term, so a steady-state error remains under load, and the
+/// caller runs the loop at its own rate.
+///
+/// The target and the measured value are in the same unit as the command,
+/// so the measurements should be scaled to -1.0~1.0 of the full speed.
+///
+/// # Example
+///
+/// ```
+/// use saberrs::sabertooth2x32::{control::PController, PacketSerial};
+/// # use saberrs::Result;
+/// # fn read_encoder() -> f32 { 0.0 }
+/// # fn example() -> Result<()> {
+/// let mut saber = PacketSerial::new("/dev/ttyUSB0")?;
+/// let controller = PController::new(0.8);
+/// loop {
+///     controller.drive_speed(&mut saber, 1, 0.5, read_encoder())?;
+/// #   break;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct PController {
+    gain: f32,
+}
+
+impl PController {
+    /// Create a controller with the proportional *gain*.
+    pub fn new(gain: f32) -> Self {
+        PController { gain }
+    }
+
+    /// Gain of the controller.
+    pub fn gain(&self) -> f32 {
+        self.gain
+    }
+
+    /// Compute the command for *target* and *measured*, clamped to -1.0~1.0.
+    /// A non-finite input or gain is an `Error::InvalidInput`.
+    pub fn command(&self, target: f32, measured: f32) -> Result<f32> {
+        let command = target + self.gain * (target - measured);
+        if !command.is_finite() {
+            let msg = format!(
+                "non-finite command (target {}, measured {}, gain {})",
+                target, measured, self.gain
+            );
+            return Err(Error::InvalidInput(msg));
+        }
+        Ok(command.clamp(-1.0, 1.0))
+    }
+
+    /// Compute the command and set it as the speed of the motor *channel*.
+    /// Return the command sent.
+    pub fn drive_speed<S: Sabertooth2x32 + ?Sized>(
+        &self,
+        saber: &mut S,
+        channel: usize,
+        target: f32,
+        measured: f32,
+    ) -> Result<f32> {
+        let command = self.command(target, measured)?;
+        saber.set_speed(channel, command)?;
+        Ok(command)
+    }
+
+    /// Compute the command and set it as the drive value (mixed mode).
+    /// Return the command sent.
+    pub fn drive<S: Sabertooth2x32 + ?Sized>(
+        &self,
+        saber: &mut S,
+        target: f32,
+        measured: f32,
+    ) -> Result<f32> {
+        let command = self.command(target, measured)?;
+        saber.set_drive(command)?;
+        Ok(command)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_command(controller: PController, target: f32, measured: f32, expected: f32) {
+        let command = controller.command(target, measured).unwrap();
+        assert!(
+            (command - expected).abs() < 1e-6,
+            "{} != {}",
+            command,
+            expected
+        );
+    }
+
+    #[test]
+    fn command() {
+        let controller = PController::new(2.0);
+        assert_command(controller, 0.5, 0.5, 0.5);
+        assert_command(controller, 0.5, 0.4, 0.7);
+        assert_command(controller, -0.3, -0.4, -0.1);
+        assert_command(controller, 0.9, 0.0, 1.0);
+        assert_command(controller, -0.5, 0.5, -1.0);
+        assert!(controller.command(f32::NAN, 0.0).is_err());
+        assert!(PController::new(f32::INFINITY).command(0.5, 0.0).is_err());
+    }
+}
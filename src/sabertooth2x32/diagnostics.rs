@@ -0,0 +1,44 @@
+use crate::error::Error;
+
+/// Readings of all the diagnostics of a Sabertooth 2x32, see
+/// `Sabertooth2x32::get_all_diagnostics()`.
+///
+/// A reading is `None` when its query failed, the error is then in
+/// *failures*.
+#[derive(Debug)]
+pub struct Diagnostics {
+    /// Battery voltage, in volts.
+    pub voltage: Option<f32>,
+    /// Current of the motor 1, in amperes.
+    pub current_m1: Option<f32>,
+    /// Current of the motor 2, in amperes.
+    pub current_m2: Option<f32>,
+    /// Temperature of the output transistors of the channel 1, in degrees
+    /// celsius.
+    pub temperature: Option<f32>,
+    /// Name of each failed reading ("voltage", "current_m1", "current_m2" or
+    /// "temperature") and its error.
+    pub failures: Vec<(&'static str, Error)>,
+}
+
+impl Diagnostics {
+    /// Return true if every reading succeeded.
+    pub fn is_complete(&self) -> bool {
+        self.failures.is_empty()
+    }
+
+    /// Return the reading or record its failure.
+    pub(crate) fn record(
+        &mut self,
+        name: &'static str,
+        reading: crate::Result<f32>,
+    ) -> Option<f32> {
+        match reading {
+            Ok(value) => Some(value),
+            Err(e) => {
+                self.failures.push((name, e));
+                None
+            }
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use super::packetserial::{MAX_ADDRESS, MIN_ADDRESS};
+
+/// Packet serial address selected by the DIP switches of a Sabertooth, given
+/// from switch 1 to switch 6 with `true` for ON.
+///
+/// The address is set by the switches 4, 5 and 6, which count in binary from
+/// 128 (all ON) to 135 (all OFF): switch 4 OFF adds 1, switch 5 OFF adds 2
+/// and switch 6 OFF adds 4. The switches 1, 2 and 3 select the mode and the
+/// battery type and do not change the address, but the address only applies
+/// in packet serial mode (switches 1 and 2 OFF).
+///
+/// # Example
+///
+/// ```
+/// use saberrs::sabertooth2x32::address_from_dip;
+///
+/// let switches = [false, false, true, false, true, true];
+/// assert_eq!(129, address_from_dip(switches));
+/// ```
+pub fn address_from_dip(switches: [bool; 6]) -> u8 {
+    let offset = switches[3..]
+        .iter()
+        .enumerate()
+        .filter(|(_, &on)| !on)
+        .map(|(bit, _)| 1 << bit)
+        .sum::<u8>();
+    MIN_ADDRESS + offset
+}
+
+/// Inverse of `address_from_dip()`: the switches to set for *address* in
+/// packet serial mode, `None` if the address cannot be selected. The switches
+/// 1 and 2 are OFF for the packet serial mode, and the switch 3 is left OFF
+/// as it does not change the address.
+///
+/// # Example
+///
+/// ```
+/// use saberrs::sabertooth2x32::dip_for_address;
+///
+/// assert_eq!(
+///     Some([false, false, false, true, true, false]),
+///     dip_for_address(132)
+/// );
+/// assert_eq!(None, dip_for_address(136));
+/// ```
+pub fn dip_for_address(address: u8) -> Option<[bool; 6]> {
+    if !(MIN_ADDRESS..=MAX_ADDRESS).contains(&address) {
+        return None;
+    }
+    let offset = address - MIN_ADDRESS;
+    let mut switches = [false; 6];
+    for (bit, switch) in switches[3..].iter_mut().enumerate() {
+        *switch = offset & (1 << bit) == 0;
+    }
+    Some(switches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_patterns() {
+        let on = true;
+        let off = false;
+        #[rustfmt::skip]
+        let patterns = [
+            ([off, off, off, on, on, on], 128),
+            ([off, off, off, off, on, on], 129),
+            ([off, off, off, on, off, on], 130),
+            ([off, off, off, off, off, on], 131),
+            ([off, off, off, on, on, off], 132),
+            ([off, off, off, off, on, off], 133),
+            ([off, off, off, on, off, off], 134),
+            ([off, off, off, off, off, off], 135),
+        ];
+        for &(switches, address) in patterns.iter() {
+            assert_eq!(address, address_from_dip(switches));
+            assert_eq!(Some(switches), dip_for_address(address));
+        }
+        // The switch 3 does not change the address.
+        assert_eq!(130, address_from_dip([off, off, on, on, off, on]));
+    }
+
+    #[test]
+    fn invalid_address() {
+        assert_eq!(None, dip_for_address(127));
+        assert_eq!(None, dip_for_address(136));
+        assert_eq!(None, dip_for_address(0));
+    }
+}
@@ -0,0 +1,26 @@
+use super::Command;
+
+/// Intended use of the motor commands, see `PacketSerial::set_drive_mode()`
+/// and `PlainText::set_drive_mode()`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DriveMode {
+    /// Each motor is driven on its own, by the speed and power commands.
+    Independent,
+    /// The motors are driven together, by the drive and turn commands.
+    Mixed,
+}
+
+impl DriveMode {
+    /// Whether *command* belongs to this mode. Commands not moving the motors,
+    /// including the speeds of zero, belong to both.
+    pub fn allows(&self, command: &Command) -> bool {
+        if !command.drives_motors() {
+            return true;
+        }
+        match command {
+            Command::Speed(..) | Command::Power(..) => *self == DriveMode::Independent,
+            Command::Drive(_) | Command::Turn(_) => *self == DriveMode::Mixed,
+            _ => true,
+        }
+    }
+}
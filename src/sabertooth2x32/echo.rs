@@ -0,0 +1,66 @@
+use std::io;
+
+use crate::error::{Error, Result};
+use crate::port::SabertoothSerial;
+use crate::utils;
+
+/// Echoes up to this length are read on the stack.
+const STACK_ECHO_LEN: usize = 32;
+
+/// What to do with the echo of the transmitted bytes, received back on a
+/// single-wire (half-duplex) hookup where the TX and RX lines are joined, or
+/// on a RS-485 transceiver with local echo.
+///
+/// This is the electrical loopback of every byte, read right after each
+/// frame. In every mode but `None`, an echo missing at the timeout of the
+/// port is an `Error::Response`. It is unrelated to the reply of the Sabertooth repeating a "Plain
+/// Text" command, see `PlainText::write_and_verify()`.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum EchoMode {
+    /// There is no echo. This is the default.
+    #[default]
+    None,
+    /// Read back and discard as many bytes as written.
+    Discard,
+    /// Same as `Discard`, and check that the bytes read back match the bytes
+    /// written. A mismatch is an `Error::Echo`. This is the loopback
+    /// confirmation, see `PacketSerial::with_loopback_confirmation()`.
+    VerifyAndDiscard,
+}
+
+/// Read back the echo of *sent* according to *mode*. An echo missing at the
+/// timeout of the port is an `Error::Response`.
+pub(crate) fn consume<T: SabertoothSerial + ?Sized>(
+    mode: EchoMode,
+    dev: &mut T,
+    sent: &[u8],
+) -> Result<()> {
+    if mode == EchoMode::None || sent.is_empty() {
+        return Ok(());
+    }
+    let mut stack = [0u8; STACK_ECHO_LEN];
+    let mut heap = Vec::new();
+    let echo = if sent.len() <= STACK_ECHO_LEN {
+        &mut stack[..sent.len()]
+    } else {
+        heap.resize(sent.len(), 0u8);
+        &mut heap[..]
+    };
+    let timeout = dev.timeout();
+    match utils::read_exact_retrying(dev, echo, timeout) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::TimedOut => {
+            let msg = format!("no echo of {:02x?} before the timeout", sent);
+            return Err(Error::Response(msg));
+        }
+        Err(e) => return Err(e.into()),
+    }
+    if mode == EchoMode::VerifyAndDiscard && *echo != *sent {
+        let msg = format!(
+            "sent {:02x?} but received {:02x?}, likely bus contention",
+            sent, echo
+        );
+        return Err(Error::Echo(msg));
+    }
+    Ok(())
+}
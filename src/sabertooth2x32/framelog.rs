@@ -0,0 +1,399 @@
+use std::fmt;
+use std::io;
+use std::str;
+
+use super::packetserial::{unpack_data_value, PacketType};
+
+/// Direction of a frame on the serial line.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Direction {
+    /// Written to the Sabertooth.
+    Tx,
+    /// Read from the Sabertooth.
+    Rx,
+}
+
+/// Protocol of a frame.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Protocol {
+    /// A line of the "Plain Text" protocol.
+    PlainText,
+    /// A packet of the "Packet Serial" protocol.
+    Packet(PacketType),
+}
+
+/// A frame written or read by an interface, see `set_frame_observer()`.
+///
+/// The data is the frame itself: for a written frame it is what the interface
+/// built, before the wire transform if any. A written frame is reported once
+/// written, before its echo is checked, so that a frame failing the echo check
+/// is reported as well.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct FrameEvent<'a> {
+    /// Whether the frame was written or read.
+    pub direction: Direction,
+    /// Protocol of the interface.
+    pub protocol: Protocol,
+    /// Bytes of the frame, for ex. `b"M1: 1023\r\n"`, or a lone `b"\r\n"`
+    /// terminating a line which was not completely written.
+    pub data: &'a [u8],
+    /// Tag given by the application to the commands sending the frame, see
+    /// `PacketSerial::tagged()` and `PlainText::tagged()`.
+    pub tag: Option<u32>,
+}
+
+/// Function called with every frame written or read, see
+/// `PacketSerial::set_frame_observer()` and `PlainText::set_frame_observer()`.
+pub type FrameObserver = Box<dyn FnMut(&FrameEvent) + Send>;
+
+/// Call the observer, if any.
+pub(crate) fn notify(
+    observer: &mut Option<FrameObserver>,
+    direction: Direction,
+    protocol: Protocol,
+    data: &[u8],
+    tag: Option<u32>,
+) {
+    if let Some(observer) = observer {
+        observer(&FrameEvent {
+            direction,
+            protocol,
+            data,
+            tag,
+        });
+    }
+}
+
+/// Human-readable content of a frame, see `DecodedFrame::decode()`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct DecodedFrame {
+    /// Direction of the frame, as reported by the `FrameEvent`.
+    pub direction: Direction,
+    /// Name of the command, for ex. "Speed", "Drive", "GetCurrent" or "Reply".
+    pub command: String,
+    /// Motor or channel targeted, for ex. "M1", "MD" or "P2".
+    pub target: String,
+    /// Signed data value, if any.
+    pub value: Option<i32>,
+    /// Address of the Sabertooth, for a packet.
+    pub address: Option<u8>,
+    /// Checksum or CRC-14 protecting the data of a packet, as received.
+    pub check: Option<(PacketType, u16)>,
+}
+
+impl DecodedFrame {
+    /// Decode a frame. `None` is returned when it does not have the form of a
+    /// frame of its protocol. The checksum or CRC is not verified.
+    pub fn decode(event: &FrameEvent) -> Option<DecodedFrame> {
+        match event.protocol {
+            Protocol::PlainText => decode_text(event.direction, event.data),
+            Protocol::Packet(packet_type) => {
+                decode_packet(event.direction, packet_type, event.data)
+            }
+        }
+    }
+
+    fn is_motion(&self) -> bool {
+        ["Speed", "Drive", "Turn", "Power"].contains(&self.command.as_str())
+    }
+}
+
+impl fmt::Display for DecodedFrame {
+    /// For ex. `[TX] Speed M1 fwd value=1023 (addr=128 csum=0x7E)`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let direction = match self.direction {
+            Direction::Tx => "TX",
+            Direction::Rx => "RX",
+        };
+        write!(f, "[{}] {} {}", direction, self.command, self.target)?;
+        if let Some(value) = self.value {
+            if self.is_motion() && value != 0 {
+                let way = if value > 0 { "fwd" } else { "rev" };
+                write!(f, " {} value={}", way, value.abs())?;
+            } else {
+                write!(f, " value={}", value)?;
+            }
+        }
+        match (self.address, self.check) {
+            (Some(address), Some((PacketType::Checksum, check))) => {
+                write!(f, " (addr={} csum=0x{:02X})", address, check)
+            }
+            (Some(address), Some((PacketType::CRC, check))) => {
+                write!(f, " (addr={} crc=0x{:04X})", address, check)
+            }
+            (Some(address), None) => write!(f, " (addr={})", address),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Name of a set command for the target, or of a get or reply command for the
+/// source, without its "Get" or "Reply" prefix.
+fn target_name(target: &[u8]) -> Option<&'static str> {
+    match target {
+        b"M1" | b"M2" | b"M*" => Some("Speed"),
+        b"MD" => Some("Drive"),
+        b"MT" => Some("Turn"),
+        b"P1" | b"P2" => Some("Power"),
+        b"R1" | b"R2" => Some("Ramp"),
+        b"Q1" | b"Q2" => Some("Aux"),
+        _ => None,
+    }
+}
+
+fn decode_packet(
+    direction: Direction,
+    packet_type: PacketType,
+    data: &[u8],
+) -> Option<DecodedFrame> {
+    let (address_offset, check_len) = match packet_type {
+        PacketType::Checksum => (0, 1),
+        PacketType::CRC => (112, 2),
+    };
+    if data.len() < 4 + 2 + check_len || data[0] < 128 + address_offset {
+        return None;
+    }
+    let (command_num, command_value) = (data[1], data[2]);
+    let (body, check) = data[4..].split_at(data.len() - 4 - check_len);
+    let check = match check_len {
+        1 => u16::from(check[0]),
+        _ => u16::from(check[0]) | (u16::from(check[1]) << 7),
+    };
+    let unpack = |buf: &[u8]| i32::from(unpack_data_value(buf));
+
+    // (command, target, value)
+    let (command, target, value) = match (command_num, body.len()) {
+        (40, 4) => {
+            let value = unpack(&body[..2]);
+            let negative = command_value & 1 == 1;
+            let value = if negative { -value } else { value };
+            let (command, value) = match command_value & !1 {
+                0 => (target_name(&body[2..])?, Some(value)),
+                16 => ("KeepAlive", None),
+                32 if value == 0 => ("Startup", None),
+                32 => ("Shutdown", None),
+                64 => ("SerialTimeout", Some(value)),
+                _ => return None,
+            };
+            (command.to_string(), &body[2..], value)
+        }
+        (41, 2) => (get_name("Get", command_value, body)?, body, None),
+        (73, 4) => {
+            let value = unpack(&body[..2]);
+            let value = if command_value & 1 == 1 {
+                -value
+            } else {
+                value
+            };
+            let command = get_name("Reply", command_value & !1, &body[2..])?;
+            (command, &body[2..], Some(value))
+        }
+        _ => return None,
+    };
+
+    Some(DecodedFrame {
+        direction,
+        command,
+        target: String::from_utf8_lossy(target).into_owned(),
+        value,
+        address: Some(data[0] - address_offset),
+        check: Some((packet_type, check)),
+    })
+}
+
+fn get_name(prefix: &str, command_value: u8, source: &[u8]) -> Option<String> {
+    let what = match command_value {
+        0 => target_name(source)?,
+        16 => "Battery",
+        32 => "Current",
+        64 => "Temperature",
+        _ => return None,
+    };
+    Some(format!("{}{}", prefix, what))
+}
+
+fn decode_text(direction: Direction, data: &[u8]) -> Option<DecodedFrame> {
+    let line = str::from_utf8(data)
+        .ok()?
+        .trim_matches(char::from(0))
+        .trim();
+    let colon = line.find(':')?;
+    let (target, rest) = (&line[..colon], line[colon + 1..].trim());
+    if target.len() != 2 {
+        return None;
+    }
+    let name = target_name(target.as_bytes());
+
+    let (command, value) = match rest {
+        "startup" => ("Startup".to_string(), None),
+        "shutdown" => ("Shutdown".to_string(), None),
+        "get" => (format!("Get{}", name?), None),
+        "getb" => ("GetBattery".to_string(), None),
+        "getc" => ("GetCurrent".to_string(), None),
+        "gett" => ("GetTemperature".to_string(), None),
+        _ => {
+            let (what, number) = match rest.chars().next()? {
+                'B' => (Some("Battery"), &rest[1..]),
+                'C' => (Some("Current"), &rest[1..]),
+                'T' => (Some("Temperature"), &rest[1..]),
+                _ => (None, rest),
+            };
+            let value = number.trim().parse::<i32>().ok()?;
+            let command = match (direction, what) {
+                (Direction::Rx, Some(what)) => format!("Reply{}", what),
+                (Direction::Rx, None) => format!("Reply{}", name?),
+                (Direction::Tx, None) => name?.to_string(),
+                (Direction::Tx, Some(_)) => return None,
+            };
+            (command, Some(value))
+        }
+    };
+
+    Some(DecodedFrame {
+        direction,
+        command,
+        target: target.to_string(),
+        value,
+        address: None,
+        check: None,
+    })
+}
+
+/// Frame observer writing each frame as a human-readable line, for ex.
+/// `[TX] Speed M1 fwd value=1023 (addr=128 csum=0x7E)`. A frame which cannot
+/// be decoded is written as its bytes in hexadecimal, and the tag of a tagged
+/// frame is appended, for ex. ` tag=42`.
+///
+/// # Example
+///
+/// ```
+/// use saberrs::sabertooth2x32::{PacketSerial, PrettyFrameLogger};
+/// # use saberrs::Result;
+/// # fn example() -> Result<()> {
+/// let mut saber = PacketSerial::new("/dev/ttyUSB0")?;
+/// saber.set_frame_observer(PrettyFrameLogger::to_writer(std::io::stderr()).into_observer());
+/// # Ok(())
+/// # }
+/// ```
+pub struct PrettyFrameLogger {
+    sink: Option<Box<dyn io::Write + Send>>,
+}
+
+impl PrettyFrameLogger {
+    /// Log the frames with `log::info!()`.
+    ///
+    /// **Requires** the "log" feature (enabled by default).
+    #[cfg(feature = "log")]
+    pub fn to_log() -> Self {
+        PrettyFrameLogger { sink: None }
+    }
+
+    /// Write the frames to *writer*, one per line. Write errors are ignored.
+    pub fn to_writer<W: io::Write + Send + 'static>(writer: W) -> Self {
+        PrettyFrameLogger {
+            sink: Some(Box::new(writer)),
+        }
+    }
+
+    /// Format a frame.
+    pub fn format(event: &FrameEvent) -> String {
+        let line = match DecodedFrame::decode(event) {
+            Some(decoded) => decoded.to_string(),
+            None => {
+                let direction = match event.direction {
+                    Direction::Tx => "TX",
+                    Direction::Rx => "RX",
+                };
+                format!("[{}] undecoded {:02x?}", direction, event.data)
+            }
+        };
+        match event.tag {
+            Some(tag) => format!("{} tag={}", line, tag),
+            None => line,
+        }
+    }
+
+    /// Log one frame.
+    pub fn log(&mut self, event: &FrameEvent) {
+        let line = PrettyFrameLogger::format(event);
+        match &mut self.sink {
+            Some(sink) => {
+                let _ = writeln!(sink, "{}", line);
+            }
+            None => log_info!("{}", line),
+        }
+    }
+
+    /// Turn the logger into an observer for `set_frame_observer()`.
+    pub fn into_observer(mut self) -> FrameObserver {
+        Box::new(move |event| self.log(event))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(direction: Direction, protocol: Protocol, data: &[u8]) -> String {
+        PrettyFrameLogger::format(&FrameEvent {
+            direction,
+            protocol,
+            data,
+            tag: None,
+        })
+    }
+
+    #[test]
+    fn format_packets() {
+        let checksum = Protocol::Packet(PacketType::Checksum);
+        let crc = Protocol::Packet(PacketType::CRC);
+        let tx = Direction::Tx;
+        let rx = Direction::Rx;
+
+        assert_eq!(
+            "[TX] Drive MD fwd value=1023 (addr=128 csum=0x17)",
+            format(tx, checksum, b"\x80\x28\x00\x28\x7f\x07MD\x17")
+        );
+        assert_eq!(
+            "[TX] Speed M1 rev value=2047 (addr=128 crc=0x0D6E)",
+            format(tx, crc, b"\xf0\x28\x01\x20\x7f\x0f\x4d\x31\x6e\x1a")
+        );
+        assert_eq!(
+            "[TX] Shutdown M2 (addr=128 csum=0x00)",
+            format(tx, checksum, b"\x80\x28\x20\x48\x01\x00M2\x00")
+        );
+        assert_eq!(
+            "[TX] GetBattery M1 (addr=128 csum=0x7E)",
+            format(tx, checksum, b"\x80\x29\x10\x39\x4D\x31\x7E")
+        );
+        assert_eq!(
+            "[RX] ReplyTemperature M1 value=-12 (addr=128 csum=0x0A)",
+            format(rx, checksum, b"\x80\x49\x41\x0A\x0C\x00\x4D\x31\x0A")
+        );
+        assert_eq!("[TX] undecoded [80, 28]", format(tx, checksum, b"\x80\x28"));
+    }
+
+    #[test]
+    fn format_lines() {
+        let tx = Direction::Tx;
+        let rx = Direction::Rx;
+        let text = Protocol::PlainText;
+
+        assert_eq!(
+            "[TX] Speed M1 fwd value=50",
+            format(tx, text, b"M1: 50\r\n")
+        );
+        assert_eq!("[TX] Turn MT value=0", format(tx, text, b"MT: 0\r\n"));
+        assert_eq!("[TX] Startup M2", format(tx, text, b"M2: startup\r\n"));
+        assert_eq!("[TX] GetCurrent M1", format(tx, text, b"M1: getc\r\n"));
+        assert_eq!(
+            "[RX] ReplyCurrent M2 value=-34",
+            format(rx, text, b"M2: C-34\r\n")
+        );
+        assert_eq!(
+            "[RX] ReplyPower P1 value=12",
+            format(rx, text, b"P1: 12\r\n")
+        );
+        assert!(format(rx, text, b"Sabertooth\r\n").contains("undecoded"));
+    }
+}
@@ -0,0 +1,28 @@
+use std::io;
+
+use crate::error::Error;
+
+/// How the interfaces check the port in `healthcheck()`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum HealthcheckMode {
+    /// Request the battery voltage and wait for the reply. This is the
+    /// default.
+    #[default]
+    Query,
+    /// Only check that the request is written and transmitted, for a
+    /// Sabertooth whose output is not wired. The reply, if any, is discarded
+    /// by the next request.
+    WriteOnly,
+}
+
+/// Report a healthcheck timing out as a disconnection, see
+/// `Error::is_disconnected()`.
+pub(crate) fn wedged(e: Error) -> Error {
+    match e {
+        Error::Io(e) if matches!(e.kind(), io::ErrorKind::TimedOut | io::ErrorKind::WriteZero) => {
+            let msg = format!("the port accepts no data, it may be wedged ({})", e);
+            Error::Io(io::Error::new(io::ErrorKind::NotConnected, msg))
+        }
+        e => e,
+    }
+}
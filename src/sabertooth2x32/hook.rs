@@ -0,0 +1,46 @@
+use std::borrow::Cow;
+
+use super::Command;
+
+/// Decision of a [PreSendHook](type.PreSendHook.html) about a command.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PreSendAction {
+    /// Send the command unchanged.
+    Allow,
+    /// Send another command instead.
+    Replace(Command),
+    /// Do not send anything. The method called still succeeds.
+    Block,
+}
+
+/// Function called with every command just before it is sent, see
+/// `PacketSerial::set_pre_send_hook()` and `PlainText::set_pre_send_hook()`.
+pub type PreSendHook = Box<dyn FnMut(Command) -> PreSendAction + Send>;
+
+/// Function applied to the bytes of every frame just before they are written,
+/// see `PacketSerial::set_wire_transform()` and `PlainText::set_wire_transform()`.
+pub type WireTransform = Box<dyn FnMut(&[u8]) -> Vec<u8> + Send>;
+
+/// Run the hook, if any, and return the command to send.
+pub(crate) fn apply(hook: &mut Option<PreSendHook>, command: Command) -> Option<Command> {
+    let action = match hook {
+        Some(hook) => hook(command),
+        None => PreSendAction::Allow,
+    };
+    match action {
+        PreSendAction::Allow => Some(command),
+        PreSendAction::Replace(other) => Some(other),
+        PreSendAction::Block => None,
+    }
+}
+
+/// Run the transform, if any, on the bytes of a frame.
+pub(crate) fn transform<'a>(
+    transform: &mut Option<WireTransform>,
+    data: &'a [u8],
+) -> Cow<'a, [u8]> {
+    match transform {
+        Some(transform) => Cow::Owned(transform(data)),
+        None => Cow::Borrowed(data),
+    }
+}
@@ -0,0 +1,63 @@
+use std::time::Duration;
+
+/// Weight of a new measurement in the average, as for the round-trip time
+/// estimation of TCP.
+const AVERAGE_WEIGHT: f64 = 0.125;
+
+/// Function called with the round-trip time of every successful request, see
+/// `PacketSerial::set_latency_observer()` and `PlainText::set_latency_observer()`.
+pub type LatencyObserver = Box<dyn FnMut(Duration) + Send>;
+
+/// Round-trip times of the requests, from the end of the write of the
+/// request to the parsing of the reply. The inter-frame and turnaround delays
+/// are not included. Failed requests are not measured.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct LatencyStats {
+    /// Last round-trip time.
+    pub last: Option<Duration>,
+    /// Exponentially weighted moving average of the round-trip times, each
+    /// new measurement having a weight of 1/8.
+    pub average: Option<Duration>,
+    /// Number of measurements.
+    pub count: u64,
+}
+
+#[derive(Default)]
+pub(crate) struct LatencyTracker {
+    pub(crate) stats: LatencyStats,
+    pub(crate) observer: Option<LatencyObserver>,
+}
+
+impl LatencyTracker {
+    pub(crate) fn record(&mut self, latency: Duration) {
+        let average = match self.stats.average {
+            Some(average) => {
+                let average = average.as_secs_f64();
+                average + AVERAGE_WEIGHT * (latency.as_secs_f64() - average)
+            }
+            None => latency.as_secs_f64(),
+        };
+        self.stats.last = Some(latency);
+        self.stats.average = Some(Duration::from_secs_f64(average));
+        self.stats.count += 1;
+        if let Some(observer) = &mut self.observer {
+            observer(latency);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn average() {
+        let mut tracker = LatencyTracker::default();
+        tracker.record(Duration::from_millis(10));
+        assert_eq!(Some(Duration::from_millis(10)), tracker.stats.average);
+        tracker.record(Duration::from_millis(18));
+        assert_eq!(Some(Duration::from_millis(18)), tracker.stats.last);
+        assert_eq!(Some(Duration::from_millis(11)), tracker.stats.average);
+        assert_eq!(2, tracker.stats.count);
+    }
+}
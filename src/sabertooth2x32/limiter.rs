@@ -0,0 +1,299 @@
+use std::time::Instant;
+
+use super::{Command, Sabertooth2x32};
+use crate::error::{Error, Result};
+
+/// Output cap of the safe mode, as a ratio of the full output.
+pub const DEFAULT_SAFE_CAP: f32 = 0.3;
+
+/// Slew rate of the safe mode, in ratio per second: going from a stop to the
+/// capped output takes 0.6 s.
+pub const DEFAULT_SAFE_SLEW_RATE: f32 = 0.5;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct Limits {
+    cap: f32,
+    slew_rate: Option<f32>,
+}
+
+impl Limits {
+    fn new(cap: f32, slew_rate: Option<f32>) -> Result<Self> {
+        if !(0.0..=1.0).contains(&cap) {
+            let msg = format!("output cap should be within 0.0~1.0 (was {})", cap);
+            return Err(Error::InvalidInput(msg));
+        }
+        match slew_rate {
+            Some(rate) if !(rate.is_finite() && rate > 0.0) => {
+                let msg = format!("slew rate should be above 0.0 (was {})", rate);
+                Err(Error::InvalidInput(msg))
+            }
+            _ => Ok(Limits { cap, slew_rate }),
+        }
+    }
+}
+
+// Last command sent, for the slew rate.
+#[derive(Clone, Copy, Debug)]
+struct Last {
+    ratio: f32,
+    at: Instant,
+    // Whether the command was reduced by the slew rate.
+    ramping: bool,
+}
+
+/// Wrapper limiting the output and the rate of change of the commands sent
+/// to a Sabertooth.
+///
+/// The speed, drive, turn and power commands are clamped to the output cap,
+/// then their acceleration since the previous command of the same kind is
+/// limited to the slew rate. Slowing down is never limited, and the other
+/// commands and requests are forwarded unchanged. By default nothing is
+/// limited.
+///
+/// A ramp starts with the first command asking for more than the previous
+/// output, which is then sent unchanged: the time elapsed since an earlier
+/// command does not count, so the output never jumps after an idle period.
+///
+/// The speed and power commands of a channel can also be clamped to an
+/// asymmetric range with `set_output_range()`, for ex. for a mechanism with
+/// more travel in one direction.
+///
+/// The safe mode applies its own cap and slew rate (by default
+/// `DEFAULT_SAFE_CAP` and `DEFAULT_SAFE_SLEW_RATE`), and leaving it restores
+/// the previous limits.
+///
+/// # Example
+///
+/// ```
+/// use saberrs::sabertooth2x32::{OutputLimiter, PlainText, Sabertooth2x32};
+/// # use saberrs::Result;
+/// # fn example() -> Result<()> {
+/// let saber = PlainText::new("/dev/ttyUSB0")?;
+/// let mut saber = OutputLimiter::new(saber).with_output_cap(0.8)?;
+/// saber.enter_safe_mode();
+/// saber.set_speed(1, 1.0)?; // ramps up to 0.3 at most
+/// saber.exit_safe_mode();
+/// saber.set_speed(1, 1.0)?; // sends 0.8
+/// # Ok(())
+/// # }
+/// ```
+pub struct OutputLimiter<S: Sabertooth2x32> {
+    inner: S,
+    limits: Limits,
+    safe_limits: Limits,
+    saved: Option<Limits>,
+    // Output range of the channels 1 and 2.
+    ranges: [(f32, f32); 2],
+    // Last command, for speed 1 and 2, drive, turn, and power 1 and 2.
+    last: [Option<Last>; 6],
+}
+
+impl<S: Sabertooth2x32> OutputLimiter<S> {
+    /// Wrap an interface, without any limit.
+    pub fn new(inner: S) -> Self {
+        OutputLimiter {
+            inner,
+            limits: Limits {
+                cap: 1.0,
+                slew_rate: None,
+            },
+            safe_limits: Limits {
+                cap: DEFAULT_SAFE_CAP,
+                slew_rate: Some(DEFAULT_SAFE_SLEW_RATE),
+            },
+            saved: None,
+            ranges: [(-1.0, 1.0); 2],
+            last: [None; 6],
+        }
+    }
+
+    /// Clamp the commands to -*cap*~*cap*. *cap* must be between 0.0 and
+    /// 1.0, else an `Error::InvalidInput` is returned.
+    pub fn with_output_cap(mut self, cap: f32) -> Result<Self> {
+        self.limits = Limits::new(cap, self.limits.slew_rate)?;
+        Ok(self)
+    }
+
+    /// Limit the acceleration of the commands to *rate* per second, for ex. 0.5 for
+    /// going from a stop to full forward in 2 s. *rate* must be a finite
+    /// number above 0.0, else an `Error::InvalidInput` is returned.
+    pub fn with_slew_rate(mut self, rate: f32) -> Result<Self> {
+        self.limits = Limits::new(self.limits.cap, Some(rate))?;
+        Ok(self)
+    }
+
+    /// Set the output cap and the slew rate applied in safe mode, validated
+    /// as by `with_output_cap()` and `with_slew_rate()`.
+    pub fn with_safe_mode(mut self, cap: f32, slew_rate: f32) -> Result<Self> {
+        self.safe_limits = Limits::new(cap, Some(slew_rate))?;
+        Ok(self)
+    }
+
+    /// Clamp the speed and power commands of *channel* to *min*~*max*, on
+    /// top of the output cap. The range must be within -1.0~1.0 and include
+    /// 0.0, so that the motor can still be stopped, for ex. -1.0~0.8 caps the
+    /// forward direction only.
+    pub fn set_output_range(&mut self, channel: usize, min: f32, max: f32) -> Result<()> {
+        let index = match_channel_to!(channel, 0, 1, "output range");
+        if !(min < max && (-1.0..=0.0).contains(&min) && (0.0..=1.0).contains(&max)) {
+            let msg = format!(
+                "output range should be within -1.0~1.0 and include 0.0 (was {}~{})",
+                min, max
+            );
+            return Err(Error::InvalidInput(msg));
+        }
+        self.ranges[index] = (min, max);
+        Ok(())
+    }
+
+    /// Output range of *channel*, -1.0~1.0 unless set with
+    /// `set_output_range()`.
+    pub fn output_range(&self, channel: usize) -> Result<(f32, f32)> {
+        Ok(self.ranges[match_channel_to!(channel, 0, 1, "output range")])
+    }
+
+    /// Apply the limits of the safe mode until `exit_safe_mode()`. Nothing is
+    /// sent: the new limits apply from the next command.
+    pub fn enter_safe_mode(&mut self) {
+        if self.saved.is_none() {
+            self.saved = Some(self.limits);
+            self.limits = self.safe_limits;
+        }
+    }
+
+    /// Restore the limits in place before `enter_safe_mode()`.
+    pub fn exit_safe_mode(&mut self) {
+        if let Some(limits) = self.saved.take() {
+            self.limits = limits;
+        }
+    }
+
+    /// Whether the safe mode is engaged.
+    pub fn in_safe_mode(&self) -> bool {
+        self.saved.is_some()
+    }
+
+    /// Return the wrapped interface.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    /// Limit *ratio*, returning it and whether the slew rate reduced it.
+    fn limit(&self, index: usize, ratio: f32, now: Instant) -> (f32, bool) {
+        if !ratio.is_finite() {
+            // Let the interface reject it.
+            return (ratio, false);
+        }
+        let ratio = ratio.max(-self.limits.cap).min(self.limits.cap);
+        match self.limits.slew_rate {
+            Some(rate) if ratio != 0.0 => {
+                // A new ramp starts now, a ramp in progress continues.
+                let (previous, at) = match self.last[index] {
+                    Some(last) if last.ramping => (last.ratio, last.at),
+                    Some(last) => (last.ratio, now),
+                    None => (0.0, now),
+                };
+                // Slowing down is immediate, a reversal accelerates from zero.
+                let base = if previous * ratio > 0.0 {
+                    previous.abs()
+                } else {
+                    0.0
+                };
+                let max_step = rate * now.duration_since(at).as_secs_f32();
+                let limited = ratio.abs().min(base + max_step);
+                (ratio.signum() * limited, limited < ratio.abs())
+            }
+            _ => (ratio, false),
+        }
+    }
+
+    fn send(&mut self, command: Command) -> Result<()> {
+        let now = Instant::now();
+        let (index, ratio) = match command {
+            Command::Speed(channel @ 1..=2, ratio) => (channel - 1, ratio),
+            Command::Drive(ratio) => (2, ratio),
+            Command::Turn(ratio) => (3, ratio),
+            Command::Power(channel @ 1..=2, ratio) => (channel + 3, ratio),
+            _ => return command.send(&mut self.inner),
+        };
+        let ratio = match command {
+            Command::Speed(channel, _) | Command::Power(channel, _) if ratio.is_finite() => {
+                let (min, max) = self.ranges[channel - 1];
+                ratio.max(min).min(max)
+            }
+            _ => ratio,
+        };
+        let (ratio, ramping) = self.limit(index, ratio, now);
+        let limited = match command {
+            Command::Speed(channel, _) => Command::Speed(channel, ratio),
+            Command::Power(channel, _) => Command::Power(channel, ratio),
+            Command::Drive(_) => Command::Drive(ratio),
+            _ => Command::Turn(ratio),
+        };
+        limited.send(&mut self.inner)?;
+        self.last[index] = Some(Last {
+            ratio,
+            at: now,
+            ramping,
+        });
+        Ok(())
+    }
+}
+
+impl<S: Sabertooth2x32> Sabertooth2x32 for OutputLimiter<S> {
+    fn startup(&mut self, channel: usize) -> Result<()> {
+        self.inner.startup(channel)
+    }
+
+    fn shutdown(&mut self, channel: usize) -> Result<()> {
+        self.inner.shutdown(channel)
+    }
+
+    fn set_speed(&mut self, channel: usize, ratio: f32) -> Result<()> {
+        self.send(Command::Speed(channel, ratio))
+    }
+
+    fn get_speed(&mut self, channel: usize) -> Result<f32> {
+        self.inner.get_speed(channel)
+    }
+
+    fn set_drive(&mut self, ratio: f32) -> Result<()> {
+        self.send(Command::Drive(ratio))
+    }
+
+    fn set_turn(&mut self, ratio: f32) -> Result<()> {
+        self.send(Command::Turn(ratio))
+    }
+
+    fn set_power(&mut self, channel: usize, ratio: f32) -> Result<()> {
+        self.send(Command::Power(channel, ratio))
+    }
+
+    fn get_power(&mut self, channel: usize) -> Result<f32> {
+        self.inner.get_power(channel)
+    }
+
+    fn set_ramp(&mut self, channel: usize, ratio: f32) -> Result<()> {
+        self.inner.set_ramp(channel, ratio)
+    }
+
+    fn set_aux(&mut self, channel: usize, ratio: f32) -> Result<()> {
+        self.inner.set_aux(channel, ratio)
+    }
+
+    fn check_commands(&self, commands: &[Command]) -> Result<()> {
+        self.inner.check_commands(commands)
+    }
+
+    fn get_voltage(&mut self, channel: usize) -> Result<f32> {
+        self.inner.get_voltage(channel)
+    }
+
+    fn get_current(&mut self, channel: usize) -> Result<f32> {
+        self.inner.get_current(channel)
+    }
+
+    fn get_temperature(&mut self, channel: usize) -> Result<f32> {
+        self.inner.get_temperature(channel)
+    }
+}
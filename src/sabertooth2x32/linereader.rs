@@ -0,0 +1,149 @@
+use std::fmt;
+use std::io;
+use std::ops::Deref;
+use std::time::Duration;
+
+use crate::error::{Error, Result};
+use crate::utils;
+
+/// Maximum length of a reply line, terminator included.
+pub const MAX_LINE_LEN: usize = 64;
+
+const ENDFLAG: u8 = b'\n';
+
+/// Line returned by [LineReader](struct.LineReader.html), kept on the stack
+/// so that reading a reply does not allocate.
+pub struct Line {
+    buf: [u8; MAX_LINE_LEN],
+    len: usize,
+}
+
+impl fmt::Debug for Line {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Line({:?})", String::from_utf8_lossy(self))
+    }
+}
+
+impl Deref for Line {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
+
+/// Buffered reader splitting the received bytes in lines.
+///
+/// The bytes are accumulated across reads, so a line may be received in
+/// several pieces, and what follows a complete line is kept for the next one.
+/// The buffer has a fixed size, the reader does not allocate.
+pub struct LineReader {
+    buf: [u8; MAX_LINE_LEN],
+    len: usize,
+}
+
+impl Default for LineReader {
+    fn default() -> Self {
+        LineReader {
+            buf: [0u8; MAX_LINE_LEN],
+            len: 0,
+        }
+    }
+}
+
+impl LineReader {
+    /// Read the next line, terminator included. The reads interrupted or
+    /// which would block are retried until *timeout*.
+    pub fn read_line<R: io::Read + ?Sized>(
+        &mut self,
+        dev: &mut R,
+        timeout: Duration,
+    ) -> Result<Line> {
+        loop {
+            if let Some(pos) = self.buf[..self.len].iter().position(|&b| b == ENDFLAG) {
+                let mut line = Line {
+                    buf: [0u8; MAX_LINE_LEN],
+                    len: pos + 1,
+                };
+                line.buf[..=pos].copy_from_slice(&self.buf[..=pos]);
+                self.buf.copy_within(pos + 1..self.len, 0);
+                self.len -= pos + 1;
+                return Ok(line);
+            }
+            if self.len >= MAX_LINE_LEN {
+                self.len = 0;
+                let msg = format!("line longer than {} bytes", MAX_LINE_LEN);
+                return Err(Error::Response(msg));
+            }
+            match utils::read_retrying(dev, &mut self.buf[self.len..], timeout)? {
+                0 => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+                n => self.len += n,
+            }
+        }
+    }
+
+    /// Forget the bytes received so far.
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_millis(10);
+
+    /// Reader returning the given chunks, one per read.
+    struct Chunks(Vec<&'static [u8]>);
+
+    impl io::Read for Chunks {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.0.is_empty() {
+                return Err(io::ErrorKind::TimedOut.into());
+            }
+            let chunk = self.0.remove(0);
+            buf[..chunk.len()].copy_from_slice(chunk);
+            Ok(chunk.len())
+        }
+    }
+
+    #[test]
+    fn split_line() {
+        let mut dev = Chunks(vec![b"M1: ", b"12", b"56\r\n"]);
+        let mut reader = LineReader::default();
+        assert_eq!(
+            &b"M1: 1256\r\n"[..],
+            &reader.read_line(&mut dev, TIMEOUT).unwrap()[..]
+        );
+    }
+
+    #[test]
+    fn coalesced_lines() {
+        let mut dev = Chunks(vec![b"M1: 1\r\nM2: 2\r\nM", b"T: 3\r\n"]);
+        let mut reader = LineReader::default();
+        assert_eq!(
+            &b"M1: 1\r\n"[..],
+            &reader.read_line(&mut dev, TIMEOUT).unwrap()[..]
+        );
+        assert_eq!(
+            &b"M2: 2\r\n"[..],
+            &reader.read_line(&mut dev, TIMEOUT).unwrap()[..]
+        );
+        assert_eq!(
+            &b"MT: 3\r\n"[..],
+            &reader.read_line(&mut dev, TIMEOUT).unwrap()[..]
+        );
+        assert!(reader.read_line(&mut dev, TIMEOUT).is_err());
+    }
+
+    #[test]
+    fn line_too_long() {
+        let mut dev = Chunks(vec![&[b'A'; 32], &[b'A'; 32], b"\r\n"]);
+        let mut reader = LineReader::default();
+        match reader.read_line(&mut dev, TIMEOUT) {
+            Err(Error::Response(_)) => {}
+            res => panic!("Expected a response error, got {:?}", res),
+        }
+    }
+}
@@ -1,10 +1,121 @@
-use crate::Result;
+use std::time::Duration;
 
+use crate::convert;
+use crate::{Error, Result};
+
+mod cache;
+mod clock;
+pub mod control;
+mod diagnostics;
+mod dip;
+mod drivemode;
+mod echo;
+mod framelog;
+mod health;
+mod hook;
+mod latency;
+mod limiter;
+mod linereader;
 mod packetserial;
 mod plaintext;
+mod presets;
+pub mod profile;
+mod queue;
+mod ratelimit;
+mod safety;
+mod sampling;
+mod scheduler;
+mod state;
+mod telemetry;
+mod trajectory;
+mod transaction;
+mod zerospeed;
 
-pub use packetserial::{PacketSerial, PacketType, DEFAULT_ADDRESS, DEFAULT_PACKET_TYPE};
+pub use clock::{NowFn, SleepFn};
+pub use diagnostics::Diagnostics;
+pub use dip::{address_from_dip, dip_for_address};
+pub use drivemode::DriveMode;
+pub use echo::EchoMode;
+pub use framelog::{
+    DecodedFrame, Direction, FrameEvent, FrameObserver, PrettyFrameLogger, Protocol,
+};
+pub use health::HealthcheckMode;
+pub use hook::{PreSendAction, PreSendHook, WireTransform};
+pub use latency::{LatencyObserver, LatencyStats};
+pub use limiter::{OutputLimiter, DEFAULT_SAFE_CAP, DEFAULT_SAFE_SLEW_RATE};
+pub use packetserial::{
+    verify_frame, PacketSerial, PacketType, PrecomputedCommand, DEFAULT_ADDRESS,
+    DEFAULT_PACKET_TYPE,
+};
+#[cfg(any(test, feature = "test-util"))]
+pub use packetserial::{Fake2x32Packet, FakeChannel};
 pub use plaintext::PlainText;
+pub use presets::SpeedPresets;
+pub use queue::AsyncQueueDriver;
+pub use ratelimit::{frame_tx_duration, RateLimitPolicy, RateLimiter};
+pub use sampling::{Sampling, Stats};
+pub use scheduler::{Query, QueryScheduler, Scheduled};
+pub use state::LastCommands;
+pub use telemetry::TelemetryPolicy;
+pub use trajectory::{
+    ProgressObserver, Trajectory, TrajectoryProgress, TrajectoryRunner, TrajectoryState,
+};
+pub use transaction::{Command, Transaction};
+pub use zerospeed::ZeroSpeed;
+
+/// Feed arbitrary bytes to the response parsers of both protocols.
+///
+/// This is an entry point for fuzzers, for example a `cargo fuzz` target. The
+/// parsers must reject malformed input with an error, and never panic.
+///
+/// **Requires** the "fuzz" feature.
+#[cfg(any(test, feature = "fuzz"))]
+pub fn parse_fuzz(input: &[u8]) {
+    use packetserial::CommandGet;
+
+    let _ = plaintext::split_response(input);
+
+    let commands = [
+        CommandGet::Value,
+        CommandGet::Battery,
+        CommandGet::Current,
+        CommandGet::Temperature,
+    ];
+    for &packet_type in [PacketType::Checksum, PacketType::CRC].iter() {
+        for &command in commands.iter() {
+            let _ = packetserial::parse_response(
+                packet_type,
+                DEFAULT_ADDRESS,
+                input,
+                command,
+                [b'M', b'1'],
+            );
+        }
+    }
+}
+
+/// Check the limit of `with_current_limit()`, which must be a finite number
+/// of amperes above zero.
+pub(crate) fn check_current_limit(amps: f32) -> Result<f32> {
+    if amps.is_finite() && amps > 0.0 {
+        Ok(amps)
+    } else {
+        let msg = format!("current limit should be above 0 A (was {})", amps);
+        Err(Error::InvalidInput(msg))
+    }
+}
+
+/// Scale `ratio` down so that a motor measured drawing `current` amperes would
+/// draw about `limit` amperes. The sign of the current is ignored, so energy
+/// regenerated into the battery is limited as well.
+pub(crate) fn limit_ratio_for_current(ratio: f32, current: f32, limit: f32) -> f32 {
+    let current = current.abs();
+    if current > limit {
+        ratio * limit / current
+    } else {
+        ratio
+    }
+}
 
 /// Trait exposing the available methods for controlling the Sabertooth 2x32.
 /// Note: implementors may also provide additional methods.
@@ -21,9 +132,23 @@ pub trait Sabertooth2x32 {
     /// backward and 1.0 for full forward (so 0.0 stops the motor).
     fn set_speed(&mut self, channel: usize, ratio: f32) -> Result<()>;
 
+    /// Same as `set_speed()` with a double precision ratio. The range is
+    /// checked in double precision, then the ratio is rounded to the nearest
+    /// `f32`, so that the same ratio sends the same value with both methods.
+    fn set_speed_f64(&mut self, channel: usize, ratio: f64) -> Result<()> {
+        self.set_speed(channel, convert::ratio_from_f64(ratio)?)
+    }
+
     /// Get the current speed of the motor. See set_motor() for the values range.
     fn get_speed(&mut self, channel: usize) -> Result<f32>;
 
+    /// Get the speed of both motors as `(motor 1, motor 2)`. By default this
+    /// takes two round-trips, the "Plain Text" interface sends both requests
+    /// at once.
+    fn get_speed_both(&mut self) -> Result<(f32, f32)> {
+        Ok((self.get_speed(1)?, self.get_speed(2)?))
+    }
+
     /// Stop the motors, ie. set both speeds to zero.
     fn stop_motors(&mut self) -> Result<()> {
         self.set_speed(1, 0.0)?;
@@ -37,16 +162,44 @@ pub trait Sabertooth2x32 {
     /// for having an effect.
     fn set_drive(&mut self, ratio: f32) -> Result<()>;
 
+    /// Same as `set_drive()` with a double precision ratio, see
+    /// `set_speed_f64()`.
+    fn set_drive_f64(&mut self, ratio: f64) -> Result<()> {
+        self.set_drive(convert::ratio_from_f64(ratio)?)
+    }
+
     /// Set the turn value. *ratio* is a ratio between -1.0 for full
     /// left and 1.0 for full right.
     /// Note: Both set_drive() and set_turn() must have been set at least once
     /// for having an effect.
     fn set_turn(&mut self, ratio: f32) -> Result<()>;
 
+    /// Same as `set_turn()` with a double precision ratio, see
+    /// `set_speed_f64()`.
+    fn set_turn_f64(&mut self, ratio: f64) -> Result<()> {
+        self.set_turn(convert::ratio_from_f64(ratio)?)
+    }
+
+    /// Prime the mixed mode by setting the drive and then the turn to zero, so
+    /// that the motors respond to the next set_drive() or set_turn() alone.
+    /// Note: like any command, it counts as activity for the serial timeout of
+    /// the Sabertooth; the timeout still expires if nothing follows.
+    fn init_mixed_mode(&mut self) -> Result<()> {
+        self.set_drive(0.0)?;
+        self.set_turn(0.0)?;
+        Ok(())
+    }
+
     /// Set the power output of the selected motor. *channel* is 1 or 2, and
     /// *ratio* is a ratio between -1.0 and 1.0.
     fn set_power(&mut self, channel: usize, ratio: f32) -> Result<()>;
 
+    /// Same as `set_power()` with a double precision ratio, see
+    /// `set_speed_f64()`.
+    fn set_power_f64(&mut self, channel: usize, ratio: f64) -> Result<()> {
+        self.set_power(channel, convert::ratio_from_f64(ratio)?)
+    }
+
     /// Return the current power output of the motor. *channel* is 1 or 2, and
     /// the returned value is a ratio between -1.0 and 1.0.
     fn get_power(&mut self, channel: usize) -> Result<f32>;
@@ -54,18 +207,291 @@ pub trait Sabertooth2x32 {
     /// Set the speed ramping of the motor.
     fn set_ramp(&mut self, channel: usize, ratio: f32) -> Result<()>;
 
+    /// Same as `set_ramp()` with a double precision ratio, see
+    /// `set_speed_f64()`.
+    fn set_ramp_f64(&mut self, channel: usize, ratio: f64) -> Result<()> {
+        self.set_ramp(channel, convert::ratio_from_f64(ratio)?)
+    }
+
     fn set_aux(&mut self, channel: usize, ratio: f32) -> Result<()>;
 
+    /// Same as `set_aux()` with a double precision ratio, see
+    /// `set_speed_f64()`.
+    fn set_aux_f64(&mut self, channel: usize, ratio: f64) -> Result<()> {
+        self.set_aux(channel, convert::ratio_from_f64(ratio)?)
+    }
+
+    /// Check that *commands* would be sent in this order, without sending
+    /// anything. By default only the channels and values are checked, the
+    /// interfaces also check the refusals of their strict safety mode from
+    /// their current state. The pre-send hook is not called.
+    fn check_commands(&self, commands: &[Command]) -> Result<()> {
+        commands.iter().try_for_each(Command::validate)
+    }
+
     /// Get the battery voltage on the selected motor, in volts.
     fn get_voltage(&mut self, channel: usize) -> Result<f32>;
 
+    /// Read the battery voltage several times and return its statistics, in
+    /// volts.
+    fn get_voltage_stats(&mut self, channel: usize, sampling: Sampling) -> Result<Stats> {
+        sampling.run(|| self.get_voltage(channel))
+    }
+
     /// Get the motor current in amperes. Positive current values mean energy is
     /// being drawn from the battery, and negative values indicate energy is
     /// being regenerated into the battery. Note: this noisy signal may vary by
     /// several amps, this is normal.
     fn get_current(&mut self, channel: usize) -> Result<f32>;
 
+    /// Get the current of both motors, in amperes, as `(motor 1, motor 2)`.
+    /// Like with `get_current()`, a negative current is regenerated into the
+    /// battery. This takes two round-trips.
+    fn get_current_both(&mut self) -> Result<(f32, f32)> {
+        Ok((self.get_current(1)?, self.get_current(2)?))
+    }
+
+    /// Read the motor current *samples* times, waiting *interval* between two
+    /// reads, and return the mean in amperes. Any failed read is an error.
+    fn get_current_avg(
+        &mut self,
+        channel: usize,
+        samples: usize,
+        interval: Duration,
+    ) -> Result<f32> {
+        let stats = self.get_current_stats(channel, Sampling::new(samples, interval))?;
+        Ok(stats.mean)
+    }
+
+    /// Read the motor current several times and return its statistics, in
+    /// amperes.
+    fn get_current_stats(&mut self, channel: usize, sampling: Sampling) -> Result<Stats> {
+        sampling.run(|| self.get_current(channel))
+    }
+
     /// Get the temperature of the output transistors for this channel, in
     /// degrees celsius.
     fn get_temperature(&mut self, channel: usize) -> Result<f32>;
+
+    /// Same as `get_temperature()`, in degrees fahrenheit.
+    fn get_temperature_fahrenheit(&mut self, channel: usize) -> Result<f32> {
+        Ok(convert::celsius_to_fahrenheit(
+            self.get_temperature(channel)?,
+        ))
+    }
+
+    /// Read the battery voltage, the current of both motors and the
+    /// temperature of the channel 1, back-to-back. The firmware has no
+    /// combined query, so this takes four round-trips.
+    ///
+    /// A failed reading does not prevent the next ones, it is reported in
+    /// the returned `Diagnostics`. An error is returned only if all the
+    /// readings failed, it is then the error of the first one.
+    fn get_all_diagnostics(&mut self) -> Result<Diagnostics> {
+        let mut diag = Diagnostics {
+            voltage: None,
+            current_m1: None,
+            current_m2: None,
+            temperature: None,
+            failures: Vec::new(),
+        };
+        diag.voltage = diag.record("voltage", self.get_voltage(1));
+        diag.current_m1 = diag.record("current_m1", self.get_current(1));
+        diag.current_m2 = diag.record("current_m2", self.get_current(2));
+        diag.temperature = diag.record("temperature", self.get_temperature(1));
+
+        if diag.failures.len() == 4 {
+            return Err(diag.failures.swap_remove(0).1);
+        }
+        Ok(diag)
+    }
+}
+
+/// Implement `Sabertooth2x32` for a pointer type by forwarding every method,
+/// including the provided ones, to the pointee.
+macro_rules! forward_sabertooth2x32 {
+    ($($pointer:tt)+) => {
+        impl<T: Sabertooth2x32 + ?Sized> Sabertooth2x32 for $($pointer)+ {
+            fn startup(&mut self, channel: usize) -> Result<()> {
+                (**self).startup(channel)
+            }
+
+            fn shutdown(&mut self, channel: usize) -> Result<()> {
+                (**self).shutdown(channel)
+            }
+
+            fn set_speed(&mut self, channel: usize, ratio: f32) -> Result<()> {
+                (**self).set_speed(channel, ratio)
+            }
+
+            fn set_speed_f64(&mut self, channel: usize, ratio: f64) -> Result<()> {
+                (**self).set_speed_f64(channel, ratio)
+            }
+
+            fn get_speed(&mut self, channel: usize) -> Result<f32> {
+                (**self).get_speed(channel)
+            }
+
+            fn get_speed_both(&mut self) -> Result<(f32, f32)> {
+                (**self).get_speed_both()
+            }
+
+            fn stop_motors(&mut self) -> Result<()> {
+                (**self).stop_motors()
+            }
+
+            fn set_drive(&mut self, ratio: f32) -> Result<()> {
+                (**self).set_drive(ratio)
+            }
+
+            fn set_drive_f64(&mut self, ratio: f64) -> Result<()> {
+                (**self).set_drive_f64(ratio)
+            }
+
+            fn set_turn(&mut self, ratio: f32) -> Result<()> {
+                (**self).set_turn(ratio)
+            }
+
+            fn set_turn_f64(&mut self, ratio: f64) -> Result<()> {
+                (**self).set_turn_f64(ratio)
+            }
+
+            fn init_mixed_mode(&mut self) -> Result<()> {
+                (**self).init_mixed_mode()
+            }
+
+            fn set_power(&mut self, channel: usize, ratio: f32) -> Result<()> {
+                (**self).set_power(channel, ratio)
+            }
+
+            fn set_power_f64(&mut self, channel: usize, ratio: f64) -> Result<()> {
+                (**self).set_power_f64(channel, ratio)
+            }
+
+            fn get_power(&mut self, channel: usize) -> Result<f32> {
+                (**self).get_power(channel)
+            }
+
+            fn set_ramp(&mut self, channel: usize, ratio: f32) -> Result<()> {
+                (**self).set_ramp(channel, ratio)
+            }
+
+            fn set_ramp_f64(&mut self, channel: usize, ratio: f64) -> Result<()> {
+                (**self).set_ramp_f64(channel, ratio)
+            }
+
+            fn set_aux(&mut self, channel: usize, ratio: f32) -> Result<()> {
+                (**self).set_aux(channel, ratio)
+            }
+
+            fn set_aux_f64(&mut self, channel: usize, ratio: f64) -> Result<()> {
+                (**self).set_aux_f64(channel, ratio)
+            }
+
+            fn check_commands(&self, commands: &[Command]) -> Result<()> {
+                (**self).check_commands(commands)
+            }
+
+            fn get_voltage(&mut self, channel: usize) -> Result<f32> {
+                (**self).get_voltage(channel)
+            }
+
+            fn get_voltage_stats(&mut self, channel: usize, sampling: Sampling) -> Result<Stats> {
+                (**self).get_voltage_stats(channel, sampling)
+            }
+
+            fn get_current(&mut self, channel: usize) -> Result<f32> {
+                (**self).get_current(channel)
+            }
+
+            fn get_current_avg(
+                &mut self,
+                channel: usize,
+                samples: usize,
+                interval: Duration,
+            ) -> Result<f32> {
+                (**self).get_current_avg(channel, samples, interval)
+            }
+
+            fn get_current_both(&mut self) -> Result<(f32, f32)> {
+                (**self).get_current_both()
+            }
+
+            fn get_current_stats(&mut self, channel: usize, sampling: Sampling) -> Result<Stats> {
+                (**self).get_current_stats(channel, sampling)
+            }
+
+            fn get_temperature(&mut self, channel: usize) -> Result<f32> {
+                (**self).get_temperature(channel)
+            }
+
+            fn get_temperature_fahrenheit(&mut self, channel: usize) -> Result<f32> {
+                (**self).get_temperature_fahrenheit(channel)
+            }
+
+            fn get_all_diagnostics(&mut self) -> Result<Diagnostics> {
+                (**self).get_all_diagnostics()
+            }
+        }
+    };
+}
+
+forward_sabertooth2x32!(&mut T);
+forward_sabertooth2x32!(Box<T>);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic pseudo-random generator, so that a failure is always
+    /// reproducible.
+    struct XorShift(u32);
+
+    impl XorShift {
+        fn next(&mut self) -> u32 {
+            let mut x = self.0;
+            x ^= x << 13;
+            x ^= x >> 17;
+            x ^= x << 5;
+            self.0 = x;
+            x
+        }
+    }
+
+    #[test]
+    fn parse_fuzz_random_input() {
+        let mut rng = XorShift(0x5abe_7007);
+        let mut buf = [0u8; 32];
+
+        for _ in 0..10_000 {
+            let len = rng.next() as usize % (buf.len() + 1);
+            for byte in &mut buf[..len] {
+                *byte = rng.next() as u8;
+            }
+            parse_fuzz(&buf[..len]);
+        }
+    }
+
+    #[test]
+    fn parse_fuzz_mutated_replies() {
+        let replies: [&[u8]; 3] = [
+            b"\x80\x49\x00\x49\x7F\x03\x4D\x31\x00",
+            b"\xF0\x49\x00\x15\x00\x0C\x4D\x31\x43\x38",
+            b"M1: C-34\r\n",
+        ];
+        let mut rng = XorShift(0x0dd_ba11);
+        let mut buf = [0u8; 16];
+
+        for _ in 0..10_000 {
+            let reply = replies[rng.next() as usize % replies.len()];
+            let len = rng.next() as usize % (reply.len() + 1);
+            buf[..len].copy_from_slice(&reply[..len]);
+            for _ in 0..(rng.next() % 3) {
+                if len > 0 {
+                    buf[rng.next() as usize % len] = rng.next() as u8;
+                }
+            }
+            parse_fuzz(&buf[..len]);
+        }
+    }
 }
@@ -4,9 +4,14 @@ pub const PACKET_SET_SIZE: usize = 9;
 pub const PACKET_GET_SIZE: usize = 7;
 pub const PACKET_REPLY_SIZE: usize = 9;
 
-fn checksum(data: &[u8]) -> u8 {
-    let s: u32 = data.iter().map(|&b| u32::from(b)).sum();
-    (s & 0x7f) as u8
+pub const fn checksum(data: &[u8]) -> u8 {
+    let mut sum = 0u32;
+    let mut i = 0;
+    while i < data.len() {
+        sum += data[i] as u32;
+        i += 1;
+    }
+    low7(sum)
 }
 
 #[derive(Clone, PartialEq, Debug)]
@@ -30,7 +35,7 @@ impl PacketSet {
         let mut buf = [0u8; PACKET_SET_SIZE];
         buf[0] = address;
         buf[1] = CMD_NUM_SET;
-        buf[2] = command_value as u8;
+        buf[2] = command_value;
         buf[3] = checksum(&buf[..3]);
         buf[4..6].copy_from_slice(&pack_data_value(data_value as u16));
         buf[6..8].copy_from_slice(&target[..2]);
@@ -71,18 +76,6 @@ impl AsRef<[u8]> for PacketGet {
     }
 }
 
-pub fn packet_is_valid(resp: &[u8], address: u8) -> std::result::Result<(), ParseError> {
-    if resp.len() != PACKET_REPLY_SIZE {
-        Err(ParseError::PacketSize)
-    } else if resp[3] != checksum(&resp[..3]) || resp[8] != checksum(&resp[4..8]) {
-        Err(ParseError::ChecksumError)
-    } else if resp[0] != address {
-        Err(ParseError::AddressError)
-    } else {
-        Ok(())
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -5,47 +5,75 @@ pub const PACKET_GET_SIZE: usize = 8;
 pub const PACKET_REPLY_SIZE: usize = 10;
 pub const PACKET_ADDR_OFFSET: u8 = 112;
 
-fn crc7(data: &[u8]) -> u8 {
-    let mut crc = 0x7fu8;
-
-    for &b in data {
-        crc ^= b;
-
-        for _ in 0..8 {
-            if (crc & 1) != 0 {
-                crc >>= 1;
-                crc ^= 0x76;
+// The CRC-7 and CRC-14 of the Sabertooth are reflected: the tables give the
+// CRC update of each byte value, computed bit by bit at compile time.
+const CRC7_POLY: u8 = 0x76;
+const CRC14_POLY: u16 = 0x22f0;
+const CRC7_TABLE: [u8; 256] = crc7_table();
+const CRC14_TABLE: [u16; 256] = crc14_table();
+
+const fn crc7_table() -> [u8; 256] {
+    let mut table = [0u8; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u8;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ CRC7_POLY
             } else {
-                crc >>= 1;
-            }
+                crc >> 1
+            };
+            bit += 1;
         }
+        table[i] = crc;
+        i += 1;
     }
-
-    crc ^ 0x7fu8
+    table
 }
 
-fn crc14(data: &[u8]) -> u16 {
-    let mut crc = 0x3fffu16;
-
-    for &b in data {
-        crc ^= u16::from(b);
-
-        for _ in 0..8 {
-            if (crc & 1) != 0 {
-                crc >>= 1;
-                crc ^= 0x22f0;
+const fn crc14_table() -> [u16; 256] {
+    let mut table = [0u16; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u16;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ CRC14_POLY
             } else {
-                crc >>= 1;
-            }
+                crc >> 1
+            };
+            bit += 1;
         }
+        table[i] = crc;
+        i += 1;
     }
+    table
+}
+
+pub const fn crc7(data: &[u8]) -> u8 {
+    let mut crc = 0x7fu8;
+    let mut i = 0;
+    while i < data.len() {
+        crc = CRC7_TABLE[(crc ^ data[i]) as usize];
+        i += 1;
+    }
+    crc ^ 0x7f
+}
 
+const fn crc14(data: &[u8]) -> u16 {
+    let mut crc = 0x3fffu16;
+    let mut i = 0;
+    while i < data.len() {
+        crc = (crc >> 8) ^ CRC14_TABLE[((crc ^ data[i] as u16) & 0xff) as usize];
+        i += 1;
+    }
     crc ^ 0x3fff
 }
 
-fn crc14_to_buf(data: &[u8]) -> [u8; 2] {
-    let crc = crc14(data);
-    [(crc & 127) as u8, ((crc >> 7) & 127) as u8]
+pub const fn crc14_to_buf(data: &[u8]) -> [u8; 2] {
+    pack_data_value(crc14(data))
 }
 
 #[derive(Clone, PartialEq, Debug)]
@@ -69,7 +97,7 @@ impl PacketSet {
         let mut buf = [0u8; PACKET_SET_SIZE];
         buf[0] = address + PACKET_ADDR_OFFSET;
         buf[1] = CMD_NUM_SET;
-        buf[2] = command_value as u8;
+        buf[2] = command_value;
         buf[3] = crc7(&buf[..3]);
         buf[4..6].copy_from_slice(&pack_data_value(data_value as u16));
         buf[6..8].copy_from_slice(&target[..2]);
@@ -114,18 +142,6 @@ impl AsRef<[u8]> for PacketGet {
     }
 }
 
-pub fn packet_is_valid(resp: &[u8], address: u8) -> std::result::Result<(), ParseError> {
-    if resp.len() != PACKET_REPLY_SIZE {
-        Err(ParseError::PacketSize)
-    } else if resp[3] != crc7(&resp[..3]) || resp[8..10] != crc14_to_buf(&resp[4..8]) {
-        Err(ParseError::ChecksumError)
-    } else if resp[0] != address + PACKET_ADDR_OFFSET {
-        Err(ParseError::AddressError)
-    } else {
-        Ok(())
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -148,6 +164,55 @@ mod tests {
         assert_eq!(0x20ee, crc14(&[203, 128]));
     }
 
+    // Bit by bit implementations of the DE reference code.
+    fn reference_crc7(data: &[u8]) -> u8 {
+        let mut crc = 0x7fu8;
+        for &b in data {
+            crc ^= b;
+            for _ in 0..8 {
+                crc = if crc & 1 != 0 {
+                    (crc >> 1) ^ 0x76
+                } else {
+                    crc >> 1
+                };
+            }
+        }
+        crc ^ 0x7f
+    }
+
+    fn reference_crc14(data: &[u8]) -> u16 {
+        let mut crc = 0x3fffu16;
+        for &b in data {
+            crc ^= u16::from(b);
+            for _ in 0..8 {
+                crc = if crc & 1 != 0 {
+                    (crc >> 1) ^ 0x22f0
+                } else {
+                    crc >> 1
+                };
+            }
+        }
+        crc ^ 0x3fff
+    }
+
+    #[test]
+    fn test_crc_tables() {
+        const CRC7: u8 = crc7(&[240, 40, 0]);
+        const CRC14: [u8; 2] = crc14_to_buf(&[127, 7, 77, 49]);
+        assert_eq!(0x0c, CRC7);
+        assert_eq!([0x17, 0x7c], CRC14);
+
+        for a in 0..=255u8 {
+            assert_eq!(reference_crc7(&[a]), crc7(&[a]));
+            assert_eq!(reference_crc14(&[a]), crc14(&[a]));
+            for &b in &[0u8, 1, 77, 127, 128, 255] {
+                let data = [a, b, a ^ b, b.wrapping_add(a)];
+                assert_eq!(reference_crc7(&data), crc7(&data));
+                assert_eq!(reference_crc14(&data), crc14(&data));
+            }
+        }
+    }
+
     #[test]
     fn test_crc_packet() {
         assert_eq!(
@@ -0,0 +1,448 @@
+use std::collections::VecDeque;
+use std::io;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use super::*;
+
+/// State of a motor channel of a [Fake2x32Packet](struct.Fake2x32Packet.html),
+/// in the native range of the commands (-2047~2047).
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct FakeChannel {
+    pub speed: i32,
+    pub power: i32,
+    pub ramp: i32,
+    pub aux: i32,
+    pub shut_down: bool,
+}
+
+/// Emulated Sabertooth 2x32 speaking "Packet Serial", for testing code using
+/// a `PacketSerial` without a serial port.
+///
+/// The fake is a `SabertoothSerial`: the frames written to it are decoded,
+/// with checksum or CRC, and update its state, and the get requests are
+/// answered with the last values set or the configured telemetry. Frames
+/// with invalid protection bytes are dropped without reply, like the
+/// Sabertooth does, and counted in `rejected_frames()`. Clones share the same
+/// state, so one clone may be given to the interface and the other used for
+/// inspecting it.
+///
+/// **Requires** the "test-util" feature.
+///
+/// # Example
+///
+/// ```
+/// use saberrs::sabertooth2x32::{Fake2x32Packet, PacketSerial, Sabertooth2x32};
+/// # use saberrs::Result;
+/// # fn example() -> Result<()> {
+/// let fake = Fake2x32Packet::new();
+/// fake.set_battery(124);
+/// let mut saber = PacketSerial::from(fake.clone());
+/// saber.set_speed(1, 0.5)?;
+/// assert_eq!(1023, fake.channel(1).speed);
+/// assert_eq!(12.4, saber.get_voltage(1)?);
+/// # Ok(())
+/// # }
+/// # example().unwrap();
+/// ```
+#[derive(Clone)]
+pub struct Fake2x32Packet {
+    state: Arc<Mutex<FakeState>>,
+}
+
+struct FakeState {
+    address: u8,
+    channels: [FakeChannel; 2],
+    drive: i32,
+    turn: i32,
+    serial_timeout: Option<Duration>,
+    battery: i32,
+    current: [i32; 2],
+    temperature: [i32; 2],
+    corrupt_replies: bool,
+    rejected: u64,
+    rx: Vec<u8>,
+    tx: VecDeque<u8>,
+    timeout: Duration,
+    baud_rate: u32,
+}
+
+impl Fake2x32Packet {
+    /// Create a fake Sabertooth at the default address, with all its values
+    /// at zero.
+    pub fn new() -> Fake2x32Packet {
+        Fake2x32Packet {
+            state: Arc::new(Mutex::new(FakeState {
+                address: DEFAULT_ADDRESS,
+                channels: [FakeChannel::default(); 2],
+                drive: 0,
+                turn: 0,
+                serial_timeout: None,
+                battery: 0,
+                current: [0; 2],
+                temperature: [0; 2],
+                corrupt_replies: false,
+                rejected: 0,
+                rx: Vec::new(),
+                tx: VecDeque::new(),
+                timeout: Duration::from_millis(100),
+                baud_rate: 9600,
+            })),
+        }
+    }
+
+    /// Answer to *address* instead of the default one. The frames for the
+    /// other addresses are ignored.
+    pub fn with_address(self, address: u8) -> Self {
+        self.lock().address = address;
+        self
+    }
+
+    /// State of the motor *channel*. Panics if *channel* is neither 1 nor 2.
+    pub fn channel(&self, channel: usize) -> FakeChannel {
+        self.lock().channels[channel_index(channel)]
+    }
+
+    /// Last value of the drive command.
+    pub fn drive(&self) -> i32 {
+        self.lock().drive
+    }
+
+    /// Last value of the turn command.
+    pub fn turn(&self) -> i32 {
+        self.lock().turn
+    }
+
+    /// Serial timeout set by the last timeout command, `None` if never set.
+    pub fn serial_timeout(&self) -> Option<Duration> {
+        self.lock().serial_timeout
+    }
+
+    /// Set the battery voltage reported, in tenths of volts.
+    pub fn set_battery(&self, tenths: i32) {
+        self.lock().battery = tenths;
+    }
+
+    /// Set the current of the motor *channel* reported, in amperes. Panics if
+    /// *channel* is neither 1 nor 2.
+    pub fn set_current(&self, channel: usize, amps: i32) {
+        self.lock().current[channel_index(channel)] = amps;
+    }
+
+    /// Set the temperature of the channel reported, in degrees celsius.
+    /// Panics if *channel* is neither 1 nor 2.
+    pub fn set_temperature(&self, channel: usize, celsius: i32) {
+        self.lock().temperature[channel_index(channel)] = celsius;
+    }
+
+    /// Send the replies with a wrong checksum or CRC.
+    pub fn set_corrupt_replies(&self, corrupt: bool) {
+        self.lock().corrupt_replies = corrupt;
+    }
+
+    /// Number of frames for this address dropped because their protection
+    /// bytes or their content were invalid.
+    pub fn rejected_frames(&self) -> u64 {
+        self.lock().rejected
+    }
+
+    fn lock(&self) -> MutexGuard<'_, FakeState> {
+        self.state.lock().unwrap()
+    }
+}
+
+impl Default for Fake2x32Packet {
+    fn default() -> Self {
+        Fake2x32Packet::new()
+    }
+}
+
+fn channel_index(channel: usize) -> usize {
+    assert!(
+        channel == 1 || channel == 2,
+        "channel should be 1 or 2 (was {})",
+        channel
+    );
+    channel - 1
+}
+
+impl FakeState {
+    fn receive(&mut self, byte: u8) {
+        // Only the address byte has its most significant bit set, it starts a
+        // new frame and drops an incomplete one.
+        if byte & 0x80 != 0 {
+            self.rx.clear();
+        } else if self.rx.is_empty() {
+            return;
+        }
+        self.rx.push(byte);
+        if self.rx.len() < 2 {
+            return;
+        }
+        let packet_type = if self.rx[0] == self.address {
+            PacketType::Checksum
+        } else if self.rx[0] == self.address.wrapping_add(crc::PACKET_ADDR_OFFSET) {
+            PacketType::CRC
+        } else {
+            self.rx.clear();
+            return;
+        };
+        let size = match (packet_type, self.rx[1]) {
+            (PacketType::Checksum, CMD_NUM_SET) => checksum::PACKET_SET_SIZE,
+            (PacketType::Checksum, CMD_NUM_GET) => checksum::PACKET_GET_SIZE,
+            (PacketType::CRC, CMD_NUM_SET) => crc::PACKET_SET_SIZE,
+            (PacketType::CRC, CMD_NUM_GET) => crc::PACKET_GET_SIZE,
+            _ => {
+                self.rejected += 1;
+                self.rx.clear();
+                return;
+            }
+        };
+        if self.rx.len() == size {
+            let frame = std::mem::take(&mut self.rx);
+            let accepted =
+                verify_protection(packet_type, &frame).is_ok() && self.process(packet_type, &frame);
+            if !accepted {
+                self.rejected += 1;
+            }
+        }
+    }
+
+    /// Apply a valid frame, return false if its content is not understood.
+    fn process(&mut self, packet_type: PacketType, frame: &[u8]) -> bool {
+        let command = frame[2] & !1;
+        let negative = frame[2] & 1 != 0;
+        if frame[1] == CMD_NUM_GET {
+            let source = [frame[4], frame[5]];
+            return match self.query(command, source) {
+                Some(value) => {
+                    self.reply(packet_type, command, value, source);
+                    true
+                }
+                None => false,
+            };
+        }
+        let mut value = i32::from(unpack_data_value(&frame[4..6]));
+        if negative {
+            value = -value;
+        }
+        let target = [frame[6], frame[7]];
+        let channel = match target[1] {
+            b'1' => Some(0),
+            b'2' => Some(1),
+            _ => None,
+        };
+        match (command, target[0], channel) {
+            (c, b'M', Some(ch)) if c == CommandSet::Value as u8 => self.channels[ch].speed = value,
+            (c, b'P', Some(ch)) if c == CommandSet::Value as u8 => self.channels[ch].power = value,
+            (c, b'R', Some(ch)) if c == CommandSet::Value as u8 => self.channels[ch].ramp = value,
+            (c, b'Q', Some(ch)) if c == CommandSet::Value as u8 => self.channels[ch].aux = value,
+            (c, b'M', None) if c == CommandSet::Value as u8 && target[1] == b'D' => {
+                self.drive = value
+            }
+            (c, b'M', None) if c == CommandSet::Value as u8 && target[1] == b'T' => {
+                self.turn = value
+            }
+            (c, b'M', Some(ch)) if c == CommandSet::Shutdown as u8 => {
+                self.channels[ch].shut_down = value != 0
+            }
+            (c, _, _) if c == CommandSet::Timeout as u8 => {
+                self.serial_timeout = Some(Duration::from_millis(value.max(0) as u64))
+            }
+            (c, _, _) if c == CommandSet::KeepAlive as u8 => {}
+            _ => return false,
+        }
+        true
+    }
+
+    fn query(&self, command: u8, source: [u8; 2]) -> Option<i32> {
+        let ch = match source[1] {
+            b'1' => 0,
+            b'2' => 1,
+            _ => return None,
+        };
+        match (command, source[0]) {
+            (c, b'M') if c == CommandGet::Value as u8 => Some(self.channels[ch].speed),
+            (c, b'P') if c == CommandGet::Value as u8 => Some(self.channels[ch].power),
+            (c, b'M') if c == CommandGet::Battery as u8 => Some(self.battery),
+            (c, b'M') if c == CommandGet::Current as u8 => Some(self.current[ch]),
+            (c, b'M') if c == CommandGet::Temperature as u8 => Some(self.temperature[ch]),
+            _ => None,
+        }
+    }
+
+    fn reply(&mut self, packet_type: PacketType, command: u8, value: i32, source: [u8; 2]) {
+        let command = if value < 0 { command + 1 } else { command };
+        let address = match packet_type {
+            PacketType::Checksum => self.address,
+            PacketType::CRC => self.address + crc::PACKET_ADDR_OFFSET,
+        };
+        let mut frame = vec![address, CMD_NUM_REPLY, command];
+        let data_value = pack_data_value(value.unsigned_abs().min(0x3fff) as u16);
+        match packet_type {
+            PacketType::Checksum => {
+                frame.push(checksum::checksum(&frame));
+                frame.extend_from_slice(&data_value);
+                frame.extend_from_slice(&source);
+                frame.push(checksum::checksum(&frame[4..8]));
+            }
+            PacketType::CRC => {
+                frame.push(crc::crc7(&frame));
+                frame.extend_from_slice(&data_value);
+                frame.extend_from_slice(&source);
+                let crcdata = crc::crc14_to_buf(&frame[4..8]);
+                frame.extend_from_slice(&crcdata);
+            }
+        }
+        if self.corrupt_replies {
+            if let Some(last) = frame.last_mut() {
+                *last ^= 1;
+            }
+        }
+        self.tx.extend(frame);
+    }
+}
+
+impl io::Read for Fake2x32Packet {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut state = self.lock();
+        if state.tx.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "no reply"));
+        }
+        let len = buf.len().min(state.tx.len());
+        for (byte, value) in buf.iter_mut().zip(state.tx.drain(..len)) {
+            *byte = value;
+        }
+        Ok(len)
+    }
+}
+
+impl io::Write for Fake2x32Packet {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut state = self.lock();
+        for &byte in buf {
+            state.receive(byte);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl SabertoothSerial for Fake2x32Packet {
+    fn set_timeout(&mut self, timeout: Duration) -> Result<()> {
+        self.lock().timeout = timeout;
+        Ok(())
+    }
+
+    fn timeout(&self) -> Duration {
+        self.lock().timeout
+    }
+
+    fn set_baud_rate(&mut self, baud_rate: u32) -> Result<()> {
+        self.lock().baud_rate = baud_rate;
+        Ok(())
+    }
+
+    fn baud_rate(&self) -> Result<u32> {
+        Ok(self.lock().baud_rate)
+    }
+
+    fn clear_all(&self) -> Result<()> {
+        self.lock().tx.clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(packet_type: PacketType) {
+        let fake = Fake2x32Packet::new().with_address(130);
+        let mut saber = PacketSerial::from(fake.clone())
+            .with_packet_type(packet_type)
+            .with_address(130);
+
+        saber.set_speed(1, 0.5).unwrap();
+        saber.set_power(2, -1.0).unwrap();
+        saber.set_drive(-0.25).unwrap();
+        saber.shutdown(2).unwrap();
+        saber
+            .set_serial_timeout(Duration::from_millis(500))
+            .unwrap();
+        assert_eq!(1023, fake.channel(1).speed);
+        assert_eq!(-2047, fake.channel(2).power);
+        assert_eq!(-511, fake.drive());
+        assert!(fake.channel(2).shut_down);
+        assert!(!fake.channel(1).shut_down);
+        assert_eq!(Some(Duration::from_millis(500)), fake.serial_timeout());
+
+        fake.set_battery(243);
+        fake.set_current(2, -12);
+        fake.set_temperature(1, 45);
+        assert_eq!(1023, saber.get_speed_raw(1).unwrap());
+        assert_eq!(-2047, saber.get_power_raw(2).unwrap());
+        assert_eq!(243, saber.get_voltage_raw(1).unwrap());
+        assert_eq!(-12, saber.get_current_raw(2).unwrap());
+        assert_eq!(45, saber.get_temperature_raw(1).unwrap());
+        assert_eq!(0, fake.rejected_frames());
+    }
+
+    #[test]
+    fn round_trip_checksum() {
+        round_trip(PacketType::Checksum);
+    }
+
+    #[test]
+    fn round_trip_crc() {
+        round_trip(PacketType::CRC);
+    }
+
+    #[test]
+    fn corrupted_request() {
+        for &packet_type in [PacketType::Checksum, PacketType::CRC].iter() {
+            let fake = Fake2x32Packet::new();
+            let mut saber = PacketSerial::from(fake.clone()).with_packet_type(packet_type);
+            // Corrupt the last protection byte.
+            saber.set_wire_transform(Box::new(|data: &[u8]| {
+                let mut data = data.to_vec();
+                if let Some(last) = data.last_mut() {
+                    *last ^= 1;
+                }
+                data
+            }));
+            saber.set_speed(1, 0.5).unwrap();
+            assert_eq!(0, fake.channel(1).speed);
+            match saber.get_speed_raw(1) {
+                Err(Error::Io(e)) => assert_eq!(io::ErrorKind::TimedOut, e.kind()),
+                res => panic!("Expected a timeout, got {:?}", res),
+            }
+            assert_eq!(2, fake.rejected_frames());
+        }
+    }
+
+    #[test]
+    fn corrupted_reply() {
+        for &packet_type in [PacketType::Checksum, PacketType::CRC].iter() {
+            let fake = Fake2x32Packet::new();
+            let mut saber = PacketSerial::from(fake.clone()).with_packet_type(packet_type);
+            fake.set_corrupt_replies(true);
+            match saber.get_voltage_raw(1) {
+                Err(Error::Response(msg)) => assert_eq!("invalid checksum or CRC", msg),
+                res => panic!("Expected a response error, got {:?}", res),
+            }
+        }
+    }
+
+    #[test]
+    fn other_address_ignored() {
+        let fake = Fake2x32Packet::new();
+        let mut saber = PacketSerial::from(fake.clone()).with_address(129);
+        saber.set_speed(1, 0.5).unwrap();
+        assert_eq!(0, fake.channel(1).speed);
+        assert_eq!(0, fake.rejected_frames());
+    }
+}
@@ -1,9 +1,25 @@
-#[allow(unused_imports)]
-use log::debug;
+use std::borrow::Cow;
+use std::thread;
+use std::time::{Duration, Instant};
 
+use crate::convert;
 use crate::error::{Error, Result};
 use crate::port::SabertoothSerial;
-use crate::sabertooth2x32::Sabertooth2x32;
+use crate::power::PowerControl;
+use crate::sabertooth2x32::cache::ReadingCache;
+use crate::sabertooth2x32::clock::{Clock, NowFn, SleepFn};
+use crate::sabertooth2x32::drivemode::DriveMode;
+use crate::sabertooth2x32::echo::{self, EchoMode};
+use crate::sabertooth2x32::framelog::{self, Direction, FrameObserver, Protocol};
+use crate::sabertooth2x32::health::{self, HealthcheckMode};
+use crate::sabertooth2x32::hook::{self, PreSendHook, WireTransform};
+use crate::sabertooth2x32::latency::{LatencyObserver, LatencyStats, LatencyTracker};
+use crate::sabertooth2x32::safety::StrictSafety;
+use crate::sabertooth2x32::telemetry::{Telemetry, TelemetryPolicy};
+use crate::sabertooth2x32::zerospeed::{ZeroSpeed, ZeroSpeedState};
+use crate::sabertooth2x32::{
+    check_current_limit, limit_ratio_for_current, Command, LastCommands, Sabertooth2x32,
+};
 use crate::utils;
 
 #[cfg(feature = "serialport")]
@@ -11,11 +27,18 @@ use crate::port::sabertoothport::SabertoothPort;
 
 mod checksum;
 mod crc;
+#[cfg(any(test, feature = "test-util"))]
+mod fake;
+mod precomputed;
+
+#[cfg(any(test, feature = "test-util"))]
+pub use fake::{Fake2x32Packet, FakeChannel};
+pub use precomputed::PrecomputedCommand;
 
 #[cfg(debug_assertions)]
 macro_rules! dbg_frame {
     ($head:ident, $frame:expr) => {
-        debug!("{} = {:?}", stringify!($head), $frame);
+        log_debug!("{} = {:?}", stringify!($head), $frame);
     };
 }
 
@@ -30,10 +53,15 @@ pub const DEFAULT_ADDRESS: u8 = 128;
 /// Default packet type when creating a [PacketSerial](struct.PacketSerial.html)
 pub const DEFAULT_PACKET_TYPE: PacketType = PacketType::CRC;
 
+/// Range of the addresses a Sabertooth can be configured with.
+pub(crate) const MIN_ADDRESS: u8 = 128;
+pub(crate) const MAX_ADDRESS: u8 = 135;
+
 const CMD_NUM_SET: u8 = 40;
 const CMD_NUM_GET: u8 = 41;
 const CMD_NUM_REPLY: u8 = 73;
 
+const PACKET_MAX_SET_SIZE: usize = crc::PACKET_SET_SIZE;
 const PACKET_MAX_REPLY_SIZE: usize = crc::PACKET_REPLY_SIZE;
 
 /// Type of frame protection for [PacketSerial](struct.PacketSerial.html).
@@ -53,12 +81,6 @@ pub enum PacketType {
     CRC,
 }
 
-pub enum ParseError {
-    PacketSize,
-    ChecksumError,
-    AddressError,
-}
-
 #[allow(unused)]
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub enum CommandSet {
@@ -77,12 +99,89 @@ pub enum CommandGet {
     Temperature = 64,
 }
 
-fn pack_data_value(value: u16) -> [u8; 2] {
-    [(value & 127) as u8, ((value >> 7) & 127) as u8]
+/// Keep the 7 low bits: only the address byte of a frame has its most
+/// significant bit set.
+const fn low7(value: u32) -> u8 {
+    (value & 0x7f) as u8
+}
+
+/// Split a 14-bit value into two bytes of 7 bits, low bits first.
+const fn pack_data_value(value: u16) -> [u8; 2] {
+    [low7(value as u32), low7((value >> 7) as u32)]
+}
+
+pub(crate) const fn unpack_data_value(buf: &[u8]) -> u16 {
+    low7(buf[0] as u32) as u16 + ((low7(buf[1] as u32) as u16) << 7)
+}
+
+/// Check a complete "Packet Serial" frame: the size for its command number,
+/// and its checksums or CRC. The packet type is deduced from the address
+/// byte. A set, get or reply frame of any address is accepted.
+///
+/// # Example
+///
+/// ```
+/// use saberrs::sabertooth2x32::verify_frame;
+///
+/// assert!(verify_frame(b"\xf0\x28\x00\x0c\x7f\x07\x4d\x31\x17\x7c").is_ok());
+/// assert!(verify_frame(b"\xf0\x28\x00\x0c\x7f\x07\x4d\x31\x17\x7d").is_err());
+/// ```
+pub fn verify_frame(frame: &[u8]) -> Result<()> {
+    let error = |s: &str| Err(Error::Response(s.to_string()));
+    let crc_addresses =
+        MIN_ADDRESS + crc::PACKET_ADDR_OFFSET..=MAX_ADDRESS + crc::PACKET_ADDR_OFFSET;
+    let packet_type = match frame.first() {
+        Some(address) if (MIN_ADDRESS..=MAX_ADDRESS).contains(address) => PacketType::Checksum,
+        Some(address) if crc_addresses.contains(address) => PacketType::CRC,
+        _ => return error("invalid address"),
+    };
+    let size = match (packet_type, frame.get(1)) {
+        (PacketType::Checksum, Some(&CMD_NUM_SET)) => checksum::PACKET_SET_SIZE,
+        (PacketType::Checksum, Some(&CMD_NUM_GET)) => checksum::PACKET_GET_SIZE,
+        (PacketType::Checksum, Some(&CMD_NUM_REPLY)) => checksum::PACKET_REPLY_SIZE,
+        (PacketType::CRC, Some(&CMD_NUM_SET)) => crc::PACKET_SET_SIZE,
+        (PacketType::CRC, Some(&CMD_NUM_GET)) => crc::PACKET_GET_SIZE,
+        (PacketType::CRC, Some(&CMD_NUM_REPLY)) => crc::PACKET_REPLY_SIZE,
+        _ => return error("invalid command num"),
+    };
+    if frame.len() != size {
+        return error("invalid packet size");
+    }
+    verify_protection(packet_type, frame)
+}
+
+/// Check the checksums or the CRC of a frame of a valid size, and that only
+/// its address byte has the most significant bit set.
+pub(crate) fn verify_protection(packet_type: PacketType, frame: &[u8]) -> Result<()> {
+    let len = frame.len();
+    let valid = frame[1..].iter().all(|&b| b == low7(b.into()))
+        && match packet_type {
+            PacketType::Checksum => {
+                frame[3] == checksum::checksum(&frame[..3])
+                    && frame[len - 1] == checksum::checksum(&frame[4..len - 1])
+            }
+            PacketType::CRC => {
+                frame[3] == crc::crc7(&frame[..3])
+                    && frame[len - 2..] == crc::crc14_to_buf(&frame[4..len - 2])
+            }
+        };
+    if valid {
+        Ok(())
+    } else {
+        Err(Error::Response("invalid checksum or CRC".to_string()))
+    }
 }
 
-fn unpack_data_value(buf: &[u8]) -> u16 {
-    u16::from(buf[0] & 127) + (u16::from(buf[1] & 127) << 7)
+fn check_address(address: u8) -> Result<()> {
+    if (MIN_ADDRESS..=MAX_ADDRESS).contains(&address) {
+        Ok(())
+    } else {
+        let msg = format!(
+            "address should be between {} and {} (was {})",
+            MIN_ADDRESS, MAX_ADDRESS, address
+        );
+        Err(Error::InvalidInput(msg))
+    }
 }
 
 /// Interface using the "Packet Serial" protocol with checksum or CRC.
@@ -90,6 +189,28 @@ pub struct PacketSerial<T: SabertoothSerial> {
     dev: T,
     address: u8,
     packet_type: PacketType,
+    current_limit: Option<f32>,
+    last: LastCommands,
+    inter_frame_delay: Duration,
+    turnaround_delay: Duration,
+    last_write: Option<Instant>,
+    clock: Clock,
+    pre_send_hook: Option<PreSendHook>,
+    wire_transform: Option<WireTransform>,
+    safety: StrictSafety,
+    serial_timeout: Option<Duration>,
+    telemetry: Telemetry,
+    echo: EchoMode,
+    shutdown_on_drop: bool,
+    frame_observer: Option<FrameObserver>,
+    tag: Option<u32>,
+    skipped_replies: u64,
+    latency: LatencyTracker,
+    cache: ReadingCache,
+    drive_mode: Option<DriveMode>,
+    tx_buf: Vec<u8>,
+    healthcheck: HealthcheckMode,
+    zero_speed: ZeroSpeedState,
 }
 
 #[cfg(feature = "serialport")]
@@ -107,9 +228,24 @@ impl PacketSerial<SabertoothPort> {
     pub fn new(port: &str) -> Result<PacketSerial<SabertoothPort>> {
         Ok(PacketSerial::from(SabertoothPort::new(port)?))
     }
+
+    /// Same as `new()`, then stop both motors of the Sabertooth at the default
+    /// address. Note that this transmits on construction.
+    pub fn new_stopped(port: &str) -> Result<PacketSerial<SabertoothPort>> {
+        PacketSerial::from_stopped(SabertoothPort::new(port)?)
+    }
 }
 
 impl<T: SabertoothSerial> PacketSerial<T> {
+    /// Create an interface with default settings from the port, then stop both
+    /// motors of the Sabertooth at the default address. Note that this
+    /// transmits on construction.
+    pub fn from_stopped(dev: T) -> Result<Self> {
+        let mut saber = PacketSerial::from(dev);
+        saber.stop_motors()?;
+        Ok(saber)
+    }
+
     /// Set the address of the Sabertooth.
     ///
     /// # Example
@@ -144,194 +280,1157 @@ impl<T: SabertoothSerial> PacketSerial<T> {
         self
     }
 
-    fn write_frame(&mut self, txdata: &[u8]) -> Result<()> {
-        dbg_frame!(tx, txdata);
-        Ok(self.dev.write_all(txdata)?)
+    /// Find the packet type expected by the Sabertooth, by requesting the
+    /// battery voltage of M1 with a CRC and then with a checksum.
+    ///
+    /// The first packet type producing a valid reply is returned, and the
+    /// packet type of the interface is left unchanged. Each attempt waits at
+    /// most for the timeout of the port, and the input of the port is cleared
+    /// before each one. If neither produces a valid reply, the error of the
+    /// checksum attempt is returned.
+    pub fn detect_packet_type(&mut self) -> Result<PacketType> {
+        let configured = self.packet_type;
+        let mut res = Err(Error::Other);
+        for &packet_type in [PacketType::CRC, PacketType::Checksum].iter() {
+            self.packet_type = packet_type;
+            res = self
+                .get(CommandGet::Battery, [b'M', b'1'])
+                .map(|_| packet_type);
+            if res.is_ok() {
+                break;
+            }
+        }
+        self.packet_type = configured;
+        res
     }
 
-    fn read_frame(&mut self, mut buf: &mut [u8]) -> Result<()> {
-        self.dev.read_exact(&mut buf)?;
-        dbg_frame!(rx, buf);
+    /// Same as `detect_packet_type()`, then use the packet type detected.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use saberrs::sabertooth2x32::PacketSerial;
+    /// # use saberrs::{Result, SabertoothPort};
+    /// # fn new_saber() -> Result<PacketSerial<SabertoothPort>> {
+    /// let saber = PacketSerial::new("/dev/ttyUSB0")?.with_detected_packet_type()?;
+    /// # Ok(saber)
+    /// # }
+    /// ```
+    pub fn with_detected_packet_type(mut self) -> Result<Self> {
+        self.packet_type = self.detect_packet_type()?;
+        Ok(self)
+    }
+
+    /// Limit the current drawn by the motors, in amperes.
+    ///
+    /// When set, each non-zero `set_speed()` first requests the current of the
+    /// motor with `get_current()`, and if it exceeds *amps* the commanded
+    /// speed is scaled down proportionally.
+    ///
+    /// This is a coarse software limiter, it reacts only when a new speed is
+    /// commanded and costs one request per command. It is not a substitute
+    /// for the current limiting of the Sabertooth or any hardware protection.
+    ///
+    /// *amps* must be a finite number above zero, else an
+    /// `Error::InvalidInput` is returned.
+    pub fn with_current_limit(mut self, amps: f32) -> Result<Self> {
+        self.current_limit = Some(check_current_limit(amps)?);
+        Ok(self)
+    }
+
+    /// Set the minimum gap between two consecutive frames, measured from the
+    /// end of the previous write. Before writing a frame the interface sleeps
+    /// for the remaining time, if any. The default is no delay.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use saberrs::sabertooth2x32::PacketSerial;
+    /// # use saberrs::{Result, SabertoothPort};
+    /// # fn new_saber() -> Result<PacketSerial<SabertoothPort>> {
+    /// let saber = PacketSerial::new("/dev/ttyUSB0")?
+    ///     .with_inter_frame_delay(Duration::from_millis(3));
+    /// # Ok(saber)
+    /// # }
+    /// ```
+    pub fn with_inter_frame_delay(mut self, delay: Duration) -> Self {
+        self.inter_frame_delay = delay;
+        self
+    }
+
+    /// Set the delay between writing a request and reading its reply, for a
+    /// half-duplex line whose transceiver needs time to switch to receiving.
+    /// The request is flushed first, so that the delay starts once it is
+    /// transmitted. The default is no delay.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use saberrs::sabertooth2x32::PacketSerial;
+    /// # use saberrs::{Result, SabertoothPort};
+    /// # fn new_saber() -> Result<PacketSerial<SabertoothPort>> {
+    /// let saber = PacketSerial::new("/dev/ttyUSB0")?
+    ///     .with_turnaround_delay(Duration::from_micros(500));
+    /// # Ok(saber)
+    /// # }
+    /// ```
+    pub fn with_turnaround_delay(mut self, delay: Duration) -> Self {
+        self.turnaround_delay = delay;
+        self
+    }
+
+    /// Use *now* and *sleep* instead of the system clock for the inter-frame
+    /// and turnaround delays, `time_since_last_command()` and the age of the readings of
+    /// `get_voltage_cached()`, for ex. to test the timing of an application
+    /// on a simulated clock. The timeouts of the port are not affected.
+    pub fn set_clock(&mut self, now: NowFn, sleep: SleepFn) {
+        self.clock = Clock::new(now, sleep);
+    }
+
+    /// Register a function called with every command just before it is sent.
+    ///
+    /// The hook may let the command through, replace it, or block it, see
+    /// [PreSendAction](enum.PreSendAction.html). A replacing command is checked
+    /// like any other, and a blocked one is not sent at all but the method
+    /// called still succeeds. It replaces the previous hook, if any.
+    ///
+    /// `broadcast_stop()` and `drive_all()` are not passed to the hook.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use saberrs::sabertooth2x32::{Command, PacketSerial, PreSendAction};
+    /// # use saberrs::Result;
+    /// # fn example() -> Result<()> {
+    /// let mut saber = PacketSerial::new("/dev/ttyUSB0")?;
+    /// // Never go forward.
+    /// saber.set_pre_send_hook(Box::new(|command| match command {
+    ///     Command::Speed(_, ratio) if ratio > 0.0 => PreSendAction::Block,
+    ///     _ => PreSendAction::Allow,
+    /// }));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_pre_send_hook(&mut self, hook: PreSendHook) {
+        self.pre_send_hook = Some(hook);
+    }
+
+    /// Register a function transforming the bytes of every frame just before
+    /// they are written, for ex. for adding the delimiters or the escaping
+    /// required by a bus bridge. Replies are read unchanged. It replaces the
+    /// previous transform, if any; by default the frames are written as-is.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use saberrs::sabertooth2x32::PacketSerial;
+    /// # use saberrs::Result;
+    /// # fn example() -> Result<()> {
+    /// let mut saber = PacketSerial::new("/dev/ttyUSB0")?;
+    /// // Prefix every frame with a start delimiter.
+    /// saber.set_wire_transform(Box::new(|frame| {
+    ///     let mut data = vec![0xaa];
+    ///     data.extend_from_slice(frame);
+    ///     data
+    /// }));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_wire_transform(&mut self, transform: WireTransform) {
+        self.wire_transform = Some(transform);
+    }
+
+    /// Register a function called with every frame written or read, for ex.
+    /// a [PrettyFrameLogger](struct.PrettyFrameLogger.html). Written frames
+    /// are passed once written, before the wire transform. It replaces the
+    /// previous observer, if any.
+    pub fn set_frame_observer(&mut self, observer: FrameObserver) {
+        self.frame_observer = Some(observer);
+    }
+
+    /// Run *f* with the frames it writes and reads tagged with *tag*, for
+    /// correlating them with the application in the frame observer. The
+    /// previous tag is restored afterwards, so calls can be nested.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use saberrs::sabertooth2x32::{PacketSerial, PrettyFrameLogger, Sabertooth2x32};
+    /// # use saberrs::Result;
+    /// # fn example() -> Result<()> {
+    /// let mut saber = PacketSerial::new("/dev/ttyUSB0")?;
+    /// saber.set_frame_observer(PrettyFrameLogger::to_writer(std::io::stderr()).into_observer());
+    /// // Logged as "[TX] Speed M1 fwd value=1023 ... tag=42".
+    /// saber.tagged(42, |saber| saber.set_speed(1, 0.5))?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn tagged<R, F: FnOnce(&mut Self) -> Result<R>>(&mut self, tag: u32, f: F) -> Result<R> {
+        let previous = self.tag.replace(tag);
+        let result = f(self);
+        self.tag = previous;
+        result
+    }
+
+    /// Require the serial timeout of the Sabertooth to be armed before driving.
+    ///
+    /// In this mode, any command making the motors move is refused with
+    /// `Error::Safety` until `set_serial_timeout()` succeeded with a non-zero
+    /// timeout, and disabling the timeout is refused as well. Such commands
+    /// are also refused for a channel believed to be shut down (see
+    /// `is_shutdown()`) instead of only logging a warning. Stopping the
+    /// motors is always allowed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use saberrs::sabertooth2x32::{PacketSerial, Sabertooth2x32};
+    /// # use saberrs::Result;
+    /// # fn example() -> Result<()> {
+    /// let mut saber = PacketSerial::new("/dev/ttyUSB0")?.with_strict_safety(true);
+    /// saber.set_serial_timeout(Duration::from_millis(500))?;
+    /// saber.set_speed(1, 0.5)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_strict_safety(mut self, strict: bool) -> Self {
+        self.safety.enabled = strict;
+        self
+    }
+
+    /// Whether the strict safety mode is enabled.
+    pub fn strict_safety(&self) -> bool {
+        self.safety.enabled
+    }
+
+    /// Whether a non-zero serial timeout has been set with
+    /// `set_serial_timeout()`.
+    pub fn timeout_armed(&self) -> bool {
+        self.safety.timeout_armed
+    }
+
+    /// Set the serial timeout of the Sabertooth: the motors stop if no command
+    /// is received for this duration. The resolution is the millisecond, and
+    /// a zero duration disables the timeout.
+    pub fn set_serial_timeout(&mut self, timeout: Duration) -> Result<()> {
+        let millis = convert::timeout_to_value(timeout)?;
+        self.safety.check_timeout(millis)?;
+        self.set(CommandSet::Timeout, millis, [b'M', b'*'])?;
+        self.safety.timeout_armed = millis > 0;
+        self.serial_timeout = Some(timeout);
         Ok(())
     }
 
-    fn set(&mut self, cmd_value: CommandSet, value: i32, target: [u8; 2]) -> Result<()> {
-        let packet =
-            PacketFrame::new_set_frame(self.packet_type, self.address, cmd_value, value, target)?;
-        self.write_frame(packet.as_ref())
+    /// Reference to the underlying port.
+    pub fn port(&self) -> &T {
+        &self.dev
     }
 
-    fn set_ratio(&mut self, ratio: f32, target: [u8; 2]) -> Result<()> {
-        let value = utils::ratio_to_value(ratio)?;
-        self.set(CommandSet::Value, value, target)
+    /// Mutable reference to the underlying port, for ex. for a setting that
+    /// the interface does not expose. Note that reading or writing directly
+    /// may desynchronize the interface from the Sabertooth, and the cached
+    /// state like `last_commands()` is not updated.
+    pub fn port_mut(&mut self) -> &mut T {
+        &mut self.dev
     }
 
-    fn reply_size(&self) -> usize {
-        match self.packet_type {
-            PacketType::Checksum => checksum::PACKET_REPLY_SIZE,
-            PacketType::CRC => crc::PACKET_REPLY_SIZE,
+    /// Set what to do with a value from the Sabertooth outside of its
+    /// plausible range, see [TelemetryPolicy](enum.TelemetryPolicy.html).
+    pub fn with_telemetry_policy(mut self, policy: TelemetryPolicy) -> Self {
+        self.telemetry.policy = policy;
+        self
+    }
+
+    /// Number of values from the Sabertooth clamped to their plausible range
+    /// with `TelemetryPolicy::Clamp`.
+    pub fn clamped_readings(&self) -> u64 {
+        self.telemetry.clamped
+    }
+
+    /// Set what to do with the echo of the frames on a single-wire hookup, see
+    /// [EchoMode](enum.EchoMode.html). The echo is read after each write,
+    /// before the reply if any.
+    pub fn with_echo_mode(mut self, mode: EchoMode) -> Self {
+        self.echo = mode;
+        self
+    }
+
+    /// Confirm the delivery of every frame on a wiring echoing the TX on the
+    /// RX, for ex. a RS-485 transceiver with local echo: after each frame the
+    /// echo is read back and compared to the bytes sent, catching line faults
+    /// immediately. A mismatch is an `Error::Echo` and a missing echo an
+    /// `Error::Response`. This is `EchoMode::VerifyAndDiscard` when *enable*
+    /// is `true`, `EchoMode::None` otherwise.
+    pub fn with_loopback_confirmation(self, enable: bool) -> Self {
+        self.with_echo_mode(if enable {
+            EchoMode::VerifyAndDiscard
+        } else {
+            EchoMode::None
+        })
+    }
+
+    /// Shut down both motors when the interface is dropped, for ex. when the
+    /// program exits by unwinding a panic. The motors already shut down with
+    /// `shutdown()` are left alone. Errors are ignored, they are only logged.
+    ///
+    /// Unlike a zero speed, the shutdown holds the motors in a hard brake
+    /// state until `startup()`. Note that nothing is sent if the process is
+    /// killed or aborts.
+    pub fn with_shutdown_on_drop(mut self, enable: bool) -> Self {
+        self.shutdown_on_drop = enable;
+        self
+    }
+
+    /// Number of replies from other addresses discarded so far while waiting
+    /// for replies, for ex. late replies of another Sabertooth on the bus.
+    pub fn skipped_replies(&self) -> u64 {
+        self.skipped_replies
+    }
+
+    /// Get the battery voltage on the motor *channel*, or the last reading of
+    /// this channel if it is at most *max_age* old, so that several parts of
+    /// an application polling it do not each cost a request. Only successful
+    /// readings are cached.
+    pub fn get_voltage_cached(&mut self, channel: usize, max_age: Duration) -> Result<f32> {
+        let now = self.clock.now();
+        if let Some(volts) = self
+            .cache
+            .voltage(channel)
+            .and_then(|c| c.get(max_age, now))
+        {
+            return Ok(volts);
+        }
+        let volts = self.get_voltage(channel)?;
+        let now = self.clock.now();
+        if let Some(cached) = self.cache.voltage(channel) {
+            cached.store(volts, now);
         }
+        Ok(volts)
     }
 
-    fn parse_response(
-        &self,
-        resp: &[u8],
-        expected_cmdvalue: CommandGet,
-        expected_source: [u8; 2],
-    ) -> Result<i32> {
-        let error = |s: &str| Err(Error::Response(s.to_string()));
+    /// Forget the cached readings, so that the next cached get sends a
+    /// request.
+    pub fn invalidate(&mut self) {
+        self.cache.invalidate();
+    }
 
-        let resp_cmdnum = resp[1];
-        let resp_cmdvalue = resp[2];
-        let resp_data_value = &resp[4..6];
-        let resp_data_source = &resp[6..8];
+    /// Round-trip times of the requests so far.
+    pub fn latency_stats(&self) -> LatencyStats {
+        self.latency.stats
+    }
 
-        let validity = match self.packet_type {
-            PacketType::Checksum => checksum::packet_is_valid(resp, self.address),
-            PacketType::CRC => crc::packet_is_valid(resp, self.address),
-        };
+    /// Register a function called with the round-trip time of every
+    /// successful request, from the end of the write of the request to the
+    /// parsing of the reply (see `LatencyStats`). It replaces the previous
+    /// observer, if any.
+    pub fn set_latency_observer(&mut self, observer: LatencyObserver) {
+        self.latency.observer = Some(observer);
+    }
+
+    /// Last values successfully sent to the Sabertooth.
+    pub fn last_commands(&self) -> &LastCommands {
+        &self.last
+    }
 
-        match validity {
-            Ok(_) => {}
-            Err(ParseError::PacketSize) => return error("invalid packet size"),
-            Err(ParseError::ChecksumError) => return error("invalid checksum or CRC"),
-            Err(ParseError::AddressError) => return error("invalid address"),
+    /// Time elapsed since the last command was sent, `None` if no command has
+    /// been sent (or since `clear_cached_state()`). Requests do not count.
+    pub fn time_since_last_command(&self) -> Option<Duration> {
+        self.last.time_since_last(self.clock.now())
+    }
+
+    /// Whether no command has been sent for more than *max*, for ex. for
+    /// stopping the motors before the serial timeout of the Sabertooth
+    /// expires when the control loop stalls. It is true if no command has
+    /// been sent at all.
+    pub fn is_stale(&self, max: Duration) -> bool {
+        match self.time_since_last_command() {
+            Some(elapsed) => elapsed > max,
+            None => true,
         }
+    }
+
+    /// Whether the motor *channel* is believed to be shut down, from the last
+    /// `shutdown()` or `startup()` sent to it, `None` if neither was sent.
+    /// Call `clear_cached_state()` after reconnecting to or power-cycling the
+    /// Sabertooth, as the state is then unknown again.
+    pub fn is_shutdown(&self, channel: usize) -> Option<bool> {
+        self.last.shut_down(channel)
+    }
+
+    /// Declare whether the motors are driven independently or in mixed mode.
+    /// The commands of the other mode, except the ones stopping the motors,
+    /// are then logged with a warning, or refused with
+    /// `Error::Safety` in strict safety mode. The default is no mode: any
+    /// command is accepted.
+    pub fn set_drive_mode(&mut self, mode: DriveMode) {
+        self.drive_mode = Some(mode);
+    }
+
+    /// Mode set with `set_drive_mode()`, if any.
+    pub fn drive_mode(&self) -> Option<DriveMode> {
+        self.drive_mode
+    }
+
+    /// Forget the last values sent, for example when the Sabertooth has been
+    /// power-cycled.
+    pub fn clear_cached_state(&mut self) {
+        self.last = LastCommands::default();
+        self.zero_speed.clear();
+    }
 
-        if resp_cmdnum != CMD_NUM_REPLY {
-            return error("invalid command num");
+    /// Build the frame of *command* for this address and packet type, see
+    /// `send_precomputed()`.
+    pub fn precompute(&self, command: Command) -> Result<PrecomputedCommand> {
+        PrecomputedCommand::new(self.packet_type, self.address, command)
+    }
+
+    /// Send a command whose frame was built beforehand, which saves computing
+    /// the checksums in a loop sending the same few commands.
+    ///
+    /// The command is otherwise handled like the other set commands: the
+    /// pre-send hook and the strict safety mode apply, and it is recorded in
+    /// `last_commands()`. The current limit does not apply. The address and
+    /// the packet type of *precomputed* must be those of the interface,
+    /// otherwise the error is an `Error::InvalidInput`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use saberrs::sabertooth2x32::{Command, PacketSerial};
+    /// # use saberrs::Result;
+    /// # fn example() -> Result<()> {
+    /// let mut saber = PacketSerial::new("/dev/ttyUSB0")?;
+    /// let forward = saber.precompute(Command::Drive(0.5))?;
+    /// let stop = saber.precompute(Command::Drive(0.0))?;
+    /// for _ in 0..100 {
+    ///     saber.send_precomputed(&forward)?;
+    /// }
+    /// saber.send_precomputed(&stop)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn send_precomputed(&mut self, precomputed: &PrecomputedCommand) -> Result<()> {
+        if precomputed.address() != self.address || precomputed.packet_type() != self.packet_type {
+            let msg = format!(
+                "frame precomputed for the address {} with {:?}, not {} with {:?}",
+                precomputed.address(),
+                precomputed.packet_type(),
+                self.address,
+                self.packet_type
+            );
+            return Err(Error::InvalidInput(msg));
         }
+        self.send_command_with(precomputed.command(), Some(precomputed))
+    }
 
-        let expected_cmdvalue = expected_cmdvalue as u8;
-        let is_negative = match resp_cmdvalue {
-            _ if resp_cmdvalue == (expected_cmdvalue + 1) => true,
-            _ if resp_cmdvalue == expected_cmdvalue => false,
-            _ => return error("invalid command value"),
+    /// Set what `set_speed()` sends for a speed of zero, by default a value
+    /// of zero, see [ZeroSpeed](enum.ZeroSpeed.html).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use saberrs::sabertooth2x32::{PacketSerial, Sabertooth2x32, ZeroSpeed};
+    /// # use saberrs::Result;
+    /// # fn example() -> Result<()> {
+    /// let mut saber = PacketSerial::new("/dev/ttyUSB0")?.with_zero_speed(ZeroSpeed::Shutdown);
+    /// saber.set_speed(1, 0.5)?;
+    /// // Shuts down the channel, holding the motor in a hard brake state.
+    /// saber.set_speed(1, 0.0)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_zero_speed(mut self, mode: ZeroSpeed) -> Self {
+        self.zero_speed.mode = mode;
+        self
+    }
+
+    /// Set how `healthcheck()` checks the port.
+    pub fn with_healthcheck_mode(mut self, mode: HealthcheckMode) -> Self {
+        self.healthcheck = mode;
+        self
+    }
+
+    /// Check that the port is still working, for ex. periodically from a
+    /// supervisor reopening the port on failure. Some USB adapters stop
+    /// transmitting while their port stays open.
+    ///
+    /// Depending on the `HealthcheckMode`, the battery voltage is requested
+    /// or the request is only written. When nothing is received, or written,
+    /// before the timeout of the port, the error is an `Error::Io` of kind
+    /// `NotConnected`, for which `Error::is_disconnected()` is `true`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use saberrs::sabertooth2x32::{PacketSerial, Sabertooth2x32};
+    /// # use saberrs::Result;
+    /// # fn example() -> Result<()> {
+    /// let mut saber = PacketSerial::new("/dev/ttyUSB0")?;
+    /// match saber.healthcheck() {
+    ///     Err(e) if e.is_disconnected() => saber = PacketSerial::new("/dev/ttyUSB0")?,
+    ///     res => res?,
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn healthcheck(&mut self) -> Result<()> {
+        let res = match self.healthcheck {
+            HealthcheckMode::Query => self.get_voltage_raw(1).map(|_| ()),
+            HealthcheckMode::WriteOnly => {
+                let packet = PacketFrame::new_get_frame(
+                    self.packet_type,
+                    self.address,
+                    CommandGet::Battery,
+                    [b'M', b'1'],
+                )?;
+                self.write_frame(packet.as_ref())
+                    .and_then(|()| Ok(self.dev.flush()?))
+            }
         };
+        res.map_err(health::wedged)
+    }
+
+    /// Power-cycle the Sabertooth with *power*, for recovering from a fault:
+    /// the power is cut for *off_time*, then *boot_time* is left for the
+    /// Sabertooth to start before configuring it again.
+    ///
+    /// The serial timeout and the ramping set before are sent again, since
+    /// the Sabertooth starts with its stored settings. The motors are not
+    /// restarted: the other last commands are forgotten, as with
+    /// `clear_cached_state()`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use saberrs::{PowerControl, Result};
+    /// use saberrs::sabertooth2x32::PacketSerial;
+    ///
+    /// # fn example(enable: &mut dyn PowerControl) -> Result<()> {
+    /// let mut saber = PacketSerial::new("/dev/ttyUSB0")?;
+    /// saber.set_serial_timeout(Duration::from_millis(500))?;
+    /// saber.power_cycle(enable, Duration::from_millis(500), Duration::from_secs(1))?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn power_cycle<P: PowerControl + ?Sized>(
+        &mut self,
+        power: &mut P,
+        off_time: Duration,
+        boot_time: Duration,
+    ) -> Result<()> {
+        power.cycle(off_time)?;
+        thread::sleep(boot_time);
+        let last = std::mem::take(&mut self.last);
+        self.zero_speed.clear();
+        self.invalidate();
+        self.dev.clear_all()?;
+        if let Some(timeout) = self.serial_timeout {
+            self.set_serial_timeout(timeout)?;
+        }
+        for channel in 1..=2 {
+            if let Some(ratio) = last.ramp(channel) {
+                self.set_ramp(channel, ratio)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the speed of the motor *channel* as sent by the Sabertooth, between
+    /// -2047 and 2047. Unlike `get_speed()`, the value is not range-checked.
+    pub fn get_speed_raw(&mut self, channel: usize) -> Result<i32> {
+        self.get(
+            CommandGet::Value,
+            [b'M', match_channel_to!(channel, b'1', b'2')],
+        )
+    }
+
+    /// Get the power output of the motor *channel* as sent by the Sabertooth,
+    /// between -2047 and 2047. The value is not range-checked.
+    pub fn get_power_raw(&mut self, channel: usize) -> Result<i32> {
+        self.get(
+            CommandGet::Value,
+            [b'P', match_channel_to!(channel, b'1', b'2')],
+        )
+    }
+
+    /// Get the battery voltage in tenths of volts, for ex. 120 for 12.0 V.
+    /// The value is not range-checked.
+    pub fn get_voltage_raw(&mut self, channel: usize) -> Result<i32> {
+        self.get(
+            CommandGet::Battery,
+            [b'M', match_channel_to!(channel, b'1', b'2')],
+        )
+    }
+
+    /// Get the motor current in whole amperes, negative when regenerating.
+    /// The value is not range-checked.
+    pub fn get_current_raw(&mut self, channel: usize) -> Result<i32> {
+        self.get(
+            CommandGet::Current,
+            [b'M', match_channel_to!(channel, b'1', b'2')],
+        )
+    }
+
+    /// Get the temperature of the output transistors in whole degrees
+    /// celsius. The value is not range-checked.
+    pub fn get_temperature_raw(&mut self, channel: usize) -> Result<i32> {
+        self.get(
+            CommandGet::Temperature,
+            [b'M', match_channel_to!(channel, b'1', b'2')],
+        )
+    }
 
-        let mut data_value = i32::from(unpack_data_value(resp_data_value));
-        if is_negative {
-            data_value = -data_value
+    fn limit_current(&mut self, channel: usize, ratio: f32) -> Result<f32> {
+        match self.current_limit {
+            Some(limit) if ratio != 0.0 => {
+                convert::ratio_to_value(ratio)?;
+                let current = self.get_current(channel)?;
+                Ok(limit_ratio_for_current(ratio, current, limit))
+            }
+            _ => Ok(ratio),
         }
+    }
 
-        if resp_data_source != &expected_source[..] {
-            return error("invalid source");
+    /// Stop the motors of all the Sabertooth chained on the serial line.
+    ///
+    /// For each address, in the given order, the speed of M1 and then M2 is set
+    /// to zero. All the frames are sent back-to-back with a single write, using
+    /// the packet type of this interface, unless an inter-frame delay is set in
+    /// which case each frame is written separately. An invalid address (not
+    /// between 128 and 135) is an error and nothing is sent.
+    ///
+    /// The pre-send hook does not apply, so that an emergency stop cannot be
+    /// blocked nor replaced.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use saberrs::sabertooth2x32::PacketSerial;
+    /// # use saberrs::Result;
+    /// # fn emergency_stop() -> Result<()> {
+    /// let mut saber = PacketSerial::new("/dev/ttyUSB0")?;
+    /// saber.broadcast_stop(&[128, 129, 130])?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn broadcast_stop(&mut self, addresses: &[u8]) -> Result<()> {
+        let mut packets = Vec::with_capacity(addresses.len() * 2);
+        for &address in addresses {
+            check_address(address)?;
+            for &channel in [b'1', b'2'].iter() {
+                packets.push(PacketFrame::new_set_frame(
+                    self.packet_type,
+                    address,
+                    CommandSet::Value,
+                    0,
+                    [b'M', channel],
+                )?);
+            }
         }
+        self.write_frames(&packets)
+    }
 
-        Ok(data_value)
+    /// Set the speeds of the motors of several Sabertooth chained on the
+    /// serial line, given as (address, M1 ratio, M2 ratio).
+    ///
+    /// The frames are sent like with `broadcast_stop()`: back-to-back with a
+    /// single write unless an inter-frame delay is set. Every address and
+    /// ratio is checked first, and nothing is sent if one is invalid. The
+    /// strict safety mode applies, but the pre-send hook and the current limit
+    /// do not. The speeds are recorded in `last_commands()` for the address
+    /// of this interface only.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use saberrs::sabertooth2x32::PacketSerial;
+    /// # use saberrs::Result;
+    /// # fn example() -> Result<()> {
+    /// let mut saber = PacketSerial::new("/dev/ttyUSB0")?;
+    /// saber.drive_all(&[(128, 0.5, 0.5), (129, 0.5, -0.5)])?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn drive_all(&mut self, speeds: &[(u8, f32, f32)]) -> Result<()> {
+        let mut packets = Vec::with_capacity(speeds.len() * 2);
+        let mut commands = Vec::new();
+        for &(address, m1, m2) in speeds {
+            check_address(address)?;
+            for &(channel, ratio) in [(1, m1), (2, m2)].iter() {
+                let command = Command::Speed(channel, ratio);
+                if self.safety.check_armed(command).is_err() {
+                    let msg = format!(
+                        "{:?} at address {} sent before arming the serial timeout",
+                        command, address
+                    );
+                    return Err(Error::Safety(msg));
+                }
+                let target = [b'M', b'0' + channel as u8];
+                let value = convert::ratio_to_value(ratio)?;
+                packets.push(PacketFrame::new_set_frame(
+                    self.packet_type,
+                    address,
+                    CommandSet::Value,
+                    value,
+                    target,
+                )?);
+                if address == self.address {
+                    self.check_state(command)?;
+                    commands.push(command);
+                }
+            }
+        }
+        self.write_frames(&packets)?;
+        let now = self.clock.now();
+        for command in commands {
+            self.last.record_sent(command, now);
+        }
+        Ok(())
+    }
+
+    /// Write set frames back-to-back, or separately when an inter-frame delay
+    /// is set.
+    fn write_frames(&mut self, packets: &[PacketFrame]) -> Result<()> {
+        if self.inter_frame_delay > Duration::from_secs(0) {
+            for packet in packets.iter() {
+                self.write_frame(packet.as_ref())?;
+            }
+            Ok(())
+        } else {
+            // The buffer is kept for the next call, so that it allocates only
+            // when more frames are sent than ever before.
+            let mut txdata = std::mem::take(&mut self.tx_buf);
+            txdata.clear();
+            txdata.reserve(packets.len() * PACKET_MAX_SET_SIZE);
+            for packet in packets.iter() {
+                txdata.extend_from_slice(packet.as_ref());
+            }
+            let res = self.write_bytes(&txdata).and_then(|wire| {
+                for packet in packets.iter() {
+                    self.notify(Direction::Tx, packet.as_ref());
+                }
+                echo::consume(self.echo, &mut self.dev, &wire)
+            });
+            self.tx_buf = txdata;
+            res
+        }
+    }
+
+    fn send_command(&mut self, command: Command) -> Result<()> {
+        self.send_command_with(command, None)
+    }
+
+    /// Send *command*, writing the frame of *precomputed* if the pre-send
+    /// hook leaves the command unchanged.
+    fn send_command_with(
+        &mut self,
+        command: Command,
+        precomputed: Option<&PrecomputedCommand>,
+    ) -> Result<()> {
+        if let Some(command) = hook::apply(&mut self.pre_send_hook, command) {
+            self.safety.check_armed(command)?;
+            self.check_state(command)?;
+            match precomputed {
+                Some(precomputed) if precomputed.command() == command => {
+                    self.write_frame(precomputed.as_bytes())?
+                }
+                _ => self.write_command(command)?,
+            }
+            self.last.record_sent(command, self.clock.now());
+        }
+        Ok(())
+    }
+
+    fn check_state(&self, command: Command) -> Result<()> {
+        self.safety
+            .check_state(command, &self.last, self.drive_mode)
+    }
+
+    fn write_command(&mut self, command: Command) -> Result<()> {
+        let packet = command_frame(self.packet_type, self.address, command)?;
+        self.write_frame(packet.as_ref())
+    }
+
+    fn notify(&mut self, direction: Direction, data: &[u8]) {
+        let protocol = Protocol::Packet(self.packet_type);
+        framelog::notify(
+            &mut self.frame_observer,
+            direction,
+            protocol,
+            data,
+            self.tag,
+        );
+    }
+
+    fn write_frame(&mut self, txdata: &[u8]) -> Result<()> {
+        let wire = self.write_bytes(txdata)?;
+        self.notify(Direction::Tx, txdata);
+        echo::consume(self.echo, &mut self.dev, &wire)
+    }
+
+    /// Write one or several frames, without notifying the observer nor
+    /// reading the echo, returning the bytes written on the line.
+    fn write_bytes<'a>(&mut self, txdata: &'a [u8]) -> Result<Cow<'a, [u8]>> {
+        if let Some(last_write) = self.last_write {
+            let elapsed = self.clock.elapsed(last_write);
+            if elapsed < self.inter_frame_delay {
+                self.clock.sleep(self.inter_frame_delay - elapsed);
+            }
+        }
+        dbg_frame!(tx, txdata);
+        // An incomplete frame needs no special care: the address is the only
+        // byte with its most significant bit set, so the Sabertooth drops the
+        // incomplete frame when receiving the address of the next one.
+        let txdata = hook::transform(&mut self.wire_transform, txdata);
+        let timeout = self.dev.timeout();
+        let res = utils::write_frame(&mut self.dev, &txdata, timeout);
+        self.last_write = Some(self.clock.now());
+        res.map_err(|(_, e)| e)?;
+        Ok(txdata)
+    }
+
+    fn read_frame(&mut self, buf: &mut [u8]) -> Result<()> {
+        let timeout = self.dev.timeout();
+        utils::read_exact_retrying(&mut self.dev, buf, timeout)?;
+        dbg_frame!(rx, buf);
+        self.notify(Direction::Rx, buf);
+        Ok(())
+    }
+
+    /// Same as `read_frame()`, waiting at most until *deadline* if it is
+    /// sooner than the timeout of the port.
+    fn read_frame_until(&mut self, buf: &mut [u8], deadline: Instant) -> Result<()> {
+        let timeout = self.dev.timeout();
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining >= timeout {
+            return self.read_frame(buf);
+        }
+        self.dev.set_timeout(remaining)?;
+        let res = self.read_frame(buf);
+        self.dev.set_timeout(timeout)?;
+        res
+    }
+
+    fn set(&mut self, cmd_value: CommandSet, value: i32, target: [u8; 2]) -> Result<()> {
+        let packet =
+            PacketFrame::new_set_frame(self.packet_type, self.address, cmd_value, value, target)?;
+        self.write_frame(packet.as_ref())
+    }
+
+    fn reply_size(&self) -> usize {
+        match self.packet_type {
+            PacketType::Checksum => checksum::PACKET_REPLY_SIZE,
+            PacketType::CRC => crc::PACKET_REPLY_SIZE,
+        }
+    }
+
+    /// Whether *byte* is the address byte of a frame for another Sabertooth.
+    fn is_foreign_address(&self, byte: u8) -> bool {
+        let offset = match self.packet_type {
+            PacketType::Checksum => 0,
+            PacketType::CRC => crc::PACKET_ADDR_OFFSET,
+        };
+        let address = byte.wrapping_sub(offset);
+        (MIN_ADDRESS..=MAX_ADDRESS).contains(&address) && address != self.address
     }
 
     fn get(&mut self, cmd_value: CommandGet, source: [u8; 2]) -> Result<i32> {
         let packet = PacketFrame::new_get_frame(self.packet_type, self.address, cmd_value, source)?;
+        let deadline = Instant::now() + self.dev.timeout();
         self.dev.clear_all()?;
         self.write_frame(packet.as_ref())?;
+        let clock = &mut self.clock;
+        utils::turnaround(&mut self.dev, self.turnaround_delay, |d| clock.sleep(d))?;
+        // The inter-frame and turnaround delays are not part of the latency.
+        let start = Instant::now();
         let mut buf = [0u8; PACKET_MAX_REPLY_SIZE];
         let resp = &mut buf[..self.reply_size()];
-        self.read_frame(resp)?;
-        self.parse_response(resp, cmd_value, source)
+        let mut skipped = 0;
+        loop {
+            if skipped > 0 && Instant::now() >= deadline {
+                let msg = format!(
+                    "no reply from address {} before the timeout ({} replies from other addresses skipped)",
+                    self.address, skipped
+                );
+                return Err(Error::Response(msg));
+            }
+            let read = if skipped > 0 {
+                self.read_frame_until(resp, deadline)
+            } else {
+                self.read_frame(resp)
+            };
+            match read {
+                Ok(()) => {}
+                Err(Error::Io(e)) if skipped > 0 => {
+                    let msg = format!("{} ({} replies from other addresses skipped)", e, skipped);
+                    return Err(Error::Io(std::io::Error::new(e.kind(), msg)));
+                }
+                Err(e) => return Err(e),
+            }
+            if !self.is_foreign_address(resp[0]) {
+                break;
+            }
+            // A late reply of another Sabertooth on the bus.
+            self.skipped_replies += 1;
+            skipped += 1;
+        }
+        if is_collision(self.packet_type, self.address, resp) {
+            let msg = format!(
+                "a reply of address {} restarts within another one",
+                self.address
+            );
+            log_warn!("{}", msg);
+            return Err(Error::AddressCollision(msg));
+        }
+        let value = parse_response(self.packet_type, self.address, resp, cmd_value, source)?;
+        self.latency.record(start.elapsed());
+        Ok(value)
     }
+}
 
-    fn get_ratio(&mut self, cmd_value: CommandGet, source: [u8; 2]) -> Result<f32> {
-        let value = self.get(cmd_value, source)?;
-        let ratio = utils::value_to_ratio(value);
-        Ok(ratio)
+/// Whether the reply frame is two overlapping replies of *address*: the frame
+/// is invalid and the address byte, which cannot be in the data of a frame,
+/// is repeated within it.
+fn is_collision(packet_type: PacketType, address: u8, resp: &[u8]) -> bool {
+    let address_byte = match packet_type {
+        PacketType::Checksum => address,
+        PacketType::CRC => address.wrapping_add(crc::PACKET_ADDR_OFFSET),
+    };
+    match resp.split_first() {
+        Some((&first, rest)) if first == address_byte => {
+            rest.contains(&address_byte) && verify_protection(packet_type, resp).is_err()
+        }
+        _ => false,
     }
 }
 
+/// Parse a reply frame and return its data value. Any input is accepted, an
+/// invalid reply is returned as an error.
+pub(crate) fn parse_response(
+    packet_type: PacketType,
+    address: u8,
+    resp: &[u8],
+    expected_cmdvalue: CommandGet,
+    expected_source: [u8; 2],
+) -> Result<i32> {
+    let error = |s: &str| Err(Error::Response(s.to_string()));
+
+    let (size, address_offset) = match packet_type {
+        PacketType::Checksum => (checksum::PACKET_REPLY_SIZE, 0),
+        PacketType::CRC => (crc::PACKET_REPLY_SIZE, crc::PACKET_ADDR_OFFSET),
+    };
+    if resp.len() != size {
+        return error("invalid packet size");
+    }
+    verify_protection(packet_type, resp)?;
+    if resp[0] != address.wrapping_add(address_offset) {
+        return error("invalid address");
+    }
+
+    // The size is valid at this point, indexing is safe.
+    let resp_cmdnum = resp[1];
+    let resp_cmdvalue = resp[2];
+    let resp_data_value = &resp[4..6];
+    let resp_data_source = &resp[6..8];
+
+    if resp_cmdnum != CMD_NUM_REPLY {
+        return error("invalid command num");
+    }
+
+    let expected_cmdvalue = expected_cmdvalue as u8;
+    let is_negative = match resp_cmdvalue {
+        _ if resp_cmdvalue == (expected_cmdvalue + 1) => true,
+        _ if resp_cmdvalue == expected_cmdvalue => false,
+        _ => return error("invalid command value"),
+    };
+
+    let mut data_value = i32::from(unpack_data_value(resp_data_value));
+    if is_negative {
+        data_value = -data_value
+    }
+
+    if resp_data_source != &expected_source[..] {
+        return error("invalid source");
+    }
+
+    Ok(data_value)
+}
+
+/// Create an interface with default settings from the port.
 impl<T: SabertoothSerial> From<T> for PacketSerial<T> {
     fn from(dev: T) -> Self {
         PacketSerial {
             dev,
             address: DEFAULT_ADDRESS,
             packet_type: DEFAULT_PACKET_TYPE,
+            current_limit: None,
+            last: LastCommands::default(),
+            inter_frame_delay: Duration::from_secs(0),
+            turnaround_delay: Duration::from_secs(0),
+            last_write: None,
+            clock: Clock::default(),
+            pre_send_hook: None,
+            wire_transform: None,
+            safety: StrictSafety::default(),
+            serial_timeout: None,
+            telemetry: Telemetry::default(),
+            echo: EchoMode::default(),
+            shutdown_on_drop: false,
+            frame_observer: None,
+            tag: None,
+            skipped_replies: 0,
+            latency: LatencyTracker::default(),
+            cache: ReadingCache::default(),
+            drive_mode: None,
+            tx_buf: Vec::new(),
+            healthcheck: HealthcheckMode::default(),
+            zero_speed: ZeroSpeedState::default(),
         }
     }
 }
 
+/// Create an interface from a clone of a shared port, for ex. a
+/// `SabertoothPortShared`, so that several interfaces may use the same port.
+///
+/// The interfaces do not know about each other: each one has its own cached
+/// state (`last_commands()`, the inter-frame delay), and a request clears the input
+/// of the port, discarding data another interface may be waiting for. Using
+/// them from several threads at once may mix their frames and replies.
 impl<T> From<&T> for PacketSerial<T>
 where
     T: SabertoothSerial + Clone,
 {
     fn from(dev: &T) -> Self {
-        PacketSerial {
-            dev: dev.clone(),
-            address: DEFAULT_ADDRESS,
-            packet_type: DEFAULT_PACKET_TYPE,
+        PacketSerial::from(dev.clone())
+    }
+}
+
+impl<T: SabertoothSerial> Drop for PacketSerial<T> {
+    fn drop(&mut self) {
+        if !self.shutdown_on_drop {
+            return;
+        }
+        for channel in 1..=2 {
+            if self.last.shut_down(channel) != Some(true) {
+                if let Err(e) = self.shutdown(channel) {
+                    log_warn!("Shutdown of M{} on drop failed: {}", channel, e);
+                }
+            }
         }
     }
 }
 
 impl<T: SabertoothSerial> Sabertooth2x32 for PacketSerial<T> {
     fn startup(&mut self, channel: usize) -> Result<()> {
-        let target = [b'M', match_channel_to!(channel, b'1', b'2')];
-        self.set(CommandSet::Shutdown, 0, target)
+        self.send_command(Command::Startup(channel))?;
+        self.zero_speed.forget(channel);
+        Ok(())
     }
 
     fn shutdown(&mut self, channel: usize) -> Result<()> {
-        let target = [b'M', match_channel_to!(channel, b'1', b'2')];
-        self.set(CommandSet::Shutdown, 1, target)
+        self.send_command(Command::Shutdown(channel))?;
+        self.zero_speed.forget(channel);
+        Ok(())
     }
 
     fn set_speed(&mut self, channel: usize, ratio: f32) -> Result<()> {
-        self.set_ratio(ratio, [b'M', match_channel_to!(channel, b'1', b'2')])
+        let ratio = self.limit_current(channel, ratio)?;
+        for &command in self.zero_speed.commands(channel, ratio).iter().flatten() {
+            self.send_command(command)?;
+            self.zero_speed.sent(command);
+            if let Command::Shutdown(_) = command {
+                // The motor is stopped, resending the last speed must not
+                // restart it.
+                self.last.record(Command::Speed(channel, 0.0));
+            }
+        }
+        Ok(())
     }
 
     fn get_speed(&mut self, channel: usize) -> Result<f32> {
-        self.get_ratio(
-            CommandGet::Value,
-            [b'M', match_channel_to!(channel, b'1', b'2')],
-        )
+        let value = self.get_speed_raw(channel)?;
+        self.telemetry.ratio("speed", value, convert::RANGE_MAX)
     }
 
     fn set_drive(&mut self, ratio: f32) -> Result<()> {
-        self.set_ratio(ratio, [b'M', b'D'])
+        self.send_command(Command::Drive(ratio))
     }
 
     fn set_turn(&mut self, ratio: f32) -> Result<()> {
-        self.set_ratio(ratio, [b'M', b'T'])
+        self.send_command(Command::Turn(ratio))
     }
 
     fn set_power(&mut self, channel: usize, ratio: f32) -> Result<()> {
-        self.set_ratio(ratio, [b'P', match_channel_to!(channel, b'1', b'2')])
+        self.send_command(Command::Power(channel, ratio))
     }
 
     fn get_power(&mut self, channel: usize) -> Result<f32> {
-        self.get_ratio(
-            CommandGet::Value,
-            [b'P', match_channel_to!(channel, b'1', b'2')],
-        )
+        let value = self.get_power_raw(channel)?;
+        self.telemetry.ratio("power", value, convert::RANGE_MAX)
     }
 
     fn set_ramp(&mut self, channel: usize, ratio: f32) -> Result<()> {
-        self.set_ratio(ratio, [b'R', match_channel_to!(channel, b'1', b'2')])
+        self.send_command(Command::Ramp(channel, ratio))
     }
 
     fn set_aux(&mut self, channel: usize, ratio: f32) -> Result<()> {
-        self.set_ratio(ratio, [b'Q', match_channel_to!(channel, b'1', b'2')])
+        self.send_command(Command::Aux(channel, ratio))
+    }
+
+    fn check_commands(&self, commands: &[Command]) -> Result<()> {
+        self.safety
+            .check_commands(commands, &self.last, &self.zero_speed, self.drive_mode)
     }
 
     fn get_voltage(&mut self, channel: usize) -> Result<f32> {
-        let value = self.get(
-            CommandGet::Battery,
-            [b'M', match_channel_to!(channel, b'1', b'2')],
-        )?;
-        Ok(value as f32 / 10.0)
+        let value = self.get_voltage_raw(channel)?;
+        self.telemetry.voltage(value)
     }
 
     fn get_current(&mut self, channel: usize) -> Result<f32> {
-        let value = self.get(
-            CommandGet::Current,
-            [b'M', match_channel_to!(channel, b'1', b'2')],
-        )?;
-        Ok(value as f32)
+        let value = self.get_current_raw(channel)?;
+        self.telemetry.current(value as f32)
     }
 
     fn get_temperature(&mut self, channel: usize) -> Result<f32> {
-        let value = self.get(
-            CommandGet::Temperature,
-            [b'M', match_channel_to!(channel, b'1', b'2')],
-        )?;
-        Ok(value as f32)
+        let value = self.get_temperature_raw(channel)?;
+        self.telemetry.temperature(value as f32)
+    }
+}
+
+/// Build the set frame of *command*.
+fn command_frame(packet_type: PacketType, address: u8, command: Command) -> Result<PacketFrame> {
+    let ratio_frame = |ratio: f32, target: [u8; 2]| {
+        let value = convert::ratio_to_value(ratio)?;
+        PacketFrame::new_set_frame(packet_type, address, CommandSet::Value, value, target)
+    };
+    match command {
+        Command::Startup(channel) => {
+            let target = [b'M', match_channel_to!(channel, b'1', b'2', "startup")];
+            PacketFrame::new_set_frame(packet_type, address, CommandSet::Shutdown, 0, target)
+        }
+        Command::Shutdown(channel) => {
+            let target = [b'M', match_channel_to!(channel, b'1', b'2', "shutdown")];
+            PacketFrame::new_set_frame(packet_type, address, CommandSet::Shutdown, 1, target)
+        }
+        Command::Speed(channel, ratio) => {
+            ratio_frame(ratio, [b'M', match_channel_to!(channel, b'1', b'2')])
+        }
+        Command::Drive(ratio) => ratio_frame(ratio, [b'M', b'D']),
+        Command::Turn(ratio) => ratio_frame(ratio, [b'M', b'T']),
+        Command::Power(channel, ratio) => {
+            ratio_frame(ratio, [b'P', match_channel_to!(channel, b'1', b'2')])
+        }
+        Command::Ramp(channel, ratio) => {
+            ratio_frame(ratio, [b'R', match_channel_to!(channel, b'1', b'2')])
+        }
+        Command::Aux(channel, ratio) => {
+            ratio_frame(ratio, [b'Q', match_channel_to!(channel, b'1', b'2')])
+        }
     }
 }
 
@@ -0,0 +1,44 @@
+use super::{check_address, command_frame, PacketFrame, PacketType};
+use crate::error::Result;
+use crate::sabertooth2x32::Command;
+
+/// Frame of a set command built once, for sending it repeatedly with
+/// [PacketSerial::send_precomputed()](struct.PacketSerial.html#method.send_precomputed).
+#[derive(Clone, PartialEq, Debug)]
+pub struct PrecomputedCommand {
+    command: Command,
+    address: u8,
+    packet_type: PacketType,
+    frame: PacketFrame,
+}
+
+impl PrecomputedCommand {
+    /// Build the frame of *command* for the Sabertooth at *address*. The
+    /// command is checked like when it is sent by an interface.
+    pub fn new(packet_type: PacketType, address: u8, command: Command) -> Result<Self> {
+        check_address(address)?;
+        Ok(PrecomputedCommand {
+            command,
+            address,
+            packet_type,
+            frame: command_frame(packet_type, address, command)?,
+        })
+    }
+
+    pub fn command(&self) -> Command {
+        self.command
+    }
+
+    pub fn address(&self) -> u8 {
+        self.address
+    }
+
+    pub fn packet_type(&self) -> PacketType {
+        self.packet_type
+    }
+
+    /// Bytes of the frame.
+    pub fn as_bytes(&self) -> &[u8] {
+        self.frame.as_ref()
+    }
+}
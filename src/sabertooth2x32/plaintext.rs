@@ -1,20 +1,74 @@
 use std::convert::From;
+use std::fmt::{self, Write as _};
 use std::str;
+use std::thread;
+use std::time::{Duration, Instant};
 
-#[allow(unused_imports)]
-use log::debug;
-
-use super::Sabertooth2x32;
+use super::cache::ReadingCache;
+use super::clock::{Clock, NowFn, SleepFn};
+use super::drivemode::DriveMode;
+use super::echo::{self, EchoMode};
+use super::framelog::{self, Direction, FrameObserver, Protocol};
+use super::health::{self, HealthcheckMode};
+use super::hook::{self, PreSendHook, WireTransform};
+use super::latency::{LatencyObserver, LatencyStats, LatencyTracker};
+use super::linereader::{Line, LineReader};
+use super::safety::StrictSafety;
+use super::telemetry::{Telemetry, TelemetryPolicy};
+use super::zerospeed::{ZeroSpeed, ZeroSpeedState};
+use super::{check_current_limit, limit_ratio_for_current, Command, LastCommands, Sabertooth2x32};
+use crate::convert;
 use crate::error::{Error, Result};
 use crate::port::SabertoothSerial;
+use crate::power::PowerControl;
 use crate::utils;
 
 #[cfg(feature = "serialport")]
 use crate::port::sabertoothport::SabertoothPort;
 
+/// Maximum length of a command line, terminator included.
+const MAX_CMD_LEN: usize = 32;
+
+/// Command line formatted on the stack, so that sending a command does not
+/// allocate.
+struct CmdStr {
+    buf: [u8; MAX_CMD_LEN],
+    len: usize,
+}
+
+impl CmdStr {
+    fn format(args: fmt::Arguments) -> Result<CmdStr> {
+        let mut cmdstr = CmdStr {
+            buf: [0u8; MAX_CMD_LEN],
+            len: 0,
+        };
+        cmdstr.write_fmt(args).map_err(|_| {
+            let msg = format!("command longer than {} bytes", MAX_CMD_LEN);
+            Error::InvalidInput(msg)
+        })?;
+        Ok(cmdstr)
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
+
+impl fmt::Write for CmdStr {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+        if end > MAX_CMD_LEN {
+            return Err(fmt::Error);
+        }
+        self.buf[self.len..end].copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
 macro_rules! make_cmd_str {
     ($token:expr, $channel:expr, $value:expr) => {
-        format!("{}{}: {}\r\n", $token, $channel, $value)
+        CmdStr::format(format_args!("{}{}: {}\r\n", $token, $channel, $value))
     };
 }
 
@@ -25,7 +79,7 @@ macro_rules! dbg_frame {
             .unwrap_or("<decode error>")
             .trim_matches(char::from(0))
             .trim(); // avoid empty lines caused by '\n' in logs
-        debug!("{} = {}", stringify!($head), frame);
+        log_debug!("{} = {}", stringify!($head), frame);
     };
 }
 
@@ -37,43 +91,583 @@ macro_rules! dbg_frame {
 /// Interface using "Plain Text" protocol.
 pub struct PlainText<T: SabertoothSerial> {
     dev: T,
+    current_limit: Option<f32>,
+    last: LastCommands,
+    strict_replies: bool,
+    skipped_lines: u64,
+    pre_send_hook: Option<PreSendHook>,
+    wire_transform: Option<WireTransform>,
+    dirty: bool,
+    reader: LineReader,
+    range: i32,
+    telemetry: Telemetry,
+    echo: EchoMode,
+    shutdown_on_drop: bool,
+    frame_observer: Option<FrameObserver>,
+    tag: Option<u32>,
+    latency: LatencyTracker,
+    clock: Clock,
+    turnaround_delay: Duration,
+    cache: ReadingCache,
+    drive_mode: Option<DriveMode>,
+    safety: StrictSafety,
+    healthcheck: HealthcheckMode,
+    zero_speed: ZeroSpeedState,
 }
 
 #[cfg(feature = "serialport")]
 impl PlainText<SabertoothPort> {
     /// Create a default new "Plain Text" interface.
     pub fn new(port: &str) -> Result<PlainText<SabertoothPort>> {
-        Ok(PlainText {
-            dev: SabertoothPort::new(port)?,
-        })
+        Ok(PlainText::from(SabertoothPort::new(port)?))
+    }
+
+    /// Same as `new()`, then stop both motors. Note that this transmits on
+    /// construction.
+    pub fn new_stopped(port: &str) -> Result<PlainText<SabertoothPort>> {
+        PlainText::from_stopped(SabertoothPort::new(port)?)
     }
 }
 
 impl<T: SabertoothSerial> PlainText<T> {
+    /// Create an interface from the port, then stop both motors. Note that
+    /// this transmits on construction.
+    pub fn from_stopped(dev: T) -> Result<Self> {
+        let mut saber = PlainText::from(dev);
+        saber.stop_motors()?;
+        Ok(saber)
+    }
+
+    /// Same as `PacketSerial::with_current_limit()`.
+    pub fn with_current_limit(mut self, amps: f32) -> Result<Self> {
+        self.current_limit = Some(check_current_limit(amps)?);
+        Ok(self)
+    }
+
+    /// Same as `PacketSerial::with_strict_safety()`, except that the "Plain
+    /// Text" protocol cannot set the serial timeout: it is configured on the
+    /// Sabertooth, for ex. with DEScribe, and declared with
+    /// `declare_serial_timeout()`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use saberrs::sabertooth2x32::{PlainText, Sabertooth2x32};
+    /// # use saberrs::Result;
+    /// # fn example() -> Result<()> {
+    /// let mut saber = PlainText::new("/dev/ttyUSB0")?.with_strict_safety(true);
+    /// saber.declare_serial_timeout(Duration::from_millis(500))?;
+    /// saber.set_speed(1, 0.5)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_strict_safety(mut self, strict: bool) -> Self {
+        self.safety.enabled = strict;
+        self
+    }
+
+    /// Whether the strict safety mode is enabled.
+    pub fn strict_safety(&self) -> bool {
+        self.safety.enabled
+    }
+
+    /// Whether a non-zero serial timeout has been declared with
+    /// `declare_serial_timeout()`.
+    pub fn timeout_armed(&self) -> bool {
+        self.safety.timeout_armed
+    }
+
+    /// Declare the serial timeout configured on the Sabertooth, a zero
+    /// duration meaning that it is disabled. Nothing is sent: this only arms
+    /// the strict safety mode, which refuses a zero duration.
+    pub fn declare_serial_timeout(&mut self, timeout: Duration) -> Result<()> {
+        let millis = convert::timeout_to_value(timeout)?;
+        self.safety.check_timeout(millis)?;
+        self.safety.timeout_armed = millis > 0;
+        Ok(())
+    }
+
+    /// Treat every unexpected line as an error when waiting for a reply.
+    ///
+    /// By default the lines which are not the expected reply, for ex. a boot
+    /// banner or the reply for another channel, are discarded and counted (see
+    /// `skipped_lines()`), and the next lines are read until the expected reply
+    /// arrives or the timeout of the port elapses. When *strict* is `true` the
+    /// first unexpected line is an `Error::Response`.
+    pub fn with_strict_replies(mut self, strict: bool) -> Self {
+        self.strict_replies = strict;
+        self
+    }
+
+    /// Number of unexpected lines discarded so far while waiting for replies.
+    pub fn skipped_lines(&self) -> u64 {
+        self.skipped_lines
+    }
+
+    /// Register a function called with every command just before it is sent.
+    ///
+    /// The hook may let the command through, replace it, or block it, see
+    /// [PreSendAction](enum.PreSendAction.html). A replacing command is checked
+    /// like any other, and a blocked one is not sent at all but the method
+    /// called still succeeds. It replaces the previous hook, if any.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use saberrs::sabertooth2x32::{Command, PlainText, PreSendAction};
+    /// # use saberrs::Result;
+    /// # fn example() -> Result<()> {
+    /// let mut saber = PlainText::new("/dev/ttyUSB0")?;
+    /// // Never go forward.
+    /// saber.set_pre_send_hook(Box::new(|command| match command {
+    ///     Command::Speed(_, ratio) if ratio > 0.0 => PreSendAction::Block,
+    ///     _ => PreSendAction::Allow,
+    /// }));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_pre_send_hook(&mut self, hook: PreSendHook) {
+        self.pre_send_hook = Some(hook);
+    }
+
+    /// Register a function transforming the bytes of every frame just before
+    /// they are written, for ex. for adding the delimiters or the escaping
+    /// required by a bus bridge. Replies are read unchanged. It replaces the
+    /// previous transform, if any; by default the frames are written as-is.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use saberrs::sabertooth2x32::PlainText;
+    /// # use saberrs::Result;
+    /// # fn example() -> Result<()> {
+    /// let mut saber = PlainText::new("/dev/ttyUSB0")?;
+    /// // Prefix every frame with a start delimiter.
+    /// saber.set_wire_transform(Box::new(|frame| {
+    ///     let mut data = vec![0xaa];
+    ///     data.extend_from_slice(frame);
+    ///     data
+    /// }));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_wire_transform(&mut self, transform: WireTransform) {
+        self.wire_transform = Some(transform);
+    }
+
+    /// Register a function called with every frame written or read, for ex.
+    /// a [PrettyFrameLogger](struct.PrettyFrameLogger.html). Written frames
+    /// are passed once written, before the wire transform. It replaces the
+    /// previous observer, if any.
+    pub fn set_frame_observer(&mut self, observer: FrameObserver) {
+        self.frame_observer = Some(observer);
+    }
+
+    /// Run *f* with the frames it writes and reads tagged with *tag*, for
+    /// correlating them with the application in the frame observer. The
+    /// previous tag is restored afterwards, so calls can be nested.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use saberrs::sabertooth2x32::{PlainText, PrettyFrameLogger, Sabertooth2x32};
+    /// # use saberrs::Result;
+    /// # fn example() -> Result<()> {
+    /// let mut saber = PlainText::new("/dev/ttyUSB0")?;
+    /// saber.set_frame_observer(PrettyFrameLogger::to_writer(std::io::stderr()).into_observer());
+    /// // Logged as "[TX] Speed M1 fwd value=1023 ... tag=42".
+    /// saber.tagged(42, |saber| saber.set_speed(1, 0.5))?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn tagged<R, F: FnOnce(&mut Self) -> Result<R>>(&mut self, tag: u32, f: F) -> Result<R> {
+        let previous = self.tag.replace(tag);
+        let result = f(self);
+        self.tag = previous;
+        result
+    }
+
+    /// Set the range of the values exchanged with the Sabertooth, when it has
+    /// been changed from the default -2047~2047 in DEScribe. A ratio of 1.0
+    /// is then sent as *max*, and a value of *max* read as 1.0. Nothing is
+    /// sent, the new range must match the configuration of the Sabertooth.
+    ///
+    /// *max* should be between 1 and 2047.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use saberrs::sabertooth2x32::{PlainText, Sabertooth2x32};
+    /// # use saberrs::Result;
+    /// # fn example() -> Result<()> {
+    /// let mut saber = PlainText::new("/dev/ttyUSB0")?;
+    /// saber.set_range(100)?;
+    /// saber.set_speed(1, 0.5)?; // sends "M1: 50"
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_range(&mut self, max: i32) -> Result<()> {
+        convert::check_range(max)?;
+        self.range = max;
+        Ok(())
+    }
+
+    /// Reference to the underlying port.
+    pub fn port(&self) -> &T {
+        &self.dev
+    }
+
+    /// Mutable reference to the underlying port, for ex. for a setting that
+    /// the interface does not expose. Note that reading or writing directly
+    /// may desynchronize the interface from the Sabertooth, and the cached
+    /// state like `last_commands()` is not updated.
+    pub fn port_mut(&mut self) -> &mut T {
+        &mut self.dev
+    }
+
+    /// Set what to do with a value from the Sabertooth outside of its
+    /// plausible range, see [TelemetryPolicy](enum.TelemetryPolicy.html).
+    pub fn with_telemetry_policy(mut self, policy: TelemetryPolicy) -> Self {
+        self.telemetry.policy = policy;
+        self
+    }
+
+    /// Number of values from the Sabertooth clamped to their plausible range
+    /// with `TelemetryPolicy::Clamp`.
+    pub fn clamped_readings(&self) -> u64 {
+        self.telemetry.clamped
+    }
+
+    /// Set what to do with the echo of the lines on a single-wire hookup, see
+    /// [EchoMode](enum.EchoMode.html). The echo is read after each write,
+    /// before the reply if any.
+    pub fn with_echo_mode(mut self, mode: EchoMode) -> Self {
+        self.echo = mode;
+        self
+    }
+
+    /// Same as `PacketSerial::with_loopback_confirmation()`.
+    pub fn with_loopback_confirmation(self, enable: bool) -> Self {
+        self.with_echo_mode(if enable {
+            EchoMode::VerifyAndDiscard
+        } else {
+            EchoMode::None
+        })
+    }
+
+    /// Shut down both motors when the interface is dropped, for ex. when the
+    /// program exits by unwinding a panic. The motors already shut down with
+    /// `shutdown()` are left alone. Errors are ignored, they are only logged.
+    ///
+    /// Unlike a zero speed, the shutdown holds the motors in a hard brake
+    /// state until `startup()`. Note that nothing is sent if the process is
+    /// killed or aborts.
+    pub fn with_shutdown_on_drop(mut self, enable: bool) -> Self {
+        self.shutdown_on_drop = enable;
+        self
+    }
+
+    /// Get the battery voltage on the motor *channel*, or the last reading of
+    /// this channel if it is at most *max_age* old, so that several parts of
+    /// an application polling it do not each cost a request. Only successful
+    /// readings are cached.
+    pub fn get_voltage_cached(&mut self, channel: usize, max_age: Duration) -> Result<f32> {
+        let now = self.clock.now();
+        if let Some(volts) = self
+            .cache
+            .voltage(channel)
+            .and_then(|c| c.get(max_age, now))
+        {
+            return Ok(volts);
+        }
+        let volts = self.get_voltage(channel)?;
+        let now = self.clock.now();
+        if let Some(cached) = self.cache.voltage(channel) {
+            cached.store(volts, now);
+        }
+        Ok(volts)
+    }
+
+    /// Forget the cached readings, so that the next cached get sends a
+    /// request.
+    pub fn invalidate(&mut self) {
+        self.cache.invalidate();
+    }
+
+    /// Round-trip times of the requests so far.
+    pub fn latency_stats(&self) -> LatencyStats {
+        self.latency.stats
+    }
+
+    /// Register a function called with the round-trip time of every
+    /// successful request, from the end of the write of the request to the
+    /// parsing of the reply (see `LatencyStats`). It replaces the previous
+    /// observer, if any.
+    pub fn set_latency_observer(&mut self, observer: LatencyObserver) {
+        self.latency.observer = Some(observer);
+    }
+
+    /// Last values successfully sent to the Sabertooth.
+    pub fn last_commands(&self) -> &LastCommands {
+        &self.last
+    }
+
+    /// Time elapsed since the last command was sent, `None` if no command has
+    /// been sent (or since `clear_cached_state()`). Requests do not count.
+    pub fn time_since_last_command(&self) -> Option<Duration> {
+        self.last.time_since_last(self.clock.now())
+    }
+
+    /// Whether no command has been sent for more than *max*, for ex. for
+    /// stopping the motors before the serial timeout of the Sabertooth
+    /// expires when the control loop stalls. It is true if no command has
+    /// been sent at all.
+    pub fn is_stale(&self, max: Duration) -> bool {
+        match self.time_since_last_command() {
+            Some(elapsed) => elapsed > max,
+            None => true,
+        }
+    }
+
+    /// Whether the motor *channel* is believed to be shut down, from the last
+    /// `shutdown()` or `startup()` sent to it, `None` if neither was sent.
+    /// Call `clear_cached_state()` after reconnecting to or power-cycling the
+    /// Sabertooth, as the state is then unknown again.
+    pub fn is_shutdown(&self, channel: usize) -> Option<bool> {
+        self.last.shut_down(channel)
+    }
+
+    /// Declare whether the motors are driven independently or in mixed mode.
+    /// The commands of the other mode, except the ones stopping the motors,
+    /// are then logged with a warning, or refused with
+    /// `Error::Safety` in strict safety mode. The default is no mode: any
+    /// command is accepted.
+    pub fn set_drive_mode(&mut self, mode: DriveMode) {
+        self.drive_mode = Some(mode);
+    }
+
+    /// Mode set with `set_drive_mode()`, if any.
+    pub fn drive_mode(&self) -> Option<DriveMode> {
+        self.drive_mode
+    }
+
+    /// Forget the last values sent, for example when the Sabertooth has been
+    /// power-cycled.
+    pub fn clear_cached_state(&mut self) {
+        self.last = LastCommands::default();
+        self.zero_speed.clear();
+    }
+
+    /// Same as `PacketSerial::with_turnaround_delay()`.
+    pub fn with_turnaround_delay(mut self, delay: Duration) -> Self {
+        self.turnaround_delay = delay;
+        self
+    }
+
+    /// Same as `PacketSerial::set_clock()`, for the turnaround delay,
+    /// `time_since_last_command()` and `get_voltage_cached()`.
+    pub fn set_clock(&mut self, now: NowFn, sleep: SleepFn) {
+        self.clock = Clock::new(now, sleep);
+    }
+
+    /// Same as `PacketSerial::with_zero_speed()`.
+    pub fn with_zero_speed(mut self, mode: ZeroSpeed) -> Self {
+        self.zero_speed.mode = mode;
+        self
+    }
+
+    /// Set how `healthcheck()` checks the port.
+    pub fn with_healthcheck_mode(mut self, mode: HealthcheckMode) -> Self {
+        self.healthcheck = mode;
+        self
+    }
+
+    /// Same as `PacketSerial::healthcheck()`.
+    pub fn healthcheck(&mut self) -> Result<()> {
+        let res = match self.healthcheck {
+            HealthcheckMode::Query => self.get_voltage(1).map(|_| ()),
+            HealthcheckMode::WriteOnly => self
+                .write_frame(make_cmd_str!('M', '1', "getb")?.as_bytes())
+                .and_then(|()| Ok(self.dev.flush()?)),
+        };
+        res.map_err(health::wedged)
+    }
+
+    /// Same as `PacketSerial::power_cycle()`, the ramping being the only
+    /// setting sent again.
+    pub fn power_cycle<P: PowerControl + ?Sized>(
+        &mut self,
+        power: &mut P,
+        off_time: Duration,
+        boot_time: Duration,
+    ) -> Result<()> {
+        power.cycle(off_time)?;
+        thread::sleep(boot_time);
+        let last = std::mem::take(&mut self.last);
+        self.zero_speed.clear();
+        self.invalidate();
+        self.dev.clear_all()?;
+        self.reader.clear();
+        self.dirty = false;
+        for channel in 1..=2 {
+            if let Some(ratio) = last.ramp(channel) {
+                self.set_ramp(channel, ratio)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Send a raw text line and check that the Sabertooth echoes it back.
+    ///
+    /// *line* is sent as-is, including its terminator (usually `"\r\n"`). The
+    /// echo is compared to it word by word, the whitespace being ignored. A
+    /// mismatching echo is an `Error::Response`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use saberrs::sabertooth2x32::PlainText;
+    /// # use saberrs::Result;
+    /// # fn example() -> Result<()> {
+    /// let mut saber = PlainText::new("/dev/ttyUSB0")?;
+    /// saber.write_and_verify(b"M1: 1000\r\n")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn write_and_verify(&mut self, line: &[u8]) -> Result<()> {
+        let echo = self.request(line)?;
+        if words(line).ne(words(&echo)) {
+            let descr = format!(
+                "expected the echo {:?} but received {:?}",
+                String::from_utf8_lossy(line),
+                String::from_utf8_lossy(&echo)
+            );
+            return Err(Error::Response(descr));
+        }
+        Ok(())
+    }
+
+    /// Send the line `"<prefix>: <value>\r\n"`, for the text commands
+    /// without a method, for ex. those of a newer firmware.
+    ///
+    /// *prefix* is the part before the colon, like `"M1"` or `"MD"`: it must
+    /// be made of ASCII letters and digits. *value* must be within ±2047,
+    /// independently of `set_range()`. Nothing is read back, apart from the
+    /// echo of the `EchoMode`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use saberrs::sabertooth2x32::PlainText;
+    /// # use saberrs::Result;
+    /// # fn example() -> Result<()> {
+    /// let mut saber = PlainText::new("/dev/ttyUSB0")?;
+    /// saber.send_text("Q1", -500)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn send_text(&mut self, prefix: &str, value: i32) -> Result<()> {
+        if prefix.is_empty() || !prefix.bytes().all(|b| b.is_ascii_alphanumeric()) {
+            let msg = format!("invalid command prefix {:?}", prefix);
+            return Err(Error::InvalidInput(msg));
+        }
+        if !(-convert::RANGE_MAX..=convert::RANGE_MAX).contains(&value) {
+            let msg = format!(
+                "value should be -{max}~{max} (was {})",
+                value,
+                max = convert::RANGE_MAX
+            );
+            return Err(Error::InvalidInput(msg));
+        }
+        let cmdstr = make_cmd_str!(prefix, "", value)?;
+        self.write_frame(cmdstr.as_bytes())
+    }
+
+    fn limit_current(&mut self, channel: usize, ratio: f32) -> Result<f32> {
+        match self.current_limit {
+            Some(limit) if ratio != 0.0 => {
+                convert::ratio_to_value(ratio)?;
+                let current = self.get_current(channel)?;
+                Ok(limit_ratio_for_current(ratio, current, limit))
+            }
+            _ => Ok(ratio),
+        }
+    }
+
+    /// Write a frame. If a previous frame was not completely written, its line
+    /// is terminated first so that it does not merge with this one.
     fn write_frame(&mut self, txdata: &[u8]) -> Result<()> {
+        let timeout = self.dev.timeout();
+        if self.dirty {
+            let resync = hook::transform(&mut self.wire_transform, b"\r\n");
+            utils::write_frame(&mut self.dev, &resync, timeout).map_err(|(_, e)| e)?;
+            self.dirty = false;
+            self.notify_tx(b"\r\n");
+            echo::consume(self.echo, &mut self.dev, &resync)?;
+        }
         dbg_frame!(tx, txdata);
-        Ok(self.dev.write_all(txdata)?)
+        let wire = hook::transform(&mut self.wire_transform, txdata);
+        utils::write_frame(&mut self.dev, &wire, timeout).map_err(|(written, e)| {
+            self.dirty = written > 0;
+            e
+        })?;
+        self.notify_tx(txdata);
+        echo::consume(self.echo, &mut self.dev, &wire)
     }
 
-    fn read_response(&mut self, rxdata: &mut [u8]) -> Result<usize> {
-        const ENDFLAG: u8 = b'\n';
+    fn notify_tx(&mut self, txdata: &[u8]) {
+        framelog::notify(
+            &mut self.frame_observer,
+            Direction::Tx,
+            Protocol::PlainText,
+            txdata,
+            self.tag,
+        );
+    }
 
-        let mut bytebuf = [0u8; 1];
-        let mut count: usize = 0;
+    fn read_line(&mut self) -> Result<Line> {
+        let timeout = self.dev.timeout();
+        let line = self.reader.read_line(&mut self.dev, timeout)?;
+        dbg_frame!(rx, &line);
+        framelog::notify(
+            &mut self.frame_observer,
+            Direction::Rx,
+            Protocol::PlainText,
+            &line,
+            self.tag,
+        );
+        Ok(line)
+    }
 
-        for byte in &mut rxdata[..] {
-            if self.dev.read(&mut bytebuf)? == 1 {
-                *byte = bytebuf[0];
-                count += 1;
-                if bytebuf[0] == ENDFLAG {
-                    break;
-                }
-            } else {
-                break;
-            }
+    fn send_command(&mut self, command: Command) -> Result<()> {
+        if let Some(command) = hook::apply(&mut self.pre_send_hook, command) {
+            self.safety.check_armed(command)?;
+            self.safety
+                .check_state(command, &self.last, self.drive_mode)?;
+            self.write_command(command)?;
+            self.last.record_sent(command, self.clock.now());
         }
+        Ok(())
+    }
 
-        Ok(count)
+    fn write_command(&mut self, command: Command) -> Result<()> {
+        match command {
+            Command::Startup(channel) => {
+                let ch = match_channel_to!(channel, '1', '2', "startup");
+                self.write_frame(make_cmd_str!('M', ch, "startup")?.as_bytes())
+            }
+            Command::Shutdown(channel) => {
+                let ch = match_channel_to!(channel, '1', '2', "shutdown");
+                self.write_frame(make_cmd_str!('M', ch, "shutdown")?.as_bytes())
+            }
+            Command::Speed(channel, ratio) => self.send_ratio_to_channel('M', channel, ratio),
+            Command::Drive(ratio) => self.send_ratio('M', 'D', ratio),
+            Command::Turn(ratio) => self.send_ratio('M', 'T', ratio),
+            Command::Power(channel, ratio) => self.send_ratio_to_channel('P', channel, ratio),
+            Command::Ramp(channel, ratio) => self.send_ratio_to_channel('R', channel, ratio),
+            Command::Aux(channel, ratio) => self.send_ratio_to_channel('Q', channel, ratio),
+        }
     }
 
     fn send_ratio_to_channel(&mut self, token: char, channel: usize, ratio: f32) -> Result<()> {
@@ -82,134 +676,307 @@ impl<T: SabertoothSerial> PlainText<T> {
     }
 
     fn send_ratio(&mut self, token: char, channel: char, ratio: f32) -> Result<()> {
-        let value = utils::ratio_to_value(ratio)?;
-        let cmdstr = make_cmd_str!(token, channel, value);
-        let buf = cmdstr.as_bytes();
-        self.write_frame(buf)
+        let value = convert::ratio_to_value_in_range(ratio, self.range)?;
+        let cmdstr = make_cmd_str!(token, channel, value)?;
+        self.write_frame(cmdstr.as_bytes())
+    }
+
+    /// Send a frame and read the first line of the reply. Data received
+    /// before the request is discarded.
+    fn request(&mut self, txdata: &[u8]) -> Result<Line> {
+        self.send_request(txdata)?;
+        self.read_line()
     }
 
-    fn request(&mut self, txdata: &[u8], rxdata: &mut [u8]) -> Result<usize> {
+    /// Send a frame, discarding the data received before, and wait for the
+    /// turnaround delay.
+    fn send_request(&mut self, txdata: &[u8]) -> Result<()> {
         self.dev.clear_all()?;
+        self.reader.clear();
         self.write_frame(txdata)?;
-        let read_len = self.read_response(rxdata)?;
-        dbg_frame!(rx, rxdata);
-        Ok(read_len)
+        let clock = &mut self.clock;
+        utils::turnaround(&mut self.dev, self.turnaround_delay, |d| clock.sleep(d))
+    }
+
+    /// Same as `get_value()` for both channels, the two requests being sent
+    /// before reading the replies. Each reply is matched by its channel.
+    fn get_values_both(&mut self, token: char, req: &str) -> Result<(i32, i32)> {
+        let requests = [
+            make_cmd_str!(token, '1', req)?,
+            make_cmd_str!(token, '2', req)?,
+        ];
+        let deadline = Instant::now() + self.dev.timeout();
+        self.dev.clear_all()?;
+        self.reader.clear();
+        for request in requests.iter() {
+            self.write_frame(request.as_bytes())?;
+        }
+        let clock = &mut self.clock;
+        utils::turnaround(&mut self.dev, self.turnaround_delay, |d| clock.sleep(d))?;
+        let start = Instant::now();
+        let mut values = [None, None];
+        let mut skipped = 0;
+        loop {
+            let resp = self.read_line()?;
+            let splitted = split_response(&resp);
+            if let Ok(SplitResponse(t, c, None, value)) = splitted {
+                let slot = match c {
+                    '1' => Some(&mut values[0]),
+                    '2' => Some(&mut values[1]),
+                    _ => None,
+                };
+                match slot {
+                    Some(slot) if t == token && slot.is_none() => {
+                        *slot = Some(value);
+                        if let [Some(m1), Some(m2)] = values {
+                            self.latency.record(start.elapsed());
+                            return Ok((m1, m2));
+                        }
+                        continue;
+                    }
+                    _ => {}
+                }
+            }
+
+            let expected = format!("{}<channel>: <value>", token);
+            if self.strict_replies {
+                splitted?;
+                let descr = format!(
+                    "expected the form {:?} but received {:?}",
+                    &expected,
+                    String::from_utf8_lossy(&resp)
+                );
+                return Err(Error::Response(descr));
+            }
+
+            self.skipped_lines += 1;
+            skipped += 1;
+            if Instant::now() >= deadline {
+                let descr = format!(
+                    "no replies of the form {:?} before the timeout ({} lines skipped)",
+                    &expected, skipped
+                );
+                return Err(Error::Response(descr));
+            }
+        }
     }
 
     fn get_value(&mut self, token: char, ch: char, prefix: Option<char>, req: &str) -> Result<i32> {
-        let cmdstr = make_cmd_str!(token, ch, req);
-        let mut rxbuf = [0u8; 32];
-        let size = self.request(cmdstr.as_bytes(), &mut rxbuf)?;
-        let resp = &rxbuf[..size];
-        let splitted = split_response(&resp)?;
-        if splitted.0 != token || splitted.1 != ch || splitted.2 != prefix {
-            let expected = format!("{}{}: {}<value>", token, splitted.1, prefix.unwrap_or(' '));
-            let received = String::from_utf8(resp.to_vec()).unwrap_or(format!("{:?}", resp));
-            let descr = format!(
-                "expected the form {:?} but received {:?}",
-                &expected, received
-            );
-            return Err(Error::Response(descr));
+        let cmdstr = make_cmd_str!(token, ch, req)?;
+        let deadline = Instant::now() + self.dev.timeout();
+        self.send_request(cmdstr.as_bytes())?;
+        let start = Instant::now();
+        let mut resp = self.read_line()?;
+        let mut skipped = 0;
+        loop {
+            let splitted = split_response(&resp);
+            if let Ok(SplitResponse(t, c, p, value)) = splitted {
+                if t == token && c == ch && p == prefix {
+                    self.latency.record(start.elapsed());
+                    return Ok(value);
+                }
+            }
+
+            let expected = format!("{}{}: {}<value>", token, ch, prefix.unwrap_or(' '));
+            if self.strict_replies {
+                splitted?;
+                let received =
+                    String::from_utf8(resp.to_vec()).unwrap_or(format!("{:?}", &resp[..]));
+                let descr = format!(
+                    "expected the form {:?} but received {:?}",
+                    &expected, received
+                );
+                return Err(Error::Response(descr));
+            }
+
+            self.skipped_lines += 1;
+            skipped += 1;
+            if Instant::now() >= deadline {
+                let descr = format!(
+                    "no reply of the form {:?} before the timeout ({} lines skipped)",
+                    &expected, skipped
+                );
+                return Err(Error::Response(descr));
+            }
+            resp = self.read_line()?;
         }
-        Ok(splitted.3)
     }
 }
 
-// should work with SabertoothPort
+/// Create an interface with default settings from the port.
 impl<T: SabertoothSerial> From<T> for PlainText<T> {
     fn from(dev: T) -> Self {
-        PlainText { dev }
+        PlainText {
+            dev,
+            current_limit: None,
+            last: LastCommands::default(),
+            strict_replies: false,
+            skipped_lines: 0,
+            pre_send_hook: None,
+            wire_transform: None,
+            dirty: false,
+            reader: LineReader::default(),
+            range: convert::RANGE_MAX,
+            telemetry: Telemetry::default(),
+            echo: EchoMode::default(),
+            shutdown_on_drop: false,
+            frame_observer: None,
+            tag: None,
+            latency: LatencyTracker::default(),
+            clock: Clock::default(),
+            turnaround_delay: Duration::from_secs(0),
+            cache: ReadingCache::default(),
+            drive_mode: None,
+            safety: StrictSafety::default(),
+            healthcheck: HealthcheckMode::default(),
+            zero_speed: ZeroSpeedState::default(),
+        }
     }
 }
 
-// should work with SabertoothPortShared
+/// Create an interface from a clone of a shared port, for ex. a
+/// `SabertoothPortShared`, so that several interfaces may use the same port.
+///
+/// The interfaces do not know about each other: each one has its own cached
+/// state (`last_commands()`, the buffered reply lines), and a request clears the input
+/// of the port, discarding data another interface may be waiting for. Using
+/// them from several threads at once may mix their frames and replies.
 impl<T> From<&T> for PlainText<T>
 where
     T: SabertoothSerial + Clone,
 {
     fn from(dev: &T) -> Self {
-        PlainText {
-            dev: (*dev).clone(),
+        PlainText::from((*dev).clone())
+    }
+}
+
+impl<T: SabertoothSerial> Drop for PlainText<T> {
+    fn drop(&mut self) {
+        if !self.shutdown_on_drop {
+            return;
+        }
+        for channel in 1..=2 {
+            if self.last.shut_down(channel) != Some(true) {
+                if let Err(e) = self.shutdown(channel) {
+                    log_warn!("Shutdown of M{} on drop failed: {}", channel, e);
+                }
+            }
         }
     }
 }
 
 impl<T: SabertoothSerial> Sabertooth2x32 for PlainText<T> {
     fn startup(&mut self, channel: usize) -> Result<()> {
-        let ch = match_channel_to!(channel, '1', '2');
-        let cmdstr = make_cmd_str!('M', ch, "startup");
-        self.write_frame(cmdstr.as_bytes())
+        self.send_command(Command::Startup(channel))?;
+        self.zero_speed.forget(channel);
+        Ok(())
     }
 
     fn shutdown(&mut self, channel: usize) -> Result<()> {
-        let ch = match_channel_to!(channel, '1', '2');
-        let cmdstr = make_cmd_str!('M', ch, "shutdown");
-        self.dev.write_all(cmdstr.as_bytes())?;
+        self.send_command(Command::Shutdown(channel))?;
+        self.zero_speed.forget(channel);
         Ok(())
     }
 
     fn set_speed(&mut self, channel: usize, ratio: f32) -> Result<()> {
-        self.send_ratio_to_channel('M', channel, ratio)
+        let ratio = self.limit_current(channel, ratio)?;
+        for &command in self.zero_speed.commands(channel, ratio).iter().flatten() {
+            self.send_command(command)?;
+            self.zero_speed.sent(command);
+            if let Command::Shutdown(_) = command {
+                // The motor is stopped, resending the last speed must not
+                // restart it.
+                self.last.record(Command::Speed(channel, 0.0));
+            }
+        }
+        Ok(())
     }
 
     fn get_speed(&mut self, channel: usize) -> Result<f32> {
         let ch = match_channel_to!(channel, '1', '2');
         let value = self.get_value('M', ch, None, "get")?;
-        Ok(utils::value_to_ratio(value))
+        self.telemetry.ratio("speed", value, self.range)
+    }
+
+    /// Send the requests of both motors back-to-back, then read the replies
+    /// in any order.
+    fn get_speed_both(&mut self) -> Result<(f32, f32)> {
+        let (m1, m2) = self.get_values_both('M', "get")?;
+        Ok((
+            self.telemetry.ratio("speed", m1, self.range)?,
+            self.telemetry.ratio("speed", m2, self.range)?,
+        ))
     }
 
     fn set_drive(&mut self, ratio: f32) -> Result<()> {
-        self.send_ratio('M', 'D', ratio)
+        self.send_command(Command::Drive(ratio))
     }
 
     fn set_turn(&mut self, ratio: f32) -> Result<()> {
-        self.send_ratio('M', 'T', ratio)
+        self.send_command(Command::Turn(ratio))
     }
 
     fn set_power(&mut self, channel: usize, ratio: f32) -> Result<()> {
-        self.send_ratio_to_channel('P', channel, ratio)
+        self.send_command(Command::Power(channel, ratio))
     }
 
     fn get_power(&mut self, channel: usize) -> Result<f32> {
         let ch = match_channel_to!(channel, '1', '2');
         let value = self.get_value('P', ch, None, "get")?;
-        Ok(utils::value_to_ratio(value))
+        self.telemetry.ratio("power", value, self.range)
     }
 
     fn set_ramp(&mut self, channel: usize, ratio: f32) -> Result<()> {
-        self.send_ratio_to_channel('R', channel, ratio)
+        self.send_command(Command::Ramp(channel, ratio))
     }
 
     fn set_aux(&mut self, channel: usize, ratio: f32) -> Result<()> {
-        self.send_ratio_to_channel('Q', channel, ratio)
+        self.send_command(Command::Aux(channel, ratio))
+    }
+
+    fn check_commands(&self, commands: &[Command]) -> Result<()> {
+        self.safety
+            .check_commands(commands, &self.last, &self.zero_speed, self.drive_mode)
     }
 
     fn get_voltage(&mut self, channel: usize) -> Result<f32> {
         let ch = match_channel_to!(channel, '1', '2');
         let value = self.get_value('M', ch, Some('B'), "getb")?;
-        Ok(value as f32 * 0.1f32)
+        self.telemetry.voltage(value)
     }
 
     fn get_current(&mut self, channel: usize) -> Result<f32> {
         let ch = match_channel_to!(channel, '1', '2');
         let value = self.get_value('M', ch, Some('C'), "getc")?;
-        Ok(value as f32 * 0.1f32)
+        self.telemetry.current(value as f32 * 0.1f32)
     }
 
     fn get_temperature(&mut self, channel: usize) -> Result<f32> {
         let ch = match_channel_to!(channel, '1', '2');
         let value = self.get_value('M', ch, Some('T'), "gett")?;
-        Ok(value as f32)
+        self.telemetry.temperature(value as f32)
     }
 }
 
+/// Iterate over the whitespace separated words of a line.
+fn words(line: &[u8]) -> impl Iterator<Item = &[u8]> {
+    line.split(|&b| b.is_ascii_whitespace() || b == 0)
+        .filter(|word| !word.is_empty())
+}
+
 /// (token, channel, Options<prefix>, value)
 /// ex.: response: b"M1: C-23" -> ('M', '1', Some('C'), -23)
 #[derive(PartialEq, Debug)]
-struct SplitResponse(char, char, Option<char>, i32);
+pub(crate) struct SplitResponse(char, char, Option<char>, i32);
 
 /// Split a response into its components.
-fn split_response(rxdata: &[u8]) -> Result<SplitResponse> {
+///
+/// The expected form is `<token><channel>: [prefix][value]`, with optional
+/// whitespace after ':' and after the prefix. The value is a decimal integer
+/// with an optional leading '-', or a hexadecimal one after "0x", for ex.
+/// "M1: 1256", "M2: C-34", "M1:T -12", "M1: B240" or "M1: -0x4E8". Anything
+/// but whitespace after the value is an error.
+pub(crate) fn split_response(rxdata: &[u8]) -> Result<SplitResponse> {
     // Get the a &str. ASCII is expected
     let resp = match str::from_utf8(rxdata) {
         Ok(r) => r,
@@ -237,19 +1004,48 @@ fn split_response(rxdata: &[u8]) -> Result<SplitResponse> {
         .skip_while(|c| !c.is_ascii_alphanumeric() && *c != '-')
         .peekable();
 
-    // Get the prefix.
+    // Get the prefix, which may be followed by whitespace.
     let prefix = if resp_iter.peek().ok_or_else(error)?.is_ascii_alphabetic() {
         resp_iter.next()
     } else {
         None
     };
+    let mut resp_iter = resp_iter.skip_while(|c| c.is_ascii_whitespace()).peekable();
 
-    // Get the value.
-    let value: i32 = resp_iter
-        .take_while(|c| c.is_ascii_digit() || *c == '-')
-        .collect::<String>()
-        .parse::<i32>()
+    // Get the value, copied on the stack for parsing it. The longest i32 has
+    // 11 characters, a longer value would not parse anyway. Some firmwares
+    // reply in hexadecimal, with a "0x" prefix which is not copied.
+    let mut digits = [0u8; 12];
+    let mut len = 0;
+    let mut radix = 10;
+    while let Some(&c) = resp_iter.peek() {
+        let accepted = match c {
+            '-' => len == 0 && radix == 10,
+            'x' | 'X' => radix == 10 && matches!(&digits[..len], b"0" | b"-0"),
+            _ if radix == 16 => c.is_ascii_hexdigit(),
+            _ => c.is_ascii_digit(),
+        };
+        if !accepted {
+            break;
+        }
+        resp_iter.next();
+        if c == 'x' || c == 'X' {
+            radix = 16;
+            len -= 1;
+            continue;
+        }
+        if len == digits.len() {
+            return Err(error());
+        }
+        digits[len] = c as u8;
+        len += 1;
+    }
+    if resp_iter.any(|c| !c.is_ascii_whitespace() && c != '\0') {
+        return Err(error());
+    }
+    let value = str::from_utf8(&digits[..len])
         .ok()
+        .and_then(|digits| i32::from_str_radix(digits, radix).ok())
         .ok_or_else(error)?;
 
     Ok(SplitResponse(token, channel, prefix, value))
@@ -282,4 +1078,61 @@ mod tests {
             Some(SplitResponse('S', '2', None, -52))
         );
     }
+
+    #[test]
+    fn test_split_response_signed() {
+        assert_eq!(
+            split_response(b"M1: T-12\r\n").ok(),
+            Some(SplitResponse('M', '1', Some('T'), -12))
+        );
+        assert_eq!(
+            split_response(b"M2: T -12\r\n").ok(),
+            Some(SplitResponse('M', '2', Some('T'), -12))
+        );
+        assert_eq!(
+            split_response(b"M1:T 7\r\n").ok(),
+            Some(SplitResponse('M', '1', Some('T'), 7))
+        );
+        assert_eq!(
+            split_response(b"M1: -0\r\n").ok(),
+            Some(SplitResponse('M', '1', None, 0))
+        );
+        assert_eq!(
+            split_response(b"M1: T-0\r\n").ok(),
+            Some(SplitResponse('M', '1', Some('T'), 0))
+        );
+        assert!(split_response(b"M1: T- 12\r\n").is_err());
+        assert!(split_response(b"M1: T\r\n").is_err());
+    }
+
+    #[test]
+    fn test_split_response_hexadecimal() {
+        assert_eq!(
+            split_response(b"M1: 0x4E8\r\n").ok(),
+            Some(SplitResponse('M', '1', None, 1256))
+        );
+        assert_eq!(
+            split_response(b"M2: -0X7ff\r\n").ok(),
+            Some(SplitResponse('M', '2', None, -2047))
+        );
+        assert_eq!(
+            split_response(b"M1: B0xF0\r\n").ok(),
+            Some(SplitResponse('M', '1', Some('B'), 240))
+        );
+        assert_eq!(
+            split_response(b"M1: 0\r\n").ok(),
+            Some(SplitResponse('M', '1', None, 0))
+        );
+        // Only a leading zero makes a prefix, and hexadecimal digits need it.
+        for response in [&b"M1: 12ab\r\n"[..], b"M1: 10x4\r\n", b"M1: 0x-5\r\n"].iter() {
+            match split_response(response) {
+                Err(Error::Response(_)) => {}
+                res => panic!("Expected a response error, got {:?}", res),
+            }
+        }
+        assert!(split_response(b"M1: 0x\r\n").is_err());
+        assert!(split_response(b"M1: 0x1FFFFFFFF\r\n").is_err());
+        assert!(split_response(b"M1: 12 5\r\n").is_err());
+        assert!(split_response(b"M1: 5-\r\n").is_err());
+    }
 }
@@ -0,0 +1,113 @@
+use super::Sabertooth2x32;
+use crate::error::{Error, Result};
+
+/// Wrapper driving the motors with named speeds, for ex. the "slow",
+/// "medium" and "fast" buttons of a user interface.
+///
+/// Each preset maps a name to a ratio between -1.0 and 1.0, applied with
+/// `drive_m1_preset()`, `drive_m2_preset()`, `drive_preset()` or
+/// `turn_preset()`. Applying an unknown name returns `Error::InvalidInput`
+/// without sending anything.
+///
+/// # Example
+///
+/// ```
+/// use saberrs::sabertooth2x32::{PacketSerial, SpeedPresets};
+/// # use saberrs::Result;
+/// # fn example() -> Result<()> {
+/// let saber = PacketSerial::new("/dev/ttyUSB0")?;
+/// let mut saber = SpeedPresets::new(saber);
+/// saber.insert("slow", 0.2)?;
+/// saber.insert("fast", 0.8)?;
+/// saber.drive_m1_preset("slow")?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct SpeedPresets<S: Sabertooth2x32> {
+    inner: S,
+    presets: Vec<(String, f32)>,
+}
+
+impl<S: Sabertooth2x32> SpeedPresets<S> {
+    /// Wrap an interface, without any preset.
+    pub fn new(inner: S) -> Self {
+        SpeedPresets {
+            inner,
+            presets: Vec::new(),
+        }
+    }
+
+    /// Register the preset *name*, replacing any preset of the same name.
+    /// *ratio* must be between -1.0 and 1.0.
+    pub fn insert(&mut self, name: &str, ratio: f32) -> Result<()> {
+        if !(-1.0..=1.0).contains(&ratio) {
+            return Err(Error::InvalidInput(format!(
+                "preset ratio {} is out of range -1.0~1.0",
+                ratio
+            )));
+        }
+        match self.presets.iter_mut().find(|(n, _)| n == name) {
+            Some(preset) => preset.1 = ratio,
+            None => self.presets.push((name.to_string(), ratio)),
+        }
+        Ok(())
+    }
+
+    /// Unregister the preset *name*, returning its ratio if it existed.
+    pub fn remove(&mut self, name: &str) -> Option<f32> {
+        let index = self.presets.iter().position(|(n, _)| n == name)?;
+        Some(self.presets.remove(index).1)
+    }
+
+    /// Ratio of the preset *name*, if it exists.
+    pub fn preset(&self, name: &str) -> Option<f32> {
+        self.presets
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|&(_, ratio)| ratio)
+    }
+
+    /// Names of the registered presets, in registration order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.presets.iter().map(|(n, _)| n.as_str())
+    }
+
+    /// Set the speed of the motor 1 to the preset *name*.
+    pub fn drive_m1_preset(&mut self, name: &str) -> Result<()> {
+        let ratio = self.lookup(name)?;
+        self.inner.set_speed(1, ratio)
+    }
+
+    /// Set the speed of the motor 2 to the preset *name*.
+    pub fn drive_m2_preset(&mut self, name: &str) -> Result<()> {
+        let ratio = self.lookup(name)?;
+        self.inner.set_speed(2, ratio)
+    }
+
+    /// Set the drive value of the mixed mode to the preset *name*.
+    pub fn drive_preset(&mut self, name: &str) -> Result<()> {
+        let ratio = self.lookup(name)?;
+        self.inner.set_drive(ratio)
+    }
+
+    /// Set the turn value of the mixed mode to the preset *name*.
+    pub fn turn_preset(&mut self, name: &str) -> Result<()> {
+        let ratio = self.lookup(name)?;
+        self.inner.set_turn(ratio)
+    }
+
+    /// Access the wrapped interface, for sending other commands.
+    pub fn inner_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Return the wrapped interface.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn lookup(&self, name: &str) -> Result<f32> {
+        self.preset(name)
+            .ok_or_else(|| Error::InvalidInput(format!("unknown speed preset \"{}\"", name)))
+    }
+}
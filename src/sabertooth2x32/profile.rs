@@ -0,0 +1,315 @@
+use std::thread;
+use std::time::Duration;
+
+use super::Sabertooth2x32;
+use crate::error::{Error, Result};
+
+/// Limits of a [Trapezoid](struct.Trapezoid.html) profile, and control period
+/// of [ramp_to()](fn.ramp_to.html).
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Limits {
+    /// Maximum change of the output per second.
+    pub velocity: f32,
+    /// Maximum increase of the rate of change of the output per second, per
+    /// second, at the start of the move.
+    pub accel: f32,
+    /// Maximum decrease of the rate of change of the output per second, per
+    /// second, at the end of the move.
+    pub decel: f32,
+    /// Time between two setpoints.
+    pub period: Duration,
+}
+
+/// Phase of a [Trapezoid](struct.Trapezoid.html) profile.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Phase {
+    /// The rate of change of the output grows at *accel*.
+    Accel,
+    /// The output changes at *velocity*.
+    Cruise,
+    /// The rate of change of the output shrinks at *decel*, down to zero at
+    /// the target.
+    Decel,
+    /// The output holds the target.
+    Done,
+}
+
+/// Trapezoidal profile of an output, from its current value to a target.
+///
+/// The rate of change of the output follows a trapezoid: it grows at *accel*
+/// up to *velocity*, stays there while cruising, then shrinks at *decel* so
+/// that the output arrives at the target without overshooting. When the move
+/// is too short for reaching *velocity*, there is no cruise phase and the
+/// rate follows a triangle. A target of the opposite sign is reached through
+/// zero like any other, in a single move.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use saberrs::sabertooth2x32::profile::Trapezoid;
+/// # use saberrs::Result;
+/// # fn example() -> Result<()> {
+/// let mut profile = Trapezoid::new(0.0, 0.5, 1.0, 2.0, 2.0)?;
+/// while let Some(output) = profile.next(Duration::from_millis(100)) {
+///     println!("{:?} {}", profile.phase(), output);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Trapezoid {
+    start: f32,
+    target: f32,
+    accel: f32,
+    decel: f32,
+    // Highest rate of change reached, *velocity* unless the profile is a
+    // triangle.
+    peak: f32,
+    // Ends of the acceleration and cruise phases, and of the profile, in
+    // seconds.
+    accel_end: f32,
+    cruise_end: f32,
+    end: f32,
+    elapsed: f32,
+    output: f32,
+}
+
+impl Trapezoid {
+    /// Create a profile from *current* to *target*, both between -1.0 and
+    /// 1.0, with the limits *velocity*, *accel* and *decel* (see
+    /// [Limits](struct.Limits.html)), which must be positive.
+    pub fn new(current: f32, target: f32, velocity: f32, accel: f32, decel: f32) -> Result<Self> {
+        for &(name, value) in &[("current output", current), ("target", target)] {
+            if !(-1.0..=1.0).contains(&value) {
+                let msg = format!("{} {} is out of range -1.0~1.0", name, value);
+                return Err(Error::InvalidInput(msg));
+            }
+        }
+        let limits = [
+            ("velocity", velocity),
+            ("acceleration", accel),
+            ("deceleration", decel),
+        ];
+        for &(name, value) in &limits {
+            if !(value.is_finite() && value > 0.0) {
+                let msg = format!("{} {} is not a positive number", name, value);
+                return Err(Error::InvalidInput(msg));
+            }
+        }
+        let distance = (target - current).abs();
+        let full = velocity * velocity / 2.0 * (1.0 / accel + 1.0 / decel);
+        let (peak, cruise) = if full > distance {
+            (
+                (2.0 * distance * accel * decel / (accel + decel)).sqrt(),
+                0.0,
+            )
+        } else {
+            (velocity, (distance - full) / velocity)
+        };
+        let accel_end = peak / accel;
+        let cruise_end = accel_end + cruise;
+        Ok(Trapezoid {
+            start: current,
+            target,
+            accel,
+            decel,
+            peak,
+            accel_end,
+            cruise_end,
+            end: cruise_end + peak / decel,
+            elapsed: 0.0,
+            output: current,
+        })
+    }
+
+    /// Current output of the profile.
+    pub fn output(&self) -> f32 {
+        self.output
+    }
+
+    /// Target of the profile.
+    pub fn target(&self) -> f32 {
+        self.target
+    }
+
+    /// Whether the output reached the target.
+    pub fn is_done(&self) -> bool {
+        self.elapsed >= self.end
+    }
+
+    /// Phase of the profile at the current output.
+    pub fn phase(&self) -> Phase {
+        if self.is_done() {
+            Phase::Done
+        } else if self.elapsed < self.accel_end {
+            Phase::Accel
+        } else if self.elapsed < self.cruise_end {
+            Phase::Cruise
+        } else {
+            Phase::Decel
+        }
+    }
+
+    /// Advance the profile by *dt* and return the new output, or `None` if
+    /// the target was already reached. The last output returned is exactly
+    /// the target.
+    pub fn next(&mut self, dt: Duration) -> Option<f32> {
+        if self.is_done() {
+            return None;
+        }
+        self.elapsed += dt.as_secs_f32();
+        self.output = if self.is_done() {
+            self.target
+        } else {
+            let t = self.elapsed;
+            let moved = match self.phase() {
+                Phase::Accel => self.accel * t * t / 2.0,
+                Phase::Cruise => {
+                    self.peak * self.accel_end / 2.0 + self.peak * (t - self.accel_end)
+                }
+                _ => {
+                    let left = self.end - t;
+                    (self.target - self.start).abs() - self.decel * left * left / 2.0
+                }
+            };
+            self.start + (self.target - self.start).signum() * moved
+        };
+        Some(self.output)
+    }
+
+    /// Time left until the output reaches the target.
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f32((self.end - self.elapsed).max(0.0))
+    }
+}
+
+/// Ramp the speed of the motor *channel* to *target* with a
+/// [Trapezoid](struct.Trapezoid.html) profile, starting from the speed
+/// reported by the Sabertooth. Block until the target is sent, sending a
+/// setpoint every `limits.period`.
+pub fn ramp_to<S: Sabertooth2x32 + ?Sized>(
+    saber: &mut S,
+    channel: usize,
+    target: f32,
+    limits: Limits,
+) -> Result<()> {
+    ramp_to_with_sleep(saber, channel, target, limits, thread::sleep)
+}
+
+/// Same as [ramp_to()](fn.ramp_to.html), waiting between the setpoints with
+/// *sleep* instead of `std::thread::sleep()`, for ex. to run on a simulated
+/// clock.
+pub fn ramp_to_with_sleep<S, F>(
+    saber: &mut S,
+    channel: usize,
+    target: f32,
+    limits: Limits,
+    mut sleep: F,
+) -> Result<()>
+where
+    S: Sabertooth2x32 + ?Sized,
+    F: FnMut(Duration),
+{
+    let current = saber.get_speed(channel)?;
+    let mut profile = Trapezoid::new(current, target, limits.velocity, limits.accel, limits.decel)?;
+    while let Some(output) = profile.next(limits.period) {
+        saber.set_speed(channel, output)?;
+        if !profile.is_done() {
+            sleep(limits.period);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outputs(mut profile: Trapezoid, dt_ms: u64) -> Vec<(Phase, f32)> {
+        let mut outputs = Vec::new();
+        while let Some(output) = profile.next(Duration::from_millis(dt_ms)) {
+            outputs.push((profile.phase(), output));
+        }
+        outputs
+    }
+
+    fn assert_outputs(expected: &[f32], outputs: &[(Phase, f32)]) {
+        assert_eq!(expected.len(), outputs.len(), "{:?}", outputs);
+        for (e, (_, o)) in expected.iter().zip(outputs) {
+            assert!((e - o).abs() < 1e-5, "{:?} != {:?}", expected, outputs);
+        }
+    }
+
+    #[test]
+    fn trapezoid_cruise() {
+        let profile = Trapezoid::new(0.0, 1.0, 1.0, 4.0, 2.0).unwrap();
+        assert_eq!(Phase::Accel, profile.phase());
+        assert_eq!(Duration::from_millis(1375), profile.duration());
+        let cruise = outputs(profile, 125);
+        assert_outputs(
+            &[
+                0.03125, 0.125, 0.25, 0.375, 0.5, 0.625, 0.75, 0.859375, 0.9375, 0.984375, 1.0,
+            ],
+            &cruise,
+        );
+        let phases: Vec<Phase> = cruise.iter().map(|&(phase, _)| phase).collect();
+        assert_eq!(
+            vec![
+                Phase::Accel,
+                Phase::Cruise,
+                Phase::Cruise,
+                Phase::Cruise,
+                Phase::Cruise,
+                Phase::Cruise,
+                Phase::Decel,
+                Phase::Decel,
+                Phase::Decel,
+                Phase::Decel,
+                Phase::Done,
+            ],
+            phases
+        );
+        // The output changes at the velocity limit while cruising.
+        for pair in cruise[1..6].windows(2) {
+            assert!((pair[1].1 - pair[0].1 - 0.125).abs() < 1e-5, "{:?}", pair);
+        }
+    }
+
+    #[test]
+    fn trapezoid_shapes() {
+        // Too short for reaching the velocity: a triangle.
+        let profile = Trapezoid::new(-0.2, 0.0, 1.0, 2.0, 2.0).unwrap();
+        assert!((profile.duration().as_secs_f32() - 0.6324555).abs() < 1e-5);
+        let triangle = outputs(profile, 100);
+        assert!(triangle.iter().all(|&(phase, _)| phase != Phase::Cruise));
+        assert_outputs(
+            &[-0.19, -0.16, -0.11, -0.0540356, -0.0175445, -0.0010534, 0.0],
+            &triangle,
+        );
+
+        // Reversal through zero, in a single move.
+        let profile = Trapezoid::new(0.5, -0.3, 1.0, 10.0, 10.0).unwrap();
+        assert!((profile.duration().as_secs_f32() - 0.9).abs() < 1e-5);
+        let reversal = outputs(profile, 100);
+        assert_outputs(
+            &[0.45, 0.35, 0.25, 0.15, 0.05, -0.05, -0.15, -0.25, -0.3],
+            &reversal,
+        );
+
+        let mut profile = Trapezoid::new(0.4, 0.4, 1.0, 1.0, 1.0).unwrap();
+        assert_eq!(Phase::Done, profile.phase());
+        assert_eq!(None, profile.next(Duration::from_millis(100)));
+        assert_eq!(Duration::from_secs(0), profile.duration());
+    }
+
+    #[test]
+    fn trapezoid_invalid() {
+        assert!(Trapezoid::new(1.5, 0.0, 1.0, 1.0, 1.0).is_err());
+        assert!(Trapezoid::new(0.0, -1.5, 1.0, 1.0, 1.0).is_err());
+        assert!(Trapezoid::new(0.0, f32::NAN, 1.0, 1.0, 1.0).is_err());
+        assert!(Trapezoid::new(0.0, 1.0, 0.0, 1.0, 1.0).is_err());
+        assert!(Trapezoid::new(0.0, 1.0, 1.0, 0.0, 1.0).is_err());
+        assert!(Trapezoid::new(0.0, 1.0, 1.0, 1.0, f32::INFINITY).is_err());
+    }
+}
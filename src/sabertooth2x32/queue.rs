@@ -0,0 +1,215 @@
+use std::collections::VecDeque;
+use std::io;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+
+use super::{Command, Sabertooth2x32};
+use crate::error::{Error, Result};
+
+struct QueueState {
+    queue: VecDeque<Command>,
+    sending: bool,
+    stop: bool,
+    // The worker thread exited, for ex. after a panic of the interface.
+    closed: bool,
+    transmitted: u64,
+    coalesced: u64,
+    // First error of the worker since the last flush.
+    error: Option<Error>,
+}
+
+struct Shared {
+    state: Mutex<QueueState>,
+    changed: Condvar,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, QueueState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Queue of commands sent to a Sabertooth by a worker thread, so that
+/// submitting a command never waits for the serial line.
+///
+/// A value command (speed, drive, turn, power, ramp and aux) replaces the
+/// one of the same kind and channel still waiting in the queue, taking its
+/// place, which is then never sent: on a slow line only the latest values are
+/// transmitted, and the order of the targets is kept. The other commands are
+/// all sent, in order. The queue holds at most *capacity* commands, submitting
+/// to a full queue fails.
+///
+/// The commands are validated when submitted. An error of the interface
+/// while sending is returned by the next `flush()`. If the worker thread
+/// panics, the queue is closed: `submit()` and `flush()` then fail with an
+/// `io::ErrorKind::BrokenPipe` error.
+///
+/// # Example
+///
+/// ```
+/// use saberrs::sabertooth2x32::{AsyncQueueDriver, Command, PacketSerial};
+/// # use saberrs::Result;
+/// # fn example() -> Result<()> {
+/// let saber = PacketSerial::new("/dev/ttyUSB0")?;
+/// let queue = AsyncQueueDriver::new(saber, 16);
+/// for i in 0..100 {
+///     queue.submit(Command::Speed(1, i as f32 / 100.0))?;
+/// }
+/// queue.flush()?;
+/// println!("{} commands coalesced", queue.coalesced_count());
+/// # Ok(())
+/// # }
+/// ```
+pub struct AsyncQueueDriver<S: Sabertooth2x32 + Send + 'static> {
+    shared: Arc<Shared>,
+    capacity: usize,
+    worker: Option<JoinHandle<S>>,
+}
+
+impl<S: Sabertooth2x32 + Send + 'static> AsyncQueueDriver<S> {
+    /// Start the worker thread sending the commands to *inner*, with a queue
+    /// of at most *capacity* commands.
+    pub fn new(inner: S, capacity: usize) -> Self {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(QueueState {
+                queue: VecDeque::with_capacity(capacity),
+                sending: false,
+                stop: false,
+                closed: false,
+                transmitted: 0,
+                coalesced: 0,
+                error: None,
+            }),
+            changed: Condvar::new(),
+        });
+        let worker_shared = shared.clone();
+        let worker = thread::spawn(move || run(inner, &worker_shared));
+        AsyncQueueDriver {
+            shared,
+            capacity,
+            worker: Some(worker),
+        }
+    }
+
+    /// Queue a command without waiting for it to be sent. An invalid command
+    /// or a full queue is an error.
+    pub fn submit(&self, command: Command) -> Result<()> {
+        command.validate()?;
+        let mut state = self.shared.lock();
+        if state.closed {
+            return Err(closed_error());
+        }
+        if let Some(index) = state.queue.iter().position(|c| c.same_target(&command)) {
+            state.queue[index] = command;
+            state.coalesced += 1;
+        } else if state.queue.len() >= self.capacity {
+            let msg = format!("command queue full ({} commands)", self.capacity);
+            return Err(Error::Io(io::Error::new(io::ErrorKind::WouldBlock, msg)));
+        } else {
+            state.queue.push_back(command);
+        }
+        self.shared.changed.notify_all();
+        Ok(())
+    }
+
+    /// Wait until all the queued commands are sent, and return the first
+    /// error of the interface since the previous flush, if any, or an error
+    /// if the queue was closed.
+    pub fn flush(&self) -> Result<()> {
+        let mut state = self.shared.lock();
+        while (!state.queue.is_empty() || state.sending) && !state.closed {
+            state = self
+                .shared
+                .changed
+                .wait(state)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+        match state.error.take() {
+            Some(e) => Err(e),
+            None if state.closed && !state.stop => Err(closed_error()),
+            None => Ok(()),
+        }
+    }
+
+    /// Number of commands waiting to be sent.
+    pub fn pending(&self) -> usize {
+        self.shared.lock().queue.len()
+    }
+
+    /// Number of commands sent to the interface so far, including failed ones.
+    pub fn transmitted_count(&self) -> u64 {
+        self.shared.lock().transmitted
+    }
+
+    /// Number of commands replaced by a later one before being sent.
+    pub fn coalesced_count(&self) -> u64 {
+        self.shared.lock().coalesced
+    }
+
+    /// Send the queued commands, stop the worker thread and return the
+    /// interface. If the worker thread panicked, the interface is lost and
+    /// the error is the one of a closed queue.
+    pub fn into_inner(mut self) -> Result<S> {
+        self.stop().ok_or_else(closed_error)
+    }
+
+    fn stop(&mut self) -> Option<S> {
+        let worker = self.worker.take()?;
+        self.shared.lock().stop = true;
+        self.shared.changed.notify_all();
+        worker.join().ok()
+    }
+}
+
+impl<S: Sabertooth2x32 + Send + 'static> Drop for AsyncQueueDriver<S> {
+    /// Send the queued commands and stop the worker thread.
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn closed_error() -> Error {
+    let msg = "the command queue worker stopped";
+    Error::Io(io::Error::new(io::ErrorKind::BrokenPipe, msg))
+}
+
+/// Close the queue when the worker thread exits, including by a panic, so
+/// that nothing waits for it anymore.
+struct CloseOnExit<'a>(&'a Shared);
+
+impl Drop for CloseOnExit<'_> {
+    fn drop(&mut self) {
+        let mut state = self.0.lock();
+        state.sending = false;
+        state.closed = true;
+        self.0.changed.notify_all();
+    }
+}
+
+fn run<S: Sabertooth2x32>(mut inner: S, shared: &Shared) -> S {
+    let _close = CloseOnExit(shared);
+    let mut state = shared.lock();
+    loop {
+        let command = match state.queue.pop_front() {
+            Some(command) => command,
+            None if state.stop => return inner,
+            None => {
+                state = shared
+                    .changed
+                    .wait(state)
+                    .unwrap_or_else(|e| e.into_inner());
+                continue;
+            }
+        };
+        state.sending = true;
+        drop(state);
+        let result = command.send(&mut inner);
+        state = shared.lock();
+        state.sending = false;
+        state.transmitted += 1;
+        if let Err(e) = result {
+            state.error.get_or_insert(e);
+        }
+        shared.changed.notify_all();
+    }
+}
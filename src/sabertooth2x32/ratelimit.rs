@@ -0,0 +1,309 @@
+use std::time::{Duration, Instant};
+
+use super::clock::{Clock, NowFn, SleepFn};
+use super::{Command, Sabertooth2x32};
+use crate::error::Result;
+
+/// Number of bits sent on the serial line per byte: 8 data bits, one start bit
+/// and one stop bit.
+const BITS_PER_BYTE: u32 = 10;
+
+/// Time needed for transmitting a frame of *frame_len* bytes at *baud_rate*.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use saberrs::sabertooth2x32::frame_tx_duration;
+///
+/// // A CRC packet is 10 bytes long.
+/// assert_eq!(Duration::from_micros(10417), frame_tx_duration(10, 9600));
+/// ```
+pub fn frame_tx_duration(frame_len: usize, baud_rate: u32) -> Duration {
+    let bits = frame_len as u64 * u64::from(BITS_PER_BYTE);
+    let micros = (bits * 1_000_000 + u64::from(baud_rate) / 2) / u64::from(baud_rate.max(1));
+    Duration::from_micros(micros)
+}
+
+/// What [RateLimiter](struct.RateLimiter.html) does with a command arriving
+/// too early.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum RateLimitPolicy {
+    /// Do not send the command. The method called still succeeds.
+    Drop,
+    /// Wait until the period has elapsed, then send the command.
+    Delay,
+}
+
+/// Wrapper limiting the rate of the commands sent to a Sabertooth, so that a
+/// fast control loop does not send more than the serial line can carry.
+///
+/// The commands making the motors move are sent at most once per period for
+/// each target: each motor channel, or the drive and turn values of the mixed
+/// mode. A command arriving before the period of its target has elapsed is
+/// dropped or delayed, according to the
+/// [RateLimitPolicy](enum.RateLimitPolicy.html). Commands stopping the motors
+/// and requests are never limited.
+///
+/// With `RateLimitPolicy::Drop`, the latest command arriving too early is held
+/// for its target, replacing any command held before, and sent once the
+/// period allows it: by the next command sent through the wrapper, or by
+/// `send_pending()`. A held command is discarded when a command of the same
+/// target is sent, for ex. a zero speed, and by `stop_motors()`.
+///
+/// Optionally, repeated calls to `stop_motors()` can be debounced with
+/// `with_stop_debounce()`, for a supervisor stopping the motors at each tick
+/// while a fault persists.
+///
+/// # Example
+///
+/// ```
+/// use saberrs::sabertooth2x32::{frame_tx_duration, PacketSerial, RateLimiter, Sabertooth2x32};
+/// # use saberrs::Result;
+/// # fn example() -> Result<()> {
+/// let saber = PacketSerial::new("/dev/ttyUSB0")?;
+/// // One CRC packet at a time on a 9600 baud line.
+/// let mut saber = RateLimiter::from_baud_rate(saber, 9600, 10);
+/// assert_eq!(frame_tx_duration(10, 9600), saber.period());
+/// for _ in 0..100 {
+///     saber.set_speed(1, 0.5)?;
+/// }
+/// println!("{} commands dropped", saber.dropped_count());
+/// # Ok(())
+/// # }
+/// ```
+pub struct RateLimiter<S: Sabertooth2x32> {
+    inner: S,
+    period: Duration,
+    policy: RateLimitPolicy,
+    slots: Vec<Slot>,
+    dropped: u64,
+    stop_window: Option<Duration>,
+    // Instant of the last stop, if nothing was sent since.
+    last_stop: Option<Instant>,
+    clock: Clock,
+}
+
+impl<S: Sabertooth2x32> RateLimiter<S> {
+    /// Wrap an interface, sending at most one command per *period*. The
+    /// default policy is `RateLimitPolicy::Drop`.
+    pub fn new(inner: S, period: Duration) -> Self {
+        RateLimiter {
+            inner,
+            period,
+            policy: RateLimitPolicy::Drop,
+            slots: Vec::new(),
+            dropped: 0,
+            stop_window: None,
+            last_stop: None,
+            clock: Clock::default(),
+        }
+    }
+
+    /// Wrap an interface, with a period of the time needed for transmitting
+    /// a frame of *frame_len* bytes at *baud_rate*, see `frame_tx_duration()`.
+    pub fn from_baud_rate(inner: S, baud_rate: u32, frame_len: usize) -> Self {
+        RateLimiter::new(inner, frame_tx_duration(frame_len, baud_rate))
+    }
+
+    /// Minimum time between two commands of a target.
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+
+    /// Use *now* and *sleep* instead of the system clock, for ex. to run a
+    /// control loop on a simulated clock.
+    pub fn with_clock(mut self, now: NowFn, sleep: SleepFn) -> Self {
+        self.clock = Clock::new(now, sleep);
+        self
+    }
+
+    /// Set what to do with the commands arriving too early.
+    pub fn with_policy(mut self, policy: RateLimitPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Collapse the calls to `stop_motors()` within *window* of a previous
+    /// stop into it. The first stop is always sent immediately, and so is a
+    /// stop following any other command.
+    pub fn with_stop_debounce(mut self, window: Duration) -> Self {
+        self.stop_window = Some(window);
+        self
+    }
+
+    /// Number of commands not sent when called so far, including the
+    /// debounced stops. With `RateLimitPolicy::Drop`, the latest of them for
+    /// each target may be sent later.
+    pub fn dropped_count(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Send the held commands whose period has elapsed, returning the number
+    /// of commands sent. To be called by a control loop which may stop
+    /// sending commands while some are held.
+    pub fn send_pending(&mut self) -> Result<usize> {
+        let mut sent = 0;
+        for index in 0..self.slots.len() {
+            let now = self.clock.now();
+            let slot = &mut self.slots[index];
+            if slot.pending.is_none() || slot.remaining(self.period, now).is_some() {
+                continue;
+            }
+            if let Some(command) = slot.pending.take() {
+                command.send(&mut self.inner)?;
+                self.slots[index].last_sent = Some(self.clock.now());
+                self.last_stop = None;
+                sent += 1;
+            }
+        }
+        Ok(sent)
+    }
+
+    /// Return the wrapped interface.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn send(&mut self, command: Command) -> Result<()> {
+        self.send_pending()?;
+        let index = self.slot(command);
+        if let Some(index) = index.filter(|_| command.drives_motors()) {
+            let now = self.clock.now();
+            if let Some(remaining) = self.slots[index].remaining(self.period, now) {
+                match self.policy {
+                    RateLimitPolicy::Drop => {
+                        self.slots[index].pending = Some(command);
+                        self.dropped += 1;
+                        return Ok(());
+                    }
+                    RateLimitPolicy::Delay => self.clock.sleep(remaining),
+                }
+            }
+        }
+        if let Some(index) = index {
+            self.slots[index].pending = None;
+        }
+        command.send(&mut self.inner)?;
+        if let Some(index) = index {
+            self.slots[index].last_sent = Some(self.clock.now());
+        }
+        self.last_stop = None;
+        Ok(())
+    }
+
+    /// Index of the slot of the target of *command*, created if needed.
+    /// `None` for the commands without a value, which are never limited.
+    fn slot(&mut self, command: Command) -> Option<usize> {
+        if !command.same_target(&command) {
+            return None;
+        }
+        match self
+            .slots
+            .iter()
+            .position(|s| s.target.same_target(&command))
+        {
+            Some(index) => Some(index),
+            None => {
+                self.slots.push(Slot {
+                    target: command,
+                    last_sent: None,
+                    pending: None,
+                });
+                Some(self.slots.len() - 1)
+            }
+        }
+    }
+}
+
+/// Rate limiting state of one target.
+struct Slot {
+    // Any command of the target.
+    target: Command,
+    last_sent: Option<Instant>,
+    // Latest command which arrived too early, with `RateLimitPolicy::Drop`.
+    pending: Option<Command>,
+}
+
+impl Slot {
+    /// Time left at *now* before a command can be sent, if any.
+    fn remaining(&self, period: Duration, now: Instant) -> Option<Duration> {
+        self.last_sent
+            .and_then(|last| period.checked_sub(now.saturating_duration_since(last)))
+            .filter(|remaining| *remaining > Duration::from_secs(0))
+    }
+}
+
+impl<S: Sabertooth2x32> Sabertooth2x32 for RateLimiter<S> {
+    fn startup(&mut self, channel: usize) -> Result<()> {
+        self.send(Command::Startup(channel))
+    }
+
+    fn shutdown(&mut self, channel: usize) -> Result<()> {
+        self.send(Command::Shutdown(channel))
+    }
+
+    fn set_speed(&mut self, channel: usize, ratio: f32) -> Result<()> {
+        self.send(Command::Speed(channel, ratio))
+    }
+
+    fn get_speed(&mut self, channel: usize) -> Result<f32> {
+        self.inner.get_speed(channel)
+    }
+
+    fn stop_motors(&mut self) -> Result<()> {
+        if let (Some(window), Some(last)) = (self.stop_window, self.last_stop) {
+            if self.clock.elapsed(last) < window {
+                self.dropped += 1;
+                return Ok(());
+            }
+        }
+        for slot in &mut self.slots {
+            slot.pending = None;
+        }
+        self.last_stop = None;
+        self.inner.stop_motors()?;
+        self.last_stop = Some(self.clock.now());
+        Ok(())
+    }
+
+    fn set_drive(&mut self, ratio: f32) -> Result<()> {
+        self.send(Command::Drive(ratio))
+    }
+
+    fn set_turn(&mut self, ratio: f32) -> Result<()> {
+        self.send(Command::Turn(ratio))
+    }
+
+    fn set_power(&mut self, channel: usize, ratio: f32) -> Result<()> {
+        self.send(Command::Power(channel, ratio))
+    }
+
+    fn get_power(&mut self, channel: usize) -> Result<f32> {
+        self.inner.get_power(channel)
+    }
+
+    fn set_ramp(&mut self, channel: usize, ratio: f32) -> Result<()> {
+        self.send(Command::Ramp(channel, ratio))
+    }
+
+    fn set_aux(&mut self, channel: usize, ratio: f32) -> Result<()> {
+        self.send(Command::Aux(channel, ratio))
+    }
+
+    fn check_commands(&self, commands: &[Command]) -> Result<()> {
+        self.inner.check_commands(commands)
+    }
+
+    fn get_voltage(&mut self, channel: usize) -> Result<f32> {
+        self.inner.get_voltage(channel)
+    }
+
+    fn get_current(&mut self, channel: usize) -> Result<f32> {
+        self.inner.get_current(channel)
+    }
+
+    fn get_temperature(&mut self, channel: usize) -> Result<f32> {
+        self.inner.get_temperature(channel)
+    }
+}
@@ -0,0 +1,104 @@
+use super::zerospeed::ZeroSpeedState;
+use super::{Command, DriveMode, LastCommands};
+use crate::error::{Error, Result};
+
+/// Strict safety mode shared by the interfaces, see
+/// `PacketSerial::with_strict_safety()`.
+#[derive(Clone, Copy, Default, Debug)]
+pub(crate) struct StrictSafety {
+    pub(crate) enabled: bool,
+    pub(crate) timeout_armed: bool,
+}
+
+impl StrictSafety {
+    /// Refuse a command making the motors move before the serial timeout is
+    /// armed, in strict mode.
+    pub(crate) fn check_armed(&self, command: Command) -> Result<()> {
+        if self.enabled && !self.timeout_armed && command.drives_motors() {
+            let msg = format!("{:?} sent before arming the serial timeout", command);
+            return Err(Error::Safety(msg));
+        }
+        Ok(())
+    }
+
+    /// Check that the command is consistent with the shutdown state and the
+    /// drive mode: an inconsistency is logged, or refused in strict mode.
+    pub(crate) fn check_state(
+        &self,
+        command: Command,
+        last: &LastCommands,
+        drive_mode: Option<DriveMode>,
+    ) -> Result<()> {
+        let mut msg = None;
+        if let Some(channel) = last.shut_down_target(command) {
+            msg = Some(format!(
+                "{:?} sent while the channel {} is shut down",
+                command, channel
+            ));
+        }
+        match drive_mode {
+            Some(mode) if msg.is_none() && !mode.allows(&command) => {
+                msg = Some(format!("{:?} sent in {:?} drive mode", command, mode));
+            }
+            _ => {}
+        }
+        match msg {
+            Some(msg) if self.enabled => Err(Error::Safety(msg)),
+            Some(msg) => {
+                log_warn!("{}", msg);
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Refuse to disable the serial timeout (*millis* of zero) in strict
+    /// mode.
+    pub(crate) fn check_timeout(&self, millis: i32) -> Result<()> {
+        if millis == 0 && self.enabled {
+            let msg = "the serial timeout cannot be disabled in strict safety mode";
+            return Err(Error::Safety(msg.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Check that *commands* would all be sent in strict mode, in this order,
+    /// from the state *last* and *zero_speed*. The channels and values are
+    /// always checked.
+    pub(crate) fn check_commands(
+        &self,
+        commands: &[Command],
+        last: &LastCommands,
+        zero_speed: &ZeroSpeedState,
+        drive_mode: Option<DriveMode>,
+    ) -> Result<()> {
+        for command in commands {
+            command.validate()?;
+        }
+        if !self.enabled {
+            return Ok(());
+        }
+        let mut last = last.clone();
+        let mut zero_speed = *zero_speed;
+        for &command in commands {
+            // Same expansion as the `set_speed()` of the interfaces.
+            let sent = match command {
+                Command::Speed(channel, ratio) => zero_speed.commands(channel, ratio),
+                _ => [Some(command), None],
+            };
+            for &sent in sent.iter().flatten() {
+                self.check_armed(sent)?;
+                self.check_state(sent, &last, drive_mode)?;
+                last.record(sent);
+                zero_speed.sent(sent);
+            }
+            if let [Some(Command::Shutdown(channel)), None] = sent {
+                last.record(Command::Speed(channel, 0.0));
+            }
+            if let Command::Startup(channel) | Command::Shutdown(channel) = command {
+                zero_speed.forget(channel);
+            }
+        }
+        Ok(())
+    }
+}
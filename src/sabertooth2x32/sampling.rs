@@ -0,0 +1,90 @@
+use std::thread;
+use std::time::Duration;
+
+use crate::error::{Error, Result};
+
+/// Settings for reading a value several times, see
+/// `Sabertooth2x32::get_current_stats()`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Sampling {
+    samples: usize,
+    interval: Duration,
+    max_failures: usize,
+}
+
+impl Sampling {
+    /// Read *samples* values, waiting *interval* between two reads. By
+    /// default any failed read is an error.
+    pub fn new(samples: usize, interval: Duration) -> Self {
+        Sampling {
+            samples,
+            interval,
+            max_failures: 0,
+        }
+    }
+
+    /// Tolerate up to *max_failures* failed reads, which are not counted as
+    /// samples. Invalid inputs always fail immediately.
+    pub fn with_max_failures(mut self, max_failures: usize) -> Self {
+        self.max_failures = max_failures;
+        self
+    }
+
+    /// Read the values with *read* and compute their statistics.
+    pub(crate) fn run<F: FnMut() -> Result<f32>>(&self, mut read: F) -> Result<Stats> {
+        if self.samples == 0 {
+            return Err(Error::InvalidInput(
+                "samples should be at least 1".to_string(),
+            ));
+        }
+
+        let mut stats = Stats {
+            min: f32::INFINITY,
+            max: f32::NEG_INFINITY,
+            mean: 0.0,
+            samples: 0,
+        };
+        let mut sum = 0.0;
+        let mut failures = 0;
+
+        for i in 0..self.samples + self.max_failures {
+            if stats.samples == self.samples {
+                break;
+            }
+            if i > 0 {
+                thread::sleep(self.interval);
+            }
+            match read() {
+                Ok(value) => {
+                    stats.min = stats.min.min(value);
+                    stats.max = stats.max.max(value);
+                    sum += value;
+                    stats.samples += 1;
+                }
+                Err(e @ Error::InvalidInput(_)) => return Err(e),
+                Err(e) => {
+                    failures += 1;
+                    if failures > self.max_failures {
+                        return Err(e);
+                    }
+                }
+            }
+        }
+
+        stats.mean = sum / stats.samples as f32;
+        Ok(stats)
+    }
+}
+
+/// Statistics of several reads of a value.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Stats {
+    /// Smallest value read.
+    pub min: f32,
+    /// Largest value read.
+    pub max: f32,
+    /// Mean of the values read.
+    pub mean: f32,
+    /// Number of successful reads.
+    pub samples: usize,
+}
@@ -0,0 +1,144 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use super::{Command, Sabertooth2x32};
+use crate::error::Result;
+
+/// Request for a reading of the Sabertooth, see
+/// [QueryScheduler](struct.QueryScheduler.html).
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Query {
+    /// `Sabertooth2x32::get_speed()` of the channel.
+    Speed(usize),
+    /// `Sabertooth2x32::get_power()` of the channel.
+    Power(usize),
+    /// `Sabertooth2x32::get_voltage()` of the channel.
+    Voltage(usize),
+    /// `Sabertooth2x32::get_current()` of the channel.
+    Current(usize),
+    /// `Sabertooth2x32::get_temperature()` of the channel.
+    Temperature(usize),
+}
+
+impl Query {
+    /// Send the request to the Sabertooth and return the reading.
+    pub fn read<S: Sabertooth2x32 + ?Sized>(&self, saber: &mut S) -> Result<f32> {
+        match *self {
+            Query::Speed(channel) => saber.get_speed(channel),
+            Query::Power(channel) => saber.get_power(channel),
+            Query::Voltage(channel) => saber.get_voltage(channel),
+            Query::Current(channel) => saber.get_current(channel),
+            Query::Temperature(channel) => saber.get_temperature(channel),
+        }
+    }
+}
+
+/// What [QueryScheduler::poll()](struct.QueryScheduler.html#method.poll)
+/// did.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Scheduled {
+    /// A command was sent.
+    Sent(Command),
+    /// A query was answered with this reading.
+    Reading(Query, f32),
+    /// Nothing is ready.
+    Idle,
+}
+
+/// Queues of commands and queries sharing one serial line, so that polling
+/// the telemetry does not delay the motor commands.
+///
+/// Each call to `poll()` does at most one transaction: a pending command is
+/// always sent first, and a query is only sent when no command is pending
+/// and the query period has elapsed since the previous query.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use saberrs::sabertooth2x32::{Command, PacketSerial, Query, QueryScheduler, Scheduled};
+/// # use saberrs::Result;
+/// # fn example() -> Result<()> {
+/// let saber = PacketSerial::new("/dev/ttyUSB0")?;
+/// let mut scheduler = QueryScheduler::new(saber, Duration::from_millis(100));
+/// scheduler.push_query(Query::Voltage(1));
+/// scheduler.push_command(Command::Drive(0.5));
+/// loop {
+///     match scheduler.poll()? {
+///         Scheduled::Reading(Query::Voltage(_), volts) => println!("{} V", volts),
+///         Scheduled::Idle => break,
+///         _ => {}
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct QueryScheduler<S: Sabertooth2x32> {
+    inner: S,
+    query_period: Duration,
+    commands: VecDeque<Command>,
+    queries: VecDeque<Query>,
+    last_query: Option<Instant>,
+}
+
+impl<S: Sabertooth2x32> QueryScheduler<S> {
+    /// Wrap an interface, sending at most one query per *query_period*.
+    pub fn new(inner: S, query_period: Duration) -> Self {
+        QueryScheduler {
+            inner,
+            query_period,
+            commands: VecDeque::new(),
+            queries: VecDeque::new(),
+            last_query: None,
+        }
+    }
+
+    /// Queue a command, sent before any query.
+    pub fn push_command(&mut self, command: Command) {
+        self.commands.push_back(command);
+    }
+
+    /// Queue a query, sent when no command is pending.
+    pub fn push_query(&mut self, query: Query) {
+        self.queries.push_back(query);
+    }
+
+    /// Number of commands waiting to be sent.
+    pub fn pending_commands(&self) -> usize {
+        self.commands.len()
+    }
+
+    /// Number of queries waiting to be sent.
+    pub fn pending_queries(&self) -> usize {
+        self.queries.len()
+    }
+
+    /// Send the next command, or else the next query if the query period
+    /// allows it. A command or query failing is removed from its queue and
+    /// its error returned.
+    pub fn poll(&mut self) -> Result<Scheduled> {
+        if let Some(command) = self.commands.pop_front() {
+            command.send(&mut self.inner)?;
+            return Ok(Scheduled::Sent(command));
+        }
+        let ready = match self.last_query {
+            Some(last) => last.elapsed() >= self.query_period,
+            None => true,
+        };
+        match self.queries.front() {
+            Some(&query) if ready => {
+                self.queries.pop_front();
+                self.last_query = Some(Instant::now());
+                let value = query.read(&mut self.inner)?;
+                Ok(Scheduled::Reading(query, value))
+            }
+            _ => Ok(Scheduled::Idle),
+        }
+    }
+
+    /// Return the wrapped interface. The pending commands and queries are
+    /// dropped.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
@@ -0,0 +1,123 @@
+use std::time::{Duration, Instant};
+
+use super::Command;
+
+/// Last values successfully sent to a Sabertooth 2x32, as ratios between -1.0
+/// and 1.0.
+///
+/// The values are recorded by the interface only once their frame has been
+/// written. They reflect what was commanded, which may differ from the actual
+/// state of the Sabertooth, for example after it was power-cycled.
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct LastCommands {
+    speed: [Option<f32>; 2],
+    power: [Option<f32>; 2],
+    ramp: [Option<f32>; 2],
+    aux: [Option<f32>; 2],
+    drive: Option<f32>,
+    turn: Option<f32>,
+    shut_down: [Option<bool>; 2],
+    sent_at: Option<Instant>,
+}
+
+fn by_channel(values: &[Option<f32>; 2], channel: usize) -> Option<f32> {
+    match channel {
+        1 => values[0],
+        2 => values[1],
+        _ => None,
+    }
+}
+
+impl LastCommands {
+    /// Last speed of the motor *channel*.
+    pub fn speed(&self, channel: usize) -> Option<f32> {
+        by_channel(&self.speed, channel)
+    }
+
+    /// Last power of the motor *channel*.
+    pub fn power(&self, channel: usize) -> Option<f32> {
+        by_channel(&self.power, channel)
+    }
+
+    /// Last ramping of the motor *channel*.
+    pub fn ramp(&self, channel: usize) -> Option<f32> {
+        by_channel(&self.ramp, channel)
+    }
+
+    /// Last aux value of the channel *channel*.
+    pub fn aux(&self, channel: usize) -> Option<f32> {
+        by_channel(&self.aux, channel)
+    }
+
+    /// Last drive value (mixed mode).
+    pub fn drive(&self) -> Option<f32> {
+        self.drive
+    }
+
+    /// Last turn value (mixed mode).
+    pub fn turn(&self) -> Option<f32> {
+        self.turn
+    }
+
+    /// Whether the last of `startup()` and `shutdown()` sent to the motor
+    /// *channel* was `shutdown()`.
+    pub fn shut_down(&self, channel: usize) -> Option<bool> {
+        match channel {
+            1 => self.shut_down[0],
+            2 => self.shut_down[1],
+            _ => None,
+        }
+    }
+
+    /// Time elapsed at *now* since the last command was sent.
+    pub(crate) fn time_since_last(&self, now: Instant) -> Option<Duration> {
+        self.sent_at
+            .map(|sent_at| now.saturating_duration_since(sent_at))
+    }
+
+    /// Channel believed to be shut down that *command* would make move, if
+    /// any. Drive and turn commands move both channels.
+    pub(crate) fn shut_down_target(&self, command: Command) -> Option<usize> {
+        if !command.drives_motors() {
+            return None;
+        }
+        let is_shut_down = |channel: usize| self.shut_down(channel) == Some(true);
+        match command {
+            Command::Speed(channel, _) | Command::Power(channel, _) => {
+                Some(channel).filter(|&channel| is_shut_down(channel))
+            }
+            _ => (1..=2).find(|&channel| is_shut_down(channel)),
+        }
+    }
+
+    /// Same as `record()`, for a command sent at *at*.
+    pub(crate) fn record_sent(&mut self, command: Command, at: Instant) {
+        self.record(command);
+        self.sent_at = Some(at);
+    }
+
+    /// Record the value of a command. Commands with an invalid channel are
+    /// ignored.
+    pub(crate) fn record(&mut self, command: Command) {
+        let slot = |values: &mut [Option<f32>; 2], channel: usize, ratio: f32| {
+            if let Some(value) = values.get_mut(channel.wrapping_sub(1)) {
+                *value = Some(ratio);
+            }
+        };
+
+        match command {
+            Command::Speed(channel, ratio) => slot(&mut self.speed, channel, ratio),
+            Command::Power(channel, ratio) => slot(&mut self.power, channel, ratio),
+            Command::Ramp(channel, ratio) => slot(&mut self.ramp, channel, ratio),
+            Command::Aux(channel, ratio) => slot(&mut self.aux, channel, ratio),
+            Command::Drive(ratio) => self.drive = Some(ratio),
+            Command::Turn(ratio) => self.turn = Some(ratio),
+            Command::Startup(channel) | Command::Shutdown(channel) => {
+                let shut_down = matches!(command, Command::Shutdown(_));
+                if let Some(value) = self.shut_down.get_mut(channel.wrapping_sub(1)) {
+                    *value = Some(shut_down);
+                }
+            }
+        }
+    }
+}
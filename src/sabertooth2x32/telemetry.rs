@@ -0,0 +1,72 @@
+use crate::convert;
+use crate::error::{Error, Result};
+
+/// Largest plausible battery voltage, in volts.
+const MAX_VOLTAGE: f32 = 60.0;
+
+/// Largest plausible motor current, in amperes (either direction).
+const MAX_CURRENT: f32 = 500.0;
+
+/// Plausible temperature range of the output transistors, in degrees celsius.
+const MIN_TEMPERATURE: f32 = -60.0;
+const MAX_TEMPERATURE: f32 = 200.0;
+
+/// What to do with a value from the Sabertooth outside of its plausible range,
+/// which most probably comes from a corrupted reply.
+///
+/// The ranges are the native range for speeds and powers (±2047 unless
+/// configured otherwise), 0~60 V for voltages, ±500 A for currents and
+/// -60~200 °C for temperatures.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum TelemetryPolicy {
+    /// Return an `Error::Response` including the value. This is the default.
+    #[default]
+    Strict,
+    /// Clamp the value to the range. The clamped values are counted, see
+    /// `clamped_readings()`.
+    Clamp,
+}
+
+/// Telemetry policy of an interface, with the number of values clamped.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub(crate) struct Telemetry {
+    pub policy: TelemetryPolicy,
+    pub clamped: u64,
+}
+
+impl Telemetry {
+    /// Convert a value in -*max*~*max* to a ratio.
+    pub fn ratio(&mut self, what: &str, value: i32, max: i32) -> Result<f32> {
+        let value = self.check(what, value as f32, -max as f32, max as f32)?;
+        convert::value_to_ratio_in_range(value as i32, max)
+    }
+
+    /// Convert a battery value, in tenths of volts, to volts.
+    pub fn voltage(&mut self, value: i32) -> Result<f32> {
+        self.check("battery voltage", value as f32 / 10.0, 0.0, MAX_VOLTAGE)
+    }
+
+    pub fn current(&mut self, amps: f32) -> Result<f32> {
+        self.check("current", amps, -MAX_CURRENT, MAX_CURRENT)
+    }
+
+    pub fn temperature(&mut self, celsius: f32) -> Result<f32> {
+        self.check("temperature", celsius, MIN_TEMPERATURE, MAX_TEMPERATURE)
+    }
+
+    fn check(&mut self, what: &str, value: f32, min: f32, max: f32) -> Result<f32> {
+        if (min..=max).contains(&value) {
+            return Ok(value);
+        }
+        match self.policy {
+            TelemetryPolicy::Strict => {
+                let msg = format!("{} out of range {}~{} (was {})", what, min, max, value);
+                Err(Error::Response(msg))
+            }
+            TelemetryPolicy::Clamp => {
+                self.clamped += 1;
+                Ok(value.max(min).min(max))
+            }
+        }
+    }
+}
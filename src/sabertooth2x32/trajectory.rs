@@ -0,0 +1,314 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::{Command, Sabertooth2x32};
+use crate::error::{Error, Result};
+
+/// List of commands to send at given times, run by a
+/// [TrajectoryRunner](struct.TrajectoryRunner.html).
+///
+/// The setpoints are kept sorted by time offset. Setpoints with the same
+/// offset are sent in the order they were added.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use saberrs::sabertooth2x32::Trajectory;
+///
+/// let trajectory = Trajectory::new()
+///     .setpoint(Duration::from_secs(0), 1, 0.5)
+///     .setpoint(Duration::from_secs(2), 1, -0.5)
+///     .setpoint(Duration::from_secs(4), 1, 0.0);
+/// assert_eq!(Duration::from_secs(4), trajectory.duration());
+/// ```
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct Trajectory {
+    setpoints: Vec<(Duration, Command)>,
+}
+
+impl Trajectory {
+    /// Create an empty trajectory.
+    pub fn new() -> Self {
+        Trajectory::default()
+    }
+
+    /// Set the speed of *channel* to *ratio* at *offset* from the start.
+    pub fn setpoint(self, offset: Duration, channel: usize, ratio: f32) -> Self {
+        self.command(offset, Command::Speed(channel, ratio))
+    }
+
+    /// Send *command* at *offset* from the start.
+    pub fn command(mut self, offset: Duration, command: Command) -> Self {
+        let index = self.setpoints.partition_point(|&(o, _)| o <= offset);
+        self.setpoints.insert(index, (offset, command));
+        self
+    }
+
+    /// Setpoints, sorted by time offset.
+    pub fn setpoints(&self) -> &[(Duration, Command)] {
+        &self.setpoints
+    }
+
+    /// Number of setpoints.
+    pub fn len(&self) -> usize {
+        self.setpoints.len()
+    }
+
+    /// Whether the trajectory has no setpoint.
+    pub fn is_empty(&self) -> bool {
+        self.setpoints.is_empty()
+    }
+
+    /// Offset of the last setpoint.
+    pub fn duration(&self) -> Duration {
+        self.setpoints
+            .last()
+            .map_or(Duration::from_secs(0), |&(o, _)| o)
+    }
+
+    /// Check the channels and values of all the setpoints.
+    pub fn validate(&self) -> Result<()> {
+        self.setpoints.iter().try_for_each(|(_, c)| c.validate())
+    }
+}
+
+/// State of a [TrajectoryRunner](struct.TrajectoryRunner.html).
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TrajectoryState {
+    /// Not started yet.
+    Idle,
+    /// Sending the setpoints as they are due.
+    Running,
+    /// Paused, the time does not advance in the trajectory.
+    Paused,
+    /// All the setpoints were sent.
+    Finished,
+    /// Aborted, the motors were stopped.
+    Aborted,
+}
+
+/// Progress of a [TrajectoryRunner](struct.TrajectoryRunner.html).
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct TrajectoryProgress {
+    /// Setpoints done so far, including the ones replaced by a later
+    /// setpoint of the same channel.
+    pub done: usize,
+    /// Setpoints of the trajectory.
+    pub total: usize,
+    /// Time elapsed in the trajectory, excluding the pauses.
+    pub elapsed: Duration,
+    /// State of the runner.
+    pub state: TrajectoryState,
+}
+
+/// Callback receiving the progress of a trajectory, each time setpoints are
+/// done or the state changes.
+pub type ProgressObserver = Box<dyn FnMut(&TrajectoryProgress) + Send>;
+
+/// Executor of a [Trajectory](struct.Trajectory.html).
+///
+/// The runner is driven by the caller: `tick()` sends the setpoints due at
+/// the given time, so the clock can be provided by the application (or a
+/// test). `run()` ticks until the end of the trajectory on the current
+/// thread instead, sleeping until each setpoint is due.
+///
+/// When several setpoints of the same channel are due in one tick, only the
+/// latest one is sent. `abort()` stops the motors.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use saberrs::sabertooth2x32::{PacketSerial, Trajectory, TrajectoryRunner};
+/// # use saberrs::Result;
+/// # fn example() -> Result<()> {
+/// let saber = PacketSerial::new("/dev/ttyUSB0")?;
+/// let trajectory = Trajectory::new()
+///     .setpoint(Duration::from_secs(0), 1, 0.5)
+///     .setpoint(Duration::from_secs(2), 1, 0.0);
+/// let mut runner = TrajectoryRunner::new(saber, trajectory)
+///     .with_progress_observer(Box::new(|p| println!("{}/{}", p.done, p.total)));
+/// runner.run()?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct TrajectoryRunner<S: Sabertooth2x32> {
+    inner: S,
+    trajectory: Trajectory,
+    state: TrajectoryState,
+    next: usize,
+    start: Option<Instant>,
+    paused_at: Option<Instant>,
+    paused: Duration,
+    elapsed: Duration,
+    observer: Option<ProgressObserver>,
+}
+
+impl<S: Sabertooth2x32> TrajectoryRunner<S> {
+    /// Wrap an interface, to run *trajectory* with it.
+    pub fn new(inner: S, trajectory: Trajectory) -> Self {
+        TrajectoryRunner {
+            inner,
+            trajectory,
+            state: TrajectoryState::Idle,
+            next: 0,
+            start: None,
+            paused_at: None,
+            paused: Duration::from_secs(0),
+            elapsed: Duration::from_secs(0),
+            observer: None,
+        }
+    }
+
+    /// Report the progress to *observer*.
+    pub fn with_progress_observer(mut self, observer: ProgressObserver) -> Self {
+        self.observer = Some(observer);
+        self
+    }
+
+    /// Start the trajectory at *now*. The setpoints are validated first, an
+    /// invalid setpoint is an error and nothing is sent. An empty trajectory
+    /// is finished as soon as started.
+    pub fn start(&mut self, now: Instant) -> Result<()> {
+        if self.state != TrajectoryState::Idle {
+            return Err(Error::InvalidInput(
+                "trajectory already started".to_string(),
+            ));
+        }
+        self.trajectory.validate()?;
+        self.start = Some(now);
+        self.set_state(TrajectoryState::Running);
+        if self.trajectory.is_empty() {
+            self.set_state(TrajectoryState::Finished);
+        }
+        Ok(())
+    }
+
+    /// Send the setpoints due at *now*, returning the number of commands
+    /// sent. Nothing is sent unless the runner is running.
+    ///
+    /// A command failing is returned as error, the setpoints after it are
+    /// sent by the next tick.
+    pub fn tick(&mut self, now: Instant) -> Result<usize> {
+        let start = match self.start {
+            Some(start) if self.state == TrajectoryState::Running => start,
+            _ => return Ok(0),
+        };
+        self.elapsed = now.saturating_duration_since(start + self.paused);
+        let setpoints = &self.trajectory.setpoints;
+        let due = setpoints.partition_point(|&(o, _)| o <= self.elapsed);
+        if due <= self.next {
+            return Ok(0);
+        }
+
+        let mut sent = 0;
+        let mut result = Ok(());
+        while self.next < due {
+            let command = setpoints[self.next].1;
+            let replaced = setpoints[self.next + 1..due]
+                .iter()
+                .any(|(_, c)| c.same_target(&command));
+            if !replaced {
+                if let Err(e) = command.send(&mut self.inner) {
+                    self.next += 1;
+                    result = Err(e);
+                    break;
+                }
+                sent += 1;
+            }
+            self.next += 1;
+        }
+        if self.next == setpoints.len() {
+            self.state = TrajectoryState::Finished;
+        }
+        self.notify();
+        result.map(|_| sent)
+    }
+
+    /// Time at which the next setpoint is due, if running.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        let start = self.start?;
+        if self.state != TrajectoryState::Running {
+            return None;
+        }
+        let &(offset, _) = self.trajectory.setpoints.get(self.next)?;
+        Some(start + self.paused + offset)
+    }
+
+    /// Pause the trajectory at *now*, until `resume()`.
+    pub fn pause(&mut self, now: Instant) {
+        if self.state == TrajectoryState::Running {
+            self.paused_at = Some(now);
+            self.set_state(TrajectoryState::Paused);
+        }
+    }
+
+    /// Resume a paused trajectory at *now*. The setpoints are delayed by the
+    /// duration of the pause.
+    pub fn resume(&mut self, now: Instant) {
+        if let Some(paused_at) = self.paused_at.take() {
+            self.paused += now.saturating_duration_since(paused_at);
+            self.set_state(TrajectoryState::Running);
+        }
+    }
+
+    /// Abort the trajectory and stop the motors.
+    pub fn abort(&mut self) -> Result<()> {
+        self.paused_at = None;
+        self.set_state(TrajectoryState::Aborted);
+        self.inner.stop_motors()
+    }
+
+    /// Start the trajectory if needed and run it until it is finished,
+    /// sleeping until each setpoint is due. The motors are stopped if a
+    /// command fails.
+    pub fn run(&mut self) -> Result<()> {
+        if self.state == TrajectoryState::Idle {
+            self.start(Instant::now())?;
+        }
+        while let Some(deadline) = self.next_deadline() {
+            let now = Instant::now();
+            if deadline > now {
+                thread::sleep(deadline - now);
+            }
+            if let Err(e) = self.tick(Instant::now()) {
+                let _ = self.abort();
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// State of the runner.
+    pub fn state(&self) -> TrajectoryState {
+        self.state
+    }
+
+    /// Progress of the trajectory, as of the last tick.
+    pub fn progress(&self) -> TrajectoryProgress {
+        TrajectoryProgress {
+            done: self.next,
+            total: self.trajectory.len(),
+            elapsed: self.elapsed,
+            state: self.state,
+        }
+    }
+
+    /// Return the wrapped interface.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn set_state(&mut self, state: TrajectoryState) {
+        self.state = state;
+        self.notify();
+    }
+
+    fn notify(&mut self) {
+        let progress = self.progress();
+        if let Some(observer) = self.observer.as_mut() {
+            observer(&progress);
+        }
+    }
+}
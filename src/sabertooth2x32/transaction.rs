@@ -0,0 +1,144 @@
+use super::Sabertooth2x32;
+use crate::convert;
+use crate::error::Result;
+
+/// A command of the [Sabertooth2x32](trait.Sabertooth2x32.html) trait, as a
+/// value. The parameters are the same as the ones of the matching method.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Command {
+    /// See `Sabertooth2x32::startup()`.
+    Startup(usize),
+    /// See `Sabertooth2x32::shutdown()`.
+    Shutdown(usize),
+    /// See `Sabertooth2x32::set_speed()`.
+    Speed(usize, f32),
+    /// See `Sabertooth2x32::set_drive()`.
+    Drive(f32),
+    /// See `Sabertooth2x32::set_turn()`.
+    Turn(f32),
+    /// See `Sabertooth2x32::set_power()`.
+    Power(usize, f32),
+    /// See `Sabertooth2x32::set_ramp()`.
+    Ramp(usize, f32),
+    /// See `Sabertooth2x32::set_aux()`.
+    Aux(usize, f32),
+}
+
+impl Command {
+    /// Check the channel and the value of the command, without sending it.
+    pub fn validate(&self) -> Result<()> {
+        match *self {
+            Command::Startup(channel) => {
+                match_channel_to!(channel, (), (), "startup");
+            }
+            Command::Shutdown(channel) => {
+                match_channel_to!(channel, (), (), "shutdown");
+            }
+            Command::Speed(channel, ratio)
+            | Command::Power(channel, ratio)
+            | Command::Ramp(channel, ratio)
+            | Command::Aux(channel, ratio) => {
+                match_channel_to!(channel, (), ());
+                convert::ratio_to_value(ratio)?;
+            }
+            Command::Drive(ratio) | Command::Turn(ratio) => {
+                convert::ratio_to_value(ratio)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the command makes the motors move.
+    pub(crate) fn drives_motors(&self) -> bool {
+        match *self {
+            Command::Speed(_, ratio)
+            | Command::Drive(ratio)
+            | Command::Turn(ratio)
+            | Command::Power(_, ratio) => ratio != 0.0,
+            _ => false,
+        }
+    }
+
+    /// Whether both commands set the same value, so that sending only the
+    /// latest of them is enough.
+    pub(crate) fn same_target(&self, other: &Command) -> bool {
+        match (*self, *other) {
+            (Command::Speed(a, _), Command::Speed(b, _))
+            | (Command::Power(a, _), Command::Power(b, _))
+            | (Command::Ramp(a, _), Command::Ramp(b, _))
+            | (Command::Aux(a, _), Command::Aux(b, _)) => a == b,
+            (Command::Drive(_), Command::Drive(_)) | (Command::Turn(_), Command::Turn(_)) => true,
+            _ => false,
+        }
+    }
+
+    /// Send the command with the given interface.
+    pub fn send<S: Sabertooth2x32 + ?Sized>(&self, saber: &mut S) -> Result<()> {
+        match *self {
+            Command::Startup(channel) => saber.startup(channel),
+            Command::Shutdown(channel) => saber.shutdown(channel),
+            Command::Speed(channel, ratio) => saber.set_speed(channel, ratio),
+            Command::Drive(ratio) => saber.set_drive(ratio),
+            Command::Turn(ratio) => saber.set_turn(ratio),
+            Command::Power(channel, ratio) => saber.set_power(channel, ratio),
+            Command::Ramp(channel, ratio) => saber.set_ramp(channel, ratio),
+            Command::Aux(channel, ratio) => saber.set_aux(channel, ratio),
+        }
+    }
+}
+
+/// Group of commands which are sent only if all of them are valid.
+///
+/// When committed, every command is checked first with
+/// `Sabertooth2x32::check_commands()`: the channels and values, and the
+/// refusals of the strict safety mode of the interface from its current state.
+/// If any of them would fail its error is returned and nothing is sent. Note
+/// that an IO error, or a command changed by a pre-send hook, may still
+/// interrupt the transmission once it started.
+///
+/// # Example
+///
+/// ```
+/// use saberrs::sabertooth2x32::{Command, PacketSerial, Transaction};
+/// # use saberrs::Result;
+/// # fn example() -> Result<()> {
+/// let mut saber = PacketSerial::new("/dev/ttyUSB0")?;
+///
+/// Transaction::new()
+///     .with(Command::Speed(1, 0.5))
+///     .with(Command::Speed(2, -0.5))
+///     .commit(&mut saber)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct Transaction {
+    commands: Vec<Command>,
+}
+
+impl Transaction {
+    /// Create an empty transaction.
+    pub fn new() -> Self {
+        Transaction::default()
+    }
+
+    /// Append a command to the transaction.
+    pub fn with(mut self, command: Command) -> Self {
+        self.commands.push(command);
+        self
+    }
+
+    /// Commands of the transaction, in sending order.
+    pub fn commands(&self) -> &[Command] {
+        &self.commands
+    }
+
+    /// Check all the commands, then send them in order.
+    pub fn commit<S: Sabertooth2x32 + ?Sized>(&self, saber: &mut S) -> Result<()> {
+        saber.check_commands(&self.commands)?;
+        for command in &self.commands {
+            command.send(saber)?;
+        }
+        Ok(())
+    }
+}
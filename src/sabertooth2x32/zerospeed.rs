@@ -0,0 +1,63 @@
+use super::Command;
+
+/// What `set_speed(channel, 0.0)` sends, see `PacketSerial::with_zero_speed()`
+/// and `PlainText::with_zero_speed()`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum ZeroSpeed {
+    /// A speed of zero: the speed control of the Sabertooth stops the motor
+    /// and the channel stays enabled. This is the default.
+    #[default]
+    Value,
+    /// A shutdown of the channel, which holds the motor in a hard brake state
+    /// (see `Sabertooth2x32::shutdown()`). The next non-zero speed of the
+    /// channel is preceded by a startup. The last speed of the channel (see
+    /// `last_commands()`) is recorded as zero.
+    Shutdown,
+}
+
+/// Channels shut down by a zero speed, which a non-zero speed starts again.
+#[derive(Clone, Copy, Default, Debug)]
+pub(crate) struct ZeroSpeedState {
+    pub(crate) mode: ZeroSpeed,
+    shut_down: [bool; 2],
+}
+
+impl ZeroSpeedState {
+    /// Commands to send for the speed *ratio* of *channel*, in order.
+    pub(crate) fn commands(&self, channel: usize, ratio: f32) -> [Option<Command>; 2] {
+        let shut_down = self.shut_down.get(channel.wrapping_sub(1)) == Some(&true);
+        match self.mode {
+            ZeroSpeed::Shutdown if ratio == 0.0 => [Some(Command::Shutdown(channel)), None],
+            _ if shut_down => [
+                Some(Command::Startup(channel)),
+                Some(Command::Speed(channel, ratio)),
+            ],
+            _ => [Some(Command::Speed(channel, ratio)), None],
+        }
+    }
+
+    /// Record a command sent for a speed.
+    pub(crate) fn sent(&mut self, command: Command) {
+        match command {
+            Command::Shutdown(channel) => self.set(channel, true),
+            Command::Startup(channel) => self.set(channel, false),
+            _ => {}
+        }
+    }
+
+    /// Forget that *channel* was shut down by a zero speed, when it is
+    /// started or shut down explicitly.
+    pub(crate) fn forget(&mut self, channel: usize) {
+        self.set(channel, false);
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.shut_down = [false; 2];
+    }
+
+    fn set(&mut self, channel: usize, shut_down: bool) {
+        if let Some(value) = self.shut_down.get_mut(channel.wrapping_sub(1)) {
+            *value = shut_down;
+        }
+    }
+}
@@ -1,7 +1,53 @@
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::convert::{RANGE_MAX, RANGE_MIN};
 use crate::error::{Error, Result};
 
-pub const RANGE_MAX: i32 = 2047;
-pub const RANGE_MIN: i32 = -2047;
+/// Maximum number of consecutive interrupted writes before giving up a frame.
+const MAX_WRITE_INTERRUPTS: usize = 8;
+
+/// Pause before retrying an I/O which would block.
+pub(crate) const WOULD_BLOCK_PAUSE: Duration = Duration::from_millis(1);
+
+// Logging macros forwarding to the `log` crate, or only checking their
+// arguments without the "log" feature.
+#[cfg(feature = "log")]
+macro_rules! log_debug {
+    ($($arg:tt)+) => { log::debug!($($arg)+) };
+}
+
+#[cfg(not(feature = "log"))]
+macro_rules! log_debug {
+    ($($arg:tt)+) => {{
+        let _ = format_args!($($arg)+);
+    }};
+}
+
+#[cfg(feature = "log")]
+macro_rules! log_info {
+    ($($arg:tt)+) => { log::info!($($arg)+) };
+}
+
+#[cfg(not(feature = "log"))]
+macro_rules! log_info {
+    ($($arg:tt)+) => {{
+        let _ = format_args!($($arg)+);
+    }};
+}
+
+#[cfg(feature = "log")]
+macro_rules! log_warn {
+    ($($arg:tt)+) => { log::warn!($($arg)+) };
+}
+
+#[cfg(not(feature = "log"))]
+macro_rules! log_warn {
+    ($($arg:tt)+) => {{
+        let _ = format_args!($($arg)+);
+    }};
+}
 
 macro_rules! match_channel_to {
     ($channel:expr, $ch1:expr, $ch2:expr) => {
@@ -14,27 +60,188 @@ macro_rules! match_channel_to {
             }
         }
     };
+    // Same, with the name of the command in the error message.
+    ($channel:expr, $ch1:expr, $ch2:expr, $command:expr) => {
+        match $channel {
+            1 => $ch1,
+            2 => $ch2,
+            _ => {
+                let msg = format!("{} channel should be 1 or 2 (was {})", $command, $channel);
+                return Err(crate::error::Error::InvalidInput(msg));
+            }
+        }
+    };
 }
 
-pub fn ratio_to_value(ratio: f32) -> Result<i32> {
-    if ratio > 1.0 || ratio < -1.0 {
-        return Err(Error::InvalidInput(format!(
-            "value ({}) out of range -1.0~1.0",
-            ratio
-        )));
+/// Write a whole frame, retrying a bounded number of times when interrupted,
+/// and until *timeout* when the write would block.
+///
+/// On failure the number of bytes of the frame that were written is returned
+/// along with the error, whose message includes it. A non-zero count means
+/// that an incomplete frame is on the line.
+pub fn write_frame<W: io::Write + ?Sized>(
+    dev: &mut W,
+    frame: &[u8],
+    timeout: Duration,
+) -> std::result::Result<(), (usize, Error)> {
+    let deadline = Instant::now() + timeout;
+    let mut written = 0;
+    let mut interrupts = 0;
+    while written < frame.len() {
+        let err = match dev.write(&frame[written..]) {
+            Ok(0) => io::Error::new(io::ErrorKind::WriteZero, "failed to write the frame"),
+            Ok(n) => {
+                written += n;
+                interrupts = 0;
+                continue;
+            }
+            Err(e)
+                if e.kind() == io::ErrorKind::Interrupted && interrupts < MAX_WRITE_INTERRUPTS =>
+            {
+                interrupts += 1;
+                continue;
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock && Instant::now() < deadline => {
+                thread::sleep(WOULD_BLOCK_PAUSE);
+                continue;
+            }
+            Err(e) => e,
+        };
+        let msg = format!(
+            "{} of {} bytes of the frame written: {}",
+            written,
+            frame.len(),
+            err
+        );
+        return Err((written, Error::Io(io::Error::new(err.kind(), msg))));
     }
+    Ok(())
+}
 
-    let value = (ratio * RANGE_MAX as f32) as i32;
+/// Read some bytes, retrying the reads which are interrupted or would block
+/// until *timeout*. The timeout of the port itself is still reported as an
+/// error of kind `TimedOut`.
+pub fn read_retrying<R: io::Read + ?Sized>(
+    dev: &mut R,
+    buf: &mut [u8],
+    timeout: Duration,
+) -> io::Result<usize> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        match dev.read(buf) {
+            Err(e) if e.kind() == io::ErrorKind::Interrupted && Instant::now() < deadline => {}
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                if Instant::now() >= deadline {
+                    return Err(io::Error::new(io::ErrorKind::TimedOut, e));
+                }
+                thread::sleep(WOULD_BLOCK_PAUSE);
+            }
+            res => return res,
+        }
+    }
+}
 
-    if value > RANGE_MAX {
-        Ok(RANGE_MAX)
-    } else if value < RANGE_MIN {
-        Ok(RANGE_MIN)
-    } else {
-        Ok(value)
+/// Same as `io::Read::read_exact()`, retrying like `read_retrying()`.
+pub fn read_exact_retrying<R: io::Read + ?Sized>(
+    dev: &mut R,
+    mut buf: &mut [u8],
+    timeout: Duration,
+) -> io::Result<()> {
+    while !buf.is_empty() {
+        match read_retrying(dev, buf, timeout)? {
+            0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+            n => buf = &mut buf[n..],
+        }
     }
+    Ok(())
+}
+
+/// Maximum absolute value of a command for the Sabertooth 2x60.
+pub const RANGE_2X60_MAX: i32 = 127;
+
+/// Convert a Sabertooth 2x32 command value (-2047~2047) to the range of the
+/// Sabertooth 2x60 (-127~127). The result is rounded to the nearest integer
+/// and out of range inputs are clamped.
+pub fn rescale_2x32_to_2x60(value: i32) -> i8 {
+    let value = value.clamp(RANGE_MIN, RANGE_MAX);
+    let scaled = (value as f32 * RANGE_2X60_MAX as f32 / RANGE_MAX as f32).round();
+    scaled as i8
+}
+
+/// Convert a Sabertooth 2x60 command value (-127~127) to the range of the
+/// Sabertooth 2x32 (-2047~2047). The result is rounded to the nearest integer
+/// and -128 is clamped to -127.
+pub fn rescale_2x60_to_2x32(value: i8) -> i32 {
+    let value = i32::from(value).clamp(-RANGE_2X60_MAX, RANGE_2X60_MAX);
+    (value as f32 * RANGE_MAX as f32 / RANGE_2X60_MAX as f32).round() as i32
 }
 
-pub fn value_to_ratio(value: i32) -> f32 {
-    value as f32 / RANGE_MAX as f32
+/// Wait for a half-duplex line to turn around after a request: flush the
+/// request, then *sleep* for *delay*. Nothing is done for a zero delay.
+pub fn turnaround<W, F>(dev: &mut W, delay: Duration, sleep: F) -> Result<()>
+where
+    W: io::Write + ?Sized,
+    F: FnOnce(Duration),
+{
+    if delay > Duration::from_secs(0) {
+        dev.flush()?;
+        sleep(delay);
+    }
+    Ok(())
+}
+
+/// Move *current* toward *target* by at most *max_step*, without going past
+/// *target*. Calling it once per iteration of a fixed-rate loop smooths a
+/// command without any timing.
+///
+/// # Example
+///
+/// ```
+/// use saberrs::step_toward;
+///
+/// assert_eq!(10, step_toward(0, 100, 10));
+/// assert_eq!(100, step_toward(95, 100, 10));
+/// ```
+pub fn step_toward(current: i8, target: i8, max_step: u8) -> i8 {
+    let (current, target, max_step) = (i16::from(current), i16::from(target), i16::from(max_step));
+    let step = (target - current).clamp(-max_step, max_step);
+    (current + step) as i8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rescale_to_2x60() {
+        assert_eq!(127, rescale_2x32_to_2x60(2047));
+        assert_eq!(0, rescale_2x32_to_2x60(0));
+        assert_eq!(-127, rescale_2x32_to_2x60(-2047));
+        assert_eq!(62, rescale_2x32_to_2x60(1000));
+        assert_eq!(127, rescale_2x32_to_2x60(5000));
+        assert_eq!(-127, rescale_2x32_to_2x60(i32::MIN));
+    }
+
+    #[test]
+    fn rescale_to_2x32() {
+        assert_eq!(2047, rescale_2x60_to_2x32(127));
+        assert_eq!(0, rescale_2x60_to_2x32(0));
+        assert_eq!(-2047, rescale_2x60_to_2x32(-127));
+        assert_eq!(-2047, rescale_2x60_to_2x32(-128));
+        assert_eq!(999, rescale_2x60_to_2x32(62));
+    }
+
+    #[test]
+    fn step_toward_target() {
+        assert_eq!(10, step_toward(0, 100, 10));
+        assert_eq!(-10, step_toward(0, -100, 10));
+        // No overshoot.
+        assert_eq!(5, step_toward(3, 5, 10));
+        assert_eq!(-5, step_toward(-3, -5, 10));
+        assert_eq!(5, step_toward(5, 5, 10));
+        assert_eq!(0, step_toward(0, 100, 0));
+        // The whole range in one step.
+        assert_eq!(127, step_toward(-128, 127, 255));
+        assert_eq!(-128, step_toward(127, -128, 255));
+    }
 }
@@ -0,0 +1,224 @@
+//! Check that the set and get paths do not allocate, with a global allocator counting
+//! the allocations of the current thread.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::io;
+use std::time::Duration;
+
+use saberrs::sabertooth2x32::{PacketSerial, PacketType, PlainText, Sabertooth2x32};
+use saberrs::{Result, SabertoothSerial};
+
+struct CountingAlloc;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|count| count.set(count.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+fn allocations() -> usize {
+    ALLOCATIONS.with(|count| count.get())
+}
+
+/// Port keeping the written bytes in a fixed buffer, since the mock port
+/// allocates.
+struct SinkPort {
+    written: [u8; 256],
+    len: usize,
+}
+
+impl SinkPort {
+    fn new() -> SinkPort {
+        SinkPort {
+            written: [0u8; 256],
+            len: 0,
+        }
+    }
+
+    fn written(&self) -> &[u8] {
+        &self.written[..self.len]
+    }
+}
+
+impl io::Read for SinkPort {
+    fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+        Err(io::ErrorKind::TimedOut.into())
+    }
+}
+
+impl io::Write for SinkPort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = buf.len().min(self.written.len() - self.len);
+        self.written[self.len..self.len + len].copy_from_slice(&buf[..len]);
+        self.len += len;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl SabertoothSerial for SinkPort {
+    fn set_timeout(&mut self, _timeout: Duration) -> Result<()> {
+        Ok(())
+    }
+
+    fn timeout(&self) -> Duration {
+        Duration::from_millis(100)
+    }
+
+    fn set_baud_rate(&mut self, _baud_rate: u32) -> Result<()> {
+        Ok(())
+    }
+
+    fn baud_rate(&self) -> Result<u32> {
+        Ok(9600)
+    }
+
+    fn clear_all(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Port answering every write with the same reply.
+struct ReplyPort {
+    reply: &'static [u8],
+    pos: usize,
+}
+
+impl ReplyPort {
+    fn new(reply: &'static [u8]) -> ReplyPort {
+        ReplyPort {
+            reply,
+            pos: reply.len(),
+        }
+    }
+}
+
+impl io::Read for ReplyPort {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.reply.len() {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        let len = buf.len().min(self.reply.len() - self.pos);
+        buf[..len].copy_from_slice(&self.reply[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+impl io::Write for ReplyPort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pos = 0;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl SabertoothSerial for ReplyPort {
+    fn set_timeout(&mut self, _timeout: Duration) -> Result<()> {
+        Ok(())
+    }
+
+    fn timeout(&self) -> Duration {
+        Duration::from_millis(100)
+    }
+
+    fn set_baud_rate(&mut self, _baud_rate: u32) -> Result<()> {
+        Ok(())
+    }
+
+    fn baud_rate(&self) -> Result<u32> {
+        Ok(9600)
+    }
+
+    fn clear_all(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn packet_get_does_not_allocate() {
+    // Battery voltage of 12.4 V.
+    let port = ReplyPort::new(b"\xf0\x49\x10\x56\x7c\x00\x4d\x31\x03\x6c");
+    let mut saber = PacketSerial::from(port);
+    saber.get_voltage(1).expect("Get value failure");
+    let before = allocations();
+    for _ in 0..100 {
+        let volts = saber.get_voltage(1).expect("Get value failure");
+        assert!((volts - 12.4).abs() < 0.001);
+    }
+    assert_eq!(before, allocations());
+}
+
+#[test]
+fn text_get_does_not_allocate() {
+    let port = ReplyPort::new(b"M1: 1023\r\n");
+    let mut saber = PlainText::from(port);
+    saber.get_speed(1).expect("Get value failure");
+    let before = allocations();
+    for _ in 0..100 {
+        let ratio = saber.get_speed(1).expect("Get value failure");
+        assert!((ratio - 0.5).abs() < 0.001);
+    }
+    assert_eq!(before, allocations());
+}
+
+#[test]
+fn packet_set_does_not_allocate() {
+    let mut port = SinkPort::new();
+    {
+        let mut saber = PacketSerial::from(&mut port);
+        let before = allocations();
+        saber.set_speed(1, 0.5).expect("Set value failure");
+        saber.shutdown(2).expect("Set value failure");
+        assert_eq!(before, allocations());
+    }
+    assert_eq!(
+        &b"\xf0\x28\x00\x0c\x7f\x07\x4d\x31\x17\x7c\xf0\x28\x20\x67\x01\x00\x4d\x32\x49\x32"[..],
+        port.written()
+    );
+
+    let mut port = SinkPort::new();
+    {
+        let mut saber = PacketSerial::from(&mut port).with_packet_type(PacketType::Checksum);
+        let before = allocations();
+        saber.set_drive(-1.0).expect("Set value failure");
+        assert_eq!(before, allocations());
+    }
+    assert_eq!(&b"\x80\x28\x01\x29\x7f\x0f\x4d\x44\x1f"[..], port.written());
+}
+
+#[test]
+fn text_set_does_not_allocate() {
+    let mut port = SinkPort::new();
+    {
+        let mut saber = PlainText::from(&mut port);
+        let before = allocations();
+        saber.set_speed(1, 0.5).expect("Set value failure");
+        saber.set_drive(-1.0).expect("Set value failure");
+        saber.startup(2).expect("Set value failure");
+        assert_eq!(before, allocations());
+    }
+    assert_eq!(
+        &b"M1: 1023\r\nMD: -2047\r\nM2: startup\r\n"[..],
+        port.written()
+    );
+}
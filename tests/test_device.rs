@@ -1,7 +1,9 @@
 use std::io::{Read, Write};
 use std::time::{Duration, Instant};
 
-use saberrs::SabertoothSerial;
+use serialport::{DataBits, FlowControl, Parity, SerialPort, StopBits};
+
+use saberrs::{Error, Framing, SabertoothPort, SabertoothSerial};
 
 mod utils;
 
@@ -75,3 +77,211 @@ fn timeout_actual() {
     do_timeout(Duration::from_millis(50));
     do_timeout(Duration::from_millis(100));
 }
+
+#[test]
+fn builder() {
+    let (_master, slave) = utils::tty_pair();
+    let name = slave.name().expect("TTY has no name");
+    drop(slave);
+    let saber = SabertoothPort::builder(&name)
+        .baud_rate(38400)
+        .timeout(Duration::from_millis(20))
+        .open_timeout(Duration::from_secs(5))
+        .open()
+        .expect("Cannot open the sabertooth device");
+    assert_eq!(38400, saber.baud_rate().unwrap());
+    assert_eq!(Duration::from_millis(20), saber.timeout());
+
+    let settings = saber.settings().expect("Cannot read the settings");
+    assert_eq!(38400, settings.baud_rate);
+    assert_eq!(DataBits::Eight, settings.data_bits);
+    assert_eq!(Parity::None, settings.parity);
+    assert_eq!(StopBits::One, settings.stop_bits);
+    assert_eq!(FlowControl::None, settings.flow_control);
+    assert_eq!(Duration::from_millis(20), settings.timeout);
+}
+
+#[test]
+fn open_timeout_missing_device() {
+    let tstart = Instant::now();
+    SabertoothPort::builder("/dev/saberrs-does-not-exist")
+        .open_timeout(Duration::from_millis(500))
+        .open_shared()
+        .expect_err("Opening a missing device should fail");
+    assert!(tstart.elapsed() < Duration::from_millis(500));
+}
+
+#[test]
+fn baud_rate_change_keeps_pending_output() {
+    let (mut saber, mut stub) = utils::saberdevice_harness();
+
+    let msg = b"M1: 1000\r\nM2: 1000\r\n";
+    saber.write_all(msg).expect("Write fail");
+    saber.set_baud_rate(38400).expect("Could not set baud rate");
+    assert_eq!(38400, saber.baud_rate().unwrap());
+
+    let mut buf = [0u8; 20];
+    stub.read_exact(&mut buf).expect("Read fail");
+    assert_eq!(&msg[..], &buf[..]);
+}
+
+#[test]
+fn settings_after_baud_rate_change() {
+    let (saber, _tty) = utils::saberdevice_harness_shared();
+    let mut dev = saber.clone();
+    dev.set_baud_rate(115200).expect("Could not set baud rate");
+    let settings = saber.settings().expect("Cannot read the settings");
+    assert_eq!(115200, settings.baud_rate);
+    assert_eq!(Duration::from_millis(100), settings.timeout);
+}
+
+#[test]
+fn exclusive_open() {
+    let (_master, slave) = utils::tty_pair();
+    let slave_name = slave.name().expect("TTY has no name");
+    drop(slave);
+
+    let saber = SabertoothPort::new(&slave_name).expect("Cannot open the sabertooth device");
+    match SabertoothPort::new(&slave_name) {
+        Err(Error::PortInUse(msg)) => assert!(msg.contains("another process"), "{}", msg),
+        Err(e) => panic!("Expected a port in use error, got {:?}", e),
+        Ok(_) => panic!("Opening an exclusive port twice should fail"),
+    }
+    drop(saber);
+
+    let _first = SabertoothPort::builder(&slave_name)
+        .exclusive(false)
+        .open()
+        .expect("Cannot open the sabertooth device");
+    let _second = SabertoothPort::builder(&slave_name)
+        .exclusive(false)
+        .open()
+        .expect("Cannot open a non-exclusive port twice");
+}
+
+#[test]
+fn open_with_framing() {
+    let (_master, slave) = utils::tty_pair();
+    let name = slave.name().expect("TTY has no name");
+    drop(slave);
+    // The Linux pty driver forces 8 data bits without parity, only the stop
+    // bits are kept.
+    let saber = SabertoothPort::builder(&name)
+        .stop_bits(StopBits::Two)
+        .open()
+        .expect("Cannot open the sabertooth device");
+    let framing = saber.framing().expect("Cannot read the framing");
+    assert_eq!(2, framing.stop_bits);
+    assert_eq!("8N2", framing.to_string());
+    let settings = saber.settings().expect("Cannot read the settings");
+    assert_eq!(StopBits::Two, settings.stop_bits);
+    drop(saber);
+
+    let saber = SabertoothPort::new(&name).expect("Cannot open the sabertooth device");
+    assert_eq!(Framing::default(), saber.framing().unwrap());
+}
+
+/// Tests shared by the serial backends, proving that they behave the same.
+mod parity {
+    use std::io::{ErrorKind, Read, Write};
+    use std::time::Duration;
+
+    use serialport::{SerialPort, TTYPort};
+
+    use saberrs::{Error, Framing, Result, SabertoothPort, SabertoothSerial};
+
+    use super::utils;
+
+    /// Open the slave of a new pty pair with *open*, and return it with the
+    /// master.
+    fn harness<P>(open: impl Fn(&str) -> Result<P>) -> (P, TTYPort) {
+        let (master, slave) = utils::tty_pair();
+        let name = slave.name().expect("TTY has no name");
+        drop(slave);
+        let saber = open(&name).expect("Cannot open the sabertooth device");
+        (saber, master)
+    }
+
+    fn write_and_read<P: SabertoothSerial>(open: impl Fn(&str) -> Result<P>) {
+        let (mut saber, mut stub) = harness(open);
+
+        let msg = b"M1: 1000\r\n";
+        saber.write_all(msg).expect("Write fail");
+        saber.flush().expect("Flush fail");
+        let mut buf = [0u8; 10];
+        stub.read_exact(&mut buf).expect("Read fail");
+        assert_eq!(&msg[..], &buf[..]);
+
+        stub.write_all(b"M1:0\r\n").expect("Write fail");
+        let mut buf = [0u8; 6];
+        saber.read_exact(&mut buf).expect("Read fail");
+        assert_eq!(b"M1:0\r\n", &buf);
+    }
+
+    fn settings<P: SabertoothSerial>(open: impl Fn(&str) -> Result<P>) {
+        let (mut saber, _stub) = harness(open);
+        assert_eq!(9600, saber.baud_rate().unwrap());
+        assert_eq!(Duration::from_millis(100), saber.timeout());
+        assert_eq!(Framing::default(), saber.framing().unwrap());
+
+        saber.set_baud_rate(38400).expect("Could not set baud rate");
+        assert_eq!(38400, saber.baud_rate().unwrap());
+    }
+
+    fn read_timeout<P: SabertoothSerial>(open: impl Fn(&str) -> Result<P>) {
+        let (mut saber, _stub) = harness(open);
+        saber
+            .set_timeout(Duration::from_millis(10))
+            .expect("Could not set timeout");
+        let mut buf = [0u8; 4];
+        let e = saber.read(&mut buf).expect_err("Timeout did not occured");
+        assert_eq!(ErrorKind::TimedOut, e.kind());
+    }
+
+    fn exclusive_open<P: SabertoothSerial>(
+        open: impl Fn(&str) -> Result<P>,
+        open_shared: impl Fn(&str) -> Result<P>,
+    ) {
+        let (_master, slave) = utils::tty_pair();
+        let name = slave.name().expect("TTY has no name");
+        drop(slave);
+        let saber = open(&name).expect("Cannot open the sabertooth device");
+        match open_shared(&name) {
+            Err(Error::PortInUse(_)) => {}
+            Err(e) => panic!("Unexpected error: {:?}", e),
+            Ok(_) => panic!("The port was opened twice"),
+        }
+        drop(saber);
+        open_shared(&name).expect("Cannot open the sabertooth device");
+    }
+
+    fn sabertoothport_shared(path: &str) -> Result<SabertoothPort> {
+        SabertoothPort::builder(path).exclusive(false).open()
+    }
+
+    #[test]
+    fn sabertoothport() {
+        write_and_read(SabertoothPort::new);
+        settings(SabertoothPort::new);
+        read_timeout(SabertoothPort::new);
+        exclusive_open(SabertoothPort::new, sabertoothport_shared);
+    }
+
+    #[cfg(feature = "termios")]
+    #[test]
+    fn termiosport() {
+        use saberrs::TermiosPort;
+
+        let shared = |path: &str| TermiosPort::builder(path).exclusive(false).open();
+        write_and_read(TermiosPort::new);
+        settings(TermiosPort::new);
+        read_timeout(TermiosPort::new);
+        exclusive_open(TermiosPort::new, shared);
+
+        let (mut saber, _stub) = harness(TermiosPort::new);
+        match saber.set_baud_rate(12345) {
+            Err(Error::InvalidInput(_)) => {}
+            r => panic!("Unexpected result: {:?}", r),
+        }
+    }
+}
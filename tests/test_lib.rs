@@ -1,7 +1,19 @@
-use std::io::Write;
+use std::io::{Read, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
-use saberrs::sabertooth2x32::{PacketSerial, PlainText, Sabertooth2x32};
+use serialport::SerialPort;
 
+use saberrs::sabertooth2x32::{
+    profile, verify_frame, AsyncQueueDriver, Command, Direction, DriveMode, EchoMode,
+    HealthcheckMode, OutputLimiter, PacketSerial, PacketType, PlainText, PreSendAction,
+    PrecomputedCommand, PrettyFrameLogger, Query, QueryScheduler, RateLimitPolicy, RateLimiter,
+    Sabertooth2x32, Sampling, Scheduled, SleepFn, SpeedPresets, TelemetryPolicy, Trajectory,
+    TrajectoryProgress, TrajectoryRunner, TrajectoryState, Transaction, ZeroSpeed,
+};
+use saberrs::{convert, Error, PowerControl, Result, SabertoothSerial, StreamPort};
+
+#[macro_use]
 mod utils;
 
 #[test]
@@ -16,3 +28,2319 @@ fn instantiate_multiple_protocols() {
         .set_speed(1, 0.2)
         .expect("Checksum interface failed");
 }
+
+#[test]
+fn transaction_commit() {
+    let (mut sabertext, mut tty) = utils::sabertext_harness();
+
+    Transaction::new()
+        .with(Command::Speed(1, 0.5))
+        .with(Command::Drive(-1.0))
+        .with(Command::Shutdown(2))
+        .commit(&mut sabertext)
+        .expect("Commit failure");
+
+    let expected = b"M1: 1023\r\nMD: -2047\r\nM2: shutdown\r\n";
+    let mut buf = [0u8; 35];
+    tty.read_exact(&mut buf).expect("Read fail");
+    assert_eq!(&expected[..], &buf[..]);
+}
+
+#[test]
+fn transaction_invalid_command() {
+    let (mut sabertext, tty) = utils::sabertext_harness();
+
+    let transactions = [
+        Transaction::new()
+            .with(Command::Speed(1, 0.5))
+            .with(Command::Speed(3, 0.5)),
+        Transaction::new()
+            .with(Command::Startup(1))
+            .with(Command::Turn(1.5)),
+        Transaction::new().with(Command::Aux(0, 0.0)),
+    ];
+
+    for transaction in transactions.iter() {
+        transaction
+            .commit(&mut sabertext)
+            .expect_err("Invalid transaction should fail");
+    }
+
+    // nothing should have been sent over serial
+    assert_eq!(0, tty.bytes_to_read().unwrap());
+}
+
+#[test]
+fn transaction_strict_safety() {
+    let port = utils::MockPort::new();
+    let mut saber = PacketSerial::from(&port)
+        .with_strict_safety(true)
+        .with_zero_speed(ZeroSpeed::Shutdown);
+    saber
+        .set_serial_timeout(Duration::from_millis(500))
+        .expect("Set timeout failure");
+    port.take_written();
+
+    // The channel 2 is shut down by the first command of each transaction.
+    let transactions = [
+        Transaction::new()
+            .with(Command::Shutdown(2))
+            .with(Command::Power(2, 0.5)),
+        Transaction::new()
+            .with(Command::Speed(1, 0.5))
+            .with(Command::Speed(2, 0.0))
+            .with(Command::Drive(0.5)),
+    ];
+    for transaction in transactions.iter() {
+        match transaction.commit(&mut saber) {
+            Err(Error::Safety(_)) => {}
+            res => panic!("Expected a safety error, got {:?}", res),
+        }
+        assert!(
+            port.take_written().is_empty(),
+            "Refused transaction was sent"
+        );
+    }
+
+    // A speed restarts a channel shut down by a zero speed.
+    Transaction::new()
+        .with(Command::Speed(2, 0.0))
+        .with(Command::Speed(2, 0.5))
+        .with(Command::Startup(1))
+        .commit(&mut saber)
+        .expect("Commit failure");
+    assert_eq!(Some(false), saber.is_shutdown(2));
+}
+
+#[test]
+fn last_commands() {
+    let port = utils::MockPort::new();
+    let mut saber = PacketSerial::from(&port);
+    assert_eq!(None, saber.last_commands().speed(1));
+
+    saber.set_speed(1, 0.5).expect("Set value failure");
+    saber.set_turn(-0.25).expect("Set value failure");
+    assert_eq!(Some(0.5), saber.last_commands().speed(1));
+    assert_eq!(None, saber.last_commands().speed(2));
+    assert_eq!(Some(-0.25), saber.last_commands().turn());
+
+    // Failed writes are not recorded.
+    port.set_fail_writes(true);
+    saber.set_speed(1, 1.0).expect_err("Write should fail");
+    saber.set_drive(1.0).expect_err("Write should fail");
+    assert_eq!(Some(0.5), saber.last_commands().speed(1));
+    assert_eq!(None, saber.last_commands().drive());
+
+    saber.clear_cached_state();
+    assert_eq!(None, saber.last_commands().speed(1));
+    assert_eq!(None, saber.last_commands().turn());
+}
+
+#[test]
+fn last_commands_text() {
+    let port = utils::MockPort::new();
+    let mut saber = PlainText::from(&port);
+
+    saber.set_power(2, -1.0).expect("Set value failure");
+    saber.set_aux(1, 0.75).expect("Set value failure");
+    saber.set_speed(3, 0.5).expect_err("Channel 3 should fail");
+    assert_eq!(Some(-1.0), saber.last_commands().power(2));
+    assert_eq!(Some(0.75), saber.last_commands().aux(1));
+    assert_eq!(None, saber.last_commands().speed(3));
+
+    port.set_fail_writes(true);
+    saber.set_ramp(1, 0.5).expect_err("Write should fail");
+    assert_eq!(None, saber.last_commands().ramp(1));
+}
+
+#[test]
+fn inter_frame_delay() {
+    let delay = Duration::from_millis(20);
+    let clock = utils::MockClock::new();
+    let (now, sleep) = clock.functions();
+    let port = utils::MockPort::new();
+    let mut saber = PacketSerial::from(&port).with_inter_frame_delay(delay);
+    saber.set_clock(now, sleep);
+
+    saber.set_speed(1, 0.5).expect("Set value failure");
+    saber.set_speed(2, 0.5).expect("Set value failure");
+    saber
+        .broadcast_stop(&[128, 129])
+        .expect("Broadcast failure");
+
+    // The broadcast frames are not coalesced: 2 + 2 * 2 writes, each one
+    // waiting for the whole delay after the first.
+    assert_eq!(6, port.take_write_times().len());
+    assert_eq!(vec![delay; 5], clock.take_sleeps());
+
+    // Only the remaining part of the delay is waited for.
+    clock.advance(Duration::from_millis(15));
+    saber.set_speed(1, 0.0).expect("Set value failure");
+    assert_eq!(vec![Duration::from_millis(5)], clock.take_sleeps());
+    clock.advance(delay);
+    saber.set_speed(1, 0.5).expect("Set value failure");
+    assert!(clock.take_sleeps().is_empty());
+}
+
+#[test]
+fn latency_excludes_delays() {
+    let delay = Duration::from_millis(100);
+    let port = utils::MockPort::new();
+    let mut saber = PacketSerial::from(&port)
+        .with_inter_frame_delay(delay)
+        .with_turnaround_delay(delay);
+    saber.set_speed(1, 0.5).expect("Set value failure");
+    port.push_reply(b"\xf0\x49\x10\x56\x7c\x00\x4d\x31\x03\x6c");
+    let tstart = Instant::now();
+    assert_eq_float!(12.4, saber.get_voltage(1).expect("Get value failure"));
+    assert!(tstart.elapsed() >= 2 * delay);
+    let latency = saber.latency_stats().last.expect("No latency measured");
+    assert!(latency < delay, "{:?} includes the delays", latency);
+
+    let mut sabertext = PlainText::from(&port).with_turnaround_delay(delay);
+    port.push_reply(b"M1: C150\r\n");
+    assert_eq_float!(15.0, sabertext.get_current(1).expect("Get value failure"));
+    port.push_reply(b"M2: -2047\r\nM1: 0\r\n");
+    let (m1, m2) = sabertext.get_speed_both().expect("Get value failure");
+    assert_eq_float!(0.0, m1);
+    assert_eq_float!(-1.0, m2);
+    let stats = sabertext.latency_stats();
+    assert_eq!(2, stats.count);
+    assert!(
+        stats.average.unwrap() < delay,
+        "{:?} includes the delays",
+        stats
+    );
+}
+
+#[test]
+fn no_inter_frame_delay() {
+    let port = utils::MockPort::new();
+    let mut saber = PacketSerial::from(&port);
+
+    saber
+        .broadcast_stop(&[128, 129])
+        .expect("Broadcast failure");
+    assert_eq!(1, port.take_write_times().len());
+}
+
+#[test]
+fn borrowed_port() {
+    let mut port = utils::MockPort::new();
+    let inspect = port.clone();
+
+    PacketSerial::from(&mut port)
+        .with_packet_type(PacketType::Checksum)
+        .set_speed(1, 0.0)
+        .expect("Set value failure");
+    PacketSerial::from(&mut port)
+        .with_address(129)
+        .set_speed(2, 0.0)
+        .expect("Set value failure");
+    PlainText::from(&mut port)
+        .set_drive(0.0)
+        .expect("Set value failure");
+
+    let mut expected = b"\x80\x28\x00\x28\x00\x00M1\x7e".to_vec();
+    expected.extend_from_slice(b"\xf1\x28\x00\x70\x00\x00M2\x14\x4c");
+    expected.extend_from_slice(b"MD: 0\r\n");
+    assert_eq!(expected, inspect.take_written());
+}
+
+#[test]
+fn pre_send_hook() {
+    let port = utils::MockPort::new();
+    let mut saber = PacketSerial::from(&port).with_packet_type(PacketType::Checksum);
+    saber.set_pre_send_hook(Box::new(|command| match command {
+        Command::Speed(_, ratio) if ratio > 0.0 => PreSendAction::Block,
+        Command::Drive(ratio) if ratio > 0.0 => PreSendAction::Replace(Command::Drive(0.0)),
+        _ => PreSendAction::Allow,
+    }));
+
+    saber
+        .set_speed(1, 0.5)
+        .expect("Blocked command should succeed");
+    assert!(port.take_written().is_empty(), "Blocked command was sent");
+    assert_eq!(None, saber.last_commands().speed(1));
+
+    saber.set_speed(1, 0.0).expect("Set value failure");
+    assert_eq!(
+        b"\x80\x28\x00\x28\x00\x00M1\x7e".to_vec(),
+        port.take_written()
+    );
+
+    saber.set_drive(1.0).expect("Set value failure");
+    assert_eq!(
+        b"\x80\x28\x00\x28\x00\x00MD\x11".to_vec(),
+        port.take_written()
+    );
+    assert_eq!(Some(0.0), saber.last_commands().drive());
+
+    // The broadcast stop is never blocked.
+    saber.set_pre_send_hook(Box::new(|_| PreSendAction::Block));
+    saber.broadcast_stop(&[129]).expect("Broadcast failure");
+    assert_eq!(
+        b"\x81\x28\x00\x29\x00\x00M1\x7e\x81\x28\x00\x29\x00\x00M2\x7f".to_vec(),
+        port.take_written()
+    );
+
+    let mut sabertext = PlainText::from(&port);
+    sabertext.set_pre_send_hook(Box::new(|_| PreSendAction::Block));
+    sabertext
+        .shutdown(1)
+        .expect("Blocked command should succeed");
+    assert!(port.take_written().is_empty(), "Blocked command was sent");
+}
+
+#[test]
+fn partial_write_text() {
+    let port = utils::MockPort::new();
+    let mut saber = PlainText::from(&port);
+
+    port.set_fail_after(Some(3));
+    let err = saber.set_speed(1, 0.5).expect_err("Write should fail");
+    assert!(format!("{}", err).contains("3 of 10 bytes"), "{}", err);
+    assert_eq!(b"M1:".to_vec(), port.take_written());
+
+    // The incomplete line is terminated before the next command.
+    port.set_fail_after(None);
+    saber.set_speed(1, 0.0).expect("Set value failure");
+    assert_eq!(b"\r\nM1: 0\r\n".to_vec(), port.take_written());
+    saber.set_speed(2, 0.0).expect("Set value failure");
+    assert_eq!(b"M2: 0\r\n".to_vec(), port.take_written());
+}
+
+#[test]
+fn partial_write_packet() {
+    let port = utils::MockPort::new();
+    let mut saber = PacketSerial::from(&port).with_packet_type(PacketType::Checksum);
+
+    port.set_fail_after(Some(4));
+    let err = saber.set_speed(1, 0.0).expect_err("Write should fail");
+    assert!(format!("{}", err).contains("4 of 9 bytes"), "{}", err);
+    assert_eq!(b"\x80\x28\x00\x28".to_vec(), port.take_written());
+}
+
+#[test]
+fn interrupted_write() {
+    let port = utils::MockPort::new();
+    let mut saber = PlainText::from(&port);
+
+    port.set_interrupts(3);
+    saber
+        .set_speed(1, 0.0)
+        .expect("Interrupted write should be retried");
+    assert_eq!(b"M1: 0\r\n".to_vec(), port.take_written());
+
+    port.set_interrupts(100);
+    saber.set_speed(1, 0.0).expect_err("Write should give up");
+    port.set_interrupts(0);
+}
+
+#[test]
+fn interrupted_io_retried() {
+    let port = utils::MockPort::new();
+    port.set_flaky(true);
+
+    let mut saber = PacketSerial::from(&port);
+    saber.set_speed(1, 0.5).expect("Set value failure");
+    assert_eq!(
+        b"\xf0\x28\x00\x0c\x7f\x07\x4d\x31\x17\x7c".to_vec(),
+        port.take_written()
+    );
+    port.push_reply(b"\xf0\x49\x10\x56\x7c\x00\x4d\x31\x03\x6c");
+    port.set_would_block(3);
+    assert_eq_float!(12.4, saber.get_voltage(1).expect("Get value failure"));
+
+    let mut sabertext = PlainText::from(&port);
+    port.push_reply(b"M1: C150\r\n");
+    port.set_would_block(3);
+    assert_eq_float!(15.0, sabertext.get_current(1).expect("Get value failure"));
+    port.set_flaky(false);
+    port.take_written();
+
+    // A missing reply is still a timeout.
+    match saber.get_voltage(1) {
+        Err(Error::Io(e)) => assert_eq!(std::io::ErrorKind::TimedOut, e.kind()),
+        res => panic!("Expected a timeout, got {:?}", res),
+    }
+}
+
+#[test]
+fn foreign_replies_deadline() {
+    let port = utils::MockPort::new();
+    port.set_block_when_empty(true);
+    let mut saber = PacketSerial::from(&port).with_packet_type(PacketType::Checksum);
+
+    // A reply of another address arrives late, then nothing: waiting for the
+    // next reply ends at the timeout of the whole request.
+    port.push_reply(b"\x81\x49\x20\x6a\x05\x00\x4d\x31\x03");
+    port.set_would_block(60);
+    let start = Instant::now();
+    match saber.get_current(1) {
+        Err(Error::Io(e)) => {
+            assert_eq!(std::io::ErrorKind::TimedOut, e.kind());
+            assert!(e.to_string().contains("1 replies"), "{}", e);
+        }
+        res => panic!("Expected a timeout, got {:?}", res),
+    }
+    let elapsed = start.elapsed();
+    assert_eq!(1, saber.skipped_replies());
+    assert_eq!(Duration::from_millis(100), port.timeout());
+    // The foreign reply is read after at least 60 ms of pauses: waiting a
+    // whole timeout again would end after 160 ms, the deadline ends at 100 ms
+    // and leaves 60 ms of slack for a loaded machine.
+    assert!(elapsed >= Duration::from_millis(100), "{:?}", elapsed);
+    assert!(elapsed < Duration::from_millis(160), "{:?}", elapsed);
+}
+
+#[test]
+fn reject_non_finite() {
+    fn check<S: Sabertooth2x32>(saber: &mut S, port: &utils::MockPort) {
+        for &ratio in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY].iter() {
+            saber
+                .set_speed(1, ratio)
+                .expect_err("set_speed should fail");
+            saber.set_drive(ratio).expect_err("set_drive should fail");
+            saber.set_turn(ratio).expect_err("set_turn should fail");
+            saber
+                .set_power(1, ratio)
+                .expect_err("set_power should fail");
+            saber.set_ramp(1, ratio).expect_err("set_ramp should fail");
+            saber.set_aux(1, ratio).expect_err("set_aux should fail");
+            assert!(port.take_written().is_empty(), "Data sent for {}", ratio);
+        }
+    }
+
+    let port = utils::MockPort::new();
+    check(&mut PacketSerial::from(&port), &port);
+    check(
+        &mut PacketSerial::from(&port)
+            .with_current_limit(10.0)
+            .expect("Valid limit"),
+        &port,
+    );
+    check(&mut PlainText::from(&port), &port);
+    check(
+        &mut PlainText::from(&port)
+            .with_current_limit(10.0)
+            .expect("Valid limit"),
+        &port,
+    );
+}
+
+#[test]
+fn current_limit_invalid() {
+    let port = utils::MockPort::new();
+    for &amps in &[0.0, -10.0, f32::NAN, f32::INFINITY] {
+        match PlainText::from(&port).with_current_limit(amps) {
+            Err(Error::InvalidInput(_)) => {}
+            Err(e) => panic!("Unexpected error for {}: {}", amps, e),
+            Ok(_) => panic!("Limit {} accepted", amps),
+        }
+        match PacketSerial::from(&port).with_current_limit(amps) {
+            Err(Error::InvalidInput(_)) => {}
+            Err(e) => panic!("Unexpected error for {}: {}", amps, e),
+            Ok(_) => panic!("Limit {} accepted", amps),
+        }
+    }
+    assert!(port.take_written().is_empty());
+}
+
+#[test]
+fn current_limit_packet() {
+    let port = utils::MockPort::new();
+    let mut saber = PacketSerial::from(&port)
+        .with_packet_type(PacketType::Checksum)
+        .with_current_limit(10.0)
+        .expect("Valid limit");
+
+    // 20A measured for a 10A limit: the speed is halved.
+    port.push_reply(b"\x80\x49\x20\x69\x14\x00\x4D\x31\x12");
+    saber.set_speed(1, 1.0).expect("Set value failure");
+    let mut expected = b"\x80\x29\x20\x49\x4D\x31\x7E".to_vec();
+    expected.extend_from_slice(b"\x80\x28\x00\x28\x7F\x07\x4D\x31\x04");
+    assert_eq!(expected, port.take_written());
+
+    // 5A measured: the speed is sent unchanged.
+    port.push_reply(b"\x80\x49\x20\x69\x05\x00\x4D\x32\x04");
+    saber.set_speed(2, -0.5).expect("Set value failure");
+    let mut expected = b"\x80\x29\x20\x49\x4D\x32\x7F".to_vec();
+    expected.extend_from_slice(b"\x80\x28\x01\x29\x7F\x07\x4D\x32\x05");
+    assert_eq!(expected, port.take_written());
+}
+
+#[test]
+fn init_mixed_mode() {
+    let port = utils::MockPort::new();
+
+    let mut saber = PacketSerial::from(&port).with_packet_type(PacketType::Checksum);
+    saber.init_mixed_mode().expect("Set value failure");
+    let mut expected = b"\x80\x28\x00\x28\x00\x00MD\x11".to_vec();
+    expected.extend_from_slice(b"\x80\x28\x00\x28\x00\x00MT\x21");
+    assert_eq!(expected, port.take_written());
+
+    let mut sabertext = PlainText::from(&port);
+    sabertext.init_mixed_mode().expect("Set value failure");
+    assert_eq!(b"MD: 0\r\nMT: 0\r\n".to_vec(), port.take_written());
+}
+
+#[test]
+fn wire_transform() {
+    let prefix = |frame: &[u8]| {
+        let mut data = vec![0xaa];
+        data.extend_from_slice(frame);
+        data
+    };
+    let port = utils::MockPort::new();
+
+    let mut saber = PacketSerial::from(&port).with_packet_type(PacketType::Checksum);
+    saber.set_wire_transform(Box::new(prefix));
+    saber.set_speed(1, 0.0).expect("Set value failure");
+    assert_eq!(
+        b"\xaa\x80\x28\x00\x28\x00\x00M1\x7e".to_vec(),
+        port.take_written()
+    );
+
+    let mut sabertext = PlainText::from(&port);
+    sabertext.set_wire_transform(Box::new(prefix));
+    sabertext.set_speed(2, 0.0).expect("Set value failure");
+    assert_eq!(b"\xaaM2: 0\r\n".to_vec(), port.take_written());
+}
+
+#[test]
+fn strict_safety() {
+    let port = utils::MockPort::new();
+    let mut saber = PacketSerial::from(&port)
+        .with_packet_type(PacketType::Checksum)
+        .with_strict_safety(true);
+    assert!(saber.strict_safety());
+    assert!(!saber.timeout_armed());
+
+    match saber.set_speed(1, 0.5) {
+        Err(Error::Safety(_)) => {}
+        res => panic!("Expected a safety error, got {:?}", res),
+    }
+    saber.set_drive(-0.1).expect_err("Drive should be refused");
+    assert!(port.take_written().is_empty(), "Refused command was sent");
+
+    // Stopping is always allowed.
+    saber.stop_motors().expect("Stop should be allowed");
+    port.take_written();
+
+    saber
+        .set_serial_timeout(Duration::from_millis(500))
+        .expect("Set timeout failure");
+    assert!(saber.timeout_armed());
+    assert_eq!(
+        b"\x80\x28\x40\x68\x74\x03M*\x6e".to_vec(),
+        port.take_written()
+    );
+    saber
+        .set_speed(1, 0.5)
+        .expect("Armed speed should be allowed");
+    port.take_written();
+
+    match saber.set_serial_timeout(Duration::from_secs(0)) {
+        Err(Error::Safety(_)) => {}
+        res => panic!("Expected a safety error, got {:?}", res),
+    }
+    assert!(saber.timeout_armed());
+    assert!(port.take_written().is_empty(), "Refused command was sent");
+}
+
+#[test]
+fn strict_safety_plaintext() {
+    let port = utils::MockPort::new();
+    let mut saber = PlainText::from(&port).with_strict_safety(true);
+    assert!(saber.strict_safety());
+    assert!(!saber.timeout_armed());
+
+    match saber.set_speed(1, 0.5) {
+        Err(Error::Safety(_)) => {}
+        res => panic!("Expected a safety error, got {:?}", res),
+    }
+    saber.stop_motors().expect("Stop should be allowed");
+    port.take_written();
+
+    match saber.declare_serial_timeout(Duration::from_secs(0)) {
+        Err(Error::Safety(_)) => {}
+        res => panic!("Expected a safety error, got {:?}", res),
+    }
+    saber
+        .declare_serial_timeout(Duration::from_millis(500))
+        .expect("Declare timeout failure");
+    assert!(saber.timeout_armed());
+    assert!(port.take_written().is_empty(), "Declaring sent a command");
+    saber
+        .set_speed(1, 0.5)
+        .expect("Armed speed should be allowed");
+    port.take_written();
+
+    saber.shutdown(2).expect("Set value failure");
+    port.take_written();
+    match saber.set_speed(2, 0.5) {
+        Err(Error::Safety(_)) => {}
+        res => panic!("Expected a safety error, got {:?}", res),
+    }
+    saber.set_drive_mode(DriveMode::Independent);
+    match saber.set_turn(0.5) {
+        Err(Error::Safety(_)) => {}
+        res => panic!("Expected a safety error, got {:?}", res),
+    }
+    assert!(port.take_written().is_empty(), "Refused command was sent");
+}
+
+#[test]
+fn rate_limiter_drop() {
+    let clock = utils::MockClock::new();
+    let (now, sleep) = clock.functions();
+    let port = utils::MockPort::new();
+    let saber = PlainText::from(&port);
+    let mut saber = RateLimiter::new(saber, Duration::from_millis(200)).with_clock(now, sleep);
+
+    for _ in 0..5 {
+        saber.set_speed(1, 0.5).expect("Set value failure");
+    }
+    assert_eq!(b"M1: 1023\r\n".to_vec(), port.take_written());
+    assert_eq!(4, saber.dropped_count());
+
+    // Stopping is never limited.
+    saber.set_speed(1, 0.0).expect("Set value failure");
+    assert_eq!(b"M1: 0\r\n".to_vec(), port.take_written());
+
+    clock.advance(Duration::from_millis(199));
+    saber.set_speed(1, 0.5).expect("Set value failure");
+    assert!(port.take_written().is_empty(), "Command sent too early");
+    clock.advance(Duration::from_millis(1));
+    saber.set_speed(1, 0.25).expect("Set value failure");
+    // The held command is sent once due, and holds back the new one.
+    assert_eq!(b"M1: 1023\r\n".to_vec(), port.take_written());
+    assert_eq!(6, saber.dropped_count());
+    clock.advance(Duration::from_millis(200));
+    assert_eq!(1, saber.send_pending().expect("Send failure"));
+    assert_eq!(b"M1: 511\r\n".to_vec(), port.take_written());
+}
+
+#[test]
+fn rate_limiter_from_baud_rate() {
+    let port = utils::MockPort::new();
+    let saber = RateLimiter::from_baud_rate(PacketSerial::from(&port), 9600, 10);
+    assert_eq!(Duration::from_micros(10417), saber.period());
+    let saber = RateLimiter::from_baud_rate(PacketSerial::from(&port), 115200, 10);
+    assert_eq!(Duration::from_micros(868), saber.period());
+}
+
+#[test]
+fn rate_limiter_drop_channels() {
+    let period = Duration::from_millis(100);
+    let clock = utils::MockClock::new();
+    let (now, sleep) = clock.functions();
+    let port = utils::MockPort::new();
+    let saber = PlainText::from(&port);
+    let mut saber = RateLimiter::new(saber, period).with_clock(now, sleep);
+
+    // Both channels are limited separately, the latest values are held.
+    for &(m1, m2) in &[(0.5, -0.5), (0.25, -0.25), (1.0, -1.0)] {
+        saber.set_speed(1, m1).expect("Set value failure");
+        saber.set_speed(2, m2).expect("Set value failure");
+    }
+    assert_eq!(b"M1: 1023\r\nM2: -1023\r\n".to_vec(), port.take_written());
+    assert_eq!(4, saber.dropped_count());
+    assert_eq!(0, saber.send_pending().expect("Send failure"));
+    clock.advance(period);
+    assert_eq!(2, saber.send_pending().expect("Send failure"));
+    assert_eq!(b"M1: 2047\r\nM2: -2047\r\n".to_vec(), port.take_written());
+
+    // A held value is sent by the next command once due.
+    saber.set_speed(2, 0.5).expect("Set value failure");
+    clock.advance(period);
+    saber.set_speed(1, 0.25).expect("Set value failure");
+    assert_eq!(b"M2: 1023\r\nM1: 511\r\n".to_vec(), port.take_written());
+
+    // Stopping a channel discards its held value.
+    saber.set_speed(1, 0.5).expect("Set value failure");
+    saber.set_speed(1, 0.0).expect("Set value failure");
+    assert_eq!(b"M1: 0\r\n".to_vec(), port.take_written());
+    clock.advance(period);
+    assert_eq!(0, saber.send_pending().expect("Send failure"));
+    assert_eq!(6, saber.dropped_count());
+}
+
+#[test]
+fn rate_limiter_delay() {
+    let period = Duration::from_millis(20);
+    let clock = utils::MockClock::new();
+    let (now, sleep) = clock.functions();
+    let port = utils::MockPort::new();
+    let saber = PlainText::from(&port);
+    let mut saber = RateLimiter::new(saber, period)
+        .with_policy(RateLimitPolicy::Delay)
+        .with_clock(now, sleep);
+
+    for _ in 0..3 {
+        saber.set_drive(0.5).expect("Set value failure");
+    }
+    assert_eq!(0, saber.dropped_count());
+    assert_eq!(b"MD: 1023\r\n".repeat(3), port.take_written());
+    // The second and third commands wait for the whole period.
+    assert_eq!(vec![period, period], clock.take_sleeps());
+
+    clock.advance(Duration::from_millis(15));
+    saber.set_drive(0.25).expect("Set value failure");
+    assert_eq!(vec![Duration::from_millis(5)], clock.take_sleeps());
+}
+
+#[test]
+fn rate_limiter_stop_debounce() {
+    let window = Duration::from_millis(100);
+    let clock = utils::MockClock::new();
+    let (now, sleep) = clock.functions();
+    let port = utils::MockPort::new();
+    let saber = PlainText::from(&port);
+    let mut saber = RateLimiter::new(saber, Duration::from_secs(0))
+        .with_stop_debounce(window)
+        .with_clock(now, sleep);
+
+    // Only the first of the stops within the window is sent.
+    for _ in 0..10 {
+        saber.stop_motors().expect("Stop failure");
+    }
+    assert_eq!(b"M1: 0\r\nM2: 0\r\n".to_vec(), port.take_written());
+    assert_eq!(9, saber.dropped_count());
+
+    // A command in between ends the debouncing.
+    saber.set_speed(1, 0.5).expect("Set value failure");
+    saber.stop_motors().expect("Stop failure");
+    saber.stop_motors().expect("Stop failure");
+    assert_eq!(
+        b"M1: 1023\r\nM1: 0\r\nM2: 0\r\n".to_vec(),
+        port.take_written()
+    );
+
+    // The window starts at the last stop sent.
+    clock.advance(window - Duration::from_millis(1));
+    saber.stop_motors().expect("Stop failure");
+    assert!(
+        port.take_written().is_empty(),
+        "Stop sent within the window"
+    );
+    clock.advance(Duration::from_millis(1));
+    saber.stop_motors().expect("Stop failure");
+    assert_eq!(b"M1: 0\r\nM2: 0\r\n".to_vec(), port.take_written());
+    assert_eq!(11, saber.dropped_count());
+}
+
+#[test]
+fn async_queue_coalescing() {
+    let port = utils::MockPort::new();
+    let saber = PlainText::from(&port);
+    // A slow line, so that the commands pile up in the queue.
+    let saber =
+        RateLimiter::new(saber, Duration::from_millis(20)).with_policy(RateLimitPolicy::Delay);
+    let queue = AsyncQueueDriver::new(saber, 4);
+
+    queue.submit(Command::Startup(1)).expect("Submit failure");
+    for i in 0..100 {
+        queue
+            .submit(Command::Speed(1, i as f32 / 100.0))
+            .expect("Submit failure");
+    }
+    queue.flush().expect("Flush failure");
+    assert_eq!(0, queue.pending());
+    assert_eq!(101, queue.transmitted_count() + queue.coalesced_count());
+    assert!(queue.transmitted_count() < 101);
+
+    // The startup and the latest speed are always sent.
+    let written = String::from_utf8(port.take_written()).unwrap();
+    assert!(written.starts_with("M1: startup\r\n"));
+    assert!(written.ends_with("M1: 2026\r\n"));
+
+    assert!(queue.submit(Command::Speed(3, 0.0)).is_err());
+    let _saber: RateLimiter<PlainText<utils::MockPort>> =
+        queue.into_inner().expect("Worker failure");
+}
+
+#[test]
+fn async_queue_coalescing_order() {
+    let port = utils::MockPort::new();
+    let mut saber = PlainText::from(&port);
+    // Keep the worker busy with the startup while the speeds are queued.
+    saber.set_pre_send_hook(Box::new(|command| {
+        if let Command::Startup(_) = command {
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        PreSendAction::Allow
+    }));
+    let queue = AsyncQueueDriver::new(saber, 4);
+
+    queue.submit(Command::Startup(1)).expect("Submit failure");
+    std::thread::sleep(Duration::from_millis(20));
+    queue
+        .submit(Command::Speed(1, 0.5))
+        .expect("Submit failure");
+    queue
+        .submit(Command::Speed(2, 0.5))
+        .expect("Submit failure");
+    queue
+        .submit(Command::Speed(1, -0.5))
+        .expect("Submit failure");
+    queue.flush().expect("Flush failure");
+    assert_eq!(1, queue.coalesced_count());
+
+    // The latest speed of the channel 1 is sent in the place of the first.
+    assert_eq!(
+        b"M1: startup\r\nM1: -1023\r\nM2: 1023\r\n".to_vec(),
+        port.take_written()
+    );
+}
+
+#[test]
+fn async_queue_worker_panic() {
+    let port = utils::MockPort::new();
+    let mut saber = PlainText::from(&port);
+    saber.set_pre_send_hook(Box::new(|_| panic!("Hook panic")));
+    let queue = AsyncQueueDriver::new(saber, 4);
+
+    queue
+        .submit(Command::Speed(1, 0.5))
+        .expect("Submit failure");
+    match queue.flush() {
+        Err(Error::Io(e)) => assert_eq!(std::io::ErrorKind::BrokenPipe, e.kind()),
+        res => panic!("Expected a closed queue, got {:?}", res),
+    }
+    match queue.submit(Command::Speed(1, 0.5)) {
+        Err(Error::Io(e)) => assert_eq!(std::io::ErrorKind::BrokenPipe, e.kind()),
+        res => panic!("Expected a closed queue, got {:?}", res),
+    }
+    assert!(port.take_written().is_empty());
+    match queue.into_inner() {
+        Err(Error::Io(e)) => assert_eq!(std::io::ErrorKind::BrokenPipe, e.kind()),
+        Err(e) => panic!("Expected a closed queue, got {:?}", e),
+        Ok(_) => panic!("Expected a closed queue, got the interface"),
+    }
+}
+
+#[test]
+fn trajectory_tick() {
+    let ms = Duration::from_millis;
+    let port = utils::MockPort::new();
+    let trajectory = Trajectory::new()
+        .setpoint(ms(0), 1, 0.5)
+        .setpoint(ms(300), 1, -0.5)
+        .setpoint(ms(100), 2, 0.25)
+        .setpoint(ms(100), 2, -0.5)
+        .setpoint(ms(400), 1, 0.0);
+    let progress = Arc::new(Mutex::new(Vec::<TrajectoryProgress>::new()));
+    let observed = progress.clone();
+    let mut runner = TrajectoryRunner::new(PlainText::from(&port), trajectory)
+        .with_progress_observer(Box::new(move |p| observed.lock().unwrap().push(*p)));
+
+    let t0 = Instant::now();
+    assert_eq!(0, runner.tick(t0).unwrap());
+    runner.start(t0).expect("Start failure");
+    assert!(runner.start(t0).is_err());
+    assert_eq!(1, runner.tick(t0).unwrap());
+    assert_eq!(b"M1: 1023\r\n".to_vec(), port.take_written());
+    assert_eq!(0, runner.tick(t0 + ms(99)).unwrap());
+
+    // Only the latest of the setpoints of the same channel is sent.
+    assert_eq!(1, runner.tick(t0 + ms(105)).unwrap());
+    assert_eq!(b"M2: -1023\r\n".to_vec(), port.take_written());
+    assert_eq!(3, runner.progress().done);
+
+    // The pause delays the next setpoints.
+    runner.pause(t0 + ms(150));
+    assert_eq!(0, runner.tick(t0 + ms(400)).unwrap());
+    assert_eq!(None, runner.next_deadline());
+    runner.resume(t0 + ms(350));
+    assert_eq!(Some(t0 + ms(500)), runner.next_deadline());
+    assert_eq!(0, runner.tick(t0 + ms(499)).unwrap());
+    assert_eq!(1, runner.tick(t0 + ms(505)).unwrap());
+    assert_eq!(b"M1: -1023\r\n".to_vec(), port.take_written());
+    assert_eq!(ms(305), runner.progress().elapsed);
+
+    runner.abort().expect("Abort failure");
+    assert_eq!(b"M1: 0\r\nM2: 0\r\n".to_vec(), port.take_written());
+    assert_eq!(0, runner.tick(t0 + ms(1000)).unwrap());
+    assert!(port.take_written().is_empty());
+
+    let states: Vec<_> = progress.lock().unwrap().iter().map(|p| p.state).collect();
+    assert_eq!(
+        vec![
+            TrajectoryState::Running,
+            TrajectoryState::Running,
+            TrajectoryState::Running,
+            TrajectoryState::Paused,
+            TrajectoryState::Running,
+            TrajectoryState::Running,
+            TrajectoryState::Aborted,
+        ],
+        states
+    );
+    assert_eq!(4, progress.lock().unwrap().last().unwrap().done);
+}
+
+#[test]
+fn trajectory_run() {
+    let ms = Duration::from_millis;
+    let port = utils::MockPort::new();
+    let trajectory = Trajectory::new()
+        .setpoint(ms(0), 1, 0.5)
+        .setpoint(ms(30), 2, 0.5)
+        .setpoint(ms(60), 1, 0.0);
+    let mut runner = TrajectoryRunner::new(PlainText::from(&port), trajectory);
+    let start = Instant::now();
+    runner.run().expect("Run failure");
+    assert_eq!(TrajectoryState::Finished, runner.state());
+    assert_eq!(
+        b"M1: 1023\r\nM2: 1023\r\nM1: 0\r\n".to_vec(),
+        port.take_written()
+    );
+    let times = port.take_write_times();
+    assert_eq!(3, times.len());
+    for (t, offset) in times.iter().zip(&[ms(0), ms(30), ms(60)]) {
+        let late = t.duration_since(start).checked_sub(*offset);
+        assert!(matches!(late, Some(late) if late < ms(20)), "{:?}", late);
+    }
+
+    // Invalid setpoints are rejected before anything is sent.
+    let trajectory = Trajectory::new()
+        .setpoint(ms(0), 1, 0.5)
+        .setpoint(ms(10), 3, 0.5);
+    let mut runner = TrajectoryRunner::new(PlainText::from(&port), trajectory);
+    assert!(runner.run().is_err());
+    assert_eq!(TrajectoryState::Idle, runner.state());
+    assert!(port.take_written().is_empty());
+
+    // An empty trajectory is finished without sending anything.
+    let mut runner = TrajectoryRunner::new(PlainText::from(&port), Trajectory::new());
+    runner.run().expect("Run failure");
+    assert_eq!(TrajectoryState::Finished, runner.state());
+    let mut runner = TrajectoryRunner::new(PlainText::from(&port), Trajectory::new());
+    runner.start(Instant::now()).expect("Start failure");
+    assert_eq!(TrajectoryState::Finished, runner.state());
+    assert_eq!(0, runner.tick(Instant::now()).unwrap());
+    assert_eq!(None, runner.next_deadline());
+    assert!(port.take_written().is_empty());
+}
+
+#[test]
+fn profile_ramp_to() {
+    let port = utils::MockPort::new();
+    let mut saber = PlainText::from(&port);
+    let limits = profile::Limits {
+        velocity: 2.0,
+        accel: 10.0,
+        decel: 10.0,
+        period: Duration::from_millis(100),
+    };
+    let mut sleeps = Vec::new();
+    port.push_reply(b"M1: 614\r\n");
+    profile::ramp_to_with_sleep(&mut saber, 1, -0.2, limits, |d| sleeps.push(d))
+        .expect("Ramp failure");
+    assert_eq!(
+        b"M1: get\r\nM1: 511\r\nM1: 204\r\nM1: -179\r\nM1: -383\r\nM1: -409\r\n".to_vec(),
+        port.take_written()
+    );
+    assert_eq!(vec![Duration::from_millis(100); 4], sleeps);
+
+    // Nothing is sent when already at the target.
+    port.push_reply(b"M1: 0\r\n");
+    profile::ramp_to_with_sleep(&mut saber, 1, 0.0, limits, |_| panic!("Sleep"))
+        .expect("Ramp failure");
+    assert_eq!(b"M1: get\r\n".to_vec(), port.take_written());
+}
+
+#[test]
+fn startup_shutdown_channel_errors() {
+    fn check<S: Sabertooth2x32>(saber: &mut S) {
+        for &channel in [0, 3].iter() {
+            let msg = format!("{}", saber.startup(channel).unwrap_err());
+            assert!(msg.contains("startup channel should be 1 or 2"), "{}", msg);
+            let msg = format!("{}", saber.shutdown(channel).unwrap_err());
+            assert!(msg.contains("shutdown channel should be 1 or 2"), "{}", msg);
+        }
+    }
+
+    let port = utils::MockPort::new();
+    check(&mut PacketSerial::from(&port));
+    check(&mut PlainText::from(&port));
+    assert!(port.take_written().is_empty());
+
+    let err = Command::Startup(0).validate().unwrap_err();
+    assert!(format!("{}", err).contains("startup channel"), "{}", err);
+}
+
+#[test]
+fn from_stopped() {
+    let port = utils::MockPort::new();
+
+    PacketSerial::from_stopped(port.clone()).expect("Construction failure");
+    let mut expected = b"\xf0\x28\x00\x0c\x00\x00M1\x66\x5c".to_vec();
+    expected.extend_from_slice(b"\xf0\x28\x00\x0c\x00\x00M2\x14\x4c");
+    assert_eq!(expected, port.take_written());
+
+    PlainText::from_stopped(port.clone()).expect("Construction failure");
+    assert_eq!(b"M1: 0\r\nM2: 0\r\n".to_vec(), port.take_written());
+
+    port.set_fail_writes(true);
+    assert!(PlainText::from_stopped(port.clone()).is_err());
+}
+
+#[test]
+fn current_stats() {
+    let port = utils::MockPort::new();
+    let mut saber = PlainText::from(&port);
+    let interval = Duration::from_millis(1);
+
+    for reply in [b"M1: C100\r\n", b"M1: C300\r\n", b"M1: C200\r\n"].iter() {
+        port.push_reply(*reply);
+    }
+    let stats = saber
+        .get_current_stats(1, Sampling::new(3, interval))
+        .expect("Sampling failure");
+    assert_eq!(3, stats.samples);
+    assert_eq_float!(10.0, stats.min);
+    assert_eq_float!(30.0, stats.max);
+    assert_eq_float!(20.0, stats.mean);
+
+    // A failed read (no reply) is tolerated only when allowed.
+    port.push_reply(b"M1: C100\r\n");
+    port.push_reply(b"");
+    port.push_reply(b"M1: C200\r\n");
+    let sampling = Sampling::new(2, interval).with_max_failures(1);
+    let stats = saber
+        .get_current_stats(1, sampling)
+        .expect("Sampling failure");
+    assert_eq!(2, stats.samples);
+    assert_eq_float!(15.0, stats.mean);
+
+    port.push_reply(b"M1: C100\r\n");
+    port.push_reply(b"");
+    saber
+        .get_current_avg(1, 2, interval)
+        .expect_err("Failed read should fail");
+
+    saber
+        .get_current_stats(3, Sampling::new(2, interval).with_max_failures(5))
+        .expect_err("Invalid channel should fail");
+    saber
+        .get_voltage_stats(1, Sampling::new(0, interval))
+        .expect_err("No sample should fail");
+}
+
+#[test]
+fn port_accessors() {
+    let mut saber = PacketSerial::from(utils::MockPort::new());
+    saber.set_speed(1, 0.0).expect("Set value failure");
+    assert_eq!(10, saber.port().take_written().len());
+
+    let mut sabertext = PlainText::from(utils::MockPort::new());
+    sabertext.port_mut().set_baud_rate(38400).unwrap();
+    assert_eq!(38400, sabertext.port().baud_rate().unwrap());
+    sabertext.set_drive(0.0).expect("Set value failure");
+    assert_eq!(b"MD: 0\r\n".to_vec(), sabertext.port().take_written());
+}
+
+#[test]
+fn telemetry_policy() {
+    let port = utils::MockPort::new();
+
+    let mut sabertext = PlainText::from(&port);
+    port.push_reply(b"M1: 9999\r\n");
+    let err = sabertext.get_speed(1).expect_err("Out of range speed");
+    assert!(format!("{}", err).contains("9999"), "{}", err);
+    port.push_reply(b"M1: B900\r\n");
+    sabertext.get_voltage(1).expect_err("Out of range voltage");
+    port.push_reply(b"M2: T999\r\n");
+    sabertext
+        .get_temperature(2)
+        .expect_err("Out of range temperature");
+
+    let mut sabertext = sabertext.with_telemetry_policy(TelemetryPolicy::Clamp);
+    port.push_reply(b"M1: 9999\r\n");
+    assert_eq_float!(1.0, sabertext.get_speed(1).expect("Clamped speed"));
+    port.push_reply(b"P2: -9999\r\n");
+    assert_eq_float!(-1.0, sabertext.get_power(2).expect("Clamped power"));
+    port.push_reply(b"M1: B900\r\n");
+    assert_eq_float!(60.0, sabertext.get_voltage(1).expect("Clamped voltage"));
+    port.push_reply(b"M1: C-99999\r\n");
+    assert_eq_float!(-500.0, sabertext.get_current(1).expect("Clamped current"));
+
+    let mut saber = PacketSerial::from(&port).with_packet_type(PacketType::Checksum);
+    port.push_reply(b"\x80\x49\x00\x49\x38\x17\x4d\x31\x4d");
+    saber.get_speed(1).expect_err("Out of range speed");
+
+    let mut saber = saber.with_telemetry_policy(TelemetryPolicy::Clamp);
+    port.push_reply(b"\x80\x49\x01\x4a\x38\x17\x4d\x31\x4d");
+    assert_eq_float!(-1.0, saber.get_speed(1).expect("Clamped speed"));
+}
+
+#[test]
+fn address_collision() {
+    let port = utils::MockPort::new();
+    let mut saber = PacketSerial::from(&port).with_packet_type(PacketType::Checksum);
+
+    // Two Sabertooths replying with a few bytes of delay.
+    port.push_reply(b"\x80\x49\x20\x69\x80\x49\x20\x69\x0B\x00\x4D\x31\x09");
+    match saber.get_current(1) {
+        Err(Error::AddressCollision(_)) => {}
+        res => panic!("Expected a collision: {:?}", res),
+    }
+
+    // A corrupted reply is not mistaken for a collision.
+    port.push_reply(b"\x80\x49\x20\x69\x0B\x00\x4D\x31\x0A");
+    match saber.get_current(1) {
+        Err(Error::Response(_)) => {}
+        res => panic!("Expected an invalid response: {:?}", res),
+    }
+    port.push_reply(b"\x80\x49\x20\x69\x0B\x00\x4D\x31\x09");
+    assert_eq_float!(11.0, saber.get_current(1).expect("Get value failure"));
+
+    let mut saber = saber.with_packet_type(PacketType::CRC);
+    port.push_reply(b"\xf0\x49\x20\x7e\x0b\xf0\x49\x20\x7e\x0b\x00\x4d\x31\x2f\x12");
+    match saber.get_current(1) {
+        Err(Error::AddressCollision(msg)) => assert!(msg.contains("128"), "{}", msg),
+        res => panic!("Expected a collision: {:?}", res),
+    }
+}
+
+#[test]
+fn telemetry_policy_boundaries() {
+    let port = utils::MockPort::new();
+
+    let mut sabertext = PlainText::from(&port);
+    port.push_reply(b"M1: 2048\r\n");
+    sabertext.get_speed(1).expect_err("Out of range speed");
+    port.push_reply(b"M2: -2048\r\n");
+    sabertext.get_speed(2).expect_err("Out of range speed");
+    port.push_reply(b"M1: 2047\r\n");
+    assert_eq_float!(1.0, sabertext.get_speed(1).expect("Get value failure"));
+    assert_eq!(0, sabertext.clamped_readings());
+
+    let mut sabertext = sabertext.with_telemetry_policy(TelemetryPolicy::Clamp);
+    port.push_reply(b"M1: 2048\r\n");
+    assert_eq_float!(1.0, sabertext.get_speed(1).expect("Clamped speed"));
+    port.push_reply(b"M2: -2048\r\n");
+    assert_eq_float!(-1.0, sabertext.get_speed(2).expect("Clamped speed"));
+    port.push_reply(b"M1: -2047\r\n");
+    assert_eq_float!(-1.0, sabertext.get_speed(1).expect("Get value failure"));
+    assert_eq!(2, sabertext.clamped_readings());
+
+    let mut saber = PacketSerial::from(&port).with_packet_type(PacketType::Checksum);
+    port.push_reply(b"\x80\x49\x00\x49\x00\x10\x4d\x31\x0e");
+    saber.get_speed(1).expect_err("Out of range speed");
+    port.push_reply(b"\x80\x49\x01\x4a\x00\x10\x4d\x32\x0f");
+    saber.get_speed(2).expect_err("Out of range speed");
+    // The raw value is never range-checked.
+    port.push_reply(b"\x80\x49\x00\x49\x00\x10\x4d\x31\x0e");
+    assert_eq!(2048, saber.get_speed_raw(1).expect("Get value failure"));
+    assert_eq_float!(1.0, convert::value_to_ratio_saturating(2048));
+    assert_eq!(0, saber.clamped_readings());
+
+    let mut saber = saber.with_telemetry_policy(TelemetryPolicy::Clamp);
+    port.push_reply(b"\x80\x49\x00\x49\x00\x10\x4d\x31\x0e");
+    assert_eq_float!(1.0, saber.get_speed(1).expect("Clamped speed"));
+    port.push_reply(b"\x80\x49\x01\x4a\x00\x10\x4d\x32\x0f");
+    assert_eq_float!(-1.0, saber.get_speed(2).expect("Clamped speed"));
+    assert_eq!(2, saber.clamped_readings());
+}
+
+#[test]
+fn trait_pointers() {
+    fn drive_forward(mut saber: impl Sabertooth2x32) {
+        saber.set_drive(0.5).expect("Set value failure");
+        saber.set_turn(0.0).expect("Set value failure");
+    }
+
+    let port = utils::MockPort::new();
+
+    let mut sabertext = PlainText::from(&port);
+    drive_forward(&mut sabertext);
+    drive_forward(&mut &mut sabertext);
+    let expected = b"MD: 1023\r\nMT: 0\r\n".repeat(2);
+    assert_eq!(expected, port.take_written());
+
+    let boxed: Vec<Box<dyn Sabertooth2x32>> = vec![
+        Box::new(PlainText::from(&port)),
+        Box::new(PacketSerial::from(&port).with_packet_type(PacketType::Checksum)),
+    ];
+    for saber in boxed {
+        drive_forward(saber);
+    }
+    let mut expected = b"MD: 1023\r\nMT: 0\r\n".to_vec();
+    expected.extend_from_slice(b"\x80\x28\x00\x28\x7f\x07MD\x17");
+    expected.extend_from_slice(b"\x80\x28\x00\x28\x00\x00MT\x21");
+    assert_eq!(expected, port.take_written());
+}
+
+#[test]
+fn all_diagnostics() {
+    let port = utils::MockPort::new();
+    let mut sabertext = PlainText::from(&port);
+
+    for reply in [
+        &b"M1: B240\r\n"[..],
+        b"M1: C52\r\n",
+        b"M2: C-13\r\n",
+        b"M1: T35\r\n",
+    ]
+    .iter()
+    {
+        port.push_reply(reply);
+    }
+    let diag = sabertext
+        .get_all_diagnostics()
+        .expect("Diagnostics failure");
+    assert!(diag.is_complete(), "{:?}", diag.failures);
+    assert_eq_float!(24.0, diag.voltage.unwrap());
+    assert_eq_float!(5.2, diag.current_m1.unwrap());
+    assert_eq_float!(-1.3, diag.current_m2.unwrap());
+    assert_eq_float!(35.0, diag.temperature.unwrap());
+    let written = port.take_written();
+    assert_eq!(
+        b"M1: getb\r\nM1: getc\r\nM2: getc\r\nM1: gett\r\n".to_vec(),
+        written
+    );
+
+    // The current of the motor 2 is missing.
+    for reply in [&b"M1: B240\r\n"[..], b"M1: C52\r\n", b"", b"M1: T35\r\n"].iter() {
+        port.push_reply(reply);
+    }
+    let diag = sabertext
+        .get_all_diagnostics()
+        .expect("Diagnostics failure");
+    assert!(!diag.is_complete());
+    assert_eq!(None, diag.current_m2);
+    assert_eq!(1, diag.failures.len());
+    assert_eq!("current_m2", diag.failures[0].0);
+    assert_eq_float!(35.0, diag.temperature.unwrap());
+
+    assert!(sabertext.get_all_diagnostics().is_err());
+}
+
+#[test]
+fn echo_mode() {
+    let port = utils::MockPort::new();
+
+    let mut saber = PacketSerial::from(&port)
+        .with_packet_type(PacketType::Checksum)
+        .with_echo_mode(EchoMode::VerifyAndDiscard);
+    let frame = b"\x80\x28\x00\x28\x00\x00MT\x21";
+    port.push_reply(frame);
+    saber.set_turn(0.0).expect("Set value failure");
+
+    // The echo comes before the reply.
+    let mut reply = b"\x80\x29\x00\x29\x4d\x31\x7e".to_vec();
+    reply.extend_from_slice(b"\x80\x49\x00\x49\x7f\x07\x4d\x31\x04");
+    port.push_reply(&reply);
+    assert_eq_float!(0.5, saber.get_speed(1).expect("Get value failure"));
+
+    let mut corrupted = frame.to_vec();
+    corrupted[4] = 0x01;
+    port.push_reply(&corrupted);
+    match saber.set_turn(0.0) {
+        Err(Error::Echo(_)) => {}
+        res => panic!("Expected an echo error, got {:?}", res),
+    }
+
+    match saber.set_turn(0.0) {
+        Err(Error::Response(_)) => {}
+        res => panic!("Expected a missing echo, got {:?}", res),
+    }
+
+    // The corrupted echo is only discarded without verification.
+    let mut saber = saber.with_echo_mode(EchoMode::Discard);
+    port.push_reply(&corrupted);
+    saber.set_turn(0.0).expect("Set value failure");
+
+    let mut sabertext = PlainText::from(&port).with_echo_mode(EchoMode::VerifyAndDiscard);
+    port.push_reply(b"MD: 0\r\n");
+    sabertext.set_drive(0.0).expect("Set value failure");
+    port.push_reply(b"M1: get\r\nM1: -1023\r\n");
+    assert_eq_float!(-0.5, sabertext.get_speed(1).expect("Get value failure"));
+    port.push_reply(b"MD: 9\r\n");
+    match sabertext.set_drive(0.0) {
+        Err(Error::Echo(_)) => {}
+        res => panic!("Expected an echo error, got {:?}", res),
+    }
+    assert!(sabertext.set_drive(0.0).is_err());
+}
+
+#[test]
+fn output_range() {
+    let port = utils::MockPort::new();
+    let mut saber = OutputLimiter::new(PlainText::from(&port));
+    saber.set_output_range(1, -1.0, 0.8).expect("Range failure");
+    saber.set_output_range(2, -0.5, 1.0).expect("Range failure");
+    assert_eq!((-1.0, 0.8), saber.output_range(1).unwrap());
+
+    saber.set_speed(1, 1.0).expect("Set value failure");
+    saber.set_speed(1, -1.0).expect("Set value failure");
+    saber.set_speed(2, -1.0).expect("Set value failure");
+    saber.set_speed(2, 1.0).expect("Set value failure");
+    saber.set_power(1, 0.9).expect("Set value failure");
+    saber.set_speed(1, 0.5).expect("Set value failure");
+    // Not a channel command.
+    saber.set_drive(1.0).expect("Set value failure");
+    assert_eq!(
+        b"M1: 1637\r\nM1: -2047\r\nM2: -1023\r\nM2: 2047\r\nP1: 1637\r\nM1: 1023\r\nMD: 2047\r\n"
+            .to_vec(),
+        port.take_written()
+    );
+
+    // Both the range and the cap apply.
+    let mut saber = saber.with_output_cap(0.6).expect("Valid cap");
+    saber.set_speed(1, -1.0).expect("Set value failure");
+    saber.set_speed(2, -1.0).expect("Set value failure");
+    assert_eq!(b"M1: -1228\r\nM2: -1023\r\n".to_vec(), port.take_written());
+
+    for &(min, max) in &[
+        (0.5, 0.2),
+        (0.1, 0.5),
+        (-0.5, -0.1),
+        (-1.5, 0.0),
+        (0.0, 0.0),
+    ] {
+        match saber.set_output_range(1, min, max) {
+            Err(Error::InvalidInput(_)) => {}
+            res => panic!(
+                "{}~{}: expected an invalid input error, got {:?}",
+                min, max, res
+            ),
+        }
+    }
+    saber
+        .set_output_range(3, -1.0, 1.0)
+        .expect_err("Invalid channel should fail");
+    saber.set_speed(2, 1.0).expect("Set value failure");
+    assert_eq!(b"M2: 1228\r\n".to_vec(), port.take_written());
+}
+
+#[test]
+fn safe_mode() {
+    fn sent_value(port: &utils::MockPort) -> i32 {
+        let line = String::from_utf8(port.take_written()).unwrap();
+        line.trim().trim_start_matches("M1: ").parse().unwrap()
+    }
+
+    let port = utils::MockPort::new();
+    let mut saber = OutputLimiter::new(PlainText::from(&port))
+        .with_output_cap(0.8)
+        .and_then(|saber| saber.with_safe_mode(0.3, 1.0))
+        .expect("Valid limits");
+
+    saber.set_speed(1, 1.0).expect("Set value failure");
+    assert_eq!(1637, sent_value(&port));
+
+    saber.enter_safe_mode();
+    assert!(saber.in_safe_mode());
+    saber.set_speed(1, 1.0).expect("Set value failure");
+    // Slowing down from 0.8 to the cap is immediate.
+    assert_eq!(614, sent_value(&port));
+    saber.set_speed(1, 0.0).expect("Set value failure");
+    assert_eq!(0, sent_value(&port));
+
+    // From a stop, the change is limited to 1.0 per second, from the first
+    // command of the ramp: the time spent stopped does not count.
+    std::thread::sleep(Duration::from_millis(50));
+    saber.set_speed(1, 1.0).expect("Set value failure");
+    assert_eq!(0, sent_value(&port));
+    std::thread::sleep(Duration::from_millis(50));
+    saber.set_speed(1, 1.0).expect("Set value failure");
+    let value = sent_value(&port);
+    assert!(value > 0 && value < 614, "{} not rate-limited", value);
+    saber.set_speed(1, -1.0).expect("Set value failure");
+    let value = sent_value(&port);
+    assert!(value <= 0 && value > -100, "{} not rate-limited", value);
+
+    saber.exit_safe_mode();
+    assert!(!saber.in_safe_mode());
+    saber.set_speed(1, 1.0).expect("Set value failure");
+    assert_eq!(1637, sent_value(&port));
+
+    // A negative or NaN limit would reverse or disable the limiting.
+    for &value in &[-0.5, 1.5, f32::NAN] {
+        let saber = OutputLimiter::new(PlainText::from(&port));
+        assert!(saber.with_output_cap(value).is_err(), "cap {}", value);
+        let saber = OutputLimiter::new(PlainText::from(&port));
+        assert!(saber.with_safe_mode(value, 1.0).is_err(), "cap {}", value);
+    }
+    for &value in &[0.0, -1.0, f32::NAN, f32::INFINITY] {
+        let saber = OutputLimiter::new(PlainText::from(&port));
+        assert!(saber.with_slew_rate(value).is_err(), "rate {}", value);
+        let saber = OutputLimiter::new(PlainText::from(&port));
+        assert!(saber.with_safe_mode(0.3, value).is_err(), "rate {}", value);
+    }
+}
+
+#[test]
+fn shared_port_constructors() {
+    let port = utils::MockPort::new();
+    let mut sabertext = PlainText::from(&port);
+    let mut saber = PacketSerial::from(port.clone()).with_packet_type(PacketType::Checksum);
+
+    port.push_reply(b"M1: B240\r\n");
+    assert_eq_float!(24.0, sabertext.get_voltage(1).expect("Get value failure"));
+    saber.set_turn(0.0).expect("Set value failure");
+    sabertext.set_drive(0.0).expect("Set value failure");
+
+    let mut expected = b"M1: getb\r\n".to_vec();
+    expected.extend_from_slice(b"\x80\x28\x00\x28\x00\x00MT\x21");
+    expected.extend_from_slice(b"MD: 0\r\n");
+    assert_eq!(expected, port.take_written());
+
+    // The cached states are independent.
+    assert_eq!(Some(0.0), saber.last_commands().turn());
+    assert_eq!(None, sabertext.last_commands().turn());
+}
+
+#[test]
+fn shutdown_on_drop() {
+    let port = utils::MockPort::new();
+
+    drop(PlainText::from(&port).with_shutdown_on_drop(true));
+    assert_eq!(
+        b"M1: shutdown\r\nM2: shutdown\r\n".to_vec(),
+        port.take_written()
+    );
+
+    // Only the motors which are not already shut down.
+    let mut saber = PacketSerial::from(&port)
+        .with_packet_type(PacketType::Checksum)
+        .with_shutdown_on_drop(true);
+    saber.shutdown(1).expect("Shutdown failure");
+    assert_eq!(Some(true), saber.last_commands().shut_down(1));
+    assert_eq!(None, saber.last_commands().shut_down(2));
+    port.take_written();
+    drop(saber);
+    assert_eq!(
+        b"\x80\x28\x20\x48\x01\x00M2\x00".to_vec(),
+        port.take_written()
+    );
+
+    let mut sabertext = PlainText::from(&port).with_shutdown_on_drop(true);
+    sabertext.shutdown(1).expect("Shutdown failure");
+    sabertext.shutdown(2).expect("Shutdown failure");
+    port.take_written();
+    drop(sabertext);
+    assert!(port.take_written().is_empty());
+
+    // Disabled by default, and errors are ignored.
+    drop(PlainText::from(&port));
+    assert!(port.take_written().is_empty());
+    port.set_fail_writes(true);
+    drop(PlainText::from(&port).with_shutdown_on_drop(true));
+}
+
+#[test]
+fn pretty_frame_logger() {
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let port = utils::MockPort::new();
+    let log = Shared::default();
+
+    let mut saber = PacketSerial::from(&port).with_packet_type(PacketType::Checksum);
+    saber.set_frame_observer(PrettyFrameLogger::to_writer(log.clone()).into_observer());
+    saber.set_drive(0.5).expect("Set value failure");
+    port.push_reply(b"\x80\x49\x20\x69\x0B\x00\x4D\x31\x09");
+    saber.get_current(1).expect("Get value failure");
+    saber.broadcast_stop(&[129]).expect("Broadcast failure");
+
+    let mut sabertext = PlainText::from(&port);
+    sabertext.set_frame_observer(PrettyFrameLogger::to_writer(log.clone()).into_observer());
+    sabertext.set_speed(2, -0.5).expect("Set value failure");
+
+    let expected = "[TX] Drive MD fwd value=1023 (addr=128 csum=0x17)
+[TX] GetCurrent M1 (addr=128 csum=0x7E)
+[RX] ReplyCurrent M1 value=11 (addr=128 csum=0x09)
+[TX] Speed M1 value=0 (addr=129 csum=0x7E)
+[TX] Speed M2 value=0 (addr=129 csum=0x7F)
+[TX] Speed M2 rev value=1023
+";
+    assert_eq!(
+        expected,
+        String::from_utf8(log.0.lock().unwrap().clone()).unwrap()
+    );
+}
+
+#[test]
+fn frame_observer_writes() {
+    let port = utils::MockPort::new();
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let recorded = events.clone();
+    let mut sabertext = PlainText::from(&port).with_echo_mode(EchoMode::VerifyAndDiscard);
+    sabertext.set_frame_observer(Box::new(move |event| {
+        recorded
+            .lock()
+            .unwrap()
+            .push((event.direction, event.data.to_vec()));
+    }));
+
+    // A frame is reported even when its echo is missing.
+    sabertext
+        .set_drive(0.0)
+        .expect_err("Missing echo should fail");
+    assert_eq!(b"MD: 0\r\n".to_vec(), port.take_written());
+
+    // The line terminating a partial write is reported before the frame.
+    let mut sabertext = sabertext.with_echo_mode(EchoMode::None);
+    port.set_fail_after(Some(3));
+    sabertext.set_speed(1, 0.5).expect_err("Write should fail");
+    port.set_fail_after(None);
+    sabertext.set_speed(1, 0.0).expect("Set value failure");
+    assert_eq!(b"M1:\r\nM1: 0\r\n".to_vec(), port.take_written());
+
+    assert_eq!(
+        vec![
+            (Direction::Tx, b"MD: 0\r\n".to_vec()),
+            (Direction::Tx, b"\r\n".to_vec()),
+            (Direction::Tx, b"M1: 0\r\n".to_vec()),
+        ],
+        *events.lock().unwrap()
+    );
+
+    let mut saber = PacketSerial::from(&port)
+        .with_packet_type(PacketType::Checksum)
+        .with_echo_mode(EchoMode::VerifyAndDiscard);
+    let recorded = events.clone();
+    events.lock().unwrap().clear();
+    saber.set_frame_observer(Box::new(move |event| {
+        recorded
+            .lock()
+            .unwrap()
+            .push((event.direction, event.data.to_vec()));
+    }));
+    saber.set_turn(0.0).expect_err("Missing echo should fail");
+    assert_eq!(
+        vec![(Direction::Tx, b"\x80\x28\x00\x28\x00\x00MT\x21".to_vec())],
+        *events.lock().unwrap()
+    );
+}
+
+#[test]
+fn detect_packet_type() {
+    let port = utils::MockPort::new();
+    let mut saber = PacketSerial::from(&port);
+
+    // The Sabertooth answers only checksum requests.
+    port.push_reply(b"");
+    port.push_reply(b"\x80\x49\x10\x59\x78\x00\x4D\x31\x76");
+    assert_eq!(PacketType::Checksum, saber.detect_packet_type().unwrap());
+    let mut expected = b"\xf0\x29\x10\x2e\x4d\x31\x06\x24".to_vec();
+    expected.extend_from_slice(b"\x80\x29\x10\x39\x4d\x31\x7e");
+    assert_eq!(expected, port.take_written());
+
+    // A garbage reply to the CRC request is not a valid reply either.
+    port.push_reply(b"\x80\x49\x10\x59\x78\x00\x4D\x31\x76\x00");
+    port.push_reply(b"\x80\x49\x10\x59\x78\x00\x4D\x31\x76");
+    let mut saber = saber
+        .with_detected_packet_type()
+        .expect("Detection failure");
+    port.take_written();
+    saber.set_turn(0.0).expect("Set value failure");
+    assert_eq!(
+        b"\x80\x28\x00\x28\x00\x00MT\x21".to_vec(),
+        port.take_written()
+    );
+
+    port.push_reply(b"\xf0\x49\x10\x56\x78\x00\x4D\x31\x54\x0A");
+    assert_eq!(PacketType::CRC, saber.detect_packet_type().unwrap());
+
+    assert!(saber.detect_packet_type().is_err());
+}
+
+#[test]
+fn stale_commands() {
+    let port = utils::MockPort::new();
+    let clock = utils::MockClock::new();
+    let (now, sleep) = clock.functions();
+    let mut saber = PacketSerial::from(&port);
+    saber.set_clock(now, sleep);
+    let max = Duration::from_millis(20);
+
+    assert_eq!(None, saber.time_since_last_command());
+    assert!(saber.is_stale(max));
+
+    saber.set_speed(1, 0.5).expect("Set value failure");
+    assert_eq!(
+        Some(Duration::from_secs(0)),
+        saber.time_since_last_command()
+    );
+    clock.advance(max);
+    assert_eq!(Some(max), saber.time_since_last_command());
+    assert!(!saber.is_stale(max));
+
+    clock.advance(Duration::from_millis(1));
+    assert!(saber.is_stale(max));
+
+    // Requests do not count as commands.
+    port.push_reply(b"\xF0\x49\x40\x2E\x1C\x00\x4D\x31\x01\x7A");
+    saber.get_temperature(1).expect("Get value failure");
+    assert!(saber.is_stale(max));
+
+    let (now, sleep) = clock.functions();
+    let mut sabertext = PlainText::from(&port);
+    sabertext.set_clock(now, sleep);
+    sabertext.set_drive(0.0).expect("Set value failure");
+    clock.advance(Duration::from_millis(5));
+    assert_eq!(
+        Some(Duration::from_millis(5)),
+        sabertext.time_since_last_command()
+    );
+    assert!(!sabertext.is_stale(max));
+    sabertext.clear_cached_state();
+    assert!(sabertext.is_stale(max));
+}
+
+#[test]
+fn read_exact_timeout() {
+    let mut port = utils::MockPort::new();
+    let writer = port.clone();
+    port.push_read(b"\x01\x02");
+    let delayed = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(20));
+        writer.push_read(b"\x03\x04");
+    });
+    let mut buf = [0u8; 4];
+    port.read_exact_timeout(&mut buf, Duration::from_millis(500))
+        .expect("Read failure");
+    delayed.join().unwrap();
+    assert_eq!([1, 2, 3, 4], buf);
+    // The timeout of the port is restored.
+    assert_eq!(Duration::from_millis(100), port.timeout());
+
+    port.push_read(b"\x05");
+    match port.read_exact_timeout(&mut buf, Duration::from_millis(20)) {
+        Err(Error::Io(e)) => {
+            assert_eq!(std::io::ErrorKind::TimedOut, e.kind());
+            assert!(e.to_string().contains("1 of 4 bytes"), "{}", e);
+        }
+        res => panic!("Expected a timeout, got {:?}", res),
+    }
+
+    // A port which would block is polled with a pause between the reads.
+    port.take_read_times();
+    port.set_block_when_empty(true);
+    port.read_exact_timeout(&mut buf, Duration::from_millis(20))
+        .expect_err("Read should time out");
+    let reads = port.take_read_times().len();
+    assert!(reads <= 21, "{} reads in 20 ms", reads);
+
+    let mut port =
+        StreamPort::new(&b"\x01"[..], std::io::sink()).with_timeout_setter(Box::new(|_| Ok(())));
+    match port.read_exact_timeout(&mut buf, Duration::from_secs(10)) {
+        Err(Error::Io(e)) => {
+            assert_eq!(std::io::ErrorKind::UnexpectedEof, e.kind());
+            assert!(e.to_string().contains("1 of 4 bytes"), "{}", e);
+        }
+        res => panic!("Expected the end of the stream, got {:?}", res),
+    }
+}
+
+#[test]
+fn query_scheduler() {
+    let port = utils::MockPort::new();
+    let saber = PacketSerial::from(&port);
+    let mut scheduler = QueryScheduler::new(saber, Duration::from_millis(50));
+
+    scheduler.push_query(Query::Voltage(1));
+    scheduler.push_command(Command::Drive(0.5));
+    // The drive command jumps the queue.
+    assert_eq!(
+        Scheduled::Sent(Command::Drive(0.5)),
+        scheduler.poll().unwrap()
+    );
+    assert_eq!(
+        b"\xf0\x28\x00\x0c\x7f\x07\x4d\x44\x1b\x76".to_vec(),
+        port.take_written()
+    );
+
+    port.push_reply(b"\xf0\x49\x10\x56\x7c\x00\x4d\x31\x03\x6c");
+    assert_eq!(
+        Scheduled::Reading(Query::Voltage(1), 12.4),
+        scheduler.poll().unwrap()
+    );
+    assert_eq!(
+        b"\xf0\x29\x10\x2e\x4d\x31\x06\x24".to_vec(),
+        port.take_written()
+    );
+
+    // The next query waits for the query period, commands do not.
+    scheduler.push_query(Query::Voltage(1));
+    assert_eq!(Scheduled::Idle, scheduler.poll().unwrap());
+    scheduler.push_command(Command::Shutdown(1));
+    assert_eq!(
+        Scheduled::Sent(Command::Shutdown(1)),
+        scheduler.poll().unwrap()
+    );
+    assert_eq!(1, scheduler.pending_queries());
+    assert_eq!(0, scheduler.pending_commands());
+}
+
+#[test]
+fn voltage_cache() {
+    let clock = utils::MockClock::new();
+    let (now, sleep) = clock.functions();
+    let port = utils::MockPort::new();
+    let mut saber = PacketSerial::from(&port);
+    saber.set_clock(now, sleep);
+    let max_age = Duration::from_millis(30);
+    let request = b"\xf0\x29\x10\x2e\x4d\x31\x06\x24".to_vec();
+
+    port.push_reply(b"\xf0\x49\x10\x56\x7c\x00\x4d\x31\x03\x6c");
+    assert_eq!(12.4, saber.get_voltage_cached(1, max_age).unwrap());
+    assert_eq!(12.4, saber.get_voltage_cached(1, max_age).unwrap());
+    assert_eq!(12.4, saber.get_voltage_cached(1, max_age).unwrap());
+    // One transaction for the three readings.
+    assert_eq!(request, port.take_written());
+
+    // The reading is used up to its maximum age.
+    clock.advance(max_age);
+    assert_eq!(12.4, saber.get_voltage_cached(1, max_age).unwrap());
+    assert!(port.take_written().is_empty());
+    clock.advance(Duration::from_millis(1));
+    port.push_reply(b"\xf0\x49\x10\x56\x7c\x00\x4d\x31\x03\x6c");
+    assert_eq!(12.4, saber.get_voltage_cached(1, max_age).unwrap());
+    assert_eq!(request, port.take_written());
+
+    saber.invalidate();
+    port.push_reply(b"\xf0\x49\x10\x56\x7c\x00\x4d\x31\x03\x6c");
+    assert_eq!(12.4, saber.get_voltage_cached(1, max_age).unwrap());
+    assert_eq!(request, port.take_written());
+
+    // Each channel has its own reading.
+    let (now, sleep) = clock.functions();
+    let mut sabertext = PlainText::from(&port);
+    sabertext.set_clock(now, sleep);
+    port.push_reply(b"M1: B124\r\n");
+    assert_eq!(12.4, sabertext.get_voltage_cached(1, max_age).unwrap());
+    clock.advance(Duration::from_millis(20));
+    port.push_reply(b"M2: B130\r\n");
+    assert_eq!(13.0, sabertext.get_voltage_cached(2, max_age).unwrap());
+    assert_eq!(12.4, sabertext.get_voltage_cached(1, max_age).unwrap());
+    assert_eq!(13.0, sabertext.get_voltage_cached(2, max_age).unwrap());
+    assert_eq!(b"M1: getb\r\nM2: getb\r\n".to_vec(), port.take_written());
+    // Only the reading of channel 1 is too old.
+    clock.advance(Duration::from_millis(20));
+    port.push_reply(b"M1: B125\r\n");
+    assert_eq!(12.5, sabertext.get_voltage_cached(1, max_age).unwrap());
+    assert_eq!(13.0, sabertext.get_voltage_cached(2, max_age).unwrap());
+    assert_eq!(b"M1: getb\r\n".to_vec(), port.take_written());
+    assert!(sabertext.get_voltage_cached(3, max_age).is_err());
+}
+
+#[test]
+fn shutdown_state() {
+    let port = utils::MockPort::new();
+    let mut saber = PacketSerial::from(&port).with_strict_safety(true);
+    saber
+        .set_serial_timeout(Duration::from_millis(500))
+        .expect("Set value failure");
+    assert_eq!(None, saber.is_shutdown(1));
+
+    saber.shutdown(1).expect("Set value failure");
+    assert_eq!(Some(true), saber.is_shutdown(1));
+    port.take_written();
+    match saber.set_speed(1, 0.5) {
+        Err(Error::Safety(_)) => {}
+        res => panic!("Expected a safety error, got {:?}", res),
+    }
+    match saber.set_drive(0.5) {
+        Err(Error::Safety(_)) => {}
+        res => panic!("Expected a safety error, got {:?}", res),
+    }
+    assert!(port.take_written().is_empty());
+    // Stopping and driving the other channel are allowed.
+    saber.set_speed(1, 0.0).expect("Set value failure");
+    saber.set_speed(2, 0.5).expect("Set value failure");
+
+    saber.startup(1).expect("Set value failure");
+    assert_eq!(Some(false), saber.is_shutdown(1));
+    saber.set_speed(1, 0.5).expect("Set value failure");
+
+    saber.clear_cached_state();
+    assert_eq!(None, saber.is_shutdown(1));
+
+    // Without strict safety, it is only a warning.
+    let mut sabertext = PlainText::from(&port);
+    sabertext.shutdown(2).expect("Set value failure");
+    assert_eq!(Some(true), sabertext.is_shutdown(2));
+    sabertext.set_speed(2, 0.5).expect("Set value failure");
+}
+
+#[test]
+fn drive_mode() {
+    let port = utils::MockPort::new();
+    let mut saber = PacketSerial::from(&port).with_strict_safety(true);
+    saber
+        .set_serial_timeout(Duration::from_millis(500))
+        .expect("Set value failure");
+    assert_eq!(None, saber.drive_mode());
+    saber.set_speed(1, 0.5).expect("Set value failure");
+    saber.set_drive(0.5).expect("Set value failure");
+
+    saber.set_drive_mode(DriveMode::Mixed);
+    port.take_written();
+    match saber.set_speed(1, 0.5) {
+        Err(Error::Safety(_)) => {}
+        res => panic!("Expected a safety error, got {:?}", res),
+    }
+    assert!(port.take_written().is_empty());
+    saber.set_drive(0.5).expect("Set value failure");
+    saber.set_turn(-0.5).expect("Set value failure");
+    // Stopping is always allowed.
+    saber.stop_motors().expect("Set value failure");
+
+    saber.set_drive_mode(DriveMode::Independent);
+    assert_eq!(Some(DriveMode::Independent), saber.drive_mode());
+    match saber.set_turn(0.5) {
+        Err(Error::Safety(_)) => {}
+        res => panic!("Expected a safety error, got {:?}", res),
+    }
+    saber.set_power(2, 0.5).expect("Set value failure");
+}
+
+#[test]
+fn disconnected_errors() {
+    let io_error = |kind| Error::Io(std::io::Error::new(kind, "mock"));
+    assert!(io_error(std::io::ErrorKind::BrokenPipe).is_disconnected());
+    assert!(!io_error(std::io::ErrorKind::TimedOut).is_disconnected());
+    assert!(!Error::Response("invalid checksum or CRC".to_string()).is_disconnected());
+    assert!(Error::Serial(serialport::Error::new(
+        serialport::ErrorKind::NoDevice,
+        "mock"
+    ))
+    .is_disconnected());
+    assert!(!Error::Serial(serialport::Error::new(
+        serialport::ErrorKind::InvalidInput,
+        "mock"
+    ))
+    .is_disconnected());
+
+    #[cfg(unix)]
+    for &(code, disconnected) in [(5, true), (6, true), (19, true), (13, false)].iter() {
+        let e = Error::Io(std::io::Error::from_raw_os_error(code));
+        assert_eq!(disconnected, e.is_disconnected(), "{}", e);
+    }
+}
+
+#[test]
+fn stream_port() {
+    let reply = std::io::Cursor::new(b"\xf0\x49\x10\x56\x7c\x00\x4d\x31\x03\x6c".to_vec());
+    let mut port = StreamPort::new(reply, Vec::new());
+    {
+        let mut saber = PacketSerial::from(&mut port);
+        saber.set_speed(1, 0.5).expect("Set value failure");
+        assert_eq_float!(12.4, saber.get_voltage(1).expect("Get value failure"));
+    }
+    let (_, written) = port.into_inner();
+    assert_eq!(
+        b"\xf0\x28\x00\x0c\x7f\x07\x4d\x31\x17\x7c\xf0\x29\x10\x2e\x4d\x31\x06\x24".to_vec(),
+        written
+    );
+
+    let reply = std::io::Cursor::new(b"M2:C-20\r\n".to_vec());
+    let mut sabertext = PlainText::from(StreamPort::new(reply, Vec::new()));
+    assert_eq_float!(-2.0, sabertext.get_current(2).expect("Get value failure"));
+
+    let applied = Arc::new(Mutex::new(Vec::new()));
+    let recorded = applied.clone();
+    let mut port = StreamPort::new(std::io::empty(), std::io::sink());
+    match port.set_baud_rate(38400) {
+        Err(Error::InvalidInput(_)) => {}
+        res => panic!("Expected an invalid input error, got {:?}", res),
+    }
+    port.set_timeout(Duration::from_millis(10))
+        .expect_err("Setting the timeout should fail");
+    let mut port = port.with_baud_rate_setter(Box::new(move |baud_rate| {
+        recorded.lock().unwrap().push(baud_rate);
+        Ok(())
+    }));
+    port.set_baud_rate(38400).expect("Set baud rate failure");
+    assert_eq!(38400, port.baud_rate().unwrap());
+    assert_eq!(vec![38400], *applied.lock().unwrap());
+}
+
+#[test]
+fn baud_rate_change_after_pending_output() {
+    use utils::MockEvent::*;
+
+    let mock = utils::MockPort::new();
+    let mut rate = mock.clone();
+    let mut port = StreamPort::new(mock.clone(), mock.clone())
+        .with_baud_rate_setter(Box::new(move |baud_rate| rate.set_baud_rate(baud_rate)));
+    let frame = b"\xf0\x28\x00\x0c\x7f\x07\x4d\x31\x17\x7c";
+    port.write_all(frame).expect("Write failure");
+    port.set_baud_rate(38400).expect("Set baud rate failure");
+    // The frame is drained at the old rate, before the rate changes.
+    assert_eq!(
+        vec![Write(frame.to_vec()), Flush, SetBaudRate(38400)],
+        mock.take_events()
+    );
+}
+
+/// Power switch recording its calls and the bytes written before them.
+struct MockPower {
+    port: utils::MockPort,
+    events: Vec<(&'static str, usize)>,
+}
+
+impl PowerControl for MockPower {
+    fn power_off(&mut self) -> Result<()> {
+        let written = self.port.take_written().len();
+        self.events.push(("off", written));
+        Ok(())
+    }
+
+    fn power_on(&mut self) -> Result<()> {
+        let written = self.port.take_written().len();
+        self.events.push(("on", written));
+        Ok(())
+    }
+}
+
+#[test]
+fn power_cycle() {
+    let port = utils::MockPort::new();
+    let mut power = MockPower {
+        port: port.clone(),
+        events: Vec::new(),
+    };
+    let mut saber = PacketSerial::from(&port);
+    saber
+        .set_serial_timeout(Duration::from_millis(500))
+        .expect("Set value failure");
+    saber.set_ramp(2, 0.25).expect("Set value failure");
+    let settings = port.take_written();
+    saber.set_speed(1, 0.5).expect("Set value failure");
+    saber.shutdown(2).expect("Set value failure");
+    port.take_written();
+
+    saber
+        .power_cycle(
+            &mut power,
+            Duration::from_millis(1),
+            Duration::from_millis(1),
+        )
+        .expect("Power cycle failure");
+    assert_eq!(vec![("off", 0), ("on", 0)], power.events);
+    assert_eq!(settings, port.take_written());
+    assert_eq!(None, saber.last_commands().speed(1));
+    assert_eq!(None, saber.is_shutdown(2));
+    assert_eq!(Some(0.25), saber.last_commands().ramp(2));
+
+    let mut sabertext = PlainText::from(&port);
+    sabertext.set_ramp(1, -0.5).expect("Set value failure");
+    sabertext.set_speed(1, 0.5).expect("Set value failure");
+    port.take_written();
+    power.events.clear();
+    sabertext
+        .power_cycle(
+            &mut power,
+            Duration::from_millis(1),
+            Duration::from_millis(1),
+        )
+        .expect("Power cycle failure");
+    assert_eq!(vec![("off", 0), ("on", 0)], power.events);
+    assert_eq!(b"R1: -1023\r\n".to_vec(), port.take_written());
+    assert_eq!(None, sabertext.last_commands().speed(1));
+}
+
+#[test]
+fn healthcheck() {
+    // The wedged port accepts the writes but never replies.
+    let port = utils::MockPort::new();
+    let mut saber = PacketSerial::from(&port);
+    let err = saber
+        .healthcheck()
+        .expect_err("Healthcheck without reply should fail");
+    assert!(err.is_disconnected(), "{:?}", err);
+    port.push_reply(b"\xf0\x49\x10\x56\x7c\x00\x4d\x31\x03\x6c");
+    saber.healthcheck().expect("Healthcheck failure");
+
+    let mut sabertext = PlainText::from(&port);
+    let err = sabertext
+        .healthcheck()
+        .expect_err("Healthcheck without reply should fail");
+    assert!(err.is_disconnected(), "{:?}", err);
+    port.push_reply(b"M1: B240\r\n");
+    sabertext.healthcheck().expect("Healthcheck failure");
+    port.take_written();
+
+    let mut saber = PacketSerial::from(&port).with_healthcheck_mode(HealthcheckMode::WriteOnly);
+    saber.healthcheck().expect("Healthcheck failure");
+    assert_eq!(
+        b"\xf0\x29\x10\x2e\x4d\x31\x06\x24".to_vec(),
+        port.take_written()
+    );
+    let mut sabertext = PlainText::from(&port).with_healthcheck_mode(HealthcheckMode::WriteOnly);
+    sabertext.healthcheck().expect("Healthcheck failure");
+    assert_eq!(b"M1: getb\r\n".to_vec(), port.take_written());
+    port.set_fail_writes(true);
+    let err = sabertext
+        .healthcheck()
+        .expect_err("Healthcheck with failing writes should fail");
+    assert!(err.is_disconnected(), "{:?}", err);
+}
+
+#[test]
+fn precomputed_command() {
+    let port = utils::MockPort::new();
+    let mut saber = PacketSerial::from(&port);
+    saber.set_drive(-1.0).expect("Set value failure");
+    let dynamic = port.take_written();
+
+    let drive = saber
+        .precompute(Command::Drive(-1.0))
+        .expect("Precompute failure");
+    assert_eq!(&dynamic[..], drive.as_bytes());
+    saber.send_precomputed(&drive).expect("Send failure");
+    saber.send_precomputed(&drive).expect("Send failure");
+    assert_eq!([&dynamic[..], &dynamic[..]].concat(), port.take_written());
+    assert_eq!(Some(-1.0), saber.last_commands().drive());
+
+    let checksum = PrecomputedCommand::new(PacketType::Checksum, 128, Command::Drive(-1.0))
+        .expect("Precompute failure");
+    assert_eq!(
+        &b"\x80\x28\x01\x29\x7f\x0f\x4d\x44\x1f"[..],
+        checksum.as_bytes()
+    );
+    let other = PrecomputedCommand::new(PacketType::CRC, 129, Command::Drive(-1.0))
+        .expect("Precompute failure");
+    for precomputed in &[checksum, other] {
+        match saber.send_precomputed(precomputed) {
+            Err(Error::InvalidInput(_)) => {}
+            res => panic!("Expected an invalid input error, got {:?}", res),
+        }
+    }
+    PrecomputedCommand::new(PacketType::CRC, 128, Command::Speed(3, 0.5))
+        .expect_err("Invalid channel should fail");
+    assert!(port.take_written().is_empty());
+}
+
+#[test]
+fn zero_speed() {
+    let port = utils::MockPort::new();
+    let mut sabertext = PlainText::from(&port);
+    sabertext.set_speed(1, 0.0).expect("Set value failure");
+    assert_eq!(b"M1: 0\r\n".to_vec(), port.take_written());
+
+    let mut sabertext = PlainText::from(&port).with_zero_speed(ZeroSpeed::Shutdown);
+    sabertext.set_speed(1, 0.5).expect("Set value failure");
+    sabertext.set_speed(1, 0.0).expect("Set value failure");
+    sabertext.set_speed(2, 0.0).expect("Set value failure");
+    sabertext.set_speed(1, -0.5).expect("Set value failure");
+    sabertext.set_speed(1, -0.5).expect("Set value failure");
+    assert_eq!(
+        b"M1: 1023\r\nM1: shutdown\r\nM2: shutdown\r\nM1: startup\r\nM1: -1023\r\nM1: -1023\r\n"
+            .to_vec(),
+        port.take_written()
+    );
+    assert_eq!(Some(false), sabertext.is_shutdown(1));
+    assert_eq!(Some(0.0), sabertext.last_commands().speed(2));
+    sabertext.set_speed(1, 0.0).expect("Set value failure");
+    assert_eq!(Some(0.0), sabertext.last_commands().speed(1));
+    port.take_written();
+
+    // An explicit shutdown is not undone by a speed.
+    sabertext.shutdown(2).expect("Set value failure");
+    sabertext.set_speed(2, 0.5).expect("Set value failure");
+    assert_eq!(
+        b"M2: shutdown\r\nM2: 1023\r\n".to_vec(),
+        port.take_written()
+    );
+
+    let mut saber = PacketSerial::from(&port);
+    saber.shutdown(1).expect("Set value failure");
+    saber.startup(1).expect("Set value failure");
+    saber.set_speed(1, 0.0).expect("Set value failure");
+    let shutdown_startup = port.take_written();
+    let mut saber = PacketSerial::from(&port).with_zero_speed(ZeroSpeed::Shutdown);
+    saber.set_speed(1, 0.5).expect("Set value failure");
+    port.take_written();
+    saber.set_speed(1, 0.0).expect("Set value failure");
+    assert_eq!(Some(0.0), saber.last_commands().speed(1));
+    saber.set_speed(1, 0.0).expect("Set value failure");
+    let shutdown = port.take_written();
+    assert_eq!(&shutdown_startup[..10], &shutdown[..10]);
+    assert_eq!(&shutdown_startup[..10], &shutdown[10..]);
+    saber.set_speed(1, 0.5).expect("Set value failure");
+    assert_eq!(
+        [
+            &shutdown_startup[10..20],
+            &b"\xf0\x28\x00\x0c\x7f\x07\x4d\x31\x17\x7c"[..]
+        ]
+        .concat(),
+        port.take_written()
+    );
+}
+
+#[test]
+fn turnaround_delay() {
+    let delay = Duration::from_millis(20);
+    let clock = utils::MockClock::new();
+    let port = utils::MockPort::new();
+    // The delay is waited for once the request is written and flushed, and
+    // before reading the reply.
+    let turnaround = || {
+        let (now, mut sleep) = clock.functions();
+        let port = port.clone();
+        let sleep: SleepFn = Box::new(move |duration| {
+            assert_eq!(Some(&utils::MockEvent::Flush), port.take_events().last());
+            assert!(
+                port.take_read_times().is_empty(),
+                "Reply read before the delay"
+            );
+            sleep(duration);
+        });
+        (now, sleep)
+    };
+
+    let mut saber = PacketSerial::from(&port).with_turnaround_delay(delay);
+    let (now, sleep) = turnaround();
+    saber.set_clock(now, sleep);
+    port.push_reply(b"\xf0\x49\x10\x56\x7c\x00\x4d\x31\x03\x6c");
+    assert_eq_float!(12.4, saber.get_voltage(1).expect("Get value failure"));
+    assert_eq!(vec![delay], clock.take_sleeps());
+    port.take_read_times();
+
+    let mut sabertext = PlainText::from(&port).with_turnaround_delay(delay);
+    let (now, sleep) = turnaround();
+    sabertext.set_clock(now, sleep);
+    port.push_reply(b"M1: B240\r\n");
+    assert_eq_float!(24.0, sabertext.get_voltage(1).expect("Get value failure"));
+    port.take_read_times();
+    port.push_reply(b"M1: 0\r\nM2: 0\r\n");
+    sabertext.get_speed_both().expect("Get value failure");
+    // Once for both requests.
+    assert_eq!(vec![delay, delay], clock.take_sleeps());
+
+    // No delay by default.
+    let mut saber = PacketSerial::from(&port);
+    let (now, sleep) = clock.functions();
+    saber.set_clock(now, sleep);
+    port.push_reply(b"\xf0\x49\x10\x56\x7c\x00\x4d\x31\x03\x6c");
+    saber.get_voltage(1).expect("Get value failure");
+    assert!(clock.take_sleeps().is_empty());
+}
+
+#[test]
+fn speed_presets() {
+    let port = utils::MockPort::new();
+    let mut saber = SpeedPresets::new(PacketSerial::from(&port));
+    saber.insert("slow", 0.2).expect("Insert failure");
+    saber.insert("fast", 0.5).expect("Insert failure");
+    saber.insert("reverse", -0.8).expect("Insert failure");
+    saber.insert("full", 1.0).expect("Insert failure");
+    assert_eq!(Some(0.5), saber.preset("fast"));
+    assert!(saber.insert("too fast", 1.5).is_err());
+
+    saber.drive_m1_preset("slow").expect("Set value failure");
+    assert_eq!(
+        b"\xf0\x28\x00\x0c\x19\x03\x4d\x31\x7a\x59".to_vec(),
+        port.take_written()
+    );
+    saber.drive_m1_preset("fast").expect("Set value failure");
+    assert_eq!(
+        b"\xf0\x28\x00\x0c\x7f\x07\x4d\x31\x17\x7c".to_vec(),
+        port.take_written()
+    );
+    saber.drive_m2_preset("reverse").expect("Set value failure");
+    assert_eq!(
+        b"\xf0\x28\x01\x20\x65\x0c\x4d\x32\x39\x26".to_vec(),
+        port.take_written()
+    );
+    saber.drive_preset("full").expect("Set value failure");
+    assert_eq!(
+        b"\xf0\x28\x00\x0c\x7f\x0f\x4d\x44\x5d\x31".to_vec(),
+        port.take_written()
+    );
+
+    // Replacing a preset changes the value sent.
+    saber.insert("slow", 0.5).expect("Insert failure");
+    saber.drive_m1_preset("slow").expect("Set value failure");
+    assert_eq!(
+        b"\xf0\x28\x00\x0c\x7f\x07\x4d\x31\x17\x7c".to_vec(),
+        port.take_written()
+    );
+
+    match saber.drive_m1_preset("medium") {
+        Err(Error::InvalidInput(_)) => {}
+        other => panic!("unexpected result {:?}", other),
+    }
+    assert_eq!(Some(-0.8), saber.remove("reverse"));
+    assert!(saber.drive_m2_preset("reverse").is_err());
+    assert!(port.take_written().is_empty());
+}
+
+#[test]
+fn f64_ratios() {
+    let ratios = [0.0f32, 0.5, -0.5, 1.0, -1.0, 0.123, -0.999, 1e-4];
+    for &ratio in &ratios {
+        let port32 = utils::MockPort::new();
+        let port64 = utils::MockPort::new();
+        let mut packet32 = PacketSerial::from(&port32);
+        let mut packet64 = PacketSerial::from(&port64);
+        packet32.set_speed(2, ratio).expect("Set value failure");
+        packet64
+            .set_speed_f64(2, f64::from(ratio))
+            .expect("Set value failure");
+        packet32.set_drive(ratio).expect("Set value failure");
+        packet64
+            .set_drive_f64(f64::from(ratio))
+            .expect("Set value failure");
+        packet32.set_turn(ratio).expect("Set value failure");
+        packet64
+            .set_turn_f64(f64::from(ratio))
+            .expect("Set value failure");
+        packet32.set_power(1, ratio).expect("Set value failure");
+        packet64
+            .set_power_f64(1, f64::from(ratio))
+            .expect("Set value failure");
+        packet32.set_ramp(1, ratio).expect("Set value failure");
+        packet64
+            .set_ramp_f64(1, f64::from(ratio))
+            .expect("Set value failure");
+        packet32.set_aux(2, ratio).expect("Set value failure");
+        packet64
+            .set_aux_f64(2, f64::from(ratio))
+            .expect("Set value failure");
+        assert_eq!(port32.take_written(), port64.take_written(), "{}", ratio);
+
+        let mut text32 = PlainText::from(&port32);
+        let mut text64 = PlainText::from(&port64);
+        text32.set_speed(1, ratio).expect("Set value failure");
+        text64
+            .set_speed_f64(1, f64::from(ratio))
+            .expect("Set value failure");
+        assert_eq!(port32.take_written(), port64.take_written(), "{}", ratio);
+    }
+
+    // Near the boundaries, the range is checked before rounding to f32.
+    let port = utils::MockPort::new();
+    let mut saber = PacketSerial::from(&port);
+    saber
+        .set_speed_f64(1, 1.0 - 1e-12)
+        .expect("Set value failure");
+    assert_eq!(
+        b"\xf0\x28\x00\x0c\x7f\x0f\x4d\x31\x51\x3b".to_vec(),
+        port.take_written()
+    );
+    for &ratio in &[1.0 + 1e-12, -1.0 - 1e-12, f64::NAN] {
+        match saber.set_speed_f64(1, ratio) {
+            Err(Error::InvalidInput(_)) => {}
+            res => panic!("{}: expected an invalid input error, got {:?}", ratio, res),
+        }
+    }
+    assert!(port.take_written().is_empty());
+}
+
+#[test]
+fn verify_frames() {
+    let frames: [&[u8]; 6] = [
+        b"\xf0\x28\x00\x0c\x7f\x07\x4d\x31\x17\x7c",
+        b"\xf0\x29\x10\x2e\x4d\x31\x06\x24",
+        b"\xf0\x49\x10\x56\x7c\x00\x4d\x31\x03\x6c",
+        b"\x80\x28\x01\x29\x7f\x0f\x4d\x44\x1f",
+        b"\x80\x29\x10\x39\x4d\x31\x7e",
+        b"\x80\x49\x10\x59\x7c\x00\x4d\x31\x7a",
+    ];
+    for frame in frames.iter() {
+        verify_frame(frame).expect("Valid frame rejected");
+
+        // Any single bit flipped is detected.
+        for i in 0..frame.len() * 8 {
+            let mut corrupted = frame.to_vec();
+            corrupted[i / 8] ^= 1 << (i % 8);
+            assert!(
+                verify_frame(&corrupted).is_err(),
+                "{:x?} accepted with bit {} flipped",
+                frame,
+                i
+            );
+        }
+        assert!(verify_frame(&frame[..frame.len() - 1]).is_err());
+    }
+    assert!(verify_frame(b"").is_err());
+    assert!(verify_frame(b"\x80").is_err());
+
+    // The frames sent by the interfaces verify.
+    let port = utils::MockPort::new();
+    let mut saber = PacketSerial::from(&port);
+    saber.set_speed(2, -0.25).expect("Set value failure");
+    verify_frame(&port.take_written()).expect("Sent frame rejected");
+    let mut saber = saber.with_packet_type(PacketType::Checksum);
+    saber.set_turn(0.75).expect("Set value failure");
+    verify_frame(&port.take_written()).expect("Sent frame rejected");
+}
+
+#[test]
+fn tagged_frames() {
+    let port = utils::MockPort::new();
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let recorded = events.clone();
+    let mut saber = PacketSerial::from(&port).with_packet_type(PacketType::Checksum);
+    saber.set_frame_observer(Box::new(move |event| {
+        let line = PrettyFrameLogger::format(event);
+        recorded
+            .lock()
+            .unwrap()
+            .push((event.direction, event.tag, line));
+    }));
+
+    saber.set_drive(0.5).expect("Set value failure");
+    let amps = saber
+        .tagged(7, |saber| {
+            saber.set_speed(1, 0.0)?;
+            saber.tagged(8, |saber| saber.set_turn(0.0))?;
+            port.push_reply(b"\x80\x49\x20\x69\x0B\x00\x4D\x31\x09");
+            saber.get_current(1)
+        })
+        .expect("Tagged commands failure");
+    assert_eq_float!(11.0, amps);
+    saber.set_drive(0.0).expect("Set value failure");
+
+    // A failed write is not reported, and the tag is cleared after it.
+    port.set_fail_writes(true);
+    saber
+        .tagged(9, |saber| saber.set_speed(1, 0.0))
+        .expect_err("Write should fail");
+    port.set_fail_writes(false);
+    saber.set_turn(0.5).expect("Set value failure");
+
+    let tags: Vec<_> = events
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(direction, tag, _)| (*direction, *tag))
+        .collect();
+    assert_eq!(
+        vec![
+            (Direction::Tx, None),
+            (Direction::Tx, Some(7)),
+            (Direction::Tx, Some(8)),
+            (Direction::Tx, Some(7)),
+            (Direction::Rx, Some(7)),
+            (Direction::Tx, None),
+            (Direction::Tx, None),
+        ],
+        tags
+    );
+    assert_eq!(
+        "[TX] Speed M1 value=0 (addr=128 csum=0x7E) tag=7",
+        events.lock().unwrap()[1].2
+    );
+}
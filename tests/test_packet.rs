@@ -1,8 +1,11 @@
 use std::io::Read;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use serialport::SerialPort;
 
 use saberrs::sabertooth2x32::Sabertooth2x32;
+use saberrs::Error;
 
 #[macro_use]
 mod utils;
@@ -138,6 +141,58 @@ mod checksum {
         test_set_method!(saberchecksum, set_aux, vectors, tty);
     }
 
+    #[test]
+    #[rustfmt::skip]
+    fn broadcast_stop() {
+        let (mut saberchecksum, mut tty) = utils::saberchecksum_harness();
+
+        saberchecksum.broadcast_stop(&[130, 128]).expect("Broadcast failure");
+        let expected = b"\x82\x28\x00\x2a\x00\x00\x4d\x31\x7e\x82\x28\x00\x2a\x00\x00\x4d\x32\x7f\
+                         \x80\x28\x00\x28\x00\x00\x4d\x31\x7e\x80\x28\x00\x28\x00\x00\x4d\x32\x7f";
+        let mut buf = [0u8; 36];
+        tty.read_exact(&mut buf).expect("Read fail");
+        assert_eq!(&expected[..], &buf[..], "Wrong data");
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn drive_all() {
+        let (mut saberchecksum, mut tty) = utils::saberchecksum_harness();
+
+        saberchecksum
+            .drive_all(&[(128, 0.5, -0.5), (129, 1.0, 0.0), (130, -1.0, 0.25)])
+            .expect("Drive failure");
+        let expected = b"\x80\x28\x00\x28\x7f\x07\x4d\x31\x04\x80\x28\x01\x29\x7f\x07\x4d\x32\x05\
+                         \x81\x28\x00\x29\x7f\x0f\x4d\x31\x0c\x81\x28\x00\x29\x00\x00\x4d\x32\x7f\
+                         \x82\x28\x01\x2b\x7f\x0f\x4d\x31\x0c\x82\x28\x00\x2a\x7f\x03\x4d\x32\x01";
+        let mut buf = [0u8; 54];
+        tty.read_exact(&mut buf).expect("Read fail");
+        assert_eq!(&expected[..], &buf[..], "Wrong data");
+        assert_eq!(Some(-0.5), saberchecksum.last_commands().speed(2));
+
+        saberchecksum
+            .drive_all(&[(128, 0.5, 0.5), (136, 0.5, 0.5)])
+            .expect_err("Address >135 should fail");
+        saberchecksum
+            .drive_all(&[(128, 0.5, 1.5)])
+            .expect_err("Ratio >1.0 should fail");
+        assert_eq!(0, tty.bytes_to_read().unwrap());
+    }
+
+    #[test]
+    fn broadcast_stop_errs() {
+        let (mut saberchecksum, tty) = utils::saberchecksum_harness();
+        saberchecksum
+            .broadcast_stop(&[128, 127])
+            .expect_err("Address <128 should fail");
+        saberchecksum
+            .broadcast_stop(&[136])
+            .expect_err("Address >135 should fail");
+
+        // nothing should have been sent over serial
+        assert_eq!(0, tty.bytes_to_read().unwrap());
+    }
+
     #[test]
     #[rustfmt::skip]
     fn get_speed() {
@@ -187,12 +242,93 @@ mod checksum {
             let vectors = [
             (1, b"\x80\x29\x40\x69\x4D\x31\x7E".to_vec(), b"\x80\x49\x40\x09\x1C\x00\x4D\x31\x1A".to_vec(), 28.0),
             (2, b"\x80\x29\x40\x69\x4D\x32\x7F".to_vec(), b"\x80\x49\x40\x09\x1D\x00\x4D\x32\x1C".to_vec(), 29.0),
+            (1, b"\x80\x29\x40\x69\x4D\x31\x7E".to_vec(), b"\x80\x49\x41\x0A\x0C\x00\x4D\x31\x0A".to_vec(), -12.0),
         ];
 
         let (mut saberchecksum, responder) = utils::saberchecksum_responder_harness();
         test_get_method!(saberchecksum, get_temperature, vectors, responder);
         responder.stop();
     }
+
+    #[test]
+    #[rustfmt::skip]
+    fn get_raw() {
+        #[rustfmt::skip]
+            let speed = [
+            (1, b"\x80\x29\x00\x29\x4D\x31\x7E".to_vec(), b"\x80\x49\x00\x49\x7F\x03\x4D\x31\x00".to_vec(), 511),
+            (2, b"\x80\x29\x00\x29\x4D\x32\x7F".to_vec(), b"\x80\x49\x01\x4A\x2E\x08\x4D\x32\x35".to_vec(), -1070),
+        ];
+        #[rustfmt::skip]
+            let voltage = [
+            (1, b"\x80\x29\x10\x39\x4D\x31\x7E".to_vec(), b"\x80\x49\x10\x59\x78\x00\x4D\x31\x76".to_vec(), 120),
+        ];
+        #[rustfmt::skip]
+            let current = [
+            (1, b"\x80\x29\x20\x49\x4D\x31\x7E".to_vec(), b"\x80\x49\x20\x69\x0B\x00\x4D\x31\x09".to_vec(), 11),
+        ];
+        #[rustfmt::skip]
+            let temperature = [
+            (1, b"\x80\x29\x40\x69\x4D\x31\x7E".to_vec(), b"\x80\x49\x41\x0A\x0C\x00\x4D\x31\x0A".to_vec(), -12),
+        ];
+
+        let (mut saberchecksum, responder) = utils::saberchecksum_responder_harness();
+        test_get_method!(saberchecksum, get_speed_raw, speed, responder);
+        test_get_method!(saberchecksum, get_voltage_raw, voltage, responder);
+        test_get_method!(saberchecksum, get_current_raw, current, responder);
+        test_get_method!(saberchecksum, get_temperature_raw, temperature, responder);
+        responder.stop();
+    }
+
+    #[test]
+    fn get_skips_foreign_replies() {
+        let (mut saberchecksum, responder) = utils::saberchecksum_responder_harness();
+        responder.set_expected(b"\x80\x29\x20\x49\x4D\x31\x7E");
+        responder.set_response(
+            b"\x81\x49\x20\x6a\x05\x00\x4d\x31\x03\x80\x49\x20\x69\x0b\x00\x4d\x31\x09",
+        );
+        let current = saberchecksum.get_current(1).expect("Get value failure");
+        assert_eq_float!(11.0, current);
+        assert_eq!(1, saberchecksum.skipped_replies());
+
+        // Only a foreign reply.
+        responder.set_expected(b"\x80\x29\x20\x49\x4D\x31\x7E");
+        responder.set_response(b"\x81\x49\x20\x6a\x05\x00\x4d\x31\x03");
+        let err = saberchecksum
+            .get_current(1)
+            .expect_err("No reply should fail");
+        assert!(format!("{}", err).contains("1 replies"), "{}", err);
+        assert_eq!(2, saberchecksum.skipped_replies());
+        responder.stop();
+    }
+
+    #[test]
+    fn loopback_confirmation() {
+        let (saberchecksum, responder) = utils::saberchecksum_responder_harness();
+        let mut saberchecksum = saberchecksum.with_loopback_confirmation(true);
+        let frame = b"\x80\x28\x00\x28\x00\x00MT\x21";
+
+        responder.set_expected(frame);
+        responder.set_response(frame);
+        saberchecksum.set_turn(0.0).expect("Set value failure");
+
+        let mut corrupted = frame.to_vec();
+        corrupted[4] = 0x01;
+        responder.set_expected(frame);
+        responder.set_response(&corrupted);
+        match saberchecksum.set_turn(0.0) {
+            Err(Error::Echo(_)) => {}
+            res => panic!("Expected an echo error, got {:?}", res),
+        }
+
+        // No echo at all.
+        responder.set_expected(frame);
+        responder.set_response(b"");
+        match saberchecksum.set_turn(0.0) {
+            Err(Error::Response(_)) => {}
+            res => panic!("Expected a missing echo, got {:?}", res),
+        }
+        responder.stop();
+    }
 }
 
 mod crc {
@@ -322,6 +458,19 @@ mod crc {
         test_set_method!(sabercrc, set_aux, vectors, tty);
     }
 
+    #[test]
+    #[rustfmt::skip]
+    fn broadcast_stop() {
+        let (mut sabercrc, mut tty) = utils::sabercrc_harness();
+
+        sabercrc.broadcast_stop(&[129, 135]).expect("Broadcast failure");
+        let expected = b"\xf1\x28\x00\x70\x00\x00\x4d\x31\x66\x5c\xf1\x28\x00\x70\x00\x00\x4d\x32\x14\x4c\
+                         \xf7\x28\x00\x4f\x00\x00\x4d\x31\x66\x5c\xf7\x28\x00\x4f\x00\x00\x4d\x32\x14\x4c";
+        let mut buf = [0u8; 40];
+        tty.read_exact(&mut buf).expect("Read fail");
+        assert_eq!(&expected[..], &buf[..], "Wrong data");
+    }
+
     #[test]
     #[rustfmt::skip]
     fn get_speed() {
@@ -350,6 +499,17 @@ mod crc {
         responder.stop();
     }
 
+    #[test]
+    fn get_voltage_negative() {
+        let (mut sabercrc, responder) = utils::sabercrc_responder_harness();
+        responder.set_expected(b"\xF0\x29\x10\x2E\x4D\x32\x74\x34");
+        responder.set_response(b"\xF0\x49\x11\x7A\x78\x00\x4D\x32\x26\x1A");
+        sabercrc
+            .get_voltage(2)
+            .expect_err("Negative voltage should fail");
+        responder.stop();
+    }
+
     #[test]
     #[rustfmt::skip]
     fn get_current() {
@@ -371,10 +531,69 @@ mod crc {
             let vectors = [
             (1, b"\xF0\x29\x40\x56\x4D\x31\x06\x24".to_vec(), b"\xF0\x49\x40\x2E\x1C\x00\x4D\x31\x01\x7A".to_vec(), 28.0),
             (2, b"\xF0\x29\x40\x56\x4D\x32\x74\x34".to_vec(), b"\xF0\x49\x40\x2E\x1D\x00\x4D\x32\x2E\x14".to_vec(), 29.0),
+            (2, b"\xF0\x29\x40\x56\x4D\x32\x74\x34".to_vec(), b"\xF0\x49\x41\x02\x0C\x00\x4D\x32\x0C\x6C".to_vec(), -12.0),
         ];
 
         let (mut sabercrc, responder) = utils::sabercrc_responder_harness();
         test_get_method!(sabercrc, get_temperature, vectors, responder);
         responder.stop();
     }
+
+    #[test]
+    #[rustfmt::skip]
+    fn get_raw() {
+        #[rustfmt::skip]
+            let speed = [
+            (1, b"\xF0\x29\x00\x6D\x4D\x31\x06\x24".to_vec(), b"\xF0\x49\x00\x15\x00\x0C\x4D\x31\x43\x38".to_vec(), 1536),
+            (2, b"\xF0\x29\x00\x6D\x4D\x32\x74\x34".to_vec(), b"\xF0\x49\x01\x39\x6B\x05\x4D\x32\x4C\x58".to_vec(), -747),
+        ];
+        #[rustfmt::skip]
+            let current = [
+            (1, b"\xF0\x29\x20\x06\x4D\x31\x06\x24".to_vec(), b"\xF0\x49\x21\x52\x02\x00\x4D\x31\x3D\x2A".to_vec(), -2),
+            (2, b"\xF0\x29\x20\x06\x4D\x32\x74\x34".to_vec(), b"\xF0\x49\x20\x7E\x12\x00\x4D\x32\x30\x3C".to_vec(), 18),
+        ];
+        #[rustfmt::skip]
+            let power = [
+            (1, b"\xF0\x29\x00\x6D\x50\x31\x39\x05".to_vec(), b"\xF0\x49\x00\x15\x00\x0C\x50\x31\x7C\x19".to_vec(), 1536),
+            (2, b"\xF0\x29\x00\x6D\x50\x32\x4B\x15".to_vec(), b"\xF0\x49\x01\x39\x6B\x05\x50\x32\x73\x79".to_vec(), -747),
+        ];
+
+        let (mut sabercrc, responder) = utils::sabercrc_responder_harness();
+        test_get_method!(sabercrc, get_speed_raw, speed, responder);
+        test_get_method!(sabercrc, get_current_raw, current, responder);
+        test_get_method!(sabercrc, get_power_raw, power, responder);
+        responder.stop();
+    }
+
+    #[test]
+    fn get_latency() {
+        let (mut sabercrc, responder) = utils::sabercrc_responder_harness();
+        let measured = Arc::new(Mutex::new(None));
+        let observed = measured.clone();
+        sabercrc.set_latency_observer(Box::new(move |latency| {
+            *observed.lock().unwrap() = Some(latency);
+        }));
+
+        responder.set_expected(b"\xF0\x29\x40\x56\x4D\x31\x06\x24");
+        responder.set_response(b"\xF0\x49\x40\x2E\x1C\x00\x4D\x31\x01\x7A");
+        sabercrc.get_temperature(1).expect("Get value failure");
+
+        let latency = measured.lock().unwrap().expect("No latency measured");
+        assert!(latency > Duration::from_secs(0) && latency < Duration::from_secs(1));
+        assert_eq!(1, sabercrc.latency_stats().count);
+        responder.stop();
+    }
+
+    #[test]
+    fn get_skips_foreign_replies() {
+        let (mut sabercrc, responder) = utils::sabercrc_responder_harness();
+        responder.set_expected(b"\xf0\x29\x20\x06\x4d\x31\x06\x24");
+        responder.set_response(
+            b"\xf2\x49\x20\x6b\x05\x00\x4d\x31\x6c\x44\xf0\x49\x20\x7e\x07\x00\x4d\x31\x37\x32",
+        );
+        let current = sabercrc.get_current(1).expect("Get value failure");
+        assert_eq_float!(7.0, current);
+        assert_eq!(1, sabercrc.skipped_replies());
+        responder.stop();
+    }
 }
@@ -1,8 +1,11 @@
 use std::io::Read;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use serialport::SerialPort;
 
-use saberrs::sabertooth2x32::Sabertooth2x32;
+use saberrs::convert;
+use saberrs::sabertooth2x32::{PlainText, Sabertooth2x32};
 
 #[macro_use]
 mod utils;
@@ -132,6 +135,39 @@ fn set_aux() {
     test_set_method!(sabertext, set_aux, vectors, tty);
 }
 
+#[test]
+fn set_matches_format() {
+    type Setter = fn(&mut PlainText<utils::MockPort>, f32) -> saberrs::Result<()>;
+    #[rustfmt::skip]
+    let methods: [(&str, Setter); 10] = [
+        ("M1", |s, r| s.set_speed(1, r)),
+        ("M2", |s, r| s.set_speed(2, r)),
+        ("MD", |s, r| s.set_drive(r)),
+        ("MT", |s, r| s.set_turn(r)),
+        ("P1", |s, r| s.set_power(1, r)),
+        ("P2", |s, r| s.set_power(2, r)),
+        ("R1", |s, r| s.set_ramp(1, r)),
+        ("R2", |s, r| s.set_ramp(2, r)),
+        ("Q1", |s, r| s.set_aux(1, r)),
+        ("Q2", |s, r| s.set_aux(2, r)),
+    ];
+
+    let port = utils::MockPort::new();
+    let mut saber = PlainText::from(&port);
+    let values = (-2047..=2047)
+        .step_by(13)
+        .chain(vec![-2047, -1, 0, 1, 2047]);
+    for value in values {
+        let ratio = value as f32 / 2047.0;
+        let sent = convert::ratio_to_value(ratio).unwrap();
+        for (token, set) in methods.iter() {
+            set(&mut saber, ratio).expect("Set value failure");
+            let expected = format!("{}: {}\r\n", token, sent);
+            assert_eq!(expected.into_bytes(), port.take_written());
+        }
+    }
+}
+
 #[test]
 fn get_speed() {
     #[rustfmt::skip]
@@ -184,12 +220,92 @@ fn get_current() {
     responder.stop();
 }
 
+#[test]
+fn get_current_both() {
+    let port = utils::MockPort::new();
+    let mut sabertext = PlainText::from(&port);
+
+    // Regenerating on the motor 2, in the reply format without spaces.
+    port.push_reply(b"M1: C150\r\n");
+    port.push_reply(b"M2:C-20\r\n");
+    let (current_m1, current_m2) = sabertext.get_current_both().expect("Get value failure");
+    assert_eq_float!(15.0, current_m1);
+    assert_eq_float!(-2.0, current_m2);
+    assert_eq!(b"M1: getc\r\nM2: getc\r\n".to_vec(), port.take_written());
+}
+
+#[test]
+fn get_speed_both() {
+    // The reply is sent after each request, only the first two lines are read.
+    for &(response, m1, m2) in [
+        (&b"M1: 1023\r\nM2: -2047\r\n"[..], 0.5, -1.0),
+        (&b"M2: -2047\r\nM1: 1023\r\n"[..], 0.5, -1.0),
+    ]
+    .iter()
+    {
+        let (mut sabertext, responder) = utils::sabertext_responder_harness();
+        responder.set_expected(b"M1: get\r\nM2: get\r\n");
+        responder.set_response(response);
+        let (speed_m1, speed_m2) = sabertext.get_speed_both().expect("Get value failure");
+        assert_eq_float!(m1, speed_m1);
+        assert_eq_float!(m2, speed_m2);
+        responder.stop();
+    }
+
+    // An unrelated line and a repeated channel are skipped.
+    let port = utils::MockPort::new();
+    let mut sabertext = PlainText::from(&port);
+    port.push_reply(b"M2: 0\r\nM2: 2047\r\nM1:C10\r\n");
+    port.push_reply(b"M1: -1023\r\n");
+    let (speed_m1, speed_m2) = sabertext.get_speed_both().expect("Get value failure");
+    assert_eq_float!(-0.5, speed_m1);
+    assert_eq_float!(0.0, speed_m2);
+    assert_eq!(2, sabertext.skipped_lines());
+}
+
+#[test]
+fn send_text() {
+    let port = utils::MockPort::new();
+    let mut sabertext = PlainText::from(&port);
+
+    sabertext.send_text("X7", -2047).expect("Send failure");
+    sabertext.send_text("MD", 150).expect("Send failure");
+    assert_eq!(b"X7: -2047\r\nMD: 150\r\n".to_vec(), port.take_written());
+
+    sabertext
+        .send_text("M1", 2048)
+        .expect_err("Out of range value should fail");
+    for prefix in &["", "M1:", "M 1", "M1\r\n"] {
+        sabertext
+            .send_text(prefix, 0)
+            .expect_err("Invalid prefix should fail");
+    }
+    sabertext
+        .send_text(&"M".repeat(40), 0)
+        .expect_err("Too long line should fail");
+    assert!(port.take_written().is_empty());
+}
+
+#[test]
+fn get_voltage_negative() {
+    let (mut sabertext, responder) = utils::sabertext_responder_harness();
+    responder.set_expected(b"M1: getb\r\n");
+    responder.set_response(b"M1: B-125\r\n");
+    sabertext
+        .get_voltage(1)
+        .expect_err("Negative voltage should fail");
+    responder.stop();
+}
+
 #[test]
 fn get_temperature() {
     #[rustfmt::skip]
     let vectors = [
         (1, b"M1: gett\r\n".to_vec(), b"M1: T30\r\n".to_vec(), 30.0),
         (2, b"M2: gett\r\n".to_vec(), b"M2:T85\r\n".to_vec(), 85.0),
+        (1, b"M1: gett\r\n".to_vec(), b"M1: T-12\r\n".to_vec(), -12.0),
+        (2, b"M2: gett\r\n".to_vec(), b"M2: T -5\r\n".to_vec(), -5.0),
+        (1, b"M1: gett\r\n".to_vec(), b"M1: T-0\r\n".to_vec(), 0.0),
     ];
 
     let (mut sabertext, responder) = utils::sabertext_responder_harness();
@@ -197,6 +313,101 @@ fn get_temperature() {
     responder.stop();
 }
 
+#[test]
+fn get_temperature_fahrenheit() {
+    #[rustfmt::skip]
+    let vectors = [
+        (1, b"M1: gett\r\n".to_vec(), b"M1: T30\r\n".to_vec(), 86.0),
+        (2, b"M2: gett\r\n".to_vec(), b"M2: T-40\r\n".to_vec(), -40.0),
+        (1, b"M1: gett\r\n".to_vec(), b"M1: T0\r\n".to_vec(), 32.0),
+    ];
+
+    let (mut sabertext, responder) = utils::sabertext_responder_harness();
+    test_get_method!(sabertext, get_temperature_fahrenheit, vectors, responder);
+    responder.stop();
+}
+
+#[test]
+fn get_hexadecimal() {
+    let (mut sabertext, responder) = utils::sabertext_responder_harness();
+    responder.set_expected(b"M1: get\r\n");
+    responder.set_response(b"M1: 0x4E8\r\n");
+    let hex = sabertext.get_speed(1).expect("Get value failure");
+    responder.set_expected(b"M1: get\r\n");
+    responder.set_response(b"M1: 1256\r\n");
+    let decimal = sabertext.get_speed(1).expect("Get value failure");
+    assert_eq!(decimal, hex);
+    assert_eq_float!(0.61358, hex);
+
+    responder.set_expected(b"M2: getb\r\n");
+    responder.set_response(b"M2: B0xF0\r\n");
+    assert_eq_float!(24.0, sabertext.get_voltage(2).expect("Get value failure"));
+    responder.stop();
+}
+
+#[test]
+fn get_coalesced_replies() {
+    let (mut sabertext, responder) = utils::sabertext_responder_harness();
+
+    // The extra line must not be taken as the reply of the next request.
+    responder.set_expected(b"M1: get\r\n");
+    responder.set_response(b"M1: 1256\r\nM2: 100\r\n");
+    assert_eq_float!(0.61358, sabertext.get_speed(1).expect("Get value failure"));
+
+    responder.set_expected(b"M2: get\r\n");
+    responder.set_response(b"M2: -2047\r\n");
+    assert_eq_float!(-1.0, sabertext.get_speed(2).expect("Get value failure"));
+
+    responder.stop();
+}
+
+#[test]
+fn get_unexpected_lines() {
+    let port = utils::MockPort::new();
+    let mut sabertext = PlainText::from(&port);
+
+    port.push_reply(b"Sabertooth 2x32\r\nM2: 100\r\nM1: 1256\r\n");
+    let speed = sabertext
+        .get_speed(1)
+        .expect("Unexpected lines should be skipped");
+    assert_eq_float!(0.61358, speed);
+    assert_eq!(2, sabertext.skipped_lines());
+
+    // No matching reply before the timeout.
+    port.push_reply(b"P1: 100\r\n");
+    sabertext.get_speed(1).expect_err("No reply should fail");
+    assert_eq!(3, sabertext.skipped_lines());
+
+    let mut sabertext = sabertext.with_strict_replies(true);
+    port.push_reply(b"Sabertooth 2x32\r\nM1: 1256\r\n");
+    sabertext.get_speed(1).expect_err("Banner should fail");
+    port.push_reply(b"M2: 100\r\nM1: 1256\r\n");
+    let err = sabertext
+        .get_speed(1)
+        .expect_err("Reply for M2 should fail");
+    assert!(format!("{}", err).contains("M1: "), "{}", err);
+    assert_eq!(3, sabertext.skipped_lines());
+}
+
+#[test]
+fn write_and_verify() {
+    let (mut sabertext, responder) = utils::sabertext_responder_harness();
+
+    responder.set_expected(b"M1: 1000\r\n");
+    responder.set_response(b"M1:  1000\r\n");
+    sabertext
+        .write_and_verify(b"M1: 1000\r\n")
+        .expect("Echo should match");
+
+    responder.set_expected(b"P2: -50\r\n");
+    responder.set_response(b"P2: -5\r\n");
+    sabertext
+        .write_and_verify(b"P2: -50\r\n")
+        .expect_err("Echo should not match");
+
+    responder.stop();
+}
+
 #[cfg(feature = "serialport")]
 #[test]
 fn test_from_serialport() {
@@ -204,3 +415,77 @@ fn test_from_serialport() {
     let (saber, _) = utils::saberdevice_harness();
     let _ = PlainText::from(saber);
 }
+
+#[test]
+fn set_speed_current_limit() {
+    let (sabertext, responder) = utils::sabertext_responder_harness();
+    let mut sabertext = sabertext.with_current_limit(10.0).expect("Valid limit");
+
+    // 20A measured for a 10A limit: the speed is halved.
+    responder.set_expected(b"M1: getc\r\nM1: 1023\r\n");
+    responder.set_response(b"M1: C200\r\n");
+    sabertext.set_speed(1, 1.0).expect("Set value failure");
+    responder.stop();
+}
+
+#[test]
+fn set_speed_current_limit_not_reached() {
+    let (sabertext, responder) = utils::sabertext_responder_harness();
+    let mut sabertext = sabertext.with_current_limit(10.0).expect("Valid limit");
+
+    responder.set_expected(b"M2: getc\r\nM2: -1023\r\n");
+    responder.set_response(b"M2: C-50\r\n");
+    sabertext.set_speed(2, -0.5).expect("Set value failure");
+    responder.stop();
+}
+
+#[test]
+fn set_range() {
+    let port = utils::MockPort::new();
+    let mut sabertext = PlainText::from(&port);
+
+    sabertext.set_range(100).expect("Set range failure");
+    sabertext.set_speed(1, 1.0).expect("Set value failure");
+    sabertext.set_power(2, -0.5).expect("Set value failure");
+    assert_eq!(b"M1: 100\r\nP2: -50\r\n".to_vec(), port.take_written());
+
+    port.push_reply(b"M1: 25\r\n");
+    assert_eq_float!(0.25, sabertext.get_speed(1).expect("Get value failure"));
+    assert_eq!(b"M1: get\r\n".to_vec(), port.take_written());
+
+    sabertext.set_range(0).expect_err("Range 0 should fail");
+    sabertext
+        .set_range(2048)
+        .expect_err("Range 2048 should fail");
+    sabertext
+        .set_range(-100)
+        .expect_err("Negative range should fail");
+
+    sabertext.set_range(2047).expect("Set range failure");
+    sabertext.set_speed(1, 1.0).expect("Set value failure");
+    assert_eq!(b"M1: 2047\r\n".to_vec(), port.take_written());
+}
+
+#[test]
+fn get_latency() {
+    let (mut sabertext, responder) = utils::sabertext_responder_harness();
+    let measured = Arc::new(Mutex::new(Vec::new()));
+    let observed = measured.clone();
+    sabertext.set_latency_observer(Box::new(move |latency| {
+        observed.lock().unwrap().push(latency);
+    }));
+    assert_eq!(None, sabertext.latency_stats().last);
+
+    responder.set_expected(b"M1: getc\r\n");
+    responder.set_response(b"M1: C100\r\n");
+    sabertext.get_current(1).expect("Get value failure");
+
+    let measured = measured.lock().unwrap();
+    assert_eq!(1, measured.len());
+    assert!(measured[0] > Duration::from_secs(0));
+    assert!(measured[0] < Duration::from_secs(1));
+    let stats = sabertext.latency_stats();
+    assert_eq!(Some(measured[0]), stats.last);
+    assert_eq!(Some(measured[0]), stats.average);
+    responder.stop();
+}
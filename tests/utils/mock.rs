@@ -0,0 +1,284 @@
+use std::collections::VecDeque;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use saberrs::sabertooth2x32::{NowFn, SleepFn};
+use saberrs::{Result, SabertoothSerial};
+
+/// In-memory `SabertoothSerial`, for tests which do not need a tty.
+/// Clones share the same state, so one clone may be given to an interface and
+/// the other used for inspecting what was written.
+#[derive(Clone)]
+pub struct MockPort {
+    state: Arc<Mutex<MockState>>,
+}
+
+/// Operation on a `MockPort`, in the order recorded by `take_events()`.
+#[derive(Clone, PartialEq, Debug)]
+pub enum MockEvent {
+    Write(Vec<u8>),
+    Flush,
+    SetBaudRate(u32),
+}
+
+struct MockState {
+    written: Vec<u8>,
+    events: Vec<MockEvent>,
+    write_times: Vec<Instant>,
+    read_times: Vec<Instant>,
+    to_read: VecDeque<u8>,
+    replies: VecDeque<Vec<u8>>,
+    fail_writes: bool,
+    fail_after: Option<usize>,
+    interrupts: usize,
+    flaky: bool,
+    flaky_failed: bool,
+    would_block: usize,
+    block_when_empty: bool,
+    timeout: Duration,
+    baud_rate: u32,
+}
+
+impl MockPort {
+    pub fn new() -> MockPort {
+        MockPort {
+            state: Arc::new(Mutex::new(MockState {
+                written: Vec::new(),
+                events: Vec::new(),
+                write_times: Vec::new(),
+                read_times: Vec::new(),
+                to_read: VecDeque::new(),
+                replies: VecDeque::new(),
+                fail_writes: false,
+                fail_after: None,
+                interrupts: 0,
+                flaky: false,
+                flaky_failed: false,
+                would_block: 0,
+                block_when_empty: false,
+                timeout: Duration::from_millis(100),
+                baud_rate: 9600,
+            })),
+        }
+    }
+
+    /// Return and forget all the bytes written so far.
+    pub fn take_written(&self) -> Vec<u8> {
+        std::mem::take(&mut self.state.lock().unwrap().written)
+    }
+
+    /// Return and forget the writes, flushes and baud rate changes so far.
+    pub fn take_events(&self) -> Vec<MockEvent> {
+        std::mem::take(&mut self.state.lock().unwrap().events)
+    }
+
+    /// Return and forget the instants of all the writes so far.
+    pub fn take_write_times(&self) -> Vec<Instant> {
+        std::mem::take(&mut self.state.lock().unwrap().write_times)
+    }
+
+    /// Return and forget the instants of all the read calls so far.
+    pub fn take_read_times(&self) -> Vec<Instant> {
+        std::mem::take(&mut self.state.lock().unwrap().read_times)
+    }
+
+    /// Queue bytes to be returned by the next reads.
+    pub fn push_read(&self, data: &[u8]) {
+        self.state.lock().unwrap().to_read.extend(data);
+    }
+
+    /// Queue a reply, made readable by the next write. Unlike `push_read()`
+    /// it is not discarded by a `clear_all()` preceding the request.
+    pub fn push_reply(&self, data: &[u8]) {
+        self.state.lock().unwrap().replies.push_back(data.to_vec());
+    }
+
+    /// Make every subsequent write fail.
+    pub fn set_fail_writes(&self, fail: bool) {
+        self.state.lock().unwrap().fail_writes = fail;
+    }
+
+    /// Accept only *count* more bytes, then make the writes fail. `None`
+    /// accepts all the bytes again.
+    pub fn set_fail_after(&self, count: Option<usize>) {
+        self.state.lock().unwrap().fail_after = count;
+    }
+
+    /// Make the next *count* writes fail with `ErrorKind::Interrupted`.
+    pub fn set_interrupts(&self, count: usize) {
+        self.state.lock().unwrap().interrupts = count;
+    }
+
+    /// Make every other read and write fail with `ErrorKind::Interrupted`,
+    /// so that each of them succeeds once retried.
+    pub fn set_flaky(&self, flaky: bool) {
+        self.state.lock().unwrap().flaky = flaky;
+    }
+
+    /// Make the next *count* reads fail with `ErrorKind::WouldBlock`.
+    pub fn set_would_block(&self, count: usize) {
+        self.state.lock().unwrap().would_block = count;
+    }
+
+    /// Make the reads fail with `ErrorKind::WouldBlock` instead of
+    /// `ErrorKind::TimedOut` when there is nothing to read.
+    pub fn set_block_when_empty(&self, block: bool) {
+        self.state.lock().unwrap().block_when_empty = block;
+    }
+}
+
+impl MockState {
+    /// Whether this call fails, when flaky.
+    fn interrupt_flaky(&mut self) -> bool {
+        if self.flaky {
+            self.flaky_failed = !self.flaky_failed;
+        }
+        self.flaky && self.flaky_failed
+    }
+}
+
+impl io::Read for MockPort {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut state = self.state.lock().unwrap();
+        state.read_times.push(Instant::now());
+        if state.interrupt_flaky() {
+            return Err(io::Error::new(
+                io::ErrorKind::Interrupted,
+                "mock interruption",
+            ));
+        }
+        if state.would_block > 0 {
+            state.would_block -= 1;
+            return Err(io::Error::new(
+                io::ErrorKind::WouldBlock,
+                "mock would block",
+            ));
+        }
+        if state.to_read.is_empty() && state.block_when_empty {
+            return Err(io::Error::new(
+                io::ErrorKind::WouldBlock,
+                "mock would block",
+            ));
+        }
+        if state.to_read.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "mock timeout"));
+        }
+        let len = buf.len().min(state.to_read.len());
+        for (byte, value) in buf.iter_mut().zip(state.to_read.drain(..len)) {
+            *byte = value;
+        }
+        Ok(len)
+    }
+}
+
+impl io::Write for MockPort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut state = self.state.lock().unwrap();
+        if state.interrupt_flaky() {
+            return Err(io::Error::new(
+                io::ErrorKind::Interrupted,
+                "mock interruption",
+            ));
+        }
+        if state.interrupts > 0 {
+            state.interrupts -= 1;
+            return Err(io::Error::new(
+                io::ErrorKind::Interrupted,
+                "mock interruption",
+            ));
+        }
+        let len = buf.len().min(state.fail_after.unwrap_or(usize::MAX));
+        if state.fail_writes || (len == 0 && !buf.is_empty()) {
+            return Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "mock write failure",
+            ));
+        }
+        if let Some(count) = state.fail_after.as_mut() {
+            *count -= len;
+        }
+        let buf = &buf[..len];
+        state.written.extend_from_slice(buf);
+        state.events.push(MockEvent::Write(buf.to_vec()));
+        state.write_times.push(Instant::now());
+        if let Some(reply) = state.replies.pop_front() {
+            state.to_read.extend(reply);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.state.lock().unwrap().events.push(MockEvent::Flush);
+        Ok(())
+    }
+}
+
+impl SabertoothSerial for MockPort {
+    fn set_timeout(&mut self, timeout: Duration) -> Result<()> {
+        self.state.lock().unwrap().timeout = timeout;
+        Ok(())
+    }
+
+    fn timeout(&self) -> Duration {
+        self.state.lock().unwrap().timeout
+    }
+
+    fn set_baud_rate(&mut self, baud_rate: u32) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.events.push(MockEvent::SetBaudRate(baud_rate));
+        state.baud_rate = baud_rate;
+        Ok(())
+    }
+
+    fn baud_rate(&self) -> Result<u32> {
+        Ok(self.state.lock().unwrap().baud_rate)
+    }
+
+    fn clear_all(&self) -> Result<()> {
+        self.state.lock().unwrap().to_read.clear();
+        Ok(())
+    }
+}
+
+/// Simulated clock, only advanced by `advance()` and by its sleep function.
+/// Clones share the same time.
+#[derive(Clone)]
+pub struct MockClock {
+    state: Arc<Mutex<(Instant, Vec<Duration>)>>,
+}
+
+impl MockClock {
+    pub fn new() -> MockClock {
+        MockClock {
+            state: Arc::new(Mutex::new((Instant::now(), Vec::new()))),
+        }
+    }
+
+    pub fn now(&self) -> Instant {
+        self.state.lock().unwrap().0
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        self.state.lock().unwrap().0 += duration;
+    }
+
+    /// Return and forget the durations slept so far.
+    pub fn take_sleeps(&self) -> Vec<Duration> {
+        std::mem::take(&mut self.state.lock().unwrap().1)
+    }
+
+    /// Functions for `with_clock()` or `set_clock()`.
+    pub fn functions(&self) -> (NowFn, SleepFn) {
+        let now = self.clone();
+        let sleep = self.clone();
+        (
+            Box::new(move || now.now()),
+            Box::new(move |duration| {
+                let mut state = sleep.state.lock().unwrap();
+                state.0 += duration;
+                state.1.push(duration);
+            }),
+        )
+    }
+}
@@ -8,7 +8,9 @@ use serialport::TTYPort;
 use saberrs::sabertooth2x32::{PacketSerial, PacketType, PlainText};
 use saberrs::{SabertoothPort, SabertoothPortShared, SabertoothSerial};
 
+mod mock;
 mod responder;
+pub use mock::{MockClock, MockEvent, MockPort};
 use responder::*;
 
 /// Return a (master, slave) tuple. The slave is set to non-exclusive and
@@ -26,6 +28,7 @@ pub fn tty_pair() -> (TTYPort, TTYPort) {
 pub fn saberdevice_harness() -> (SabertoothPort, TTYPort) {
     let (master, slave) = tty_pair();
     let slave_name = &slave.name().expect("TTY has no name");
+    drop(slave);
     let saber = SabertoothPort::new(slave_name).expect("Cannot open the sabertooth device");
     (saber, master)
 }
@@ -33,6 +36,7 @@ pub fn saberdevice_harness() -> (SabertoothPort, TTYPort) {
 pub fn saberdevice_harness_shared() -> (SabertoothPortShared, TTYPort) {
     let (master, slave) = tty_pair();
     let slave_name = &slave.name().expect("TTY has no name");
+    drop(slave);
     let saber = SabertoothPortShared::new(slave_name).expect("Cannot open the sabertooth device");
     (saber, master)
 }
@@ -131,7 +135,7 @@ macro_rules! test_get_method {
                     panic!("{}", e);
                 }
             } else {
-                panic!($responder.join_panic().unwrap_err());
+                panic!("{}", $responder.join_panic().unwrap_err());
             }
         }
     };
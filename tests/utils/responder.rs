@@ -7,6 +7,7 @@ use std::time::Duration;
 
 use serialport::SerialPort;
 
+#[allow(clippy::upper_case_acronyms)]
 pub enum ResponderType {
     Text,     // response sent when b'\n' is received
     Checksum, // response is sent after the last expected byte is received
@@ -48,6 +49,12 @@ impl Responder {
                 // Process command of parent: stop or update data
                 match rx.try_recv() {
                     Ok(ResponderCmd::Stop) | Err(mpsc::TryRecvError::Disconnected) => {
+                        // Expected bytes may still be in flight, consume them
+                        // before checking.
+                        let mut buf = [0u8; 1];
+                        while !self.expected.is_empty() && self.tty.read_exact(&mut buf).is_ok() {
+                            self.assert_next_byte(buf[0]);
+                        }
                         if !self.expected.is_empty() {
                             panic!("Expected data were not received: {:?}", self.expected)
                         }
@@ -82,10 +89,10 @@ impl Responder {
     fn assert_next_byte(&mut self, received: u8) {
         let expected_byte = self.expected.pop_front().expect("Received too many bytes");
         if received != expected_byte {
-            panic!(format!(
+            panic!(
                 "Expected {:#02x} ({:?}) but received {:#02x} ({:?})",
                 expected_byte, expected_byte as char, received, received as char
-            ))
+            )
         }
         if self.must_respond(received) {
             self.tty